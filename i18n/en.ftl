@@ -0,0 +1,95 @@
+# SPDX-License-Identifier: GPL-3.0-or-later
+# Copyright (C) 2026 Mark Wells Dev
+#
+# English messages, also the fallback for every other language.
+# Syntax: https://projectfluent.org/fluent/guide/
+
+## Output labels
+
+label-warning = warning:
+label-error = error:
+label-debug = debug:
+trigger-external = external
+package-word =
+    { $count ->
+        [one] package
+       *[other] packages
+    }
+
+## Confirmation prompts (answered with y or yes)
+
+prompt-clear = :: Clear { $count } package(s) from queue? [y/N]
+prompt-clear-tagged = :: Clear { $count } package(s) tagged '{ $tag }' from queue? [y/N]
+prompt-rebuild = :: Rebuild { $count } package(s)? [y/N]
+prompt-rebuild-estimate = :: Rebuild { $count } package(s), est. { $estimate }? [y/N]
+prompt-restore = :: Replace the queue ({ $queued } package(s)) and history with the backup from { $created } ({ $count } package(s))? [y/N]
+
+## Errors
+
+error-no-database = No database found at { $path }. Run a command as root first to create it.
+
+db-sqlite = database error: { $error }
+db-io = I/O error: { $error }
+db-schema-too-new = database schema version { $version } is newer than this version of anneal supports ({ $supported }), upgrade anneal
+
+config-io = failed to read config: { $error }
+config-parse = config line { $line }: { $message }
+
+pacman-pactree = failed to run pactree: { $error }
+pacman-pacman = failed to run pacman: { $error }
+pacman-pactree-exit = pactree exited with code { $code }
+pacman-pacman-exit = pacman exited with code { $code }
+pacman-checkupdates = failed to run checkupdates (is pacman-contrib installed?): { $error }
+pacman-checkupdates-exit = checkupdates exited with code { $code }
+pacman-ldd = failed to run ldd: { $error }
+pacman-fixture = failed to read fixture { $path }: { $error }
+
+no-helper = No AUR helper detected. Set 'helper' in /etc/anneal/config.conf
+    Supported helpers: { $helpers }, or '{ $makepkg }' to build without one
+ambiguous-helper = Multiple AUR helpers found: { $helpers }. Set 'helper' in /etc/anneal/config.conf
+helper-not-found = AUR helper '{ $name }' not found in PATH
+helper-template = Invalid helper command: { $message }
+helper-spawn = Failed to start AUR helper: { $error }
+helper-failed = AUR helper exited with code { $code }
+package-not-in-queue = Package '{ $package }' is not in the queue (use -f to force)
+unknown-profile = Profile '{ $name }' is not defined in /etc/anneal/config.conf
+makepkg-spawn = Failed to start makepkg: { $error }
+makepkg-failed = makepkg exited with code { $code } for '{ $package }'
+checkout-failed = Failed to get the AUR repository of '{ $base }': { $error }
+pacman-busy = pacman is still running after { $minutes } minutes ({ $lock } exists)
+rebuild-running = Another rebuild is already running ({ $lock } is locked)
+
+git-offline = offline mode is enabled and there is no checkout
+git-dir = cannot create the checkout directory: { $error }
+git-spawn = failed to run git: { $error }
+git-failed = git exited with code { $code }
+git-no-pkgbuild = no PKGBUILD in the repository (not an AUR package?)
+
+net-offline = offline mode is enabled
+net-spawn = failed to run curl: { $error }
+net-unreachable = network unreachable (curl exited with code { $code })
+net-request = request failed (curl exited with code { $code })
+
+aur-net = AUR request failed: { $error }
+aur-json = invalid AUR response: { $error }
+aur-rpc = AUR RPC error: { $message }
+
+backup-io = cannot access { $path }: { $error }
+backup-invalid = { $path } is not a valid backup: { $message }
+
+hook-io = cannot write { $path }: { $error }
+hook-modified = { $path } exists with different content. Use --force to overwrite.
+
+override-invalid-name = invalid name '{ $name }'
+override-invalid-pattern = invalid pattern '{ $pattern }', expected a package name with optional * and ? wildcards
+override-would-empty = removing every entry would leave { $path } empty, which changes its meaning; remove the whole override instead
+
+build-order-io = failed to read { $file }: { $error }
+build-order-parse = { $file } line { $line }: expected '<pkg> before <pkg>' or '<pkg> after <pkg>', got '{ $content }'
+build-order-cycle = { $file } orders these packages in a cycle: { $packages }
+
+trigger-list-io = cannot read { $path }: { $error }
+trigger-list-parse = line { $line }: { $message }
+trigger-list-no-version = missing version line
+trigger-list-empty = no triggers in list
+trigger-list-fetch = failed to fetch trigger list: { $error }
@@ -0,0 +1,189 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! Append-only audit log of queue mutations.
+//!
+//! When `audit_log` is set in the config, every change to the queue is
+//! appended to that file as one tab-separated line, independent of the
+//! database (which only keeps trigger history, and prunes it):
+//!
+//! ```text
+//! <timestamp>\t<actor>\t<action>\t<packages>\t<command line>
+//! 2026-01-15T10:32:01.123Z\tuid=0 sudo_user=alice\tunmark\tqt6gtk2\tanneal unmark qt6gtk2
+//! ```
+//!
+//! Packages are comma-separated (`-` if none). Tabs and newlines in the
+//! command line are escaped so each entry stays on one line.
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::output;
+use crate::time::now_iso8601;
+
+/// File mode for a newly created audit log.
+const LOG_MODE: u32 = 0o640;
+
+/// Audit log writer.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    /// Path of the log file.
+    path: PathBuf,
+}
+
+impl AuditLog {
+    /// Create an audit log writing to the given file.
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Get the audit log from the configuration, if enabled.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        config.audit_log.as_ref().map(|path| Self::new(path.into()))
+    }
+
+    /// Append an entry for a queue mutation.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the log file can't be opened or written.
+    pub fn record(&self, action: &str, packages: &[&str]) -> io::Result<()> {
+        let command_line: Vec<String> = std::env::args().collect();
+        let entry = format_entry(
+            &now_iso8601(),
+            &describe_actor(),
+            action,
+            packages,
+            &command_line,
+        );
+
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .mode(LOG_MODE)
+            .open(&self.path)?;
+
+        // Single write so concurrent writers can't interleave within a line
+        file.write_all(entry.as_bytes())
+    }
+}
+
+/// Record a queue change in the audit log, if enabled.
+///
+/// Failures only warn: hooks must not fail because the log is unwritable.
+pub fn record(config: &Config, action: &str, packages: &[&str]) {
+    if packages.is_empty() {
+        return;
+    }
+    if let Some(log) = AuditLog::from_config(config)
+        && let Err(e) = log.record(action, packages)
+    {
+        output::warning(&format!("Failed to write audit log: {e}"));
+    }
+}
+
+/// Describe who is making the change.
+///
+/// Includes the original user when running through sudo or pkexec.
+fn describe_actor() -> String {
+    // SAFETY: getuid is always safe to call
+    let uid = unsafe { libc::getuid() };
+    let mut actor = format!("uid={uid}");
+
+    if let Ok(user) = std::env::var("SUDO_USER") {
+        actor.push_str(&format!(" sudo_user={user}"));
+    } else if let Ok(pkexec_uid) = std::env::var("PKEXEC_UID") {
+        actor.push_str(&format!(" pkexec_uid={pkexec_uid}"));
+    }
+
+    actor
+}
+
+/// Format a log entry (including the trailing newline).
+fn format_entry(
+    timestamp: &str,
+    actor: &str,
+    action: &str,
+    packages: &[&str],
+    command_line: &[String],
+) -> String {
+    let packages = if packages.is_empty() {
+        "-".to_string()
+    } else {
+        packages.join(",")
+    };
+    let command_line = escape(&command_line.join(" "));
+
+    format!("{timestamp}\t{actor}\t{action}\t{packages}\t{command_line}\n")
+}
+
+/// Escape characters that would break the line format.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_format() {
+        let entry = format_entry(
+            "2026-01-15T10:32:01.123Z",
+            "uid=0 sudo_user=alice",
+            "mark",
+            &["qt6gtk2", "qt6ct"],
+            &[
+                "anneal".into(),
+                "mark".into(),
+                "qt6gtk2".into(),
+                "qt6ct".into(),
+            ],
+        );
+        assert_eq!(
+            entry,
+            "2026-01-15T10:32:01.123Z\tuid=0 sudo_user=alice\tmark\tqt6gtk2,qt6ct\tanneal mark qt6gtk2 qt6ct\n"
+        );
+    }
+
+    #[test]
+    fn entry_without_packages() {
+        let entry = format_entry("ts", "uid=0", "clear", &[], &["anneal".into()]);
+        assert_eq!(entry, "ts\tuid=0\tclear\t-\tanneal\n");
+    }
+
+    #[test]
+    fn command_line_escaped() {
+        let entry = format_entry("ts", "uid=0", "mark", &["a"], &["a\tb\nc\\".into()]);
+        assert_eq!(entry, "ts\tuid=0\tmark\ta\ta\\tb\\nc\\\\\n");
+        assert_eq!(entry.lines().count(), 1);
+    }
+
+    #[test]
+    fn record_appends() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let log = AuditLog::new(path.clone());
+
+        log.record("mark", &["pkg1"]).unwrap();
+        log.record("unmark", &["pkg1"]).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].split('\t').nth(2), Some("mark"));
+        assert_eq!(lines[1].split('\t').nth(2), Some("unmark"));
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        assert!(AuditLog::from_config(&Config::default()).is_none());
+    }
+}
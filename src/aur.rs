@@ -0,0 +1,641 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! AUR RPC client.
+//!
+//! Queries the AUR RPC interface (v5) for package metadata. Requests go
+//! through [`crate::net`], which invokes `curl`, matching how the rest of
+//! Anneal delegates to system tools (pacman, pactree, ldd) instead
+//! of linking network stacks.
+//!
+//! Only the fields Anneal actually uses are extracted from the response.
+//!
+//! Info queries are batched: as many packages as fit within the URL length
+//! limit go into one `info` request, and when a query needs several requests
+//! they are fetched concurrently.
+//!
+//! ## Caching
+//!
+//! Info results are cached on disk, one file per package, for
+//! `aur_cache_minutes` (default 60). Packages the AUR doesn't know about are
+//! cached too, so local packages don't cause a request on every run. The
+//! cache lives in `/var/cache/anneal/aur` for root and
+//! `$XDG_CACHE_HOME/anneal/aur` otherwise (`ANNEAL_CACHE_DIR` overrides the
+//! base directory).
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use serde_json::Value;
+
+use crate::config::Config;
+use crate::net::{self, NetError, NetOptions};
+use crate::srcinfo::Srcinfo;
+use crate::tr;
+
+/// Base URL of the AUR RPC interface.
+pub const AUR_RPC_URL: &str = "https://aur.archlinux.org/rpc/v5";
+
+/// URL prefix for a package base's current `.SRCINFO`.
+pub const AUR_SRCINFO_URL: &str = "https://aur.archlinux.org/cgit/aur.git/plain/.SRCINFO?h=";
+
+/// Maximum length of an RPC request URL.
+///
+/// The AUR rejects request URIs beyond roughly 4400 bytes; stay below that.
+const MAX_URL_LEN: usize = 4000;
+
+/// Maximum number of RPC requests in flight at once.
+const MAX_CONCURRENT_REQUESTS: usize = 4;
+
+/// System cache directory (used when running as root).
+pub const SYSTEM_CACHE_DIR: &str = "/var/cache/anneal";
+
+/// Get the cache directory, checking the ANNEAL_CACHE_DIR environment variable.
+///
+/// Root uses the system cache directory; other users get a per-user cache
+/// under `$XDG_CACHE_HOME` (or `~/.cache`), since they can't write to it.
+pub fn get_cache_dir() -> PathBuf {
+    if let Ok(dir) = std::env::var("ANNEAL_CACHE_DIR") {
+        return PathBuf::from(dir);
+    }
+
+    // SAFETY: geteuid is always safe to call
+    if unsafe { libc::geteuid() } == 0 {
+        return PathBuf::from(SYSTEM_CACHE_DIR);
+    }
+
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map_or_else(|| PathBuf::from(SYSTEM_CACHE_DIR), |dir| dir.join("anneal"))
+}
+
+/// Package metadata returned by the AUR RPC `info` endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AurPackage {
+    /// Package name.
+    pub name: String,
+    /// Package base name.
+    pub package_base: String,
+    /// Current version on the AUR.
+    pub version: String,
+    /// Maintainer username (None for orphaned packages).
+    pub maintainer: Option<String>,
+    /// Unix timestamp when the package was flagged out-of-date, if flagged.
+    pub out_of_date: Option<i64>,
+}
+
+impl AurPackage {
+    /// Returns true if the package is flagged out-of-date on the AUR.
+    pub fn is_flagged(&self) -> bool {
+        self.out_of_date.is_some()
+    }
+}
+
+/// AUR RPC errors.
+#[derive(Debug)]
+pub enum AurError {
+    /// The request could not be made or failed.
+    Net(NetError),
+    /// The response was not valid JSON.
+    Json(serde_json::Error),
+    /// The RPC returned an error response.
+    Rpc(String),
+}
+
+impl std::fmt::Display for AurError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Net(e) => f.write_str(&tr!("aur-net", error = e.to_string())),
+            Self::Json(e) => f.write_str(&tr!("aur-json", error = e.to_string())),
+            Self::Rpc(msg) => f.write_str(&tr!("aur-rpc", message = msg.as_str())),
+        }
+    }
+}
+
+impl AurError {
+    /// Returns true if the AUR couldn't be queried because the network is
+    /// unavailable or offline mode is enabled.
+    pub fn is_offline(&self) -> bool {
+        matches!(self, Self::Net(e) if e.is_offline())
+    }
+}
+
+impl std::error::Error for AurError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Net(e) => Some(e),
+            Self::Json(e) => Some(e),
+            Self::Rpc(_) => None,
+        }
+    }
+}
+
+/// Client for the AUR RPC interface.
+#[derive(Debug, Clone, Default)]
+pub struct AurClient {
+    /// Network options (offline mode, proxy).
+    net: NetOptions,
+    /// Info cache (None disables caching).
+    cache: Option<InfoCache>,
+}
+
+impl AurClient {
+    /// Create a client with explicit network options and cache.
+    pub fn new(net: NetOptions, cache: Option<InfoCache>) -> Self {
+        Self { net, cache }
+    }
+
+    /// Create a client from the configuration, using the default cache directory.
+    pub fn from_config(config: &Config) -> Self {
+        let cache = (config.aur_cache_minutes > 0).then(|| {
+            InfoCache::new(
+                get_cache_dir().join("aur"),
+                Duration::from_secs(u64::from(config.aur_cache_minutes) * 60),
+            )
+        });
+        Self::new(NetOptions::from_config(config), cache)
+    }
+
+    /// Query the AUR for package info.
+    ///
+    /// Packages that don't exist on the AUR are simply absent from the result.
+    /// Fresh cache entries are used instead of querying the RPC.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response can't be parsed.
+    pub fn info(&self, packages: &[&str]) -> Result<Vec<AurPackage>, AurError> {
+        let mut found = Vec::new();
+        let mut missing = Vec::new();
+
+        for pkg in packages {
+            match self.cache.as_ref().and_then(|cache| cache.get(pkg)) {
+                Some(Some(cached)) => found.push(cached),
+                Some(None) => {} // Cached as not on the AUR
+                None => missing.push(*pkg),
+            }
+        }
+
+        if missing.is_empty() {
+            return Ok(found);
+        }
+
+        let results = self.fetch_info(&missing)?;
+
+        if let Some(cache) = &self.cache {
+            let by_name: HashMap<&str, &Value> = results
+                .iter()
+                .filter_map(|value| Some((value["Name"].as_str()?, value)))
+                .collect();
+            for pkg in &missing {
+                cache.put(pkg, by_name.get(pkg).copied());
+            }
+        }
+
+        found.extend(results.iter().filter_map(parse_package));
+        Ok(found)
+    }
+
+    /// Query the AUR for all packages maintained by a user.
+    ///
+    /// Not cached: the result is only used for a one-off mark.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response can't be parsed.
+    pub fn by_maintainer(&self, maintainer: &str) -> Result<Vec<AurPackage>, AurError> {
+        let url = format!(
+            "{AUR_RPC_URL}/search/{}?by=maintainer",
+            encode_component(maintainer)
+        );
+        let body = net::fetch(&url, &self.net).map_err(AurError::Net)?;
+        Ok(parse_info_results(&body)?
+            .iter()
+            .filter_map(parse_package)
+            .collect())
+    }
+
+    /// Fetch the current `.SRCINFO` of a package base.
+    ///
+    /// Not cached: it's only fetched to confirm a mark.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails.
+    pub fn srcinfo(&self, package_base: &str) -> Result<Srcinfo, AurError> {
+        let url = format!("{AUR_SRCINFO_URL}{}", encode_component(package_base));
+        let body = net::fetch(&url, &self.net).map_err(AurError::Net)?;
+        Ok(Srcinfo::parse(&body))
+    }
+
+    /// Fetch raw info results, splitting the query into URL-sized chunks.
+    fn fetch_info(&self, packages: &[&str]) -> Result<Vec<Value>, AurError> {
+        let chunks = chunk_packages(packages);
+        let mut results = Vec::new();
+
+        for batch in chunks.chunks(MAX_CONCURRENT_REQUESTS) {
+            let responses: Vec<Result<Vec<Value>, AurError>> = std::thread::scope(|scope| {
+                let handles: Vec<_> = batch
+                    .iter()
+                    .map(|chunk| scope.spawn(|| self.fetch_chunk(chunk)))
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|handle| {
+                        handle.join().unwrap_or_else(|_| {
+                            Err(AurError::Rpc("request thread panicked".into()))
+                        })
+                    })
+                    .collect()
+            });
+
+            for response in responses {
+                results.extend(response?);
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Fetch a single `info` request.
+    fn fetch_chunk(&self, packages: &[&str]) -> Result<Vec<Value>, AurError> {
+        let body = net::fetch(&info_url(packages), &self.net).map_err(AurError::Net)?;
+        parse_info_results(&body)
+    }
+}
+
+/// On-disk cache of AUR info results with a time-to-live.
+///
+/// Cache operations are best-effort: unreadable or unwritable entries are
+/// treated as misses.
+#[derive(Debug, Clone)]
+pub struct InfoCache {
+    /// Directory holding one `<package>.json` file per entry.
+    dir: PathBuf,
+    /// How long an entry stays fresh.
+    ttl: Duration,
+}
+
+impl InfoCache {
+    /// Create a cache rooted at the given directory.
+    pub fn new(dir: PathBuf, ttl: Duration) -> Self {
+        Self { dir, ttl }
+    }
+
+    /// Look up a fresh cache entry.
+    ///
+    /// Returns:
+    /// - `None` on a cache miss (no entry, expired, or unreadable)
+    /// - `Some(None)` if the package is cached as not existing on the AUR
+    /// - `Some(Some(pkg))` for a cached result
+    fn get(&self, package: &str) -> Option<Option<AurPackage>> {
+        let path = self.entry_path(package)?;
+        if !is_fresh(&path, self.ttl) {
+            return None;
+        }
+
+        let contents = fs::read_to_string(&path).ok()?;
+        let value: Value = serde_json::from_str(&contents).ok()?;
+        if value.is_null() {
+            return Some(None);
+        }
+        parse_package(&value).map(Some)
+    }
+
+    /// Store a result (or `None` for "not on the AUR").
+    fn put(&self, package: &str, value: Option<&Value>) {
+        let Some(path) = self.entry_path(package) else {
+            return;
+        };
+        if fs::create_dir_all(&self.dir).is_err() {
+            return;
+        }
+        let contents = value.map_or_else(|| "null".to_string(), Value::to_string);
+        let _ = fs::write(path, contents);
+    }
+
+    /// Path of a package's cache entry.
+    ///
+    /// Returns None for names that can't safely be used as file names
+    /// (queue entries aren't validated, so this can't be assumed).
+    fn entry_path(&self, package: &str) -> Option<PathBuf> {
+        if package.is_empty() || package.starts_with('.') || package.contains('/') {
+            return None;
+        }
+        Some(self.dir.join(format!("{package}.json")))
+    }
+}
+
+/// Check whether a file was modified within the TTL.
+fn is_fresh(path: &Path, ttl: Duration) -> bool {
+    fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age < ttl)
+}
+
+/// Build an `info` request URL for the given packages.
+fn info_url(packages: &[&str]) -> String {
+    let args: Vec<String> = packages
+        .iter()
+        .map(|pkg| format!("arg[]={}", encode_component(pkg)))
+        .collect();
+    format!("{AUR_RPC_URL}/info?{}", args.join("&"))
+}
+
+/// Split packages into chunks whose `info` URL fits within [`MAX_URL_LEN`].
+///
+/// A name too long to share a request still gets a chunk of its own.
+fn chunk_packages<'a>(packages: &[&'a str]) -> Vec<Vec<&'a str>> {
+    let base_len = info_url(&[]).len();
+    let mut chunks: Vec<Vec<&str>> = Vec::new();
+    let mut current = Vec::new();
+    let mut len = base_len;
+
+    for pkg in packages {
+        let arg_len = "arg[]=".len() + encode_component(pkg).len();
+        // One more for the "&" separator
+        if !current.is_empty() && len + 1 + arg_len > MAX_URL_LEN {
+            chunks.push(std::mem::take(&mut current));
+            len = base_len;
+        }
+        len += arg_len + usize::from(!current.is_empty());
+        current.push(*pkg);
+    }
+
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Parse an RPC `info` or `search` response body into its raw result objects.
+fn parse_info_results(body: &str) -> Result<Vec<Value>, AurError> {
+    let mut value: Value = serde_json::from_str(body).map_err(AurError::Json)?;
+
+    if value["type"] == "error" {
+        let msg = value["error"].as_str().unwrap_or("unknown error");
+        return Err(AurError::Rpc(msg.to_string()));
+    }
+
+    match value["results"].take() {
+        Value::Array(results) => Ok(results),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Parse an RPC `info` response body.
+#[cfg(test)]
+fn parse_info_response(body: &str) -> Result<Vec<AurPackage>, AurError> {
+    Ok(parse_info_results(body)?
+        .iter()
+        .filter_map(parse_package)
+        .collect())
+}
+
+/// Extract an `AurPackage` from a single RPC result object.
+fn parse_package(value: &Value) -> Option<AurPackage> {
+    let name = value["Name"].as_str()?.to_string();
+    Some(AurPackage {
+        package_base: value["PackageBase"]
+            .as_str()
+            .map_or_else(|| name.clone(), String::from),
+        version: value["Version"].as_str().unwrap_or_default().to_string(),
+        maintainer: value["Maintainer"].as_str().map(String::from),
+        out_of_date: value["OutOfDate"].as_i64(),
+        name,
+    })
+}
+
+/// Percent-encode a URL query component.
+///
+/// Package names are restricted to `[a-z0-9@._+-]`, but `+` and `@` still
+/// need encoding to survive as query values.
+fn encode_component(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char);
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+    use super::*;
+
+    const RESPONSE: &str = r#"{
+        "resultcount": 2,
+        "results": [
+            {
+                "Name": "qt6gtk2",
+                "PackageBase": "qt6gtk2",
+                "Version": "0.2-5",
+                "Maintainer": "someone",
+                "OutOfDate": null
+            },
+            {
+                "Name": "yin-yang",
+                "PackageBase": "yin-yang",
+                "Version": "3.4-1",
+                "Maintainer": null,
+                "OutOfDate": 1718000000
+            }
+        ],
+        "type": "multiinfo",
+        "version": 5
+    }"#;
+
+    #[test]
+    fn parse_results() {
+        let packages = parse_info_response(RESPONSE).unwrap();
+        assert_eq!(packages.len(), 2);
+
+        assert_eq!(packages[0].name, "qt6gtk2");
+        assert_eq!(packages[0].version, "0.2-5");
+        assert_eq!(packages[0].maintainer, Some("someone".into()));
+        assert!(!packages[0].is_flagged());
+
+        assert_eq!(packages[1].name, "yin-yang");
+        assert_eq!(packages[1].maintainer, None);
+        assert_eq!(packages[1].out_of_date, Some(1_718_000_000));
+        assert!(packages[1].is_flagged());
+    }
+
+    #[test]
+    fn parse_empty_results() {
+        let body = r#"{"resultcount":0,"results":[],"type":"multiinfo","version":5}"#;
+        assert!(parse_info_response(body).unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_error_response() {
+        let body = r#"{"error":"Incorrect request type specified.","resultcount":0,"results":[],"type":"error","version":5}"#;
+        match parse_info_response(body).unwrap_err() {
+            AurError::Rpc(msg) => assert!(msg.contains("Incorrect request type")),
+            e => panic!("expected RPC error, got {e:?}"),
+        }
+    }
+
+    #[test]
+    fn parse_invalid_json() {
+        assert!(matches!(
+            parse_info_response("<html>").unwrap_err(),
+            AurError::Json(_)
+        ));
+    }
+
+    fn offline() -> NetOptions {
+        NetOptions {
+            offline: true,
+            ..NetOptions::default()
+        }
+    }
+
+    #[test]
+    fn offline_info_is_offline_error() {
+        let client = AurClient::new(offline(), None);
+        let err = client.info(&["qt6gtk2"]).unwrap_err();
+        assert!(err.is_offline());
+    }
+
+    #[test]
+    fn offline_srcinfo_is_offline_error() {
+        let client = AurClient::new(offline(), None);
+        assert!(client.srcinfo("qt6gtk2").unwrap_err().is_offline());
+    }
+
+    #[test]
+    fn offline_maintainer_is_offline_error() {
+        let client = AurClient::new(offline(), None);
+        assert!(client.by_maintainer("alice").unwrap_err().is_offline());
+    }
+
+    #[test]
+    fn empty_query_makes_no_request() {
+        let client = AurClient::new(offline(), None);
+        assert!(client.info(&[]).unwrap().is_empty());
+    }
+
+    mod cache {
+        use super::*;
+
+        fn temp_cache(ttl: Duration) -> (tempfile::TempDir, InfoCache) {
+            let dir = tempfile::tempdir().unwrap();
+            let cache = InfoCache::new(dir.path().join("aur"), ttl);
+            (dir, cache)
+        }
+
+        fn result(name: &str) -> Value {
+            serde_json::json!({
+                "Name": name,
+                "PackageBase": name,
+                "Version": "1.0-1",
+                "Maintainer": "someone",
+                "OutOfDate": null,
+            })
+        }
+
+        #[test]
+        fn hit_and_miss() {
+            let (_dir, cache) = temp_cache(Duration::from_secs(3600));
+            assert_eq!(cache.get("pkg1"), None);
+
+            cache.put("pkg1", Some(&result("pkg1")));
+            let cached = cache.get("pkg1").unwrap().unwrap();
+            assert_eq!(cached.name, "pkg1");
+            assert_eq!(cached.version, "1.0-1");
+        }
+
+        #[test]
+        fn caches_not_found() {
+            let (_dir, cache) = temp_cache(Duration::from_secs(3600));
+            cache.put("local-pkg", None);
+            assert_eq!(cache.get("local-pkg"), Some(None));
+        }
+
+        #[test]
+        fn expired_entries_miss() {
+            let (_dir, cache) = temp_cache(Duration::ZERO);
+            cache.put("pkg1", Some(&result("pkg1")));
+            assert_eq!(cache.get("pkg1"), None);
+        }
+
+        #[test]
+        fn unsafe_names_not_cached() {
+            let (dir, cache) = temp_cache(Duration::from_secs(3600));
+            cache.put("../escape", None);
+            cache.put(".hidden", None);
+            assert_eq!(cache.get("../escape"), None);
+            assert!(!dir.path().join("escape.json").exists());
+        }
+
+        #[test]
+        fn client_serves_from_cache_offline() {
+            let (_dir, cache) = temp_cache(Duration::from_secs(3600));
+            cache.put("pkg1", Some(&result("pkg1")));
+            cache.put("local-pkg", None);
+
+            // Everything is cached, so no request is needed even offline
+            let client = AurClient::new(offline(), Some(cache));
+            let info = client.info(&["pkg1", "local-pkg"]).unwrap();
+            assert_eq!(info.len(), 1);
+            assert_eq!(info[0].name, "pkg1");
+
+            // An uncached package still requires the network
+            assert!(client.info(&["pkg1", "pkg2"]).unwrap_err().is_offline());
+        }
+    }
+
+    #[test]
+    fn url_encodes_package_names() {
+        let url = info_url(&["gtk+2", "foo"]);
+        assert_eq!(url, format!("{AUR_RPC_URL}/info?arg[]=gtk%2B2&arg[]=foo"));
+    }
+
+    #[test]
+    fn small_query_is_one_chunk() {
+        let chunks = chunk_packages(&["pkg1", "pkg2", "pkg3"]);
+        assert_eq!(chunks, vec![vec!["pkg1", "pkg2", "pkg3"]]);
+    }
+
+    #[test]
+    fn large_query_is_chunked() {
+        let names: Vec<String> = (0..300).map(|i| format!("python-package-{i:03}")).collect();
+        let packages: Vec<&str> = names.iter().map(String::as_str).collect();
+        let chunks = chunk_packages(&packages);
+
+        assert!(chunks.len() > 1);
+        assert!(chunks.len() <= 3);
+        for chunk in &chunks {
+            assert!(info_url(chunk).len() <= MAX_URL_LEN);
+        }
+        // Order and contents are preserved
+        assert_eq!(chunks.concat(), packages);
+    }
+
+    #[test]
+    fn oversized_name_gets_own_chunk() {
+        let long = "a".repeat(MAX_URL_LEN);
+        let chunks = chunk_packages(&["pkg1", &long, "pkg2"]);
+        assert_eq!(
+            chunks,
+            vec![vec!["pkg1"], vec![long.as_str()], vec!["pkg2"]]
+        );
+    }
+
+    #[test]
+    fn encode_leaves_unreserved_alone() {
+        assert_eq!(encode_component("python-foo_bar.1"), "python-foo_bar.1");
+        assert_eq!(encode_component("lib@home"), "lib%40home");
+    }
+}
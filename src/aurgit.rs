@@ -0,0 +1,200 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! AUR git checkouts for the built-in makepkg backend (`helper = makepkg`).
+//!
+//! Without an AUR helper, `rebuild` clones each package base's AUR git
+//! repository into `<cache>/clone/<pkgbase>` (see [`crate::aur::get_cache_dir`])
+//! and runs makepkg there. An existing checkout is fast-forwarded instead, so
+//! local commits on top of the AUR's (a patched PKGBUILD) are kept, and a
+//! diverged history stops the build rather than being overwritten.
+//!
+//! In offline mode an existing checkout is built as it is; a missing one is
+//! an error.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::aur;
+use crate::net::NetOptions;
+use crate::tr;
+
+/// Base URL of the AUR's git repositories.
+pub const AUR_GIT_URL: &str = "https://aur.archlinux.org";
+
+/// How a checkout was brought up to date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Synced {
+    /// The repository was cloned.
+    Cloned,
+    /// An existing checkout was fast-forwarded.
+    Updated,
+    /// Offline mode is enabled; the existing checkout was left as it is.
+    Offline,
+}
+
+/// Errors cloning or updating a checkout.
+#[derive(Debug)]
+pub enum GitError {
+    /// Offline mode is enabled and there is no checkout yet.
+    Offline,
+    /// The checkout directory couldn't be created.
+    Dir(io::Error),
+    /// Failed to run git.
+    Spawn(io::Error),
+    /// git exited with a non-zero code.
+    Failed(i32),
+    /// The repository has no PKGBUILD (the AUR serves an empty repository
+    /// for any name).
+    NoPkgbuild,
+}
+
+impl std::fmt::Display for GitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Offline => f.write_str(&tr!("git-offline")),
+            Self::Dir(e) => f.write_str(&tr!("git-dir", error = e.to_string())),
+            Self::Spawn(e) => f.write_str(&tr!("git-spawn", error = e.to_string())),
+            Self::Failed(code) => f.write_str(&tr!("git-failed", code = *code)),
+            Self::NoPkgbuild => f.write_str(&tr!("git-no-pkgbuild")),
+        }
+    }
+}
+
+impl std::error::Error for GitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Dir(e) | Self::Spawn(e) => Some(e),
+            Self::Offline | Self::Failed(_) | Self::NoPkgbuild => None,
+        }
+    }
+}
+
+/// Get the git URL of a package base on the AUR.
+pub fn repo_url(package_base: &str) -> String {
+    format!("{AUR_GIT_URL}/{package_base}.git")
+}
+
+/// Get the directory holding the checkouts.
+pub fn clone_dir() -> PathBuf {
+    aur::get_cache_dir().join("clone")
+}
+
+/// Clone a repository into a directory, or fast-forward the checkout
+/// already there.
+///
+/// # Errors
+///
+/// Returns an error if git fails, if offline mode leaves no checkout to
+/// build, or if the cloned repository has no PKGBUILD (the empty clone is
+/// removed again).
+pub fn sync(url: &str, dir: &Path, opts: &NetOptions) -> Result<Synced, GitError> {
+    let exists = dir.join(".git").is_dir();
+    if opts.offline {
+        return if exists {
+            Ok(Synced::Offline)
+        } else {
+            Err(GitError::Offline)
+        };
+    }
+
+    let mut command = Command::new("git");
+    command.envs(opts.proxy_env());
+    let synced = if exists {
+        command
+            .arg("-C")
+            .arg(dir)
+            .args(["pull", "--ff-only", "--quiet"]);
+        Synced::Updated
+    } else {
+        if let Some(parent) = dir.parent() {
+            fs::create_dir_all(parent).map_err(GitError::Dir)?;
+        }
+        command.args(["clone", "--quiet", url]).arg(dir);
+        Synced::Cloned
+    };
+
+    let status = command.status().map_err(GitError::Spawn)?;
+    if !status.success() {
+        return Err(GitError::Failed(status.code().unwrap_or(-1)));
+    }
+    if !dir.join("PKGBUILD").exists() {
+        if synced == Synced::Cloned {
+            let _ = fs::remove_dir_all(dir);
+        }
+        return Err(GitError::NoPkgbuild);
+    }
+    Ok(synced)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {args:?}");
+    }
+
+    #[test]
+    fn aur_url() {
+        assert_eq!(repo_url("qt6gtk2"), "https://aur.archlinux.org/qt6gtk2.git");
+    }
+
+    #[test]
+    fn clone_then_update() {
+        let temp = tempfile::tempdir().unwrap();
+        let origin = temp.path().join("origin");
+        fs::create_dir(&origin).unwrap();
+        git(&origin, &["init", "--quiet"]);
+        fs::write(origin.join("PKGBUILD"), "pkgver=1\n").unwrap();
+        git(&origin, &["add", "PKGBUILD"]);
+        git(&origin, &["commit", "--quiet", "-m", "1"]);
+
+        let url = origin.to_string_lossy();
+        let dir = temp.path().join("clone/foo");
+        let opts = NetOptions::default();
+        assert_eq!(sync(&url, &dir, &opts).unwrap(), Synced::Cloned);
+
+        fs::write(origin.join("PKGBUILD"), "pkgver=2\n").unwrap();
+        git(&origin, &["commit", "--quiet", "-am", "2"]);
+        assert_eq!(sync(&url, &dir, &opts).unwrap(), Synced::Updated);
+        assert_eq!(
+            fs::read_to_string(dir.join("PKGBUILD")).unwrap(),
+            "pkgver=2\n"
+        );
+
+        let offline = NetOptions {
+            offline: true,
+            ..NetOptions::default()
+        };
+        assert_eq!(sync(&url, &dir, &offline).unwrap(), Synced::Offline);
+        assert!(matches!(
+            sync(&url, &temp.path().join("clone/bar"), &offline),
+            Err(GitError::Offline)
+        ));
+    }
+
+    #[test]
+    fn empty_repository_is_removed() {
+        let temp = tempfile::tempdir().unwrap();
+        let origin = temp.path().join("origin");
+        fs::create_dir(&origin).unwrap();
+        git(&origin, &["init", "--quiet"]);
+
+        let dir = temp.path().join("clone/missing");
+        let result = sync(&origin.to_string_lossy(), &dir, &NetOptions::default());
+        assert!(matches!(result, Err(GitError::NoPkgbuild)));
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,323 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! Portable backups of the queue and its history (`backup` / `restore`).
+//!
+//! A backup is a JSON document with every row of the tables in
+//! [`BACKUP_TABLES`], keyed by column name:
+//!
+//! ```json
+//! {
+//!   "format": "anneal-backup",
+//!   "version": 1,
+//!   "created_at": "2026-01-15T10:32:01.123Z",
+//!   "tables": {
+//!     "queue": [{"package": "qt6gtk2", "first_marked_at": "..."}],
+//!     ...
+//!   }
+//! }
+//! ```
+//!
+//! Rows are keyed by column rather than stored positionally so a backup
+//! stays readable (and restorable) when columns are added later: unknown
+//! columns and tables are ignored, missing ones restore as NULL. Overrides,
+//! ignored packages and the config live in `/etc` and aren't included.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rusqlite::types::Value as SqlValue;
+use serde_json::{Map, Value, json};
+
+use crate::db::{BACKUP_TABLES, Database, DbError, Rows};
+use crate::time::now_iso8601;
+use crate::tr;
+
+/// Value of the `format` field.
+const FORMAT: &str = "anneal-backup";
+
+/// Current backup format version.
+const VERSION: u64 = 1;
+
+/// Backup errors.
+#[derive(Debug)]
+pub enum BackupError {
+    /// The backup file couldn't be read or written.
+    Io(PathBuf, io::Error),
+    /// The file isn't a backup this version can restore.
+    Invalid(PathBuf, String),
+    /// Reading or restoring the database failed.
+    Db(DbError),
+}
+
+impl std::fmt::Display for BackupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(path, e) => f.write_str(&tr!(
+                "backup-io",
+                path = path.display().to_string(),
+                error = e.to_string()
+            )),
+            Self::Invalid(path, msg) => f.write_str(&tr!(
+                "backup-invalid",
+                path = path.display().to_string(),
+                message = msg.as_str()
+            )),
+            Self::Db(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for BackupError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(_, e) => Some(e),
+            Self::Invalid(..) => None,
+            Self::Db(e) => Some(e),
+        }
+    }
+}
+
+impl From<DbError> for BackupError {
+    fn from(e: DbError) -> Self {
+        Self::Db(e)
+    }
+}
+
+/// A backup read from a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backup {
+    /// When the backup was made (ISO8601).
+    pub created_at: String,
+    /// Rows of the backed-up tables.
+    tables: Vec<(&'static str, Rows)>,
+}
+
+impl Backup {
+    /// Packages queued in the backup.
+    pub fn queued(&self) -> Vec<String> {
+        self.tables
+            .iter()
+            .filter(|(table, _)| *table == "queue")
+            .flat_map(|(_, rows)| rows)
+            .filter_map(|row| match row.first() {
+                Some(SqlValue::Text(package)) => Some(package.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Write a backup of the database to a file.
+///
+/// Returns the number of queued packages saved.
+///
+/// # Errors
+///
+/// Returns an error if the database can't be read or the file written.
+pub fn write(db: &Database, path: &Path) -> Result<usize, BackupError> {
+    let mut tables = Map::new();
+    let mut queued = 0;
+    for (table, columns) in BACKUP_TABLES {
+        let rows = db.export_table(table, columns)?;
+        if *table == "queue" {
+            queued = rows.len();
+        }
+        let rows = rows
+            .into_iter()
+            .map(|row| {
+                let object: Map<String, Value> = columns
+                    .iter()
+                    .zip(row)
+                    .map(|(column, value)| (column.to_string(), to_json(value)))
+                    .collect();
+                Value::Object(object)
+            })
+            .collect();
+        tables.insert(table.to_string(), Value::Array(rows));
+    }
+
+    let backup = json!({
+        "format": FORMAT,
+        "version": VERSION,
+        "created_at": now_iso8601(),
+        "tables": tables,
+    });
+    let mut content = serde_json::to_string_pretty(&backup)
+        .map_err(|e| BackupError::Io(path.to_path_buf(), io::Error::other(e)))?;
+    content.push('\n');
+    fs::write(path, content).map_err(|e| BackupError::Io(path.to_path_buf(), e))?;
+    Ok(queued)
+}
+
+/// Read a backup file.
+///
+/// # Errors
+///
+/// Returns an error if the file can't be read, or isn't a backup of a
+/// format version this build understands.
+pub fn read(path: &Path) -> Result<Backup, BackupError> {
+    let content = fs::read_to_string(path).map_err(|e| BackupError::Io(path.to_path_buf(), e))?;
+    let value: Value = serde_json::from_str(&content)
+        .map_err(|e| BackupError::Invalid(path.to_path_buf(), e.to_string()))?;
+    parse(&value).map_err(|msg| BackupError::Invalid(path.to_path_buf(), msg))
+}
+
+/// Replace the queue and history with a backup.
+///
+/// # Errors
+///
+/// Returns an error if the database operation fails, in which case nothing
+/// is changed.
+pub fn restore(db: &mut Database, backup: &Backup) -> Result<(), BackupError> {
+    db.import_tables(&backup.tables)?;
+    Ok(())
+}
+
+/// Parse a backup document.
+fn parse(value: &Value) -> Result<Backup, String> {
+    if value.get("format").and_then(Value::as_str) != Some(FORMAT) {
+        return Err(format!("missing \"format\": \"{FORMAT}\""));
+    }
+    match value.get("version").and_then(Value::as_u64) {
+        Some(VERSION) => {}
+        Some(version) => return Err(format!("unsupported version {version}")),
+        None => return Err("missing version".into()),
+    }
+    let created_at = value
+        .get("created_at")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+    let Some(objects) = value.get("tables").and_then(Value::as_object) else {
+        return Err("missing tables".into());
+    };
+
+    let mut tables = Vec::new();
+    for (table, columns) in BACKUP_TABLES {
+        let Some(rows) = objects.get(*table) else {
+            continue;
+        };
+        let Some(rows) = rows.as_array() else {
+            return Err(format!("{table} is not a list"));
+        };
+        let mut parsed = Vec::with_capacity(rows.len());
+        for row in rows {
+            let Some(row) = row.as_object() else {
+                return Err(format!("{table} has a row that is not an object"));
+            };
+            let values = columns
+                .iter()
+                .map(|column| {
+                    from_json(row.get(*column).unwrap_or(&Value::Null))
+                        .ok_or_else(|| format!("{table}.{column} has an invalid value"))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            parsed.push(values);
+        }
+        tables.push((*table, parsed));
+    }
+
+    Ok(Backup { created_at, tables })
+}
+
+/// Convert a database value to JSON.
+fn to_json(value: SqlValue) -> Value {
+    match value {
+        SqlValue::Null | SqlValue::Blob(_) => Value::Null,
+        SqlValue::Integer(n) => json!(n),
+        SqlValue::Real(x) => json!(x),
+        SqlValue::Text(s) => Value::String(s),
+    }
+}
+
+/// Convert a JSON value to a database value.
+///
+/// The tables only hold text, integers and NULL.
+fn from_json(value: &Value) -> Option<SqlValue> {
+    match value {
+        Value::Null => Some(SqlValue::Null),
+        Value::String(s) => Some(SqlValue::Text(s.clone())),
+        Value::Number(n) => n.as_i64().map(SqlValue::Integer),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_and_restore() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("backup.json");
+
+        let mut db = Database::open_at(&dir.path().join("a.db"), 0).unwrap();
+        db.mark("qt6gtk2", Some("qt6-base"), Some("6.7.0-1"))
+            .unwrap();
+        db.mark("python-foo", None, None).unwrap();
+        db.add_tags("qt6gtk2", &["qt"]).unwrap();
+        db.unmark("python-foo", crate::db::Origin::Cli).unwrap();
+        assert_eq!(write(&db, &file).unwrap(), 1);
+
+        let backup = read(&file).unwrap();
+        assert_eq!(backup.queued(), vec!["qt6gtk2"]);
+
+        let mut other = Database::open_at(&dir.path().join("b.db"), 0).unwrap();
+        other.mark("unrelated", None, None).unwrap();
+        restore(&mut other, &backup).unwrap();
+
+        assert_eq!(other.list().unwrap(), db.list().unwrap());
+        assert_eq!(
+            other.get_events("qt6gtk2").unwrap(),
+            db.get_events("qt6gtk2").unwrap()
+        );
+        assert_eq!(
+            other.get_queue_events("python-foo").unwrap(),
+            db.get_queue_events("python-foo").unwrap()
+        );
+        assert_eq!(other.get_tags("qt6gtk2").unwrap(), vec!["qt"]);
+        assert!(other.get_events("unrelated").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse(&json!({"version": 1, "tables": {}})).is_err());
+        assert_eq!(
+            parse(&json!({"format": FORMAT, "version": 2, "tables": {}})).unwrap_err(),
+            "unsupported version 2"
+        );
+        assert!(
+            parse(&json!({
+                "format": FORMAT,
+                "version": 1,
+                "tables": {"queue": [{"package": true}]}
+            }))
+            .unwrap_err()
+            .contains("queue.package")
+        );
+
+        // Unknown tables and columns are ignored, missing columns are NULL
+        let backup = parse(&json!({
+            "format": FORMAT,
+            "version": 1,
+            "tables": {
+                "future": [],
+                "queue": [{"package": "foo", "first_marked_at": "t", "extra": 1}],
+                "queue_events": [{"id": 1, "package": "foo", "action": "unmarked", "at": "t"}]
+            }
+        }))
+        .unwrap();
+        assert_eq!(backup.queued(), vec!["foo"]);
+        assert_eq!(backup.tables[1].1[0][3], SqlValue::Null);
+
+        // Missing NOT NULL columns restore with their default
+        let mut db = Database::open_at(Path::new(crate::db::MEMORY_DB_PATH), 0).unwrap();
+        restore(&mut db, &backup).unwrap();
+        let entry = &db.list().unwrap()[0];
+        assert!(!entry.pinned);
+        assert_eq!(entry.priority, crate::db::Priority::Normal);
+    }
+}
@@ -0,0 +1,137 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! Logs of per-package builds (`build_log_dir`).
+//!
+//! When a helper or makepkg run builds a single package, its output is
+//! copied to `<build_log_dir>/<package>-<timestamp>.log` while still being
+//! shown on the terminal, so a failed build can be looked into after the
+//! scrollback is gone. The helper's stdout and stderr become pipes, so it
+//! may turn off colors; its prompts still get through since the output is
+//! copied as it's written, not line by line.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Get the log file of a build started at an ISO8601 timestamp.
+///
+/// The timestamp is compacted to `YYYYMMDDTHHMMSS` (no colons in the name).
+pub fn log_path(dir: &Path, package: &str, started_at: &str) -> PathBuf {
+    let stamp: String = started_at
+        .chars()
+        .take(19)
+        .filter(|c| !matches!(c, '-' | ':'))
+        .collect();
+    dir.join(format!("{package}-{stamp}.log"))
+}
+
+/// Create a log file, and its directory if needed.
+///
+/// # Errors
+///
+/// Returns an error if the directory or file can't be created.
+pub fn create(path: &Path) -> io::Result<File> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    File::create(path)
+}
+
+/// Run a command, copying its output to the terminal and a log file.
+///
+/// The log starts with the command line.
+///
+/// # Errors
+///
+/// Returns an error if the command can't be started.
+pub fn run(command: &mut Command, mut log: File) -> io::Result<ExitStatus> {
+    let args: Vec<String> = std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    // The log is best-effort from here on: a full disk shouldn't stop the
+    // build
+    let _ = writeln!(log, "$ {}", args.join(" "));
+
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let log = Arc::new(Mutex::new(log));
+    let mut copies = Vec::new();
+    if let Some(stdout) = child.stdout.take() {
+        copies.push(tee(stdout, io::stdout(), Arc::clone(&log)));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        copies.push(tee(stderr, io::stderr(), Arc::clone(&log)));
+    }
+
+    let status = child.wait();
+    for copy in copies {
+        let _ = copy.join();
+    }
+    status
+}
+
+/// Copy a stream to a terminal stream and the log in a thread.
+fn tee(
+    mut from: impl Read + Send + 'static,
+    mut to: impl Write + Send + 'static,
+    log: Arc<Mutex<File>>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut buf = [0; 8192];
+        loop {
+            let n = match from.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            };
+            let _ = to.write_all(&buf[..n]);
+            let _ = to.flush();
+            if let Ok(mut log) = log.lock() {
+                let _ = log.write_all(&buf[..n]);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_path_compacts_timestamp() {
+        assert_eq!(
+            log_path(
+                Path::new("/var/log/anneal"),
+                "qt6gtk2",
+                "2026-01-15T10:32:01.123Z"
+            ),
+            PathBuf::from("/var/log/anneal/qt6gtk2-20260115T103201.log")
+        );
+    }
+
+    #[test]
+    fn run_copies_output_to_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs/foo.log");
+
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo built; echo oops >&2; exit 3"]);
+        let status = run(&mut command, create(&path).unwrap()).unwrap();
+        assert_eq!(status.code(), Some(3));
+
+        let log = fs::read_to_string(&path).unwrap();
+        assert!(log.starts_with("$ sh -c "), "log: {log}");
+        assert!(log.contains("built\n"), "log: {log}");
+        assert!(log.contains("oops\n"), "log: {log}");
+    }
+}
@@ -0,0 +1,134 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! Trigger versions captured before a transaction (`capture-versions`).
+//!
+//! alpm doesn't tell hooks which versions a transaction replaces. The
+//! PreTransaction hook runs `capture-versions` with the packages about to be
+//! upgraded, which saves the installed versions of the triggers among them,
+//! one `name version` per line. `trigger --hook` in the PostTransaction hook
+//! takes these as the old versions, and removes the file so a capture only
+//! applies to the transaction it was made for. A transaction that fails
+//! leaves it behind until the next one overwrites it, and `/run` doesn't
+//! survive a reboot.
+//!
+//! The default database's capture is [`CAPTURE_FILE`]; another database
+//! (`ANNEAL_DB_PATH`) keeps its capture next to it, like the rebuild lock.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::db::{self, DEFAULT_DB_PATH};
+
+/// Capture file of the default database.
+pub const CAPTURE_FILE: &str = "/run/anneal/versions";
+
+/// Get the capture file of a database.
+///
+/// Returns `None` for an in-memory database, which doesn't outlive the
+/// command that captured.
+pub fn capture_path(db_path: &Path) -> Option<PathBuf> {
+    if db::is_memory_path(db_path) {
+        None
+    } else if db_path == Path::new(DEFAULT_DB_PATH) {
+        Some(PathBuf::from(CAPTURE_FILE))
+    } else {
+        let mut path = db_path.as_os_str().to_owned();
+        path.push(".versions");
+        Some(PathBuf::from(path))
+    }
+}
+
+/// Save captured versions, replacing an earlier capture.
+///
+/// Creates the directory of [`CAPTURE_FILE`] if needed.
+///
+/// # Errors
+///
+/// Returns an error if the file can't be written.
+pub fn save(path: &Path, versions: &[(String, String)]) -> io::Result<()> {
+    if path == Path::new(CAPTURE_FILE)
+        && let Some(parent) = path.parent()
+    {
+        fs::create_dir_all(parent)?;
+    }
+    let content: String = versions
+        .iter()
+        .map(|(name, version)| format!("{name} {version}\n"))
+        .collect();
+    fs::write(path, content)
+}
+
+/// Read and remove the captured versions, by package name.
+///
+/// No capture reads as empty.
+///
+/// # Errors
+///
+/// Returns an error if the file exists but can't be read or removed.
+pub fn take(path: &Path) -> io::Result<HashMap<String, String>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e),
+    };
+    fs::remove_file(path)?;
+    Ok(parse(&content))
+}
+
+/// Parse a capture file, skipping malformed lines.
+fn parse(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (name, version) = line.trim().split_once(' ')?;
+            Some((name.to_string(), version.trim().to_string()))
+        })
+        .filter(|(name, version)| !name.is_empty() && !version.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capture_paths() {
+        assert_eq!(
+            capture_path(Path::new(DEFAULT_DB_PATH)),
+            Some(PathBuf::from(CAPTURE_FILE))
+        );
+        assert_eq!(
+            capture_path(Path::new("/tmp/test.db")),
+            Some(PathBuf::from("/tmp/test.db.versions"))
+        );
+        assert_eq!(capture_path(Path::new(db::MEMORY_DB_PATH)), None);
+    }
+
+    #[test]
+    fn save_then_take_once() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("a.db.versions");
+
+        let versions = vec![
+            ("qt6-base".to_string(), "6.7.0-1".to_string()),
+            ("python".to_string(), "3.12.7-1".to_string()),
+        ];
+        save(&path, &versions).unwrap();
+        let taken = take(&path).unwrap();
+        assert_eq!(taken.len(), 2);
+        assert_eq!(taken["qt6-base"], "6.7.0-1");
+        assert!(!path.exists());
+        assert!(take(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_skips_malformed_lines() {
+        let versions = parse("qt6-base 6.7.0-1\nbroken\n\n ffmpeg  2:7.0-1 \n");
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions["ffmpeg"], "2:7.0-1");
+    }
+}
@@ -5,9 +5,17 @@
 //!
 //! Uses clap for argument parsing with derive macros.
 
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+
 use clap::{Parser, Subcommand};
 use clap_complete::Shell;
 
+use crate::db::{Priority, SortOrder};
+use crate::output::ColorChoice;
+use crate::overrides::OverrideKind;
+use crate::time::{parse_duration, parse_time_spec, time_after};
+
 /// Proactive AUR rebuild management for Arch Linux.
 #[derive(Parser, Debug)]
 #[command(name = "anneal")]
@@ -18,6 +26,39 @@ pub struct Cli {
     #[arg(long, short, global = true)]
     pub quiet: bool,
 
+    /// Show more details: `list` adds queue details, and other commands
+    /// explain their decisions on stderr (-vv for per-package reasons).
+    #[arg(long, short, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Print machine-readable JSON on stdout (list, query, why, history,
+    /// override list/show, triggers, stats, count, status, config, trigger,
+    /// preview, verify, rebuild); other commands print nothing on stdout.
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    /// Print a stable, space-separated format for scripts (list, query,
+    /// history); see the design doc for the fields.
+    #[arg(long, global = true, conflicts_with = "json")]
+    pub porcelain: bool,
+
+    /// When to color output (`auto` colors terminals unless NO_COLOR is
+    /// set).
+    #[arg(long, global = true, value_enum, value_name = "WHEN", default_value_t)]
+    pub color: ColorChoice,
+
+    /// Show timestamps without how long ago they were ("3 days ago").
+    #[arg(long, global = true)]
+    pub absolute_time: bool,
+
+    /// Disable network features (overrides `offline` in the config).
+    #[arg(long, global = true)]
+    pub offline: bool,
+
+    /// Read package data from fixture files instead of pacman (for development).
+    #[arg(long, global = true, hide = true, value_name = "DIR")]
+    pub fixture_dir: Option<PathBuf>,
+
     /// The subcommand to execute.
     #[command(subcommand)]
     pub command: Command,
@@ -28,10 +69,30 @@ pub struct Cli {
 pub enum Command {
     /// Add packages to the rebuild queue.
     Mark {
-        /// Packages to mark for rebuild.
-        #[arg(required = true)]
+        /// Packages to mark for rebuild. Glob patterns (`'python-*'`) match
+        /// installed foreign packages.
+        #[arg(required_unless_present_any = ["group", "maintainer", "file", "foreign"])]
         packages: Vec<String>,
 
+        /// Mark every installed foreign package, except ignored ones,
+        /// `skip_packages` and -bin packages.
+        #[arg(long, conflicts_with_all = ["packages", "file", "group", "maintainer"])]
+        foreign: bool,
+
+        /// Also mark the packages listed in a file (whitespace-separated,
+        /// `#` comments, globs allowed).
+        #[arg(long, value_name = "FILE")]
+        file: Option<PathBuf>,
+
+        /// Mark the installed foreign members of a pacman group (or a group
+        /// defined in /etc/anneal/groups), recorded as the trigger.
+        #[arg(long, conflicts_with = "trigger")]
+        group: Option<String>,
+
+        /// Mark the installed foreign packages maintained by this AUR user.
+        #[arg(long, conflicts_with = "group")]
+        maintainer: Option<String>,
+
         /// Trigger package that caused the mark.
         #[arg(long)]
         trigger: Option<String>,
@@ -39,20 +100,121 @@ pub enum Command {
         /// Version of the trigger package.
         #[arg(long = "trigger-version", requires = "trigger")]
         trigger_version: Option<String>,
+
+        /// Mark packages even if they aren't installed.
+        #[arg(long)]
+        allow_missing: bool,
+
+        /// Tag the marked packages (repeatable).
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+
+        /// Note why the packages are marked, shown by `list --verbose` and
+        /// `why` (replaces an earlier reason).
+        #[arg(long)]
+        reason: Option<String>,
+
+        /// Rebuild priority of the marked packages (replaces an earlier one).
+        #[arg(long, value_enum)]
+        priority: Option<Priority>,
+
+        /// Drop the packages from the queue after this long (30m, 12h, 30d,
+        /// 2w) unless they're marked again or pinned.
+        #[arg(long, value_name = "DURATION", value_parser = parse_expiry)]
+        expires: Option<String>,
     },
 
     /// Remove packages from the rebuild queue.
     Unmark {
-        /// Packages to remove (reads from stdin if empty).
+        /// Packages to remove (reads from stdin if empty, or prompts on a terminal).
         packages: Vec<String>,
 
         /// Exit with code 2 if any package wasn't in the queue.
         #[arg(long)]
         strict: bool,
+
+        /// Pick packages to remove from a numbered list of the queue.
+        #[arg(long, short, conflicts_with = "packages")]
+        interactive: bool,
+
+        /// Remove pinned packages too.
+        #[arg(long)]
+        include_pinned: bool,
+    },
+
+    /// Keep queued packages in the queue through rebuilds and clears.
+    Pin {
+        /// Queued packages to pin.
+        #[arg(required = true)]
+        packages: Vec<String>,
+    },
+
+    /// Let pinned packages leave the queue again.
+    Unpin {
+        /// Packages to unpin.
+        #[arg(required = true)]
+        packages: Vec<String>,
+    },
+
+    /// Set the rebuild priority of queued packages.
+    Prioritize {
+        /// Priority to set.
+        #[arg(value_enum)]
+        priority: Priority,
+
+        /// Queued packages to change.
+        #[arg(required = true)]
+        packages: Vec<String>,
     },
 
     /// Show the current rebuild queue.
-    List,
+    List {
+        /// Sort order.
+        #[arg(long, value_enum, default_value_t)]
+        sort: SortOrder,
+
+        /// Reverse the sort order.
+        #[arg(long, short)]
+        reverse: bool,
+
+        /// Only show packages with this tag.
+        #[arg(long)]
+        tag: Option<String>,
+
+        /// Only show packages this trigger marked.
+        #[arg(long)]
+        trigger: Option<String>,
+
+        /// Only show packages first marked at or after this time (e.g. 7d,
+        /// 12h, 2026-01-15).
+        #[arg(long, value_parser = parse_time)]
+        since: Option<String>,
+
+        /// Only show packages first marked before this time (e.g. 7d, 12h,
+        /// 2026-01-15).
+        #[arg(long, value_parser = parse_time)]
+        before: Option<String>,
+
+        /// Print each package with a format string, e.g.
+        /// "{package}\t{trigger}\t{age}" (fields: package, trigger,
+        /// trigger_version, first_marked_at, age, tags, reason).
+        #[arg(long, conflicts_with = "verbose")]
+        format: Option<String>,
+
+        /// Print a table with each package's trigger, trigger version, the
+        /// date it was first marked and its age in days.
+        #[arg(long, short, conflicts_with_all = ["format", "verbose"])]
+        long: bool,
+
+        /// Print only package names, each followed by a NUL byte (for
+        /// `xargs -0`).
+        #[arg(short = '0', long, conflicts_with_all = ["format", "verbose", "long"])]
+        null: bool,
+
+        /// Exit with code 2 if any package is listed, 0 if none is.
+        #[arg(long)]
+        check: bool,
+    },
 
     /// Reset the rebuild queue.
     Clear {
@@ -62,6 +224,26 @@ pub enum Command {
 
         /// Only clear events for this trigger (keeps queue intact).
         trigger: Option<String>,
+
+        /// Only remove packages with this tag from the queue.
+        #[arg(long, conflicts_with = "trigger")]
+        tag: Option<String>,
+
+        /// Remove pinned packages too.
+        #[arg(long, conflicts_with = "trigger")]
+        include_pinned: bool,
+
+        /// Put back the queue removed by the last full clear.
+        #[arg(long, conflicts_with_all = ["force", "trigger", "tag", "include_pinned"])]
+        undo: bool,
+    },
+
+    /// Drop queue entries that no longer need a rebuild and prune old
+    /// history.
+    Clean {
+        /// Show what would be removed without changing anything.
+        #[arg(long)]
+        dry_run: bool,
     },
 
     /// Rebuild queued packages.
@@ -70,7 +252,7 @@ pub enum Command {
         #[arg(short, long)]
         force: bool,
 
-        /// Include packages detected by checkrebuild.
+        /// Include foreign packages with broken linkage.
         #[arg(long)]
         checkrebuild: bool,
 
@@ -78,6 +260,63 @@ pub enum Command {
         #[arg(long)]
         cmd: Option<String>,
 
+        /// Skip packages flagged out-of-date on the AUR (they stay queued).
+        #[arg(long)]
+        skip_flagged: bool,
+
+        /// Skip queued packages whose installed version changed since they
+        /// were marked (they stay queued).
+        #[arg(long)]
+        skip_stale: bool,
+
+        /// Unmark queued packages whose installed version changed since they
+        /// were marked instead of rebuilding them.
+        #[arg(long, conflicts_with = "skip_stale")]
+        unmark_stale: bool,
+
+        /// Pass the helper arguments of a profile defined in the config.
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// Only rebuild queued packages with this tag.
+        #[arg(long, conflicts_with = "packages")]
+        tag: Option<String>,
+
+        /// Check rebuilt packages with namcap, pacman -Qkk and ldd.
+        #[arg(long)]
+        validate: bool,
+
+        /// Only rebuild queued packages whose last rebuild failed.
+        #[arg(long, conflicts_with_all = ["packages", "tag"])]
+        failed: bool,
+
+        /// Run the helper once per package and keep going after failures.
+        #[arg(long, short)]
+        keep_going: bool,
+
+        /// Wait for a running pacman transaction (or another rebuild) to
+        /// finish before building.
+        #[arg(long)]
+        wait_for_pacman: bool,
+
+        /// Print the helper (and makepkg) commands that would run, then exit.
+        #[arg(long, short = 'n')]
+        dry_run: bool,
+
+        /// Pick packages to leave out from a numbered list before building.
+        #[arg(long, short)]
+        interactive: bool,
+
+        /// Leave out packages matching this glob pattern (repeatable); they
+        /// stay queued.
+        #[arg(long, value_name = "PATTERN")]
+        exclude: Vec<String>,
+
+        /// Only rebuild the first N queued packages (highest priority, then
+        /// oldest); the rest stay queued.
+        #[arg(long, value_name = "N", conflicts_with = "packages")]
+        limit: Option<NonZeroUsize>,
+
         /// Only rebuild these packages (must be in queue).
         packages: Vec<String>,
 
@@ -98,6 +337,69 @@ pub enum Command {
         /// Packages to check.
         #[arg(required = true)]
         packages: Vec<String>,
+
+        /// End each queued package with a NUL byte instead of a newline.
+        #[arg(short = '0', long)]
+        null: bool,
+    },
+
+    /// Show the history of a package (marks, rebuilds, unmarks) in order.
+    Timeline {
+        /// Package to show.
+        package: String,
+    },
+
+    /// Explain why a package is in the queue.
+    Why {
+        /// Package to explain.
+        package: String,
+    },
+
+    /// Show the trigger events recorded for a package, newest first.
+    History {
+        /// Package to show.
+        package: String,
+
+        /// Show rebuild attempts instead of trigger events.
+        #[arg(long)]
+        rebuilds: bool,
+
+        /// Show at most this many events.
+        #[arg(long, short = 'n')]
+        limit: Option<usize>,
+
+        /// Show oldest events first.
+        #[arg(long, short)]
+        reverse: bool,
+    },
+
+    /// Print the number of queued packages (exit code 2 if none).
+    Count,
+
+    /// Show the queue size and the age of the oldest entry in one line.
+    Status {
+        /// Print a waybar custom module JSON object instead.
+        #[arg(long)]
+        waybar: bool,
+    },
+
+    /// Summarize the queue and the trigger history.
+    Stats,
+
+    /// Save the queue and its history to a JSON file.
+    Backup {
+        /// File to write.
+        file: PathBuf,
+    },
+
+    /// Replace the queue and its history with a backup.
+    Restore {
+        /// Skip confirmation prompt.
+        #[arg(short, long)]
+        force: bool,
+
+        /// Backup file to read.
+        file: PathBuf,
     },
 
     /// List configured triggers.
@@ -109,12 +411,127 @@ pub enum Command {
         #[arg(long)]
         dry_run: bool,
 
+        /// With --dry-run, print only the package names that would be
+        /// marked, each followed by a NUL byte.
+        #[arg(short = '0', long, requires = "dry_run")]
+        null: bool,
+
+        /// Run as the pacman hook: read upgraded package names from stdin,
+        /// look up their old and new versions, and log to the system log.
+        #[arg(long, conflicts_with_all = ["dry_run", "packages"])]
+        hook: bool,
+
         /// Packages to process (reads from stdin if empty).
         packages: Vec<String>,
     },
 
-    /// Dump current configuration.
-    Config,
+    /// Save the installed versions of triggers about to be upgraded, for
+    /// `trigger --hook` (run by the PreTransaction hook, reads stdin).
+    CaptureVersions,
+
+    /// Mark foreign packages left behind by a Python upgrade.
+    Scan {
+        /// Show what would be marked without modifying the queue.
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Also mark foreign packages needing shared libraries that aren't
+        /// installed.
+        #[arg(long)]
+        checkrebuild: bool,
+    },
+
+    /// Check an installed package for missing shared libraries and symbol
+    /// versions (exit code 1 if it has any).
+    Verify {
+        /// Package to check.
+        package: String,
+    },
+
+    /// Show which packages the pending system upgrade would mark.
+    Preview {
+        /// Update list as printed by checkupdates or `pacman -Qu` (`-` for
+        /// stdin); runs checkupdates if not given.
+        file: Option<PathBuf>,
+    },
+
+    /// Manage packages built from local PKGBUILD checkouts.
+    Source {
+        /// What to do.
+        #[command(subcommand)]
+        action: SourceAction,
+    },
+
+    /// Download the latest curated trigger list.
+    UpdateTriggers,
+
+    /// Never mark packages (creates an empty package override).
+    Ignore {
+        /// Packages to ignore.
+        #[arg(required_unless_present = "list")]
+        packages: Vec<String>,
+
+        /// List ignored packages.
+        #[arg(long, conflicts_with = "packages")]
+        list: bool,
+    },
+
+    /// Stop ignoring packages.
+    Unignore {
+        /// Packages to stop ignoring.
+        #[arg(required = true)]
+        packages: Vec<String>,
+    },
+
+    /// Define or remove user triggers.
+    #[command(name = "trigger-def")]
+    TriggerDef {
+        /// What to do.
+        #[command(subcommand)]
+        action: TriggerDefAction,
+    },
+
+    /// Manage trigger and package override files.
+    Override {
+        /// What to do.
+        #[command(subcommand)]
+        action: OverrideAction,
+    },
+
+    /// Install the pacman hooks that run `anneal trigger` after upgrades.
+    Setup {
+        /// Directory to install the hooks into [default: /etc/pacman.d/hooks].
+        #[arg(long, value_name = "DIR")]
+        dir: Option<PathBuf>,
+
+        /// Overwrite hooks that differ from the ones anneal would install.
+        #[arg(long, short)]
+        force: bool,
+
+        /// Print the hooks instead of installing them.
+        #[arg(long, conflicts_with_all = ["dir", "force"])]
+        print: bool,
+
+        /// Install a systemd timer running `scan --checkrebuild` instead of
+        /// the hook [default dir: /etc/systemd/system].
+        #[arg(long)]
+        systemd: bool,
+    },
+
+    /// Show or change the configuration (dumps it without an action).
+    Config {
+        /// What to do.
+        #[command(subcommand)]
+        action: Option<ConfigAction>,
+    },
+
+    /// Maintain the database (shows its size and row counts without an
+    /// action).
+    Db {
+        /// What to do.
+        #[command(subcommand)]
+        action: Option<DbAction>,
+    },
 
     /// Generate shell completions.
     Completions {
@@ -124,21 +541,213 @@ pub enum Command {
     },
 }
 
+/// Local source actions.
+#[derive(Subcommand, Debug)]
+pub enum SourceAction {
+    /// Register the PKGBUILD directory a package is built from.
+    Add {
+        /// Package name.
+        package: String,
+
+        /// Directory containing the PKGBUILD.
+        path: PathBuf,
+    },
+
+    /// Stop tracking a package's local checkout.
+    Remove {
+        /// Package name.
+        package: String,
+    },
+
+    /// List registered local sources.
+    List,
+}
+
+/// Configuration actions.
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+    /// Print the value of an option.
+    Get {
+        /// Option name (`profile.<name>` for profiles).
+        key: String,
+    },
+
+    /// Set an option in the config file.
+    Set {
+        /// Option name (`profile.<name>` for profiles).
+        key: String,
+
+        /// New value, joined with spaces if several are given (empty to unset
+        /// an optional key).
+        #[arg(required = true, allow_hyphen_values = true)]
+        value: Vec<String>,
+    },
+}
+
+/// Database maintenance actions.
+#[derive(Subcommand, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DbAction {
+    /// Check the database for corruption (exit code 1 if damaged).
+    Check,
+
+    /// Rebuild the database file to reclaim unused space.
+    Vacuum,
+}
+
+/// User trigger definition actions.
+#[derive(Subcommand, Debug)]
+pub enum TriggerDefAction {
+    /// Define a trigger that marks packages matching the patterns when it
+    /// upgrades (adds patterns if it's already defined).
+    Add {
+        /// Package that triggers rebuilds.
+        name: String,
+
+        /// Patterns of AUR packages to mark.
+        #[arg(required = true)]
+        patterns: Vec<String>,
+    },
+
+    /// Remove a user trigger.
+    Remove {
+        /// Trigger to remove.
+        name: String,
+    },
+}
+
+/// Parse a `--since`/`--before` time into an ISO8601 timestamp.
+fn parse_time(spec: &str) -> Result<String, String> {
+    parse_time_spec(spec).ok_or_else(|| {
+        format!("invalid time '{spec}', expected an age (30m, 12h, 7d, 2w) or a date (2026-01-15)")
+    })
+}
+
+/// Parse a `mark --expires` duration into the ISO8601 expiry time.
+fn parse_expiry(spec: &str) -> Result<String, String> {
+    match parse_duration(spec) {
+        Some(secs) if secs > 0 => Ok(time_after(secs)),
+        _ => Err(format!(
+            "invalid duration '{spec}', expected a count and unit (30m, 12h, 30d, 2w)"
+        )),
+    }
+}
+
+/// Override file actions.
+#[derive(Subcommand, Debug)]
+pub enum OverrideAction {
+    /// List all override files.
+    List,
+
+    /// Show an override's entries and what they mean.
+    Show {
+        /// Kind of override.
+        #[arg(value_enum)]
+        kind: OverrideKind,
+
+        /// Trigger or package name.
+        name: String,
+    },
+
+    /// Add entries to an override, creating it if needed.
+    ///
+    /// Without entries, creates an empty override: the trigger is disabled,
+    /// or the package is never marked.
+    Add {
+        /// Kind of override.
+        #[arg(value_enum)]
+        kind: OverrideKind,
+
+        /// Trigger or package name.
+        name: String,
+
+        /// Package patterns (trigger) or trigger patterns (package).
+        entries: Vec<String>,
+    },
+
+    /// Remove entries from an override, or the whole override without
+    /// entries.
+    Remove {
+        /// Kind of override.
+        #[arg(value_enum)]
+        kind: OverrideKind,
+
+        /// Trigger or package name.
+        name: String,
+
+        /// Entries to remove.
+        entries: Vec<String>,
+    },
+}
+
 impl Command {
-    /// Returns true if this command requires root privileges.
-    pub fn requires_root(&self) -> bool {
+    /// Returns true if this command writes the database, which root or a user
+    /// with write access to it (e.g., the `anneal` group) may do.
+    pub fn writes_database(&self) -> bool {
         match self {
             Self::Mark { .. } | Self::Unmark { .. } | Self::Clear { .. } => true,
-            Self::Trigger { dry_run, .. } => !dry_run,
+            Self::Pin { .. } | Self::Unpin { .. } | Self::Prioritize { .. } => true,
+            Self::Restore { .. } | Self::CaptureVersions => true,
+            Self::Trigger { dry_run, .. }
+            | Self::Scan { dry_run, .. }
+            | Self::Clean { dry_run } => !dry_run,
+            Self::Source { action } => !matches!(action, SourceAction::List),
+            Self::Db { action } => *action == Some(DbAction::Vacuum),
+            _ => false,
+        }
+    }
+
+    /// Returns true if this command writes root-owned files outside the
+    /// database (hooks, units, configuration), which only root may do.
+    pub fn writes_system_files(&self) -> bool {
+        match self {
+            Self::Setup { print, .. } => !print,
+            Self::Config { action } => matches!(action, Some(ConfigAction::Set { .. })),
+            Self::Ignore { list, .. } => !list,
+            Self::UpdateTriggers | Self::TriggerDef { .. } | Self::Unignore { .. } => true,
+            Self::Override { action } => matches!(
+                action,
+                OverrideAction::Add { .. } | OverrideAction::Remove { .. }
+            ),
             _ => false,
         }
     }
 
+    /// Returns true if this command changes anything, and so reads only the
+    /// system config.
+    pub fn modifies_system(&self) -> bool {
+        self.writes_database() || self.writes_system_files()
+    }
+
+    /// Returns true if a non-root user may run this command through polkit.
+    pub fn allows_polkit(&self) -> bool {
+        matches!(
+            self,
+            Self::Mark { .. }
+                | Self::Unmark { .. }
+                | Self::Clear { .. }
+                | Self::Pin { .. }
+                | Self::Unpin { .. }
+                | Self::Prioritize { .. }
+        )
+    }
+
+    /// Returns true if this command has a `--porcelain` format.
+    pub fn supports_porcelain(&self) -> bool {
+        matches!(
+            self,
+            Self::List { .. } | Self::Query { .. } | Self::History { .. }
+        )
+    }
+
     /// Returns true if this command modifies the queue (excluding dry-run).
     pub fn modifies_queue(&self) -> bool {
         match self {
             Self::Mark { .. } | Self::Unmark { .. } | Self::Clear { .. } => true,
-            Self::Trigger { dry_run, .. } => !dry_run,
+            Self::Pin { .. } | Self::Unpin { .. } | Self::Prioritize { .. } => true,
+            Self::Restore { .. } => true,
+            Self::Trigger { dry_run, .. }
+            | Self::Scan { dry_run, .. }
+            | Self::Clean { dry_run } => !dry_run,
             _ => false,
         }
     }
@@ -164,6 +773,7 @@ mod tests {
                 packages,
                 trigger,
                 trigger_version,
+                ..
             } => {
                 assert_eq!(packages, vec!["pkg1", "pkg2"]);
                 assert!(trigger.is_none());
@@ -189,6 +799,7 @@ mod tests {
                 packages,
                 trigger,
                 trigger_version,
+                ..
             } => {
                 assert_eq!(packages, vec!["pkg1"]);
                 assert_eq!(trigger, Some("qt6-base".to_string()));
@@ -198,13 +809,108 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_mark_expires() {
+        let cli = Cli::parse_from(["anneal", "mark", "pkg1", "--expires", "30d"]);
+        match cli.command {
+            Command::Mark { expires, .. } => {
+                assert!(expires.is_some_and(|at| at > crate::time::now_iso8601()));
+            }
+            _ => panic!("expected Mark command"),
+        }
+        assert!(Cli::try_parse_from(["anneal", "mark", "pkg1", "--expires", "30"]).is_err());
+        assert!(Cli::try_parse_from(["anneal", "mark", "pkg1", "--expires", "0d"]).is_err());
+    }
+
+    #[test]
+    fn parse_mark_group() {
+        let cli = Cli::parse_from(["anneal", "mark", "--group", "kde-applications"]);
+        match cli.command {
+            Command::Mark {
+                packages, group, ..
+            } => {
+                assert!(packages.is_empty());
+                assert_eq!(group, Some("kde-applications".into()));
+            }
+            _ => panic!("expected Mark command"),
+        }
+    }
+
+    #[test]
+    fn mark_requires_packages_or_group() {
+        assert!(Cli::try_parse_from(["anneal", "mark"]).is_err());
+        assert!(Cli::try_parse_from(["anneal", "mark", "--group", "g", "--trigger", "t"]).is_err());
+    }
+
+    #[test]
+    fn parse_mark_maintainer() {
+        let cli = Cli::parse_from(["anneal", "mark", "--maintainer", "alice"]);
+        match cli.command {
+            Command::Mark {
+                packages,
+                maintainer,
+                ..
+            } => {
+                assert!(packages.is_empty());
+                assert_eq!(maintainer, Some("alice".into()));
+            }
+            _ => panic!("expected Mark command"),
+        }
+        assert!(
+            Cli::try_parse_from(["anneal", "mark", "--maintainer", "alice", "--group", "g"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn parse_mark_file() {
+        let cli = Cli::parse_from(["anneal", "mark", "--file", "pkglist.txt"]);
+        match cli.command {
+            Command::Mark { packages, file, .. } => {
+                assert!(packages.is_empty());
+                assert_eq!(file, Some(PathBuf::from("pkglist.txt")));
+            }
+            _ => panic!("expected Mark command"),
+        }
+    }
+
+    #[test]
+    fn parse_mark_foreign() {
+        let cli = Cli::parse_from(["anneal", "mark", "--foreign", "--trigger", "glibc"]);
+        match cli.command {
+            Command::Mark {
+                foreign, trigger, ..
+            } => {
+                assert!(foreign);
+                assert_eq!(trigger, Some("glibc".into()));
+            }
+            _ => panic!("expected Mark command"),
+        }
+        assert!(Cli::try_parse_from(["anneal", "mark", "--foreign", "pkg1"]).is_err());
+    }
+
+    #[test]
+    fn parse_mark_allow_missing() {
+        let cli = Cli::parse_from(["anneal", "mark", "--allow-missing", "pkg1"]);
+        match cli.command {
+            Command::Mark { allow_missing, .. } => assert!(allow_missing),
+            _ => panic!("expected Mark command"),
+        }
+    }
+
     #[test]
     fn parse_unmark() {
         let cli = Cli::parse_from(["anneal", "unmark", "pkg1"]);
         match cli.command {
-            Command::Unmark { packages, strict } => {
+            Command::Unmark {
+                packages,
+                strict,
+                interactive,
+                ..
+            } => {
                 assert_eq!(packages, vec!["pkg1"]);
                 assert!(!strict);
+                assert!(!interactive);
             }
             _ => panic!("expected Unmark command"),
         }
@@ -219,24 +925,133 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_unmark_interactive() {
+        let cli = Cli::parse_from(["anneal", "unmark", "-i"]);
+        match cli.command {
+            Command::Unmark { interactive, .. } => assert!(interactive),
+            _ => panic!("expected Unmark command"),
+        }
+    }
+
+    #[test]
+    fn unmark_interactive_conflicts_with_packages() {
+        let result = Cli::try_parse_from(["anneal", "unmark", "--interactive", "pkg1"]);
+        assert!(result.is_err());
+    }
+
     #[test]
     fn parse_list() {
         let cli = Cli::parse_from(["anneal", "list"]);
-        assert!(matches!(cli.command, Command::List));
+        assert!(matches!(
+            cli.command,
+            Command::List {
+                sort: SortOrder::Age,
+                reverse: false,
+                tag: None,
+                trigger: None,
+                since: None,
+                before: None,
+                format: None,
+                long: false,
+                null: false,
+                check: false,
+            }
+        ));
+    }
+
+    #[test]
+    fn parse_list_filters() {
+        let cli = Cli::parse_from([
+            "anneal",
+            "list",
+            "--trigger",
+            "qt6-base",
+            "--since",
+            "2026-01-15",
+        ]);
+        match cli.command {
+            Command::List {
+                trigger,
+                since,
+                before,
+                ..
+            } => {
+                assert_eq!(trigger, Some("qt6-base".into()));
+                assert_eq!(since, Some("2026-01-15T00:00:00.000Z".into()));
+                assert_eq!(before, None);
+            }
+            _ => panic!("expected List command"),
+        }
+
+        assert!(Cli::try_parse_from(["anneal", "list", "--before", "last week"]).is_err());
+    }
+
+    #[test]
+    fn parse_list_sort() {
+        let cli = Cli::parse_from(["anneal", "list", "--sort", "trigger", "-r"]);
+        assert!(matches!(
+            cli.command,
+            Command::List {
+                sort: SortOrder::Trigger,
+                reverse: true,
+                tag: None,
+                ..
+            }
+        ));
+        assert!(Cli::try_parse_from(["anneal", "list", "--sort", "size"]).is_err());
     }
 
     #[test]
     fn parse_clear() {
         let cli = Cli::parse_from(["anneal", "clear"]);
         match cli.command {
-            Command::Clear { force, trigger } => {
+            Command::Clear {
+                force,
+                trigger,
+                tag,
+                undo,
+                ..
+            } => {
                 assert!(!force);
                 assert!(trigger.is_none());
+                assert!(tag.is_none());
+                assert!(!undo);
             }
             _ => panic!("expected Clear command"),
         }
     }
 
+    #[test]
+    fn parse_clear_undo() {
+        let cli = Cli::parse_from(["anneal", "clear", "--undo"]);
+        match cli.command {
+            Command::Clear { undo, .. } => assert!(undo),
+            _ => panic!("expected Clear command"),
+        }
+        assert!(Cli::try_parse_from(["anneal", "clear", "--undo", "-f"]).is_err());
+        assert!(Cli::try_parse_from(["anneal", "clear", "--undo", "qt6-base"]).is_err());
+        assert!(Cli::try_parse_from(["anneal", "clear", "--undo", "--include-pinned"]).is_err());
+    }
+
+    #[test]
+    fn parse_tags() {
+        let cli = Cli::parse_from(["anneal", "mark", "--tag", "gaming", "--tag", "qt", "pkg1"]);
+        match cli.command {
+            Command::Mark { tags, .. } => assert_eq!(tags, vec!["gaming", "qt"]),
+            _ => panic!("expected Mark command"),
+        }
+
+        let cli = Cli::parse_from(["anneal", "list", "--tag", "gaming"]);
+        match cli.command {
+            Command::List { tag, .. } => assert_eq!(tag, Some("gaming".into())),
+            _ => panic!("expected List command"),
+        }
+
+        assert!(Cli::try_parse_from(["anneal", "clear", "--tag", "gaming", "qt6-base"]).is_err());
+        assert!(Cli::try_parse_from(["anneal", "rebuild", "--tag", "gaming", "pkg1"]).is_err());
+    }
+
     #[test]
     fn parse_clear_force() {
         let cli = Cli::parse_from(["anneal", "clear", "-f"]);
@@ -246,6 +1061,31 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_restore() {
+        let cli = Cli::parse_from(["anneal", "restore", "-f", "backup.json"]);
+        match cli.command {
+            Command::Restore { force, file } => {
+                assert!(force);
+                assert_eq!(file, PathBuf::from("backup.json"));
+            }
+            _ => panic!("expected Restore command"),
+        }
+        assert!(
+            Command::Restore {
+                force: false,
+                file: PathBuf::new(),
+            }
+            .writes_database()
+        );
+        assert!(
+            !Command::Backup {
+                file: PathBuf::new(),
+            }
+            .writes_database()
+        );
+    }
+
     #[test]
     fn parse_clear_trigger() {
         let cli = Cli::parse_from(["anneal", "clear", "qt6-base"]);
@@ -265,12 +1105,38 @@ mod tests {
                 force,
                 checkrebuild,
                 cmd,
+                skip_flagged,
+                skip_stale,
+                unmark_stale,
+                profile,
+                tag,
+                validate,
+                failed,
+                keep_going,
+                wait_for_pacman,
+                dry_run,
+                interactive,
+                exclude,
+                limit,
                 packages,
                 helper_args,
             } => {
                 assert!(!force);
+                assert!(!skip_stale);
+                assert!(!unmark_stale);
+                assert!(!failed);
+                assert!(!keep_going);
+                assert!(!wait_for_pacman);
+                assert!(!dry_run);
+                assert!(!interactive);
+                assert!(exclude.is_empty());
+                assert!(limit.is_none());
+                assert!(tag.is_none());
+                assert!(!validate);
                 assert!(!checkrebuild);
                 assert!(cmd.is_none());
+                assert!(!skip_flagged);
+                assert!(profile.is_none());
                 assert!(packages.is_empty());
                 assert!(helper_args.is_empty());
             }
@@ -287,6 +1153,8 @@ mod tests {
             "--checkrebuild",
             "--cmd",
             "yay",
+            "--skip-flagged",
+            "-k",
             "pkg1",
             "--",
             "--noconfirm",
@@ -296,12 +1164,17 @@ mod tests {
                 force,
                 checkrebuild,
                 cmd,
+                skip_flagged,
+                keep_going,
                 packages,
                 helper_args,
+                ..
             } => {
                 assert!(force);
+                assert!(keep_going);
                 assert!(checkrebuild);
                 assert_eq!(cmd, Some("yay".to_string()));
+                assert!(skip_flagged);
                 assert_eq!(packages, vec!["pkg1"]);
                 assert_eq!(helper_args, vec!["--noconfirm"]);
             }
@@ -322,8 +1195,9 @@ mod tests {
     fn parse_query() {
         let cli = Cli::parse_from(["anneal", "query", "pkg1", "pkg2"]);
         match cli.command {
-            Command::Query { packages } => {
+            Command::Query { packages, null } => {
                 assert_eq!(packages, vec!["pkg1", "pkg2"]);
+                assert!(!null);
             }
             _ => panic!("expected Query command"),
         }
@@ -333,13 +1207,24 @@ mod tests {
     fn parse_triggers() {
         let cli = Cli::parse_from(["anneal", "triggers"]);
         assert!(matches!(cli.command, Command::Triggers));
+
+        let cli = Cli::parse_from(["anneal", "stats"]);
+        assert!(matches!(cli.command, Command::Stats));
+
+        let cli = Cli::parse_from(["anneal", "count"]);
+        assert!(matches!(cli.command, Command::Count));
+
+        let cli = Cli::parse_from(["anneal", "status", "--waybar"]);
+        assert!(matches!(cli.command, Command::Status { waybar: true }));
     }
 
     #[test]
     fn parse_trigger() {
         let cli = Cli::parse_from(["anneal", "trigger", "qt6-base"]);
         match cli.command {
-            Command::Trigger { dry_run, packages } => {
+            Command::Trigger {
+                dry_run, packages, ..
+            } => {
                 assert!(!dry_run);
                 assert_eq!(packages, vec!["qt6-base"]);
             }
@@ -356,10 +1241,270 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_trigger_hook() {
+        let cli = Cli::parse_from(["anneal", "trigger", "--hook"]);
+        assert!(matches!(cli.command, Command::Trigger { hook: true, .. }));
+        assert!(cli.command.writes_database());
+
+        // Targets only come from stdin, and the hook always marks
+        assert!(Cli::try_parse_from(["anneal", "trigger", "--hook", "qt6-base"]).is_err());
+        assert!(Cli::try_parse_from(["anneal", "trigger", "--hook", "--dry-run"]).is_err());
+    }
+
+    #[test]
+    fn parse_verify() {
+        let cli = Cli::parse_from(["anneal", "verify", "qt6gtk2"]);
+        match &cli.command {
+            Command::Verify { package } => assert_eq!(package, "qt6gtk2"),
+            _ => panic!("expected Verify command"),
+        }
+        assert!(!cli.command.modifies_system());
+        assert!(Cli::try_parse_from(["anneal", "verify"]).is_err());
+    }
+
+    #[test]
+    fn parse_capture_versions() {
+        let cli = Cli::parse_from(["anneal", "capture-versions"]);
+        assert!(matches!(cli.command, Command::CaptureVersions));
+        assert!(cli.command.writes_database());
+        assert!(!cli.command.modifies_queue());
+        assert!(Cli::try_parse_from(["anneal", "capture-versions", "qt6-base"]).is_err());
+    }
+
+    #[test]
+    fn null_flag() {
+        let cli = Cli::parse_from(["anneal", "list", "-0"]);
+        assert!(matches!(cli.command, Command::List { null: true, .. }));
+        let cli = Cli::parse_from(["anneal", "query", "--null", "qt6gtk2"]);
+        assert!(matches!(cli.command, Command::Query { null: true, .. }));
+        let cli = Cli::parse_from(["anneal", "trigger", "--dry-run", "-0", "qt6-base"]);
+        assert!(matches!(cli.command, Command::Trigger { null: true, .. }));
+
+        // Only the dry run prints package names
+        assert!(Cli::try_parse_from(["anneal", "trigger", "-0", "qt6-base"]).is_err());
+        assert!(Cli::try_parse_from(["anneal", "list", "-0", "--long"]).is_err());
+    }
+
+    #[test]
+    fn parse_preview() {
+        let cli = Cli::parse_from(["anneal", "preview"]);
+        assert!(matches!(cli.command, Command::Preview { file: None }));
+        assert!(!cli.command.modifies_system());
+        assert!(!cli.command.modifies_queue());
+
+        let cli = Cli::parse_from(["anneal", "preview", "-"]);
+        match cli.command {
+            Command::Preview { file } => assert_eq!(file, Some(PathBuf::from("-"))),
+            _ => panic!("expected Preview command"),
+        }
+    }
+
+    #[test]
+    fn parse_scan() {
+        let cli = Cli::parse_from(["anneal", "scan", "--dry-run"]);
+        match cli.command {
+            Command::Scan {
+                dry_run,
+                checkrebuild,
+            } => {
+                assert!(dry_run);
+                assert!(!checkrebuild);
+            }
+            _ => panic!("expected Scan command"),
+        }
+
+        let cli = Cli::parse_from(["anneal", "scan", "--checkrebuild"]);
+        assert!(matches!(
+            cli.command,
+            Command::Scan {
+                dry_run: false,
+                checkrebuild: true
+            }
+        ));
+    }
+
+    #[test]
+    fn parse_ignore() {
+        let cli = Cli::parse_from(["anneal", "ignore", "foo", "bar"]);
+        assert!(cli.command.writes_system_files());
+        assert!(!cli.command.writes_database());
+        match cli.command {
+            Command::Ignore { packages, list } => {
+                assert_eq!(packages, vec!["foo", "bar"]);
+                assert!(!list);
+            }
+            _ => panic!("expected Ignore command"),
+        }
+
+        let cli = Cli::parse_from(["anneal", "ignore", "--list"]);
+        assert!(!cli.command.modifies_system());
+        let cli = Cli::parse_from(["anneal", "unignore", "foo"]);
+        assert!(cli.command.writes_system_files());
+
+        assert!(Cli::try_parse_from(["anneal", "ignore"]).is_err());
+        assert!(Cli::try_parse_from(["anneal", "ignore", "--list", "foo"]).is_err());
+        assert!(Cli::try_parse_from(["anneal", "unignore"]).is_err());
+    }
+
+    #[test]
+    fn parse_update_triggers() {
+        // The downloaded list is root-owned, next to the database
+        let cli = Cli::parse_from(["anneal", "update-triggers"]);
+        assert!(matches!(cli.command, Command::UpdateTriggers));
+        assert!(cli.command.writes_system_files());
+        assert!(!cli.command.writes_database());
+    }
+
+    #[test]
+    fn parse_trigger_def() {
+        let cli = Cli::parse_from(["anneal", "trigger-def", "add", "my-lib", "my-app", "my-*"]);
+        assert!(cli.command.writes_system_files());
+        assert!(!cli.command.writes_database());
+        match cli.command {
+            Command::TriggerDef {
+                action: TriggerDefAction::Add { name, patterns },
+            } => {
+                assert_eq!(name, "my-lib");
+                assert_eq!(patterns, vec!["my-app", "my-*"]);
+            }
+            _ => panic!("expected TriggerDef add command"),
+        }
+
+        // A definition needs at least one pattern
+        assert!(Cli::try_parse_from(["anneal", "trigger-def", "add", "my-lib"]).is_err());
+    }
+
+    #[test]
+    fn parse_override_add() {
+        let cli = Cli::parse_from(["anneal", "override", "add", "trigger", "my-lib", "app-*"]);
+        assert!(cli.command.writes_system_files());
+        assert!(!cli.command.writes_database());
+        match cli.command {
+            Command::Override {
+                action:
+                    OverrideAction::Add {
+                        kind,
+                        name,
+                        entries,
+                    },
+            } => {
+                assert_eq!(kind, OverrideKind::Trigger);
+                assert_eq!(name, "my-lib");
+                assert_eq!(entries, vec!["app-*"]);
+            }
+            _ => panic!("expected Override add command"),
+        }
+
+        let cli = Cli::parse_from(["anneal", "override", "list"]);
+        assert!(!cli.command.modifies_system());
+
+        assert!(Cli::try_parse_from(["anneal", "override", "add", "group", "x"]).is_err());
+    }
+
+    #[test]
+    fn parse_setup() {
+        let cli = Cli::parse_from(["anneal", "setup", "--dir", "/tmp/hooks", "-f"]);
+        match cli.command {
+            Command::Setup {
+                dir,
+                force,
+                print,
+                systemd,
+            } => {
+                assert_eq!(dir, Some(PathBuf::from("/tmp/hooks")));
+                assert!(force);
+                assert!(!print);
+                assert!(!systemd);
+            }
+            _ => panic!("expected Setup command"),
+        }
+
+        // Installing hooks needs root even for members of the anneal group
+        let cli = Cli::parse_from(["anneal", "setup"]);
+        assert!(cli.command.writes_system_files());
+        assert!(!cli.command.writes_database());
+        let cli = Cli::parse_from(["anneal", "setup", "--print"]);
+        assert!(!cli.command.modifies_system());
+
+        assert!(Cli::try_parse_from(["anneal", "setup", "--print", "-f"]).is_err());
+    }
+
+    #[test]
+    fn parse_source_add() {
+        let cli = Cli::parse_from(["anneal", "source", "add", "my-pkg", "/src/my-pkg"]);
+        match cli.command {
+            Command::Source {
+                action: SourceAction::Add { package, path },
+            } => {
+                assert_eq!(package, "my-pkg");
+                assert_eq!(path, PathBuf::from("/src/my-pkg"));
+            }
+            _ => panic!("expected Source add command"),
+        }
+    }
+
+    #[test]
+    fn parse_why() {
+        let cli = Cli::parse_from(["anneal", "why", "qt6gtk2"]);
+        match cli.command {
+            Command::Why { package } => assert_eq!(package, "qt6gtk2"),
+            _ => panic!("expected Why command"),
+        }
+    }
+
+    #[test]
+    fn parse_history() {
+        let cli = Cli::parse_from(["anneal", "history", "qt6gtk2", "-n", "5", "-r"]);
+        match cli.command {
+            Command::History {
+                package,
+                rebuilds,
+                limit,
+                reverse,
+            } => {
+                assert_eq!(package, "qt6gtk2");
+                assert!(!rebuilds);
+                assert_eq!(limit, Some(5));
+                assert!(reverse);
+            }
+            _ => panic!("expected History command"),
+        }
+    }
+
     #[test]
     fn parse_config() {
         let cli = Cli::parse_from(["anneal", "config"]);
-        assert!(matches!(cli.command, Command::Config));
+        assert!(matches!(cli.command, Command::Config { action: None }));
+
+        let cli = Cli::parse_from(["anneal", "config", "set", "profile.fast", "--nocheck"]);
+        assert!(cli.command.writes_system_files());
+        assert!(!cli.command.writes_database());
+        match cli.command {
+            Command::Config {
+                action: Some(ConfigAction::Set { key, value }),
+            } => {
+                assert_eq!(key, "profile.fast");
+                assert_eq!(value, vec!["--nocheck"]);
+            }
+            _ => panic!("expected config set command"),
+        }
+
+        let cli = Cli::parse_from(["anneal", "config", "get", "helper"]);
+        assert!(!cli.command.modifies_system());
+    }
+
+    #[test]
+    fn parse_db() {
+        let cli = Cli::parse_from(["anneal", "db"]);
+        assert!(matches!(cli.command, Command::Db { action: None }));
+        assert!(!cli.command.writes_database());
+
+        let cli = Cli::parse_from(["anneal", "db", "check"]);
+        assert!(!cli.command.writes_database());
+
+        let cli = Cli::parse_from(["anneal", "db", "vacuum"]);
+        assert!(cli.command.writes_database());
     }
 
     #[test]
@@ -372,65 +1517,231 @@ mod tests {
     }
 
     #[test]
-    fn requires_root() {
+    fn porcelain_flag() {
+        let cli = Cli::parse_from(["anneal", "history", "--porcelain", "qt6gtk2"]);
+        assert!(cli.porcelain);
+        assert!(cli.command.supports_porcelain());
+        assert!(
+            !Cli::parse_from(["anneal", "stats"])
+                .command
+                .supports_porcelain()
+        );
+        assert!(Cli::try_parse_from(["anneal", "--json", "--porcelain", "list"]).is_err());
+    }
+
+    #[test]
+    fn json_flag_global() {
+        let cli = Cli::parse_from(["anneal", "--json", "list"]);
+        assert!(cli.json);
+
+        let cli = Cli::parse_from(["anneal", "list", "--json"]);
+        assert!(cli.json);
+    }
+
+    #[test]
+    fn offline_flag_global() {
+        let cli = Cli::parse_from(["anneal", "--offline", "rebuild"]);
+        assert!(cli.offline);
+
+        let cli = Cli::parse_from(["anneal", "rebuild", "--offline"]);
+        assert!(cli.offline);
+
+        let cli = Cli::parse_from(["anneal", "rebuild"]);
+        assert!(!cli.offline);
+    }
+
+    #[test]
+    fn verbose_flag_counts() {
+        let cli = Cli::parse_from(["anneal", "-vv", "trigger", "qt6-base"]);
+        assert_eq!(cli.verbose, 2);
+
+        let cli = Cli::parse_from(["anneal", "list", "--verbose"]);
+        assert_eq!(cli.verbose, 1);
+        assert!(Cli::try_parse_from(["anneal", "list", "-v", "--format", "{package}"]).is_err());
+    }
+
+    #[test]
+    fn color_flag_global() {
+        let cli = Cli::parse_from(["anneal", "list", "--color", "never"]);
+        assert_eq!(cli.color, ColorChoice::Never);
+
+        let cli = Cli::parse_from(["anneal", "list"]);
+        assert_eq!(cli.color, ColorChoice::Auto);
+        assert!(Cli::try_parse_from(["anneal", "--color", "sometimes", "list"]).is_err());
+    }
+
+    #[test]
+    fn fixture_dir_global() {
+        let cli = Cli::parse_from(["anneal", "trigger", "--fixture-dir", "/tmp/fx", "qt6-base"]);
+        assert_eq!(cli.fixture_dir, Some(PathBuf::from("/tmp/fx")));
+
+        let cli = Cli::parse_from(["anneal", "trigger", "qt6-base"]);
+        assert_eq!(cli.fixture_dir, None);
+    }
+
+    #[test]
+    fn allows_polkit() {
+        let cli = Cli::parse_from(["anneal", "mark", "pkg1"]);
+        assert!(cli.command.allows_polkit());
+        let cli = Cli::parse_from(["anneal", "unmark", "pkg1"]);
+        assert!(cli.command.allows_polkit());
+        let cli = Cli::parse_from(["anneal", "clear", "-f"]);
+        assert!(cli.command.allows_polkit());
+        let cli = Cli::parse_from(["anneal", "pin", "pkg1"]);
+        assert!(cli.command.allows_polkit());
+        let cli = Cli::parse_from(["anneal", "unpin", "pkg1"]);
+        assert!(cli.command.allows_polkit());
+        let cli = Cli::parse_from(["anneal", "prioritize", "high", "pkg1"]);
+        assert!(cli.command.allows_polkit());
+        assert!(cli.command.modifies_queue());
+        let cli = Cli::parse_from(["anneal", "restore", "backup.json"]);
+        assert!(!cli.command.allows_polkit());
+        let cli = Cli::parse_from(["anneal", "trigger", "qt6-base"]);
+        assert!(!cli.command.allows_polkit());
+    }
+
+    #[test]
+    fn writes_database() {
         assert!(
             Command::Mark {
                 packages: vec![],
+                foreign: false,
+                file: None,
+                group: None,
+                maintainer: None,
                 trigger: None,
-                trigger_version: None
+                trigger_version: None,
+                allow_missing: false,
+                tags: vec![],
+                reason: None,
+                priority: None,
+                expires: None,
             }
-            .requires_root()
+            .writes_database()
         );
         assert!(
             Command::Unmark {
                 packages: vec![],
-                strict: false
+                strict: false,
+                interactive: false,
+                include_pinned: false,
             }
-            .requires_root()
+            .writes_database()
         );
         assert!(
             Command::Clear {
                 force: false,
-                trigger: None
+                trigger: None,
+                tag: None,
+                include_pinned: false,
+                undo: false,
             }
-            .requires_root()
+            .writes_database()
         );
         assert!(
             Command::Trigger {
                 dry_run: false,
+                null: false,
+                hook: false,
                 packages: vec![]
             }
-            .requires_root()
+            .writes_database()
+        );
+
+        assert!(
+            Command::Scan {
+                dry_run: false,
+                checkrebuild: false
+            }
+            .writes_database()
+        );
+        assert!(
+            Command::Source {
+                action: SourceAction::Remove {
+                    package: "pkg".into()
+                }
+            }
+            .writes_database()
+        );
+        assert!(
+            !Command::Source {
+                action: SourceAction::List
+            }
+            .writes_database()
         );
 
         // dry_run doesn't require root
         assert!(
             !Command::Trigger {
                 dry_run: true,
+                null: false,
+                hook: false,
                 packages: vec![]
             }
-            .requires_root()
+            .writes_database()
+        );
+        assert!(
+            !Command::Scan {
+                dry_run: true,
+                checkrebuild: true
+            }
+            .writes_database()
         );
 
-        assert!(!Command::List.requires_root());
+        assert!(
+            !Command::List {
+                sort: SortOrder::Age,
+                reverse: false,
+                tag: None,
+                trigger: None,
+                since: None,
+                before: None,
+                format: None,
+                long: false,
+                null: false,
+                check: false,
+            }
+            .writes_database()
+        );
         assert!(
             !Command::IsMarked {
                 package: String::new()
             }
-            .requires_root()
+            .writes_database()
+        );
+        assert!(
+            !Command::Query {
+                packages: vec![],
+                null: false
+            }
+            .writes_database()
         );
-        assert!(!Command::Query { packages: vec![] }.requires_root());
-        assert!(!Command::Triggers.requires_root());
-        assert!(!Command::Config.requires_root());
+        assert!(!Command::Triggers.writes_database());
+        assert!(!Command::Stats.writes_database());
+        assert!(!Command::Count.writes_database());
+        assert!(!Command::Config { action: None }.writes_database());
         assert!(
             !Command::Rebuild {
                 force: false,
                 checkrebuild: false,
                 cmd: None,
+                skip_flagged: false,
+                skip_stale: false,
+                unmark_stale: false,
+                profile: None,
+                tag: None,
+                validate: false,
+                failed: false,
+                keep_going: false,
+                wait_for_pacman: false,
+                dry_run: false,
+                interactive: false,
+                exclude: vec![],
+                limit: None,
                 packages: vec![],
                 helper_args: vec![],
             }
-            .requires_root()
+            .writes_database()
         );
     }
 
@@ -439,28 +1750,44 @@ mod tests {
         assert!(
             Command::Mark {
                 packages: vec![],
+                foreign: false,
+                file: None,
+                group: None,
+                maintainer: None,
                 trigger: None,
-                trigger_version: None
+                trigger_version: None,
+                allow_missing: false,
+                tags: vec![],
+                reason: None,
+                priority: None,
+                expires: None,
             }
             .modifies_queue()
         );
         assert!(
             Command::Unmark {
                 packages: vec![],
-                strict: false
+                strict: false,
+                interactive: false,
+                include_pinned: false,
             }
             .modifies_queue()
         );
         assert!(
             Command::Clear {
                 force: false,
-                trigger: None
+                trigger: None,
+                tag: None,
+                include_pinned: false,
+                undo: false,
             }
             .modifies_queue()
         );
         assert!(
             Command::Trigger {
                 dry_run: false,
+                null: false,
+                hook: false,
                 packages: vec![]
             }
             .modifies_queue()
@@ -470,12 +1797,28 @@ mod tests {
         assert!(
             !Command::Trigger {
                 dry_run: true,
+                null: false,
+                hook: false,
                 packages: vec![]
             }
             .modifies_queue()
         );
 
-        assert!(!Command::List.modifies_queue());
+        assert!(
+            !Command::List {
+                sort: SortOrder::Age,
+                reverse: false,
+                tag: None,
+                trigger: None,
+                since: None,
+                before: None,
+                format: None,
+                long: false,
+                null: false,
+                check: false,
+            }
+            .modifies_queue()
+        );
         assert!(
             !Command::IsMarked {
                 package: String::new()
@@ -3,18 +3,212 @@
 
 //! Database operations for the rebuild queue.
 //!
-//! Uses SQLite with WAL mode for concurrent access from pacman hooks.
+//! Uses SQLite in rollback journal (DELETE) mode by default, so non-root
+//! users can read the database without write access to its directory. WAL
+//! mode can be enabled with `journal_mode = wal`; see [`JournalMode`].
 //! The database stores:
-//! - `queue`: Packages currently marked for rebuild
+//! - `queue`: Packages currently marked for rebuild, with the reason given
+//!   to `mark --reason`, their priority, whether they're pinned
+//!   (`anneal pin`), when they expire (`mark --expires`) and the version
+//!   installed when they were marked
 //! - `trigger_events`: History of trigger events for debugging
+//! - `queue_events`: History of how packages left the queue (unmark, clear,
+//!   rebuild) and rebuild failures, with where each change came from
+//! - `queue_tags`: Free-form tags on queue entries, dropped with the entry
+//! - `local_sources`: Packages built from local PKGBUILD checkouts
+//! - `rebuilds`: One row per package per rebuild attempt, with its outcome
+//! - `cleared_queues`: The queue as it was before the last `clear`, for
+//!   `clear --undo`
+//!
+//! Setting `ANNEAL_DB_PATH=:memory:` uses a fresh in-memory database instead,
+//! which never touches the filesystem and is discarded on exit.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
-use std::path::Path;
+use rusqlite::{Connection, OpenFlags, OptionalExtension, params, params_from_iter};
 
-use rusqlite::{Connection, OpenFlags, OptionalExtension, params};
+use crate::time::{cutoff_date, now_iso8601, parse_iso8601};
+use crate::tr;
 
 /// Default database path.
 pub const DEFAULT_DB_PATH: &str = "/var/lib/anneal/anneal.db";
 
+/// Database path that selects an in-memory database.
+pub const MEMORY_DB_PATH: &str = ":memory:";
+
+/// Tables saved by a backup, with their columns.
+pub const BACKUP_TABLES: &[(&str, &[&str])] = &[
+    (
+        "queue",
+        &[
+            "package",
+            "first_marked_at",
+            "reason",
+            "pinned",
+            "priority",
+            "expires_at",
+            "marked_version",
+        ],
+    ),
+    ("queue_tags", &["package", "tag"]),
+    (
+        "trigger_events",
+        &[
+            "id",
+            "package",
+            "trigger_package",
+            "trigger_version",
+            "marked_at",
+        ],
+    ),
+    (
+        "queue_events",
+        &["id", "package", "action", "detail", "at", "origin"],
+    ),
+    ("local_sources", &["package", "path", "added_at"]),
+    (
+        "rebuilds",
+        &[
+            "id",
+            "package",
+            "started_at",
+            "finished_at",
+            "exit_code",
+            "helper",
+        ],
+    ),
+];
+
+/// Rows of a table, with values in column order.
+pub type Rows = Vec<Vec<rusqlite::types::Value>>;
+
+/// SQLite journal mode of the database (`journal_mode` in the config).
+///
+/// With the default rollback journal, a write holds an exclusive lock on
+/// the whole database, so a hook marking packages has to wait for a
+/// `rebuild` unmarking them (and vice versa). WAL lets readers and a writer
+/// proceed concurrently. Its `-wal` and `-shm` side files are given the
+/// owner, group and mode of the database file whenever it is opened for
+/// writing, so users who can read (or write) the database can read (or
+/// write) them too.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum JournalMode {
+    /// Rollback journal, deleted after each transaction.
+    #[default]
+    Delete,
+    /// Write-ahead log.
+    Wal,
+}
+
+impl JournalMode {
+    /// Name used in the config and by SQLite.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Delete => "delete",
+            Self::Wal => "wal",
+        }
+    }
+}
+
+/// Journal mode set by the config.
+static JOURNAL_MODE: OnceLock<JournalMode> = OnceLock::new();
+
+/// Set the journal mode databases are opened for writing with.
+///
+/// Only the first call has an effect.
+pub fn set_journal_mode(mode: JournalMode) {
+    let _ = JOURNAL_MODE.set(mode);
+}
+
+/// Get the journal mode databases are opened for writing with.
+fn journal_mode() -> JournalMode {
+    JOURNAL_MODE.get().copied().unwrap_or_default()
+}
+
+/// SQLite side files of a WAL-mode database.
+const WAL_SUFFIXES: &[&str] = &["-wal", "-shm"];
+
+/// Path of a side file of the database (`anneal.db-wal`).
+fn side_file(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Give the WAL side files the owner, group and mode of the database.
+///
+/// Best-effort: changing the owner needs root, and a failure only means
+/// some users can't read the database until a privileged write.
+fn share_wal_files(path: &Path) {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return;
+    };
+    for suffix in WAL_SUFFIXES {
+        let file = side_file(path, suffix);
+        if !file.exists() {
+            continue;
+        }
+        let _ = std::os::unix::fs::chown(&file, Some(metadata.uid()), Some(metadata.gid()));
+        let _ = std::fs::set_permissions(
+            &file,
+            std::fs::Permissions::from_mode(metadata.mode() & 0o666),
+        );
+    }
+}
+
+/// Create an empty database file, group-writable if its directory is.
+///
+/// SQLite would create the file with the umask applied (usually 0644),
+/// shutting out a group allowed to write the directory, such as the
+/// `anneal` group of the packaged tmpfiles.d entry. Journals get the mode of
+/// the database file, so this covers them too.
+fn create_db_file(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mode = if std::fs::metadata(dir)?.mode() & 0o020 != 0 {
+        0o664
+    } else {
+        0o644
+    };
+
+    match std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+    {
+        Ok(file) => file.set_permissions(std::fs::Permissions::from_mode(mode)),
+        // Created concurrently, e.g. by a hook
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns true if the database file is in WAL mode, from its header.
+fn is_wal_file(path: &Path) -> bool {
+    use std::io::Read;
+
+    // Bytes 18 and 19 are the file format read/write versions, 2 for WAL
+    let mut header = [0u8; 20];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .is_ok_and(|()| header[18] == 2)
+}
+
+/// Returns true if the path selects an in-memory database.
+pub fn is_memory_path(path: &Path) -> bool {
+    path.as_os_str() == MEMORY_DB_PATH
+}
+
 /// Get the database path, checking ANNEAL_DB_PATH environment variable.
 pub fn get_db_path() -> std::path::PathBuf {
     std::env::var("ANNEAL_DB_PATH")
@@ -22,6 +216,48 @@ pub fn get_db_path() -> std::path::PathBuf {
         .unwrap_or_else(|_| std::path::PathBuf::from(DEFAULT_DB_PATH))
 }
 
+/// Check whether the current user can write the database at `path`.
+///
+/// Rollback journals are created next to the database, so this requires
+/// write access to the directory as well as the file. If the database or its
+/// directory doesn't exist yet, the nearest existing ancestor must be writable
+/// so it can be created. Uses the effective user and group IDs, so access
+/// through group membership (e.g., a group-writable setgid directory) counts.
+pub fn is_writable(path: &Path) -> bool {
+    if is_memory_path(path) {
+        return true;
+    }
+
+    if path.exists() {
+        return can_access(path, libc::W_OK)
+            && path
+                .parent()
+                .is_some_and(|dir| can_access(dir, libc::W_OK | libc::X_OK));
+    }
+
+    path.ancestors()
+        .skip(1)
+        .find(|dir| dir.exists())
+        .is_some_and(|dir| can_access(dir, libc::W_OK | libc::X_OK))
+}
+
+/// Check access to a path with the effective user and group IDs.
+fn can_access(path: &Path, mode: libc::c_int) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    // An empty parent means the current directory
+    let path = if path.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        path
+    };
+    let Ok(c_path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: c_path is a valid NUL-terminated string for the duration of the call
+    unsafe { libc::faccessat(libc::AT_FDCWD, c_path.as_ptr(), mode, libc::AT_EACCESS) == 0 }
+}
+
 /// Database connection wrapper.
 pub struct Database {
     conn: Connection,
@@ -31,15 +267,141 @@ pub struct Database {
 
 /// A package in the rebuild queue.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct QueueEntry {
     /// Package name.
     pub package: String,
     /// When the package was first marked (ISO8601).
     pub first_marked_at: String,
+    /// Why it was marked, as given with `mark --reason`.
+    pub reason: Option<String>,
+    /// Kept in the queue by successful rebuilds and `clear` (`anneal pin`).
+    pub pinned: bool,
+    /// Rebuild priority (`mark --priority`, `anneal prioritize`).
+    pub priority: Priority,
+    /// When the entry leaves the queue on its own (`mark --expires`, ISO8601).
+    pub expires_at: Option<String>,
+    /// Version of the package installed when it was last marked.
+    pub marked_version: Option<String>,
+}
+
+/// Priority of a queued package.
+///
+/// Listings by age and rebuilds take higher priorities first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Priority {
+    /// Rebuilt after everything else.
+    Low,
+    /// The default.
+    #[default]
+    Normal,
+    /// Rebuilt before everything else.
+    High,
+}
+
+impl Priority {
+    /// Name shown to the user.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Normal => "normal",
+            Self::High => "high",
+        }
+    }
+
+    /// Value stored in the database (higher sorts first).
+    fn to_db(self) -> i64 {
+        match self {
+            Self::Low => -1,
+            Self::Normal => 0,
+            Self::High => 1,
+        }
+    }
+
+    /// Parse a stored value.
+    fn from_db(value: i64) -> Self {
+        match value {
+            ..0 => Self::Low,
+            0 => Self::Normal,
+            1.. => Self::High,
+        }
+    }
+}
+
+/// Conditions a queue listing must match.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListFilter {
+    /// Only packages this trigger marked since they were queued.
+    pub trigger: Option<String>,
+    /// Only packages first marked at or after this time (ISO8601).
+    pub since: Option<String>,
+    /// Only packages first marked before this time (ISO8601).
+    pub before: Option<String>,
+}
+
+impl ListFilter {
+    /// Returns true if the filter excludes anything.
+    pub fn is_active(&self) -> bool {
+        self.trigger.is_some() || self.since.is_some() || self.before.is_some()
+    }
+}
+
+/// Ordering for queue listings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SortOrder {
+    /// Alphabetical by package name.
+    Name,
+    /// Highest priority first, then oldest mark first.
+    #[default]
+    Age,
+    /// By the most recent trigger (external marks last).
+    Trigger,
+}
+
+impl SortOrder {
+    /// SQL `ORDER BY` clause for this ordering.
+    fn order_by(self, reverse: bool) -> String {
+        let dir = if reverse { "DESC" } else { "ASC" };
+        match self {
+            Self::Name => format!("package {dir}"),
+            Self::Age => {
+                let priority = if reverse { "ASC" } else { "DESC" };
+                format!("priority {priority}, first_marked_at {dir}, package {dir}")
+            }
+            Self::Trigger => {
+                format!("latest_trigger IS NULL {dir}, latest_trigger {dir}, package {dir}")
+            }
+        }
+    }
+}
+
+/// Aggregate figures about the queue and its history.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Packages in the queue.
+    pub queued: usize,
+    /// Queued packages per most recent trigger (`None` for external marks),
+    /// largest first.
+    pub queued_by_trigger: Vec<(Option<String>, usize)>,
+    /// Trigger events per trigger in the window, busiest first.
+    pub busiest_triggers: Vec<(String, usize)>,
+    /// External marks in the window.
+    pub external_marks: usize,
+    /// Times a package left the queue (unmarked, cleared or rebuilt) in the
+    /// window.
+    pub dequeued: usize,
+    /// Average time those packages spent in the queue, in seconds.
+    pub average_wait_secs: Option<u64>,
 }
 
 /// A trigger event in the history.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TriggerEvent {
     /// Event ID.
     pub id: i64,
@@ -53,6 +415,158 @@ pub struct TriggerEvent {
     pub marked_at: String,
 }
 
+/// Something that happened to a queued package, other than being marked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueAction {
+    /// Removed from the queue with `unmark` (or the remove hook).
+    Unmarked,
+    /// Removed from the queue by `clear`.
+    Cleared,
+    /// Rebuilt successfully and removed from the queue.
+    Rebuilt,
+    /// A rebuild was attempted and failed (the package stays queued).
+    RebuildFailed,
+    /// Upgraded or reinstalled outside anneal and removed from the queue.
+    Reinstalled,
+    /// Found uninstalled (by the pacman hook or `clean`) and removed from the
+    /// queue.
+    Uninstalled,
+    /// Took over the queue entry of a replaced package.
+    Renamed,
+    /// Put back in the queue by `clear --undo`.
+    Restored,
+    /// Dropped from the queue when its `mark --expires` time passed.
+    Expired,
+}
+
+impl QueueAction {
+    /// Actions that take a package out of the queue, ending its stay.
+    const DEQUEUES: &[Self] = &[
+        Self::Unmarked,
+        Self::Cleared,
+        Self::Rebuilt,
+        Self::Reinstalled,
+        Self::Uninstalled,
+        Self::Expired,
+    ];
+
+    /// Name stored in the database.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Unmarked => "unmarked",
+            Self::Cleared => "cleared",
+            Self::Rebuilt => "rebuilt",
+            Self::RebuildFailed => "rebuild_failed",
+            Self::Reinstalled => "reinstalled",
+            Self::Uninstalled => "uninstalled",
+            Self::Renamed => "renamed",
+            Self::Restored => "restored",
+            Self::Expired => "expired",
+        }
+    }
+
+    /// Parse a stored action name.
+    fn from_db(s: &str) -> Option<Self> {
+        match s {
+            "unmarked" => Some(Self::Unmarked),
+            "cleared" => Some(Self::Cleared),
+            "rebuilt" => Some(Self::Rebuilt),
+            "rebuild_failed" => Some(Self::RebuildFailed),
+            "reinstalled" => Some(Self::Reinstalled),
+            "uninstalled" => Some(Self::Uninstalled),
+            "renamed" => Some(Self::Renamed),
+            "restored" => Some(Self::Restored),
+            "expired" => Some(Self::Expired),
+            _ => None,
+        }
+    }
+}
+
+/// Where a queue change came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// A command run by the user.
+    Cli,
+    /// A pacman hook (`trigger`, or `unmark` reading removed packages).
+    Hook,
+    /// `anneal rebuild`.
+    Rebuild,
+}
+
+impl Origin {
+    /// Name stored in the database.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Cli => "cli",
+            Self::Hook => "hook",
+            Self::Rebuild => "rebuild",
+        }
+    }
+
+    /// Parse a stored origin name.
+    fn from_db(s: &str) -> Option<Self> {
+        match s {
+            "cli" => Some(Self::Cli),
+            "hook" => Some(Self::Hook),
+            "rebuild" => Some(Self::Rebuild),
+            _ => None,
+        }
+    }
+}
+
+/// A queue event in the history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueEvent {
+    /// Event ID.
+    pub id: i64,
+    /// Package the event applies to.
+    pub package: String,
+    /// What happened.
+    pub action: QueueAction,
+    /// Extra context (exit code for failed rebuilds, old name for renames).
+    pub detail: Option<String>,
+    /// Where the change came from (`None` for events recorded before
+    /// origins were).
+    pub origin: Option<Origin>,
+    /// When it happened (ISO8601).
+    pub at: String,
+}
+
+/// A package built from a local PKGBUILD checkout instead of the AUR.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalSource {
+    /// Package name.
+    pub package: String,
+    /// Directory containing the PKGBUILD.
+    pub path: String,
+    /// When the source was registered (ISO8601).
+    pub added_at: String,
+}
+
+/// A rebuild attempt for one package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RebuildRecord {
+    /// Record ID.
+    pub id: i64,
+    /// Package that was rebuilt.
+    pub package: String,
+    /// When the build started (ISO8601).
+    pub started_at: String,
+    /// When the build finished (ISO8601).
+    pub finished_at: String,
+    /// Exit code of the build, `None` if it was killed by a signal.
+    pub exit_code: Option<i32>,
+    /// Program that ran the build (AUR helper or `makepkg`).
+    pub helper: String,
+}
+
+impl RebuildRecord {
+    /// Returns true if the build succeeded.
+    pub fn succeeded(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
 /// Database errors.
 #[derive(Debug)]
 pub enum DbError {
@@ -60,13 +574,20 @@ pub enum DbError {
     Sqlite(rusqlite::Error),
     /// I/O error (e.g., creating directory).
     Io(std::io::Error),
+    /// The database was created by a newer version (its schema version).
+    SchemaTooNew(u32),
 }
 
 impl std::fmt::Display for DbError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Sqlite(e) => write!(f, "database error: {e}"),
-            Self::Io(e) => write!(f, "I/O error: {e}"),
+            Self::Sqlite(e) => f.write_str(&tr!("db-sqlite", error = e.to_string())),
+            Self::Io(e) => f.write_str(&tr!("db-io", error = e.to_string())),
+            Self::SchemaTooNew(version) => f.write_str(&tr!(
+                "db-schema-too-new",
+                version = *version,
+                supported = SCHEMA_VERSION
+            )),
         }
     }
 }
@@ -76,6 +597,7 @@ impl std::error::Error for DbError {
         match self {
             Self::Sqlite(e) => Some(e),
             Self::Io(e) => Some(e),
+            Self::SchemaTooNew(_) => None,
         }
     }
 }
@@ -92,6 +614,162 @@ impl From<std::io::Error> for DbError {
     }
 }
 
+/// A schema migration, run inside a transaction.
+type Migration = fn(&Connection) -> rusqlite::Result<()>;
+
+/// Schema migrations in order: applying `MIGRATIONS[n]` brings a database
+/// from version `n` to `n + 1`. Append new migrations, never edit old ones.
+const MIGRATIONS: &[Migration] = &[
+    schema_v1, schema_v2, schema_v3, schema_v4, schema_v5, schema_v6,
+];
+
+/// Current schema version.
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Version 1: the schema as of versioning.
+///
+/// Databases from before versioning may have any subset of it, so every
+/// step is idempotent.
+fn schema_v1(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        r"
+        -- Packages currently marked for rebuild
+        CREATE TABLE IF NOT EXISTS queue (
+            package TEXT PRIMARY KEY,
+            first_marked_at TEXT NOT NULL
+        );
+
+        -- Trigger event history
+        CREATE TABLE IF NOT EXISTS trigger_events (
+            id INTEGER PRIMARY KEY,
+            package TEXT NOT NULL,
+            trigger_package TEXT,
+            trigger_version TEXT,
+            marked_at TEXT NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_trigger_events_package
+            ON trigger_events(package);
+        CREATE INDEX IF NOT EXISTS idx_trigger_events_trigger
+            ON trigger_events(trigger_package);
+        CREATE INDEX IF NOT EXISTS idx_trigger_events_marked_at
+            ON trigger_events(marked_at);
+        CREATE INDEX IF NOT EXISTS idx_trigger_events_package_marked_at
+            ON trigger_events(package, marked_at);
+        CREATE INDEX IF NOT EXISTS idx_queue_first_marked_at
+            ON queue(first_marked_at);
+
+        -- Queue event history (unmark, clear, rebuild)
+        CREATE TABLE IF NOT EXISTS queue_events (
+            id INTEGER PRIMARY KEY,
+            package TEXT NOT NULL,
+            action TEXT NOT NULL,
+            detail TEXT,
+            at TEXT NOT NULL,
+            origin TEXT
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_queue_events_package_at
+            ON queue_events(package, at);
+        CREATE INDEX IF NOT EXISTS idx_queue_events_at
+            ON queue_events(at);
+
+        -- Tags on queue entries
+        CREATE TABLE IF NOT EXISTS queue_tags (
+            package TEXT NOT NULL,
+            tag TEXT NOT NULL,
+            PRIMARY KEY (package, tag)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_queue_tags_tag
+            ON queue_tags(tag);
+
+        -- Packages built from local PKGBUILD checkouts
+        CREATE TABLE IF NOT EXISTS local_sources (
+            package TEXT PRIMARY KEY,
+            path TEXT NOT NULL,
+            added_at TEXT NOT NULL
+        );
+
+        -- Rebuild attempts, one row per package per build
+        CREATE TABLE IF NOT EXISTS rebuilds (
+            id INTEGER PRIMARY KEY,
+            package TEXT NOT NULL,
+            started_at TEXT NOT NULL,
+            finished_at TEXT NOT NULL,
+            exit_code INTEGER,
+            helper TEXT NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_rebuilds_package_started_at
+            ON rebuilds(package, started_at);
+
+        -- Queue before the last clear, for clear --undo
+        CREATE TABLE IF NOT EXISTS cleared_queues (
+            package TEXT PRIMARY KEY,
+            first_marked_at TEXT NOT NULL,
+            tags TEXT,
+            cleared_at TEXT NOT NULL
+        );
+        ",
+    )?;
+
+    if !column_exists(conn, "queue_events", "origin")? {
+        conn.execute("ALTER TABLE queue_events ADD COLUMN origin TEXT", [])?;
+    }
+    Ok(())
+}
+
+/// Version 2: free-text mark reasons on queue entries, kept across a clear.
+fn schema_v2(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE queue ADD COLUMN reason TEXT;
+         ALTER TABLE cleared_queues ADD COLUMN reason TEXT;",
+    )
+}
+
+/// Version 3: pinned queue entries, kept across a clear and its undo.
+fn schema_v3(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE queue ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+         ALTER TABLE cleared_queues ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;",
+    )
+}
+
+/// Version 4: queue priorities, kept across a clear and its undo.
+fn schema_v4(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE queue ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
+         ALTER TABLE cleared_queues ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;",
+    )
+}
+
+/// Version 5: expiry times on queue entries (`mark --expires`).
+fn schema_v5(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE queue ADD COLUMN expires_at TEXT;
+         ALTER TABLE cleared_queues ADD COLUMN expires_at TEXT;",
+    )
+}
+
+/// Version 6: the installed version of queued packages when marked.
+fn schema_v6(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE queue ADD COLUMN marked_version TEXT;
+         ALTER TABLE cleared_queues ADD COLUMN marked_version TEXT;",
+    )
+}
+
+/// Check whether a table has a column.
+fn column_exists(conn: &Connection, table: &str, column: &str) -> rusqlite::Result<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info(?1) WHERE name = ?2",
+        params![table, column],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
 impl Database {
     /// Open the database at the default path.
     ///
@@ -113,10 +791,17 @@ impl Database {
     /// Returns an error if the directory cannot be created, database cannot
     /// be opened, or schema initialization fails.
     pub fn open_at(path: &Path, retention_days: u32) -> Result<Self, DbError> {
+        if is_memory_path(path) {
+            return Self::open_in_memory(retention_days);
+        }
+
         // Create parent directory if needed
         if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)?;
         }
+        if !path.exists() {
+            create_db_file(path)?;
+        }
 
         let conn = Connection::open(path)?;
         let mut db = Self {
@@ -124,19 +809,51 @@ impl Database {
             retention_days,
         };
         db.init()?;
+        if journal_mode() == JournalMode::Wal {
+            share_wal_files(path);
+        }
+        Ok(db)
+    }
+
+    /// Open a new, empty in-memory database with the full schema.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if schema initialization fails.
+    pub fn open_in_memory(retention_days: u32) -> Result<Self, DbError> {
+        let conn = Connection::open_in_memory()?;
+        let mut db = Self {
+            conn,
+            retention_days,
+        };
+        db.init()?;
         Ok(db)
     }
 
     /// Open the database in read-only mode.
     ///
+    /// An in-memory path opens an empty (writable) in-memory database, since
+    /// there is nothing to protect.
+    ///
     /// # Errors
     ///
     /// Returns an error if the database doesn't exist or cannot be opened.
     pub fn open_readonly(path: &Path) -> Result<Self, DbError> {
+        if is_memory_path(path) {
+            return Self::open_in_memory(0);
+        }
+
         // We use immutable=1 to prevent SQLite from trying to create side files
-        // (-shm, -wal) even if the database was left in WAL mode.
+        // (-shm, -wal), which needs write access to the directory. A WAL
+        // database with a -wal file may have changes not yet checkpointed into
+        // the main file, so it's read through the (shared) side files instead.
+        // Without one, the last writer checkpointed everything on close.
         let path_str = path.to_string_lossy();
-        let uri = format!("file:{}?immutable=1", path_str);
+        let uri = if is_wal_file(path) && side_file(path, "-wal").exists() {
+            format!("file:{path_str}?mode=ro")
+        } else {
+            format!("file:{path_str}?immutable=1")
+        };
         let conn = Connection::open_with_flags(
             &uri,
             OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI,
@@ -147,42 +864,49 @@ impl Database {
         })
     }
 
-    /// Initialize the database schema.
+    /// Initialize the database, migrating its schema to the current version.
     fn init(&mut self) -> Result<(), DbError> {
-        // Use DELETE mode to ensure read-only users can access the DB.
-        // WAL mode requires write access to the directory to create -shm files,
-        // which prevents non-root users from running `anneal list`.
-        self.conn.pragma_update(None, "journal_mode", "DELETE")?;
-
-        self.conn.execute_batch(
-            r"
-            -- Packages currently marked for rebuild
-            CREATE TABLE IF NOT EXISTS queue (
-                package TEXT PRIMARY KEY,
-                first_marked_at TEXT NOT NULL
-            );
-
-            -- Trigger event history
-            CREATE TABLE IF NOT EXISTS trigger_events (
-                id INTEGER PRIMARY KEY,
-                package TEXT NOT NULL,
-                trigger_package TEXT,
-                trigger_version TEXT,
-                marked_at TEXT NOT NULL
-            );
-
-            CREATE INDEX IF NOT EXISTS idx_trigger_events_package
-                ON trigger_events(package);
-            CREATE INDEX IF NOT EXISTS idx_trigger_events_trigger
-                ON trigger_events(trigger_package);
-            CREATE INDEX IF NOT EXISTS idx_trigger_events_marked_at
-                ON trigger_events(marked_at);
-            ",
-        )?;
+        // DELETE mode by default so read-only users can access the DB: WAL
+        // mode needs the side files to be readable, see JournalMode
+        self.conn
+            .pragma_update(None, "journal_mode", journal_mode().as_str())?;
+
+        self.migrate()?;
+        // Opportunistic, like pruning old events
+        self.prune_expired()?;
+        Ok(())
+    }
+
+    /// Apply the migrations the database hasn't seen yet.
+    ///
+    /// Each migration runs in its own transaction together with the version
+    /// bump, so an interrupted upgrade resumes where it stopped.
+    fn migrate(&mut self) -> Result<(), DbError> {
+        let version = self.schema_version()?;
+        if version > SCHEMA_VERSION {
+            return Err(DbError::SchemaTooNew(version));
+        }
 
+        for (applied, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            let tx = self.conn.transaction()?;
+            migration(&tx)?;
+            tx.pragma_update(None, "user_version", applied + 1)?;
+            tx.commit()?;
+        }
         Ok(())
     }
 
+    /// Get the schema version of the database (0 if it predates versioning).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn schema_version(&self) -> Result<u32, DbError> {
+        Ok(self
+            .conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))?)
+    }
+
     /// Mark a package for rebuild.
     ///
     /// If the package is already in the queue, this is a no-op for the queue
@@ -199,21 +923,52 @@ impl Database {
         trigger_package: Option<&str>,
         trigger_version: Option<&str>,
     ) -> Result<bool, DbError> {
+        Ok(self.mark_all(&[package], trigger_package, trigger_version)? > 0)
+    }
+
+    /// Mark several packages for rebuild in one transaction, as
+    /// [`mark`](Self::mark) does for one.
+    ///
+    /// Either every package is marked or, on error, none is. Returns the
+    /// number of packages newly added to the queue.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn mark_all(
+        &mut self,
+        packages: &[&str],
+        trigger_package: Option<&str>,
+        trigger_version: Option<&str>,
+    ) -> Result<usize, DbError> {
         let now = now_iso8601();
         let tx = self.conn.transaction()?;
 
-        // Try to insert into queue (ignore if already exists)
-        let newly_added = tx.execute(
-            "INSERT OR IGNORE INTO queue (package, first_marked_at) VALUES (?1, ?2)",
-            params![package, now],
-        )? > 0;
-
-        // Always record the trigger event
-        tx.execute(
-            "INSERT INTO trigger_events (package, trigger_package, trigger_version, marked_at)
-             VALUES (?1, ?2, ?3, ?4)",
-            params![package, trigger_package, trigger_version, now],
-        )?;
+        let mut newly_added = 0;
+        for package in packages {
+            // Try to insert into queue (ignore if already exists)
+            let added = tx.execute(
+                "INSERT OR IGNORE INTO queue (package, first_marked_at) VALUES (?1, ?2)",
+                params![package, now],
+            )? > 0;
+            // A new mark is a reason to rebuild, so an earlier expiry no
+            // longer applies
+            if added {
+                newly_added += 1;
+            } else {
+                tx.execute(
+                    "UPDATE queue SET expires_at = NULL WHERE package = ?1",
+                    params![package],
+                )?;
+            }
+
+            // Always record the trigger event
+            tx.execute(
+                "INSERT INTO trigger_events (package, trigger_package, trigger_version, marked_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![package, trigger_package, trigger_version, now],
+            )?;
+        }
 
         tx.commit()?;
 
@@ -230,130 +985,1103 @@ impl Database {
     /// # Errors
     ///
     /// Returns an error if the database operation fails.
-    pub fn unmark(&mut self, package: &str) -> Result<bool, DbError> {
-        let removed = self
-            .conn
-            .execute("DELETE FROM queue WHERE package = ?1", params![package])?
-            > 0;
-        Ok(removed)
+    pub fn unmark(&mut self, package: &str, origin: Origin) -> Result<bool, DbError> {
+        self.dequeue(package, QueueAction::Unmarked, origin)
     }
 
-    /// Check if a package is in the rebuild queue.
+    /// Remove a package from the queue after a successful rebuild.
+    ///
+    /// Pinned packages stay in the queue. Returns `true` if the package was
+    /// removed.
     ///
     /// # Errors
     ///
-    /// Returns an error if the database query fails.
-    pub fn is_marked(&self, package: &str) -> Result<bool, DbError> {
-        let count: i64 = self.conn.query_row(
-            "SELECT COUNT(*) FROM queue WHERE package = ?1",
-            params![package],
-            |row| row.get(0),
-        )?;
-        Ok(count > 0)
+    /// Returns an error if the database operation fails.
+    pub fn complete_rebuild(&mut self, package: &str) -> Result<bool, DbError> {
+        if self.is_pinned(package)? {
+            return Ok(false);
+        }
+        self.dequeue(package, QueueAction::Rebuilt, Origin::Rebuild)
     }
 
-    /// List all packages in the rebuild queue.
+    /// Remove a package from the queue after it was upgraded or reinstalled
+    /// by something other than `anneal rebuild`.
+    ///
+    /// Pinned packages stay in the queue. Returns `true` if the package was
+    /// removed.
     ///
     /// # Errors
     ///
-    /// Returns an error if the database query fails.
-    pub fn list(&self) -> Result<Vec<QueueEntry>, DbError> {
-        let mut stmt = self
-            .conn
-            .prepare("SELECT package, first_marked_at FROM queue ORDER BY first_marked_at")?;
-
-        let entries = stmt
-            .query_map([], |row| {
-                Ok(QueueEntry {
-                    package: row.get(0)?,
-                    first_marked_at: row.get(1)?,
-                })
-            })?
-            .collect::<Result<Vec<_>, _>>()?;
-
-        Ok(entries)
+    /// Returns an error if the database operation fails.
+    pub fn complete_reinstall(&mut self, package: &str, origin: Origin) -> Result<bool, DbError> {
+        if self.is_pinned(package)? {
+            return Ok(false);
+        }
+        self.dequeue(package, QueueAction::Reinstalled, origin)
     }
 
-    /// Query which of the given packages are in the queue.
+    /// Remove a package that is no longer installed from the queue.
+    ///
+    /// Pinned packages stay in the queue. Returns `true` if the package was
+    /// removed.
     ///
     /// # Errors
     ///
-    /// Returns an error if the database query fails.
-    pub fn query(&self, packages: &[&str]) -> Result<Vec<String>, DbError> {
-        if packages.is_empty() {
-            return Ok(Vec::new());
+    /// Returns an error if the database operation fails.
+    pub fn complete_uninstall(&mut self, package: &str, origin: Origin) -> Result<bool, DbError> {
+        if self.is_pinned(package)? {
+            return Ok(false);
         }
-
-        // Build a query with placeholders for each package
-        let placeholders: Vec<_> = packages.iter().map(|_| "?").collect();
-        let sql = format!(
-            "SELECT package FROM queue WHERE package IN ({}) ORDER BY package",
-            placeholders.join(", ")
-        );
-
-        let mut stmt = self.conn.prepare(&sql)?;
-        let params: Vec<&dyn rusqlite::ToSql> =
-            packages.iter().map(|p| p as &dyn rusqlite::ToSql).collect();
-
-        let found = stmt
-            .query_map(params.as_slice(), |row| row.get(0))?
-            .collect::<Result<Vec<String>, _>>()?;
-
-        Ok(found)
+        self.dequeue(package, QueueAction::Uninstalled, origin)
     }
 
-    /// Clear the entire rebuild queue.
+    /// Record a failed rebuild attempt with the helper's exit code.
     ///
-    /// Does not clear trigger event history.
+    /// The package stays in the queue.
     ///
     /// # Errors
     ///
     /// Returns an error if the database operation fails.
-    pub fn clear(&mut self) -> Result<usize, DbError> {
-        let count = self.conn.execute("DELETE FROM queue", [])?;
-        Ok(count)
+    pub fn record_rebuild_failure(&mut self, package: &str, exit_code: i32) -> Result<(), DbError> {
+        self.conn.execute(
+            "INSERT INTO queue_events (package, action, detail, at, origin)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                package,
+                QueueAction::RebuildFailed.as_str(),
+                exit_code.to_string(),
+                now_iso8601(),
+                Origin::Rebuild.as_str()
+            ],
+        )?;
+        Ok(())
     }
 
-    /// Clear trigger events for a specific trigger package.
+    /// Record a finished rebuild attempt for a package.
     ///
-    /// If a package in the queue has no remaining triggers after this operation,
-    /// it is removed from the queue entirely.
+    /// Recorded for every package built, queued or not. `exit_code` is
+    /// `None` if the build was killed by a signal.
     ///
     /// # Errors
     ///
     /// Returns an error if the database operation fails.
-    pub fn clear_trigger_events(&mut self, trigger_package: &str) -> Result<usize, DbError> {
-        let tx = self.conn.transaction()?;
-
-        // Delete the matching trigger events
-        let count = tx.execute(
-            "DELETE FROM trigger_events WHERE trigger_package = ?1",
-            params![trigger_package],
+    pub fn record_rebuild(
+        &mut self,
+        package: &str,
+        helper: &str,
+        started_at: &str,
+        exit_code: Option<i32>,
+    ) -> Result<(), DbError> {
+        self.conn.execute(
+            "INSERT INTO rebuilds (package, started_at, finished_at, exit_code, helper)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![package, started_at, now_iso8601(), exit_code, helper],
+        )?;
+        Ok(())
+    }
+
+    /// Get rebuild attempts for a package, newest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn get_rebuilds(&self, package: &str) -> Result<Vec<RebuildRecord>, DbError> {
+        // Read-only connections to a database created by an older version
+        // don't have the table until the next write
+        if !self.has_table("rebuilds")? {
+            return Ok(Vec::new());
+        }
+        let mut stmt = self.conn.prepare(
+            "SELECT id, package, started_at, finished_at, exit_code, helper
+             FROM rebuilds WHERE package = ?1 ORDER BY started_at DESC, id DESC",
+        )?;
+        let records = stmt
+            .query_map(params![package], |row| {
+                Ok(RebuildRecord {
+                    id: row.get(0)?,
+                    package: row.get(1)?,
+                    started_at: row.get(2)?,
+                    finished_at: row.get(3)?,
+                    exit_code: row.get(4)?,
+                    helper: row.get(5)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(records)
+    }
+
+    /// Estimate how long each package takes to build, in seconds.
+    ///
+    /// The estimate is the average of the package's last three successful
+    /// builds. A helper run that built several packages (rows with the same
+    /// start time) is split evenly between them. Packages without a
+    /// successful build are left out.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn build_durations(&self) -> Result<HashMap<String, u64>, DbError> {
+        if !self.has_table("rebuilds")? {
+            return Ok(HashMap::new());
+        }
+        let mut stmt = self.conn.prepare(
+            "SELECT package, started_at, finished_at,
+                    (SELECT COUNT(*) FROM rebuilds b WHERE b.started_at = r.started_at)
+             FROM rebuilds r WHERE exit_code = 0
+             ORDER BY package, started_at DESC",
+        )?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, u64>(3)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut builds: HashMap<String, Vec<u64>> = HashMap::new();
+        for (package, started_at, finished_at, batch) in rows {
+            let (Some(start), Some(finish)) =
+                (parse_iso8601(&started_at), parse_iso8601(&finished_at))
+            else {
+                continue;
+            };
+            let durations = builds.entry(package).or_default();
+            if durations.len() < 3 {
+                durations.push(finish.saturating_sub(start) / batch.max(1));
+            }
+        }
+        Ok(builds
+            .into_iter()
+            .map(|(package, durations)| {
+                let average = durations.iter().sum::<u64>() / durations.len() as u64;
+                (package, average)
+            })
+            .collect())
+    }
+
+    /// Get packages whose most recent rebuild attempt failed, sorted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn failed_rebuilds(&self) -> Result<Vec<String>, DbError> {
+        if !self.has_table("rebuilds")? {
+            return Ok(Vec::new());
+        }
+        let mut stmt = self.conn.prepare(
+            "SELECT package FROM rebuilds r
+             WHERE id = (
+                 SELECT id FROM rebuilds WHERE package = r.package
+                 ORDER BY started_at DESC, id DESC LIMIT 1
+             )
+             AND (exit_code IS NULL OR exit_code != 0)
+             ORDER BY package",
+        )?;
+        let packages = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(packages)
+    }
+
+    /// Remove a package from the queue, recording why.
+    fn dequeue(
+        &mut self,
+        package: &str,
+        action: QueueAction,
+        origin: Origin,
+    ) -> Result<bool, DbError> {
+        let tx = self.conn.transaction()?;
+
+        let removed = tx.execute("DELETE FROM queue WHERE package = ?1", params![package])? > 0;
+        if removed {
+            tx.execute(
+                "INSERT INTO queue_events (package, action, at, origin) VALUES (?1, ?2, ?3, ?4)",
+                params![package, action.as_str(), now_iso8601(), origin.as_str()],
+            )?;
+            tx.execute(
+                "DELETE FROM queue_tags WHERE package = ?1",
+                params![package],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    /// Move a queued package and its event history to a new name.
+    ///
+    /// Used when a package is replaced by another (e.g., an upstream rename).
+    /// If the new name is already queued, the entries are merged and the
+    /// earlier mark time is kept.
+    ///
+    /// Returns `true` if the old package was in the queue.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn rename(&mut self, old: &str, new: &str, origin: Origin) -> Result<bool, DbError> {
+        let tx = self.conn.transaction()?;
+
+        let moved = tx.execute(
+            "INSERT INTO queue
+                 (package, first_marked_at, reason, pinned, priority, expires_at, marked_version)
+             SELECT ?2, first_marked_at, reason, pinned, priority, expires_at, marked_version
+             FROM queue WHERE package = ?1
+             ON CONFLICT(package) DO UPDATE
+             SET first_marked_at = MIN(first_marked_at, excluded.first_marked_at),
+                 reason = COALESCE(reason, excluded.reason),
+                 pinned = MAX(pinned, excluded.pinned),
+                 priority = MAX(priority, excluded.priority),
+                 expires_at = MAX(expires_at, excluded.expires_at),
+                 marked_version = COALESCE(marked_version, excluded.marked_version)",
+            params![old, new],
+        )? > 0;
+
+        if moved {
+            tx.execute("DELETE FROM queue WHERE package = ?1", params![old])?;
+            tx.execute(
+                "UPDATE trigger_events SET package = ?2 WHERE package = ?1",
+                params![old, new],
+            )?;
+            tx.execute(
+                "UPDATE queue_events SET package = ?2 WHERE package = ?1",
+                params![old, new],
+            )?;
+            tx.execute(
+                "UPDATE OR IGNORE queue_tags SET package = ?2 WHERE package = ?1",
+                params![old, new],
+            )?;
+            tx.execute("DELETE FROM queue_tags WHERE package = ?1", params![old])?;
+            tx.execute(
+                "INSERT INTO queue_events (package, action, detail, at, origin)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    new,
+                    QueueAction::Renamed.as_str(),
+                    old,
+                    now_iso8601(),
+                    origin.as_str()
+                ],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(moved)
+    }
+
+    /// Check if a package is in the rebuild queue.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn is_marked(&self, package: &str) -> Result<bool, DbError> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM queue WHERE package = ?1",
+            params![package],
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    /// Check if a package is queued and pinned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn is_pinned(&self, package: &str) -> Result<bool, DbError> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM queue WHERE package = ?1 AND pinned != 0",
+            params![package],
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    /// Set when a queued package leaves the queue on its own.
+    ///
+    /// Returns `true` if the package is in the queue.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn set_expiry(&mut self, package: &str, expires_at: &str) -> Result<bool, DbError> {
+        let updated = self.conn.execute(
+            "UPDATE queue SET expires_at = ?2 WHERE package = ?1",
+            params![package, expires_at],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Record the installed version of a queued package as of its latest
+    /// mark.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn set_marked_version(&mut self, package: &str, version: &str) -> Result<(), DbError> {
+        self.conn.execute(
+            "UPDATE queue SET marked_version = ?2 WHERE package = ?1",
+            params![package, version],
+        )?;
+        Ok(())
+    }
+
+    /// Set the priority of a queued package.
+    ///
+    /// Returns `true` if the package is in the queue.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn set_priority(&mut self, package: &str, priority: Priority) -> Result<bool, DbError> {
+        let updated = self.conn.execute(
+            "UPDATE queue SET priority = ?2 WHERE package = ?1",
+            params![package, priority.to_db()],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Pin or unpin a queued package.
+    ///
+    /// Returns `true` if the package is in the queue.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn set_pinned(&mut self, package: &str, pinned: bool) -> Result<bool, DbError> {
+        let updated = self.conn.execute(
+            "UPDATE queue SET pinned = ?2 WHERE package = ?1",
+            params![package, pinned],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Count the packages in the rebuild queue.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn count(&self) -> Result<usize, DbError> {
+        let n: i64 = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM queue", [], |row| row.get(0))?;
+        Ok(count(n))
+    }
+
+    /// List all packages in the rebuild queue.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn list(&self) -> Result<Vec<QueueEntry>, DbError> {
+        self.list_sorted(SortOrder::Age, false)
+    }
+
+    /// List all packages in the rebuild queue in the given order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn list_sorted(&self, order: SortOrder, reverse: bool) -> Result<Vec<QueueEntry>, DbError> {
+        self.list_filtered(order, reverse, &ListFilter::default())
+    }
+
+    /// List the packages in the rebuild queue matching a filter, in the given
+    /// order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn list_filtered(
+        &self,
+        order: SortOrder,
+        reverse: bool,
+        filter: &ListFilter,
+    ) -> Result<Vec<QueueEntry>, DbError> {
+        let mut conditions = Vec::new();
+        let mut values: Vec<&str> = Vec::new();
+        if let Some(trigger) = &filter.trigger {
+            values.push(trigger);
+            conditions.push(format!(
+                "EXISTS (SELECT 1 FROM trigger_events e
+                         WHERE e.package = queue.package AND e.trigger_package = ?{}
+                           AND e.marked_at >= queue.first_marked_at)",
+                values.len()
+            ));
+        }
+        if let Some(since) = &filter.since {
+            values.push(since);
+            conditions.push(format!("first_marked_at >= ?{}", values.len()));
+        }
+        if let Some(before) = &filter.before {
+            values.push(before);
+            conditions.push(format!("first_marked_at < ?{}", values.len()));
+        }
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+
+        // Read-only connections to an older database lack the newer columns
+        let reason = if self.has_column("queue", "reason")? {
+            "reason"
+        } else {
+            "NULL"
+        };
+        let pinned = if self.has_column("queue", "pinned")? {
+            "pinned"
+        } else {
+            "0"
+        };
+        let priority = if self.has_column("queue", "priority")? {
+            "priority"
+        } else {
+            "0"
+        };
+        let expires_at = if self.has_column("queue", "expires_at")? {
+            "expires_at"
+        } else {
+            "NULL"
+        };
+        let marked_version = if self.has_column("queue", "marked_version")? {
+            "marked_version"
+        } else {
+            "NULL"
+        };
+        let sql = format!(
+            "SELECT package, first_marked_at, {reason}, {pinned}, {priority} AS priority,
+                 {expires_at}, {marked_version},
+                 (SELECT trigger_package FROM trigger_events e
+                  WHERE e.package = queue.package
+                  ORDER BY marked_at DESC, id DESC LIMIT 1) AS latest_trigger
+             FROM queue {where_clause} ORDER BY {}",
+            order.order_by(reverse)
+        );
+        let mut stmt = self.conn.prepare(&sql)?;
+
+        let entries = stmt
+            .query_map(params_from_iter(values), |row| {
+                Ok(QueueEntry {
+                    package: row.get(0)?,
+                    first_marked_at: row.get(1)?,
+                    reason: row.get(2)?,
+                    pinned: row.get(3)?,
+                    priority: Priority::from_db(row.get(4)?),
+                    expires_at: row.get(5)?,
+                    marked_version: row.get(6)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(entries)
+    }
+
+    /// Set the reason a queued package was marked, replacing any earlier one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn set_reason(&mut self, package: &str, reason: &str) -> Result<(), DbError> {
+        self.conn.execute(
+            "UPDATE queue SET reason = ?2 WHERE package = ?1",
+            params![package, reason],
+        )?;
+        Ok(())
+    }
+
+    /// Attach tags to a queued package.
+    ///
+    /// Tags already present are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn add_tags(&mut self, package: &str, tags: &[&str]) -> Result<(), DbError> {
+        let tx = self.conn.transaction()?;
+        for tag in tags {
+            tx.execute(
+                "INSERT OR IGNORE INTO queue_tags (package, tag) VALUES (?1, ?2)",
+                params![package, tag],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Get the tags of a queued package, sorted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn get_tags(&self, package: &str) -> Result<Vec<String>, DbError> {
+        if !self.has_table("queue_tags")? {
+            return Ok(Vec::new());
+        }
+        let mut stmt = self
+            .conn
+            .prepare("SELECT tag FROM queue_tags WHERE package = ?1 ORDER BY tag")?;
+        let tags = stmt
+            .query_map(params![package], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(tags)
+    }
+
+    /// Get the queued packages carrying a tag, sorted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn tagged_packages(&self, tag: &str) -> Result<Vec<String>, DbError> {
+        if !self.has_table("queue_tags")? {
+            return Ok(Vec::new());
+        }
+        let mut stmt = self.conn.prepare(
+            "SELECT t.package FROM queue_tags t JOIN queue q ON q.package = t.package
+             WHERE t.tag = ?1 ORDER BY t.package",
+        )?;
+        let packages = stmt
+            .query_map(params![tag], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(packages)
+    }
+
+    /// Register (or move) the local PKGBUILD checkout of a package.
+    ///
+    /// Returns true if the package wasn't registered before.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn add_source(&mut self, package: &str, path: &str) -> Result<bool, DbError> {
+        let existed = self.get_source(package)?.is_some();
+        self.conn.execute(
+            "INSERT INTO local_sources (package, path, added_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(package) DO UPDATE SET path = excluded.path",
+            params![package, path, now_iso8601()],
+        )?;
+        Ok(!existed)
+    }
+
+    /// Stop tracking the local checkout of a package.
+    ///
+    /// Returns true if the package was registered.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn remove_source(&mut self, package: &str) -> Result<bool, DbError> {
+        let count = self.conn.execute(
+            "DELETE FROM local_sources WHERE package = ?1",
+            params![package],
+        )?;
+        Ok(count > 0)
+    }
+
+    /// Get the local checkout directory of a package, if registered.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn get_source(&self, package: &str) -> Result<Option<String>, DbError> {
+        if !self.has_table("local_sources")? {
+            return Ok(None);
+        }
+        let path = self
+            .conn
+            .query_row(
+                "SELECT path FROM local_sources WHERE package = ?1",
+                params![package],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(path)
+    }
+
+    /// List registered local sources, sorted by package.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn list_sources(&self) -> Result<Vec<LocalSource>, DbError> {
+        // Read-only connections to a database created by an older version
+        // don't have the table until the next write
+        if !self.has_table("local_sources")? {
+            return Ok(Vec::new());
+        }
+        let mut stmt = self
+            .conn
+            .prepare("SELECT package, path, added_at FROM local_sources ORDER BY package")?;
+        let sources = stmt
+            .query_map([], |row| {
+                Ok(LocalSource {
+                    package: row.get(0)?,
+                    path: row.get(1)?,
+                    added_at: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(sources)
+    }
+
+    /// Count the rows of each table, in schema order.
+    ///
+    /// Tables missing from a database created by an older version count as
+    /// empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn table_counts(&self) -> Result<Vec<(&'static str, usize)>, DbError> {
+        let tables = BACKUP_TABLES
+            .iter()
+            .map(|(table, _)| *table)
+            .chain(["cleared_queues"]);
+        let mut counts = Vec::new();
+        for table in tables {
+            let n: i64 = if self.has_table(table)? {
+                self.conn
+                    .query_row(&format!("SELECT COUNT(*) FROM \"{table}\""), [], |row| {
+                        row.get(0)
+                    })?
+            } else {
+                0
+            };
+            counts.push((table, count(n)));
+        }
+        Ok(counts)
+    }
+
+    /// Run SQLite's integrity check.
+    ///
+    /// Returns the problems found, empty if the database is intact.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the check can't be run.
+    pub fn integrity_check(&self) -> Result<Vec<String>, DbError> {
+        let mut stmt = self.conn.prepare("PRAGMA integrity_check")?;
+        let mut messages = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        if messages == ["ok"] {
+            messages.clear();
+        }
+        Ok(messages)
+    }
+
+    /// Rebuild the database file, reclaiming the space of deleted rows.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails (e.g., not enough
+    /// disk space for the temporary copy).
+    pub fn vacuum(&self) -> Result<(), DbError> {
+        self.conn.execute_batch("VACUUM")?;
+        Ok(())
+    }
+
+    /// Read all rows of a table, in rowid order.
+    ///
+    /// A missing table (or column) in a database created by an older
+    /// version reads as empty (or NULL).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn export_table(&self, table: &str, columns: &[&str]) -> Result<Rows, DbError> {
+        if !self.has_table(table)? {
+            return Ok(Vec::new());
+        }
+        let mut select = Vec::new();
+        for column in columns {
+            select.push(if self.has_column(table, column)? {
+                format!("\"{column}\"")
+            } else {
+                "NULL".to_string()
+            });
+        }
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM \"{table}\" ORDER BY rowid",
+            select.join(", ")
+        ))?;
+        let rows = stmt
+            .query_map([], |row| {
+                (0..columns.len())
+                    .map(|i| row.get(i))
+                    .collect::<Result<Vec<_>, _>>()
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    /// Replace the contents of the backed-up tables.
+    ///
+    /// Every table in [`BACKUP_TABLES`] is emptied, then filled with the
+    /// given rows (in the columns of [`BACKUP_TABLES`]), all in one
+    /// transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails, e.g. a row breaks
+    /// a constraint. Nothing is changed then.
+    pub fn import_tables(&mut self, tables: &[(&str, Rows)]) -> Result<(), DbError> {
+        let tx = self.conn.transaction()?;
+
+        for (table, _) in BACKUP_TABLES {
+            tx.execute(&format!("DELETE FROM \"{table}\""), [])?;
+        }
+        for (table, rows) in tables {
+            let Some((_, columns)) = BACKUP_TABLES.iter().find(|(name, _)| name == table) else {
+                continue;
+            };
+            // Backups made before a NOT NULL column was added restore with
+            // its default
+            let placeholders = columns
+                .iter()
+                .enumerate()
+                .map(|(i, column)| {
+                    let default: Option<String> = tx
+                        .query_row(
+                            "SELECT dflt_value FROM pragma_table_info(?1) \
+                             WHERE name = ?2 AND \"notnull\" = 1",
+                            params![table, column],
+                            |row| row.get(0),
+                        )
+                        .optional()?
+                        .flatten();
+                    Ok(match default {
+                        Some(default) => format!("COALESCE(?{}, {default})", i + 1),
+                        None => format!("?{}", i + 1),
+                    })
+                })
+                .collect::<Result<Vec<_>, DbError>>()?;
+            let mut stmt = tx.prepare(&format!(
+                "INSERT INTO \"{table}\" ({}) VALUES ({})",
+                columns.join(", "),
+                placeholders.join(", ")
+            ))?;
+            for row in rows {
+                stmt.execute(params_from_iter(row))?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Check whether a table has a column.
+    fn has_column(&self, table: &str, column: &str) -> Result<bool, DbError> {
+        Ok(column_exists(&self.conn, table, column)?)
+    }
+
+    /// Check whether a table exists.
+    fn has_table(&self, name: &str) -> Result<bool, DbError> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            params![name],
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    /// Query which of the given packages are in the queue.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn query(&self, packages: &[&str]) -> Result<Vec<String>, DbError> {
+        if packages.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Build a query with placeholders for each package
+        let placeholders: Vec<_> = packages.iter().map(|_| "?").collect();
+        let sql = format!(
+            "SELECT package FROM queue WHERE package IN ({}) ORDER BY package",
+            placeholders.join(", ")
+        );
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let params: Vec<&dyn rusqlite::ToSql> =
+            packages.iter().map(|p| p as &dyn rusqlite::ToSql).collect();
+
+        let found = stmt
+            .query_map(params.as_slice(), |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+
+        Ok(found)
+    }
+
+    /// Clear the entire rebuild queue, except pinned packages unless
+    /// `include_pinned` is set.
+    ///
+    /// Does not clear trigger event history. The cleared entries (with their
+    /// tags) are saved first, replacing the previous snapshot, so
+    /// [`undo_clear`] can bring them back.
+    ///
+    /// [`undo_clear`]: Self::undo_clear
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn clear(&mut self, include_pinned: bool) -> Result<usize, DbError> {
+        let now = now_iso8601();
+        let tx = self.conn.transaction()?;
+
+        tx.execute("DELETE FROM cleared_queues", [])?;
+        tx.execute(
+            "INSERT INTO cleared_queues
+                 (package, first_marked_at, tags, reason, pinned, priority, expires_at,
+                  marked_version, cleared_at)
+             SELECT package, first_marked_at,
+                    (SELECT GROUP_CONCAT(tag, char(10)) FROM queue_tags t
+                     WHERE t.package = queue.package),
+                    reason, pinned, priority, expires_at, marked_version, ?1
+             FROM queue WHERE pinned = 0 OR ?2",
+            params![now, include_pinned],
+        )?;
+
+        tx.execute(
+            "INSERT INTO queue_events (package, action, at, origin)
+             SELECT package, ?1, ?2, ?3 FROM queue WHERE pinned = 0 OR ?4",
+            params![
+                QueueAction::Cleared.as_str(),
+                now,
+                Origin::Cli.as_str(),
+                include_pinned
+            ],
+        )?;
+        let count = tx.execute(
+            "DELETE FROM queue WHERE pinned = 0 OR ?1",
+            params![include_pinned],
+        )?;
+        tx.execute(
+            "DELETE FROM queue_tags WHERE package NOT IN (SELECT package FROM queue)",
+            [],
+        )?;
+
+        tx.commit()?;
+        Ok(count)
+    }
+
+    /// Put back the queue saved by the last [`clear`](Self::clear).
+    ///
+    /// Packages marked again since keep the earlier mark time, and the
+    /// snapshot is dropped so it can only be restored once.
+    ///
+    /// Returns the restored packages (sorted), or `None` if there's no
+    /// snapshot.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn undo_clear(&mut self) -> Result<Option<Vec<String>>, DbError> {
+        let now = now_iso8601();
+        let tx = self.conn.transaction()?;
+
+        let snapshot = {
+            let mut stmt = tx.prepare(
+                "SELECT package, first_marked_at, tags, reason, pinned, priority, expires_at,
+                        marked_version
+                 FROM cleared_queues ORDER BY package",
+            )?;
+            stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, bool>(4)?,
+                    row.get::<_, i64>(5)?,
+                    row.get::<_, Option<String>>(6)?,
+                    row.get::<_, Option<String>>(7)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?
+        };
+        if snapshot.is_empty() {
+            return Ok(None);
+        }
+
+        for (
+            package,
+            first_marked_at,
+            tags,
+            reason,
+            pinned,
+            priority,
+            expires_at,
+            marked_version,
+        ) in &snapshot
+        {
+            tx.execute(
+                "INSERT INTO queue
+                     (package, first_marked_at, reason, pinned, priority, expires_at,
+                      marked_version)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                 ON CONFLICT(package) DO UPDATE
+                 SET first_marked_at = MIN(first_marked_at, excluded.first_marked_at),
+                     reason = COALESCE(reason, excluded.reason),
+                     pinned = MAX(pinned, excluded.pinned),
+                     priority = MAX(priority, excluded.priority),
+                     expires_at = MAX(expires_at, excluded.expires_at),
+                     marked_version = COALESCE(marked_version, excluded.marked_version)",
+                params![
+                    package,
+                    first_marked_at,
+                    reason,
+                    pinned,
+                    priority,
+                    expires_at,
+                    marked_version
+                ],
+            )?;
+            for tag in tags.iter().flat_map(|tags| tags.split('\n')) {
+                tx.execute(
+                    "INSERT OR IGNORE INTO queue_tags (package, tag) VALUES (?1, ?2)",
+                    params![package, tag],
+                )?;
+            }
+            tx.execute(
+                "INSERT INTO queue_events (package, action, at, origin) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    package,
+                    QueueAction::Restored.as_str(),
+                    now,
+                    Origin::Cli.as_str()
+                ],
+            )?;
+        }
+        tx.execute("DELETE FROM cleared_queues", [])?;
+
+        tx.commit()?;
+        Ok(Some(
+            snapshot.into_iter().map(|(package, ..)| package).collect(),
+        ))
+    }
+
+    /// Remove all queued packages carrying a tag, except pinned packages
+    /// unless `include_pinned` is set.
+    ///
+    /// Returns the removed package names.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn clear_tag(&mut self, tag: &str, include_pinned: bool) -> Result<Vec<String>, DbError> {
+        let mut packages = self.tagged_packages(tag)?;
+        if !include_pinned {
+            let pinned = self.pinned_packages()?;
+            packages.retain(|pkg| !pinned.contains(pkg));
+        }
+        for package in &packages {
+            self.dequeue(package, QueueAction::Cleared, Origin::Cli)?;
+        }
+        Ok(packages)
+    }
+
+    /// Get the pinned packages in the queue, sorted by name.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn pinned_packages(&self) -> Result<Vec<String>, DbError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT package FROM queue WHERE pinned != 0 ORDER BY package")?;
+        let packages = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(packages)
+    }
+
+    /// Clear trigger events for a specific trigger package.
+    ///
+    /// If a package in the queue has no remaining triggers after this operation,
+    /// it is removed from the queue entirely, unless it's pinned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn clear_trigger_events(&mut self, trigger_package: &str) -> Result<usize, DbError> {
+        let tx = self.conn.transaction()?;
+
+        // Delete the matching trigger events
+        let count = tx.execute(
+            "DELETE FROM trigger_events WHERE trigger_package = ?1",
+            params![trigger_package],
         )?;
 
         // Remove packages from queue that no longer have ANY trigger events
         tx.execute(
-            "DELETE FROM queue WHERE package NOT IN (SELECT DISTINCT package FROM trigger_events)",
+            "INSERT INTO queue_events (package, action, at, origin)
+             SELECT package, ?1, ?2, ?3 FROM queue
+             WHERE pinned = 0
+               AND package NOT IN (SELECT DISTINCT package FROM trigger_events)",
+            params![
+                QueueAction::Cleared.as_str(),
+                now_iso8601(),
+                Origin::Cli.as_str()
+            ],
+        )?;
+        tx.execute(
+            "DELETE FROM queue
+             WHERE pinned = 0
+               AND package NOT IN (SELECT DISTINCT package FROM trigger_events)",
+            [],
+        )?;
+        tx.execute(
+            "DELETE FROM queue_tags WHERE package NOT IN (SELECT package FROM queue)",
             [],
         )?;
 
-        tx.commit()?;
-        Ok(count)
+        tx.commit()?;
+        Ok(count)
+    }
+
+    /// Get trigger events for a package.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn get_events(&self, package: &str) -> Result<Vec<TriggerEvent>, DbError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, package, trigger_package, trigger_version, marked_at
+             FROM trigger_events WHERE package = ?1 ORDER BY marked_at DESC, id DESC",
+        )?;
+
+        let events = stmt
+            .query_map(params![package], |row| {
+                Ok(TriggerEvent {
+                    id: row.get(0)?,
+                    package: row.get(1)?,
+                    trigger_package: row.get(2)?,
+                    trigger_version: row.get(3)?,
+                    marked_at: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(events)
     }
 
-    /// Get trigger events for a package.
+    /// Get the most recent trigger event for a package.
     ///
     /// # Errors
     ///
     /// Returns an error if the database query fails.
-    pub fn get_events(&self, package: &str) -> Result<Vec<TriggerEvent>, DbError> {
+    pub fn get_latest_event(&self, package: &str) -> Result<Option<TriggerEvent>, DbError> {
         let mut stmt = self.conn.prepare(
             "SELECT id, package, trigger_package, trigger_version, marked_at
-             FROM trigger_events WHERE package = ?1 ORDER BY marked_at DESC",
+             FROM trigger_events WHERE package = ?1 ORDER BY marked_at DESC LIMIT 1",
         )?;
 
-        let events = stmt
-            .query_map(params![package], |row| {
+        let event = stmt
+            .query_row(params![package], |row| {
                 Ok(TriggerEvent {
                     id: row.get(0)?,
                     package: row.get(1)?,
@@ -361,175 +2089,917 @@ impl Database {
                     trigger_version: row.get(3)?,
                     marked_at: row.get(4)?,
                 })
+            })
+            .optional()?;
+
+        Ok(event)
+    }
+
+    /// Get queue events for a package, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn get_queue_events(&self, package: &str) -> Result<Vec<QueueEvent>, DbError> {
+        // Read-only connections to an older database lack the table, or
+        // its origin column
+        if !self.has_table("queue_events")? {
+            return Ok(Vec::new());
+        }
+        let origin = if self.has_column("queue_events", "origin")? {
+            "origin"
+        } else {
+            "NULL"
+        };
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id, package, action, detail, at, {origin}
+             FROM queue_events WHERE package = ?1 ORDER BY at, id"
+        ))?;
+
+        let rows = stmt
+            .query_map(params![package], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, Option<String>>(5)?,
+                ))
             })?
             .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(events)
+        // Skip actions written by a newer version
+        let events = rows
+            .into_iter()
+            .filter_map(|(id, package, action, detail, at, origin)| {
+                Some(QueueEvent {
+                    id,
+                    package,
+                    action: QueueAction::from_db(&action)?,
+                    detail,
+                    origin: origin.as_deref().and_then(Origin::from_db),
+                    at,
+                })
+            })
+            .collect();
+
+        Ok(events)
+    }
+
+    /// Summarize the queue and the history recorded since `since` (ISO8601).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn stats(&self, since: &str) -> Result<Stats, DbError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT latest_trigger, COUNT(*) AS n FROM (
+                 SELECT (SELECT trigger_package FROM trigger_events e
+                         WHERE e.package = queue.package
+                         ORDER BY marked_at DESC, id DESC LIMIT 1) AS latest_trigger
+                 FROM queue
+             )
+             GROUP BY latest_trigger
+             ORDER BY n DESC, latest_trigger IS NULL, latest_trigger",
+        )?;
+        let queued_by_trigger = stmt
+            .query_map([], |row| Ok((row.get(0)?, count(row.get(1)?))))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut stmt = self.conn.prepare(
+            "SELECT trigger_package, COUNT(*) AS n FROM trigger_events
+             WHERE trigger_package IS NOT NULL AND marked_at >= ?1
+             GROUP BY trigger_package
+             ORDER BY n DESC, trigger_package",
+        )?;
+        let busiest_triggers = stmt
+            .query_map(params![since], |row| Ok((row.get(0)?, count(row.get(1)?))))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let external_marks: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM trigger_events
+             WHERE trigger_package IS NULL AND marked_at >= ?1",
+            params![since],
+            |row| row.get(0),
+        )?;
+
+        // A stay starts at the first mark after the package last left the
+        // queue; stays whose marks were pruned are left out. Read-only
+        // connections to an older database have no queue events yet.
+        let (dequeued, average_wait): (i64, Option<f64>) = if !self.has_table("queue_events")? {
+            (0, None)
+        } else {
+            let dequeues = QueueAction::DEQUEUES
+                .iter()
+                .map(|action| format!("'{}'", action.as_str()))
+                .collect::<Vec<_>>()
+                .join(", ");
+            self.conn.query_row(
+                &format!(
+                    "SELECT COUNT(start), AVG((julianday(at) - julianday(start)) * 86400) FROM (
+                         SELECT q.at,
+                             (SELECT MIN(e.marked_at) FROM trigger_events e
+                              WHERE e.package = q.package AND e.marked_at <= q.at
+                                AND e.marked_at > COALESCE(
+                                    (SELECT MAX(p.at) FROM queue_events p
+                                     WHERE p.package = q.package AND p.action IN ({dequeues})
+                                       AND p.at < q.at),
+                                    '')) AS start
+                         FROM queue_events q
+                         WHERE q.action IN ({dequeues}) AND q.at >= ?1
+                     )"
+                ),
+                params![since],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?
+        };
+
+        Ok(Stats {
+            queued: self.count()?,
+            queued_by_trigger,
+            busiest_triggers,
+            external_marks: count(external_marks),
+            dequeued: count(dequeued),
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            average_wait_secs: average_wait.map(|secs| secs.max(0.0).round() as u64),
+        })
+    }
+
+    /// List queued packages whose expiry time has passed.
+    ///
+    /// They are removed the next time the database is opened for writing.
+    /// Pinned entries don't expire.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn expired_packages(&self) -> Result<Vec<String>, DbError> {
+        if !self.has_column("queue", "expires_at")? {
+            return Ok(Vec::new());
+        }
+        let mut stmt = self.conn.prepare(
+            "SELECT package FROM queue
+             WHERE expires_at <= ?1 AND pinned = 0 ORDER BY package",
+        )?;
+        let packages = stmt
+            .query_map(params![now_iso8601()], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(packages)
+    }
+
+    /// Count the trigger events, queue events and rebuild records older than
+    /// a cutoff (see [`prune_old_events`](Self::prune_old_events)).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn count_events_before(&self, cutoff: &str) -> Result<usize, DbError> {
+        // Read-only connections to an older database lack the newer tables
+        let mut counts = vec!["(SELECT COUNT(*) FROM trigger_events WHERE marked_at < ?1)"];
+        if self.has_table("queue_events")? {
+            counts.push("(SELECT COUNT(*) FROM queue_events WHERE at < ?1)");
+        }
+        if self.has_table("rebuilds")? {
+            counts.push("(SELECT COUNT(*) FROM rebuilds WHERE finished_at < ?1)");
+        }
+        let total: i64 = self.conn.query_row(
+            &format!("SELECT {}", counts.join(" + ")),
+            params![cutoff],
+            |row| row.get(0),
+        )?;
+        Ok(count(total))
+    }
+
+    /// Drop queue entries whose expiry time has passed, recording why.
+    ///
+    /// Pinned entries don't expire.
+    fn prune_expired(&mut self) -> Result<usize, DbError> {
+        let now = now_iso8601();
+        let tx = self.conn.transaction()?;
+
+        tx.execute(
+            "INSERT INTO queue_events (package, action, at)
+             SELECT package, ?1, ?2 FROM queue
+             WHERE expires_at <= ?2 AND pinned = 0",
+            params![QueueAction::Expired.as_str(), now],
+        )?;
+        let count = tx.execute(
+            "DELETE FROM queue WHERE expires_at <= ?1 AND pinned = 0",
+            params![now],
+        )?;
+        if count > 0 {
+            tx.execute(
+                "DELETE FROM queue_tags WHERE package NOT IN (SELECT package FROM queue)",
+                [],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(count)
+    }
+
+    /// Prune trigger events, queue events and rebuild records older than the
+    /// retention period.
+    ///
+    /// Also done after every mark. Returns the number of rows removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn prune_old_events(&mut self) -> Result<usize, DbError> {
+        if self.retention_days == 0 {
+            return Ok(0);
+        }
+
+        let cutoff = cutoff_date(self.retention_days);
+        let count = self.conn.execute(
+            "DELETE FROM trigger_events WHERE marked_at < ?1",
+            params![cutoff],
+        )?;
+        let count = count
+            + self
+                .conn
+                .execute("DELETE FROM queue_events WHERE at < ?1", params![cutoff])?
+            + self.conn.execute(
+                "DELETE FROM rebuilds WHERE finished_at < ?1",
+                params![cutoff],
+            )?;
+        Ok(count)
+    }
+}
+
+/// Convert an SQL count to a `usize`.
+fn count(n: i64) -> usize {
+    usize::try_from(n).unwrap_or(0)
+}
+
+#[cfg(test)]
+#[allow(clippy::expect_used)]
+mod tests {
+    use super::*;
+
+    fn temp_db() -> (tempfile::TempDir, Database) {
+        let dir = tempfile::tempdir().expect("create temp dir");
+        let path = dir.path().join("test.db");
+        let db = Database::open_at(&path, 90).expect("open db");
+        (dir, db)
+    }
+
+    #[test]
+    fn mark_and_list() {
+        let (_dir, mut db) = temp_db();
+
+        assert!(db.mark("pkg1", None, None).expect("mark"));
+        assert!(
+            db.mark("pkg2", Some("qt6-base"), Some("6.7.0"))
+                .expect("mark")
+        );
+
+        let queue = db.list().expect("list");
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue[0].package, "pkg1");
+        assert_eq!(queue[1].package, "pkg2");
+    }
+
+    #[test]
+    fn list_by_priority_then_age() {
+        let (_dir, mut db) = temp_db();
+
+        for pkg in ["old", "middle", "new"] {
+            db.mark(pkg, None, None).expect("mark");
+        }
+        assert!(db.set_priority("new", Priority::High).expect("priority"));
+        assert!(db.set_priority("old", Priority::Low).expect("priority"));
+        assert!(
+            !db.set_priority("missing", Priority::High)
+                .expect("priority")
+        );
+
+        let names = |queue: Vec<QueueEntry>| -> Vec<String> {
+            queue.into_iter().map(|entry| entry.package).collect()
+        };
+        assert_eq!(
+            names(db.list().expect("list")),
+            vec!["new", "middle", "old"]
+        );
+        assert_eq!(
+            names(db.list_sorted(SortOrder::Age, true).expect("list")),
+            vec!["old", "middle", "new"]
+        );
+        assert_eq!(db.list().expect("list")[0].priority, Priority::High);
+
+        // Name order ignores priorities
+        assert_eq!(
+            names(db.list_sorted(SortOrder::Name, false).expect("list")),
+            vec!["middle", "new", "old"]
+        );
+    }
+
+    #[test]
+    fn list_sorted_by_name() {
+        let (_dir, mut db) = temp_db();
+
+        db.mark("zeta", None, None).expect("mark");
+        db.mark("alpha", None, None).expect("mark");
+        db.mark("mid", None, None).expect("mark");
+
+        let names = |queue: Vec<QueueEntry>| -> Vec<String> {
+            queue.into_iter().map(|e| e.package).collect()
+        };
+
+        let queue = db.list_sorted(SortOrder::Name, false).expect("list");
+        assert_eq!(names(queue), vec!["alpha", "mid", "zeta"]);
+
+        let queue = db.list_sorted(SortOrder::Name, true).expect("list");
+        assert_eq!(names(queue), vec!["zeta", "mid", "alpha"]);
+    }
+
+    #[test]
+    fn list_sorted_by_trigger() {
+        let (_dir, mut db) = temp_db();
+
+        db.mark("pkg1", None, None).expect("mark");
+        db.mark("pkg2", Some("qt6-base"), None).expect("mark");
+        db.mark("pkg3", Some("gtk4"), None).expect("mark");
+        // Latest trigger wins
+        db.mark("pkg4", Some("qt6-base"), None).expect("mark");
+        db.mark("pkg4", Some("boost"), None).expect("mark");
+
+        let queue = db.list_sorted(SortOrder::Trigger, false).expect("list");
+        let names: Vec<&str> = queue.iter().map(|e| e.package.as_str()).collect();
+        // External marks sort last
+        assert_eq!(names, vec!["pkg4", "pkg3", "pkg2", "pkg1"]);
+    }
+
+    #[test]
+    fn list_filtered() {
+        let (_dir, mut db) = temp_db();
+
+        db.mark("old", Some("qt6-base"), None).expect("mark");
+        db.mark("new", Some("gtk4"), None).expect("mark");
+        db.mark("new", Some("qt6-base"), None).expect("mark");
+        db.conn
+            .execute(
+                "UPDATE queue SET first_marked_at = '2026-01-01T00:00:00.000Z' WHERE package = 'old'",
+                [],
+            )
+            .expect("backdate");
+
+        let names = |filter: ListFilter| -> Vec<String> {
+            db.list_filtered(SortOrder::Name, false, &filter)
+                .expect("list")
+                .into_iter()
+                .map(|e| e.package)
+                .collect()
+        };
+
+        assert_eq!(
+            names(ListFilter {
+                trigger: Some("qt6-base".into()),
+                ..ListFilter::default()
+            }),
+            vec!["new", "old"]
+        );
+        assert_eq!(
+            names(ListFilter {
+                trigger: Some("gtk4".into()),
+                ..ListFilter::default()
+            }),
+            vec!["new"]
+        );
+        assert_eq!(
+            names(ListFilter {
+                since: Some("2026-02-01T00:00:00.000Z".into()),
+                ..ListFilter::default()
+            }),
+            vec!["new"]
+        );
+        assert_eq!(
+            names(ListFilter {
+                trigger: Some("qt6-base".into()),
+                before: Some("2026-02-01T00:00:00.000Z".into()),
+                ..ListFilter::default()
+            }),
+            vec!["old"]
+        );
+        assert!(
+            names(ListFilter {
+                trigger: Some("boost".into()),
+                ..ListFilter::default()
+            })
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn list_sorted_default_is_age() {
+        let (_dir, mut db) = temp_db();
+
+        db.mark("b", None, None).expect("mark");
+        db.mark("a", None, None).expect("mark");
+
+        assert_eq!(
+            db.list().expect("list"),
+            db.list_sorted(SortOrder::default(), false).expect("list")
+        );
+    }
+
+    #[test]
+    fn writable_database_in_temp_dir() {
+        let (dir, _db) = temp_db();
+        assert!(is_writable(&dir.path().join("test.db")));
+        // Not created yet, but the directory is writable
+        assert!(is_writable(&dir.path().join("sub/new.db")));
+    }
+
+    #[test]
+    fn mark_idempotent() {
+        let (_dir, mut db) = temp_db();
+
+        assert!(db.mark("pkg1", None, None).expect("first mark"));
+        assert!(!db.mark("pkg1", None, None).expect("second mark"));
+
+        let queue = db.list().expect("list");
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[test]
+    fn mark_creates_event_even_when_already_marked() {
+        let (_dir, mut db) = temp_db();
+
+        db.mark("pkg1", Some("trigger1"), None).expect("first mark");
+        db.mark("pkg1", Some("trigger2"), None)
+            .expect("second mark");
+
+        let events = db.get_events("pkg1").expect("events");
+        assert_eq!(events.len(), 2);
+    }
+
+    #[test]
+    fn mark_all_in_one_transaction() {
+        let (_dir, mut db) = temp_db();
+
+        db.mark("pkg1", None, None).expect("mark");
+        let added = db
+            .mark_all(&["pkg1", "pkg2", "pkg3"], Some("glibc"), None)
+            .expect("mark_all");
+        assert_eq!(added, 2);
+        assert_eq!(db.list().expect("list").len(), 3);
+        assert_eq!(db.get_events("pkg1").expect("events").len(), 2);
+    }
+
+    #[test]
+    fn unmark() {
+        let (_dir, mut db) = temp_db();
+
+        db.mark("pkg1", None, None).expect("mark");
+        assert!(db.is_marked("pkg1").expect("is_marked"));
+
+        assert!(db.unmark("pkg1", Origin::Cli).expect("unmark"));
+        assert!(!db.is_marked("pkg1").expect("is_marked"));
+
+        // Unmark non-existent returns false
+        assert!(!db.unmark("pkg1", Origin::Cli).expect("unmark again"));
+    }
+
+    #[test]
+    fn rename_moves_entry_and_events() {
+        let (_dir, mut db) = temp_db();
+
+        db.mark("old-pkg", Some("qt6-base"), Some("6.7.0"))
+            .expect("mark");
+        assert!(
+            db.rename("old-pkg", "new-pkg", Origin::Hook)
+                .expect("rename")
+        );
+
+        assert!(!db.is_marked("old-pkg").expect("is_marked"));
+        assert!(db.is_marked("new-pkg").expect("is_marked"));
+        assert!(db.get_events("old-pkg").expect("events").is_empty());
+
+        let events = db.get_events("new-pkg").expect("events");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].trigger_package, Some("qt6-base".into()));
+    }
+
+    #[test]
+    fn rename_merges_into_existing_entry() {
+        let (_dir, mut db) = temp_db();
+
+        db.mark("old-pkg", Some("trigger1"), None).expect("mark");
+        let first_marked = db.list().expect("list")[0].first_marked_at.clone();
+        db.mark("new-pkg", Some("trigger2"), None).expect("mark");
+
+        assert!(
+            db.rename("old-pkg", "new-pkg", Origin::Hook)
+                .expect("rename")
+        );
+
+        let queue = db.list().expect("list");
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue[0].package, "new-pkg");
+        assert_eq!(queue[0].first_marked_at, first_marked);
+        assert_eq!(db.get_events("new-pkg").expect("events").len(), 2);
+    }
+
+    #[test]
+    fn rename_not_queued() {
+        let (_dir, mut db) = temp_db();
+        assert!(
+            !db.rename("old-pkg", "new-pkg", Origin::Hook)
+                .expect("rename")
+        );
+        assert!(!db.is_marked("new-pkg").expect("is_marked"));
     }
 
-    /// Get the most recent trigger event for a package.
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if the database query fails.
-    pub fn get_latest_event(&self, package: &str) -> Result<Option<TriggerEvent>, DbError> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, package, trigger_package, trigger_version, marked_at
-             FROM trigger_events WHERE package = ?1 ORDER BY marked_at DESC LIMIT 1",
-        )?;
+    #[test]
+    fn queue_events_recorded() {
+        let (_dir, mut db) = temp_db();
 
-        let event = stmt
-            .query_row(params![package], |row| {
-                Ok(TriggerEvent {
-                    id: row.get(0)?,
-                    package: row.get(1)?,
-                    trigger_package: row.get(2)?,
-                    trigger_version: row.get(3)?,
-                    marked_at: row.get(4)?,
-                })
-            })
-            .optional()?;
+        db.mark("pkg1", None, None).expect("mark");
+        db.record_rebuild_failure("pkg1", 1)
+            .expect("record failure");
+        assert!(db.is_marked("pkg1").expect("is_marked"));
+        assert!(db.complete_rebuild("pkg1").expect("complete"));
+        db.mark("pkg1", None, None).expect("mark");
+        assert!(db.unmark("pkg1", Origin::Cli).expect("unmark"));
+        // Not queued, nothing to record
+        assert!(!db.unmark("pkg1", Origin::Cli).expect("unmark"));
+
+        let actions: Vec<(QueueAction, Option<String>, Option<Origin>)> = db
+            .get_queue_events("pkg1")
+            .expect("queue events")
+            .into_iter()
+            .map(|e| (e.action, e.detail, e.origin))
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                (
+                    QueueAction::RebuildFailed,
+                    Some("1".into()),
+                    Some(Origin::Rebuild)
+                ),
+                (QueueAction::Rebuilt, None, Some(Origin::Rebuild)),
+                (QueueAction::Unmarked, None, Some(Origin::Cli)),
+            ]
+        );
+    }
 
-        Ok(event)
+    #[test]
+    fn migrations() {
+        let dir = tempfile::tempdir().expect("create temp dir");
+        let path = dir.path().join("test.db");
+
+        let db = Database::open_at(&path, 90).expect("open db");
+        assert_eq!(db.schema_version().expect("version"), SCHEMA_VERSION);
+        drop(db);
+
+        // Reopening doesn't run migrations again
+        let db = Database::open_at(&path, 90).expect("reopen db");
+        assert_eq!(db.schema_version().expect("version"), SCHEMA_VERSION);
+        db.conn
+            .pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+            .expect("set version");
+        drop(db);
+
+        assert!(matches!(
+            Database::open_at(&path, 90),
+            Err(DbError::SchemaTooNew(v)) if v == SCHEMA_VERSION + 1
+        ));
     }
 
-    /// Prune trigger events older than retention period.
-    fn prune_old_events(&mut self) -> Result<usize, DbError> {
-        if self.retention_days == 0 {
-            return Ok(0);
+    #[test]
+    fn origin_column_added_to_old_database() {
+        let dir = tempfile::tempdir().expect("create temp dir");
+        let path = dir.path().join("test.db");
+        {
+            let conn = Connection::open(&path).expect("open");
+            conn.execute_batch(
+                "CREATE TABLE queue_events (
+                     id INTEGER PRIMARY KEY,
+                     package TEXT NOT NULL,
+                     action TEXT NOT NULL,
+                     detail TEXT,
+                     at TEXT NOT NULL
+                 );
+                 INSERT INTO queue_events (package, action, at)
+                 VALUES ('pkg1', 'unmarked', '2026-01-10T10:00:00.000Z');",
+            )
+            .expect("create old schema");
         }
 
-        let cutoff = cutoff_date(self.retention_days);
-        let count = self.conn.execute(
-            "DELETE FROM trigger_events WHERE marked_at < ?1",
-            params![cutoff],
-        )?;
-        Ok(count)
-    }
-}
+        // Read-only access leaves the schema alone
+        let db = Database::open_readonly(&path).expect("open readonly");
+        assert_eq!(db.get_queue_events("pkg1").expect("events")[0].origin, None);
+        drop(db);
 
-/// Get current time as ISO8601 string with millisecond precision.
-fn now_iso8601() -> String {
-    use std::time::{SystemTime, UNIX_EPOCH};
+        // No retention, so the old event isn't pruned
+        let mut db = Database::open_at(&path, 0).expect("open db");
+        assert_eq!(db.schema_version().expect("version"), SCHEMA_VERSION);
+        db.mark("pkg1", None, None).expect("mark");
+        db.unmark("pkg1", Origin::Hook).expect("unmark");
+        let origins: Vec<Option<Origin>> = db
+            .get_queue_events("pkg1")
+            .expect("events")
+            .into_iter()
+            .map(|e| e.origin)
+            .collect();
+        assert_eq!(origins, vec![None, Some(Origin::Hook)]);
+    }
 
-    let duration = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default();
+    #[test]
+    fn clear_records_queue_events() {
+        let (_dir, mut db) = temp_db();
+        db.mark("pkg1", None, None).expect("mark");
+        db.mark("pkg2", Some("qt6-base"), None).expect("mark");
 
-    let secs = duration.as_secs();
-    let millis = duration.subsec_millis();
+        db.clear(false).expect("clear");
 
-    // Convert to date components (simplified - doesn't handle leap seconds)
-    let days = secs / 86400;
-    let time_secs = secs % 86400;
-    let hours = time_secs / 3600;
-    let minutes = (time_secs % 3600) / 60;
-    let seconds = time_secs % 60;
+        for pkg in ["pkg1", "pkg2"] {
+            let events = db.get_queue_events(pkg).expect("queue events");
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0].action, QueueAction::Cleared);
+        }
+    }
 
-    // Calculate date from days since epoch (1970-01-01)
-    let (year, month, day) = days_to_date(days);
+    #[test]
+    fn rename_records_queue_event() {
+        let (_dir, mut db) = temp_db();
+        db.mark("old-pkg", None, None).expect("mark");
+        db.record_rebuild_failure("old-pkg", 1)
+            .expect("record failure");
 
-    format!("{year:04}-{month:02}-{day:02}T{hours:02}:{minutes:02}:{seconds:02}.{millis:03}Z")
-}
+        db.rename("old-pkg", "new-pkg", Origin::Hook)
+            .expect("rename");
 
-/// Calculate cutoff date for retention period.
-fn cutoff_date(retention_days: u32) -> String {
-    use std::time::{SystemTime, UNIX_EPOCH};
+        let events = db.get_queue_events("new-pkg").expect("queue events");
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].action, QueueAction::Renamed);
+        assert_eq!(events[1].detail, Some("old-pkg".into()));
+        assert!(
+            db.get_queue_events("old-pkg")
+                .expect("queue events")
+                .is_empty()
+        );
+    }
 
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default();
+    #[test]
+    fn tags() {
+        let (_dir, mut db) = temp_db();
+        db.mark("pkg1", None, None).expect("mark");
+        db.mark("pkg2", None, None).expect("mark");
+        db.add_tags("pkg1", &["gaming", "qt"]).expect("tag");
+        db.add_tags("pkg1", &["gaming"]).expect("tag again");
+        db.add_tags("pkg2", &["qt"]).expect("tag");
+
+        assert_eq!(db.get_tags("pkg1").expect("tags"), vec!["gaming", "qt"]);
+        assert_eq!(
+            db.tagged_packages("qt").expect("tagged"),
+            vec!["pkg1", "pkg2"]
+        );
+        assert_eq!(db.tagged_packages("gaming").expect("tagged"), vec!["pkg1"]);
+    }
 
-    let cutoff_secs = now
-        .as_secs()
-        .saturating_sub(u64::from(retention_days) * 86400);
+    #[test]
+    fn tags_dropped_with_entry() {
+        let (_dir, mut db) = temp_db();
+        db.mark("pkg1", None, None).expect("mark");
+        db.add_tags("pkg1", &["gaming"]).expect("tag");
+        db.unmark("pkg1", Origin::Cli).expect("unmark");
 
-    let days = cutoff_secs / 86400;
-    let (year, month, day) = days_to_date(days);
+        // Marking again doesn't bring back old tags
+        db.mark("pkg1", None, None).expect("mark");
+        assert!(db.get_tags("pkg1").expect("tags").is_empty());
+    }
 
-    format!("{year:04}-{month:02}-{day:02}T00:00:00Z")
-}
+    #[test]
+    fn clear_tag() {
+        let (_dir, mut db) = temp_db();
+        db.mark("pkg1", None, None).expect("mark");
+        db.mark("pkg2", None, None).expect("mark");
+        db.add_tags("pkg1", &["gaming"]).expect("tag");
 
-/// Convert days since Unix epoch to (year, month, day).
-fn days_to_date(days: u64) -> (i32, u32, u32) {
-    // Algorithm from https://howardhinnant.github.io/date_algorithms.html
-    let z = days as i64 + 719468;
-    let era = if z >= 0 { z } else { z - 146096 } / 146097;
-    let doe = (z - era * 146097) as u32;
-    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
-    let y = yoe as i64 + era * 400;
-    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
-    let mp = (5 * doy + 2) / 153;
-    let d = doy - (153 * mp + 2) / 5 + 1;
-    let m = if mp < 10 { mp + 3 } else { mp - 9 };
-    let y = if m <= 2 { y + 1 } else { y };
+        assert_eq!(
+            db.clear_tag("gaming", false).expect("clear tag"),
+            vec!["pkg1"]
+        );
+        assert!(!db.is_marked("pkg1").expect("is_marked"));
+        assert!(db.is_marked("pkg2").expect("is_marked"));
+    }
 
-    (y as i32, m, d)
-}
+    #[test]
+    fn rename_moves_tags() {
+        let (_dir, mut db) = temp_db();
+        db.mark("old-pkg", None, None).expect("mark");
+        db.add_tags("old-pkg", &["gaming"]).expect("tag");
 
-#[cfg(test)]
-#[allow(clippy::expect_used)]
-mod tests {
-    use super::*;
+        db.rename("old-pkg", "new-pkg", Origin::Hook)
+            .expect("rename");
 
-    fn temp_db() -> (tempfile::TempDir, Database) {
-        let dir = tempfile::tempdir().expect("create temp dir");
-        let path = dir.path().join("test.db");
-        let db = Database::open_at(&path, 90).expect("open db");
-        (dir, db)
+        assert_eq!(db.get_tags("new-pkg").expect("tags"), vec!["gaming"]);
+        assert!(db.get_tags("old-pkg").expect("tags").is_empty());
     }
 
     #[test]
-    fn mark_and_list() {
+    fn local_sources() {
         let (_dir, mut db) = temp_db();
 
-        assert!(db.mark("pkg1", None, None).expect("mark"));
+        assert!(db.add_source("my-pkg", "/home/me/src/my-pkg").expect("add"));
         assert!(
-            db.mark("pkg2", Some("qt6-base"), Some("6.7.0"))
-                .expect("mark")
+            !db.add_source("my-pkg", "/srv/pkgbuilds/my-pkg")
+                .expect("move")
         );
+        assert!(db.add_source("other", "/srv/pkgbuilds/other").expect("add"));
 
-        let queue = db.list().expect("list");
-        assert_eq!(queue.len(), 2);
-        assert_eq!(queue[0].package, "pkg1");
-        assert_eq!(queue[1].package, "pkg2");
+        assert_eq!(
+            db.get_source("my-pkg").expect("get"),
+            Some("/srv/pkgbuilds/my-pkg".into())
+        );
+        let sources = db.list_sources().expect("list");
+        assert_eq!(
+            sources
+                .iter()
+                .map(|s| s.package.as_str())
+                .collect::<Vec<_>>(),
+            vec!["my-pkg", "other"]
+        );
+
+        assert!(db.remove_source("my-pkg").expect("remove"));
+        assert!(!db.remove_source("my-pkg").expect("remove again"));
+        assert_eq!(db.get_source("my-pkg").expect("get"), None);
     }
 
     #[test]
-    fn mark_idempotent() {
+    fn rebuilds_recorded() {
         let (_dir, mut db) = temp_db();
 
-        assert!(db.mark("pkg1", None, None).expect("first mark"));
-        assert!(!db.mark("pkg1", None, None).expect("second mark"));
+        db.record_rebuild("pkg1", "paru", "2026-01-10T10:00:00Z", Some(1))
+            .expect("record");
+        db.record_rebuild("pkg1", "paru", "2026-01-11T10:00:00Z", Some(0))
+            .expect("record");
+        db.record_rebuild("pkg2", "makepkg", "2026-01-11T10:00:00Z", None)
+            .expect("record");
+
+        let records = db.get_rebuilds("pkg1").expect("rebuilds");
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].started_at, "2026-01-11T10:00:00Z");
+        assert!(records[0].succeeded());
+        assert!(!records[1].succeeded());
+        assert_eq!(records[1].helper, "paru");
+
+        let records = db.get_rebuilds("pkg2").expect("rebuilds");
+        assert_eq!(records[0].exit_code, None);
+        assert_eq!(records[0].helper, "makepkg");
+
+        assert!(db.get_rebuilds("pkg3").expect("rebuilds").is_empty());
+    }
 
-        let queue = db.list().expect("list");
-        assert_eq!(queue.len(), 1);
+    #[test]
+    fn build_durations_from_successful_builds() {
+        let (_dir, db) = temp_db();
+        let insert = |package: &str, started_at: &str, finished_at: &str, exit_code: i32| {
+            db.conn
+                .execute(
+                    "INSERT INTO rebuilds (package, started_at, finished_at, exit_code, helper)
+                     VALUES (?1, ?2, ?3, ?4, 'paru')",
+                    params![package, started_at, finished_at, exit_code],
+                )
+                .expect("insert");
+        };
+
+        // Last three successful builds of pkg1: 60s, 120s, 180s
+        insert("pkg1", "2026-01-01T10:00:00Z", "2026-01-01T11:00:00Z", 0);
+        insert("pkg1", "2026-01-02T10:00:00Z", "2026-01-02T10:01:00Z", 0);
+        insert("pkg1", "2026-01-03T10:00:00Z", "2026-01-03T10:02:00Z", 0);
+        insert("pkg1", "2026-01-04T10:00:00Z", "2026-01-04T10:03:00Z", 0);
+        insert("pkg1", "2026-01-05T10:00:00Z", "2026-01-05T12:00:00Z", 1);
+        // pkg2 and pkg3 shared a 10 minute helper run
+        insert("pkg2", "2026-01-06T10:00:00Z", "2026-01-06T10:10:00Z", 0);
+        insert("pkg3", "2026-01-06T10:00:00Z", "2026-01-06T10:10:00Z", 0);
+        // pkg4 never built
+        insert("pkg4", "2026-01-07T10:00:00Z", "2026-01-07T10:10:00Z", 2);
+
+        let durations = db.build_durations().expect("durations");
+        assert_eq!(durations.get("pkg1"), Some(&120));
+        assert_eq!(durations.get("pkg2"), Some(&300));
+        assert_eq!(durations.get("pkg3"), Some(&300));
+        assert_eq!(durations.get("pkg4"), None);
     }
 
     #[test]
-    fn mark_creates_event_even_when_already_marked() {
+    fn stats() {
         let (_dir, mut db) = temp_db();
 
-        db.mark("pkg1", Some("trigger1"), None).expect("first mark");
-        db.mark("pkg1", Some("trigger2"), None)
-            .expect("second mark");
+        db.mark("pkg1", Some("qt6-base"), None).expect("mark");
+        db.mark("pkg2", Some("qt6-base"), None).expect("mark");
+        db.mark("pkg2", Some("boost"), None).expect("mark");
+        db.mark("pkg3", None, None).expect("mark");
+        db.mark("pkg4", Some("qt6-base"), None).expect("mark");
+
+        // A finished stay of one hour, then a second one of two hours
+        db.conn
+            .execute_batch(
+                "INSERT INTO trigger_events (package, trigger_package, marked_at)
+                 VALUES ('old', 'icu', '2026-01-10T10:00:00.000Z'),
+                        ('old', 'icu', '2026-01-10T10:30:00.000Z'),
+                        ('old', 'icu', '2026-01-12T10:00:00.000Z');
+                 INSERT INTO queue_events (package, action, at)
+                 VALUES ('old', 'rebuilt', '2026-01-10T11:00:00.000Z'),
+                        ('old', 'unmarked', '2026-01-12T12:00:00.000Z');",
+            )
+            .expect("insert history");
+
+        let stats = db.stats("2026-01-11T00:00:00Z").expect("stats");
+        assert_eq!(stats.queued, 4);
+        assert_eq!(
+            stats.queued_by_trigger,
+            vec![
+                (Some("qt6-base".to_string()), 2),
+                (Some("boost".to_string()), 1),
+                (None, 1)
+            ]
+        );
+        // Events before the window don't count
+        assert_eq!(
+            stats.busiest_triggers,
+            vec![
+                ("qt6-base".to_string(), 3),
+                ("boost".to_string(), 1),
+                ("icu".to_string(), 1)
+            ]
+        );
+        assert_eq!(stats.external_marks, 1);
+        assert_eq!(stats.dequeued, 1);
+        assert_eq!(stats.average_wait_secs, Some(7200));
 
-        let events = db.get_events("pkg1").expect("events");
-        assert_eq!(events.len(), 2);
+        let stats = db.stats("").expect("stats");
+        assert_eq!(stats.dequeued, 2);
+        assert_eq!(stats.average_wait_secs, Some(5400));
     }
 
     #[test]
-    fn unmark() {
+    fn stats_count_reinstalls() {
+        let (_dir, db) = temp_db();
+
+        // A stay of one hour ended by a reinstall, then one of three hours:
+        // the second starts after the reinstall, not at the first mark
+        db.conn
+            .execute_batch(
+                "INSERT INTO trigger_events (package, trigger_package, marked_at)
+                 VALUES ('pkg', 'icu', '2026-01-10T10:00:00.000Z'),
+                        ('pkg', 'icu', '2026-01-12T10:00:00.000Z');
+                 INSERT INTO queue_events (package, action, at)
+                 VALUES ('pkg', 'reinstalled', '2026-01-10T11:00:00.000Z'),
+                        ('pkg', 'rebuild_failed', '2026-01-12T11:00:00.000Z'),
+                        ('pkg', 'uninstalled', '2026-01-12T13:00:00.000Z');",
+            )
+            .expect("insert history");
+
+        let stats = db.stats("").expect("stats");
+        assert_eq!(stats.dequeued, 2);
+        assert_eq!(stats.average_wait_secs, Some(7200));
+    }
+
+    #[test]
+    fn stats_empty() {
+        let (_dir, db) = temp_db();
+
+        let stats = db.stats("").expect("stats");
+        assert_eq!(stats, Stats::default());
+    }
+
+    #[test]
+    fn failed_rebuilds_uses_last_attempt() {
         let (_dir, mut db) = temp_db();
 
-        db.mark("pkg1", None, None).expect("mark");
-        assert!(db.is_marked("pkg1").expect("is_marked"));
+        // Failed, then succeeded
+        db.record_rebuild("pkg1", "paru", "2026-01-10T10:00:00Z", Some(1))
+            .expect("record");
+        db.record_rebuild("pkg1", "paru", "2026-01-11T10:00:00Z", Some(0))
+            .expect("record");
+        // Succeeded, then failed
+        db.record_rebuild("pkg2", "paru", "2026-01-10T10:00:00Z", Some(0))
+            .expect("record");
+        db.record_rebuild("pkg2", "paru", "2026-01-11T10:00:00Z", Some(1))
+            .expect("record");
+        // Killed
+        db.record_rebuild("pkg3", "makepkg", "2026-01-11T10:00:00Z", None)
+            .expect("record");
+
+        assert_eq!(db.failed_rebuilds().expect("failed"), vec!["pkg2", "pkg3"]);
+    }
 
-        assert!(db.unmark("pkg1").expect("unmark"));
-        assert!(!db.is_marked("pkg1").expect("is_marked"));
+    #[test]
+    fn count_queue() {
+        let (_dir, mut db) = temp_db();
 
-        // Unmark non-existent returns false
-        assert!(!db.unmark("pkg1").expect("unmark again"));
+        assert_eq!(db.count().expect("count"), 0);
+        db.mark("pkg1", None, None).expect("mark");
+        db.mark("pkg1", Some("qt6-base"), None).expect("mark");
+        db.mark("pkg2", None, None).expect("mark");
+        assert_eq!(db.count().expect("count"), 2);
     }
 
     #[test]
@@ -566,13 +3036,229 @@ mod tests {
         db.mark("pkg1", None, None).expect("mark");
         db.mark("pkg2", None, None).expect("mark");
 
-        let count = db.clear().expect("clear");
+        let count = db.clear(false).expect("clear");
         assert_eq!(count, 2);
 
         let queue = db.list().expect("list");
         assert!(queue.is_empty());
     }
 
+    #[test]
+    fn readonly_sees_uncheckpointed_wal() {
+        let (dir, mut db) = temp_db();
+        let path = dir.path().join("test.db");
+        assert!(!is_wal_file(&path));
+
+        db.conn
+            .pragma_update(None, "journal_mode", "wal")
+            .expect("enable wal");
+        db.conn
+            .pragma_update(None, "wal_autocheckpoint", 0)
+            .expect("disable checkpoints");
+        db.mark("pkg1", None, None).expect("mark");
+        assert!(is_wal_file(&path));
+        assert!(side_file(&path, "-wal").exists());
+
+        // Still open, so the mark is only in the -wal file
+        let reader = Database::open_readonly(&path).expect("open readonly");
+        assert!(reader.is_marked("pkg1").expect("is_marked"));
+    }
+
+    #[test]
+    fn new_database_group_writable_in_group_writable_dir() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let dir = tempfile::tempdir().expect("create temp dir");
+        let shared = dir.path().join("shared");
+        std::fs::create_dir(&shared).expect("create dir");
+        std::fs::set_permissions(&shared, std::fs::Permissions::from_mode(0o2775))
+            .expect("chmod dir");
+
+        for (path, mode) in [
+            (shared.join("anneal.db"), 0o664),
+            (dir.path().join("private.db"), 0o644),
+        ] {
+            Database::open_at(&path, 90).expect("open db");
+            let metadata = std::fs::metadata(&path).expect("metadata");
+            assert_eq!(metadata.mode() & 0o777, mode, "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn maintenance() {
+        let (_dir, mut db) = temp_db();
+        db.mark("pkg1", Some("qt6-base"), None).expect("mark");
+        db.mark("pkg1", Some("boost"), None).expect("mark");
+
+        let counts = db.table_counts().expect("counts");
+        assert!(counts.contains(&("queue", 1)));
+        assert!(counts.contains(&("trigger_events", 2)));
+        assert!(counts.contains(&("cleared_queues", 0)));
+
+        assert!(db.integrity_check().expect("check").is_empty());
+        db.vacuum().expect("vacuum");
+    }
+
+    #[test]
+    fn undo_clear() {
+        let (_dir, mut db) = temp_db();
+        assert_eq!(db.undo_clear().expect("undo"), None);
+
+        db.mark("pkg1", None, None).expect("mark");
+        db.mark("pkg2", None, None).expect("mark");
+        db.add_tags("pkg1", &["a", "b"]).expect("tag");
+        db.set_reason("pkg1", "icu 76").expect("reason");
+        let before = db.list().expect("list");
+
+        db.clear(false).expect("clear");
+        // Marked again after the clear: merged with the snapshot
+        db.mark("pkg2", None, None).expect("mark");
+
+        assert_eq!(
+            db.undo_clear().expect("undo"),
+            Some(vec!["pkg1".to_string(), "pkg2".to_string()])
+        );
+        assert_eq!(db.list().expect("list"), before);
+        assert_eq!(db.get_tags("pkg1").expect("tags"), vec!["a", "b"]);
+        assert_eq!(
+            db.get_queue_events("pkg1").expect("queue events")[1].action,
+            QueueAction::Restored
+        );
+
+        // The snapshot is gone once restored
+        assert_eq!(db.undo_clear().expect("undo"), None);
+    }
+
+    #[test]
+    fn mark_reason() {
+        let (_dir, mut db) = temp_db();
+
+        db.mark("pkg1", None, None).expect("mark");
+        assert_eq!(db.list().expect("list")[0].reason, None);
+        db.set_reason("pkg1", "rebuilt against icu 76")
+            .expect("reason");
+
+        // Marking again keeps the reason unless a new one is given
+        db.mark("pkg1", Some("icu"), None).expect("mark");
+        db.rename("pkg1", "pkg2", Origin::Cli).expect("rename");
+        assert_eq!(
+            db.list().expect("list")[0].reason.as_deref(),
+            Some("rebuilt against icu 76")
+        );
+
+        // Unqueued packages get no reason
+        db.set_reason("pkg1", "gone").expect("reason");
+        assert_eq!(db.list().expect("list").len(), 1);
+    }
+
+    #[test]
+    fn pinned_entries() {
+        let (_dir, mut db) = temp_db();
+
+        db.mark("pkg1", None, None).expect("mark");
+        db.mark("pkg2", None, None).expect("mark");
+        assert!(db.set_pinned("pkg1", true).expect("pin"));
+        assert!(!db.set_pinned("pkg3", true).expect("pin"));
+        assert_eq!(db.pinned_packages().expect("pinned"), vec!["pkg1"]);
+
+        // A successful rebuild leaves a pinned package queued
+        assert!(!db.complete_rebuild("pkg1").expect("rebuild"));
+        assert!(db.is_marked("pkg1").expect("is_marked"));
+
+        assert_eq!(db.clear(false).expect("clear"), 1);
+        assert_eq!(db.list().expect("list")[0].package, "pkg1");
+        db.undo_clear().expect("undo");
+
+        assert_eq!(db.clear(true).expect("clear"), 2);
+        db.undo_clear().expect("undo");
+        assert!(db.is_pinned("pkg1").expect("is_pinned"));
+        assert!(!db.is_pinned("pkg2").expect("is_pinned"));
+
+        assert!(db.set_pinned("pkg1", false).expect("unpin"));
+        assert!(db.complete_rebuild("pkg1").expect("rebuild"));
+    }
+
+    #[test]
+    fn reinstalled_packages() {
+        let (_dir, mut db) = temp_db();
+
+        db.mark("pkg1", Some("qt6-base"), None).expect("mark");
+        db.mark("pkg2", None, None).expect("mark");
+        db.set_pinned("pkg2", true).expect("pin");
+
+        assert!(
+            db.complete_reinstall("pkg1", Origin::Hook)
+                .expect("reinstall")
+        );
+        assert!(
+            !db.complete_reinstall("pkg2", Origin::Hook)
+                .expect("reinstall")
+        );
+        assert!(
+            !db.complete_reinstall("pkg3", Origin::Hook)
+                .expect("reinstall")
+        );
+        assert!(!db.is_marked("pkg1").expect("is_marked"));
+        assert!(db.is_marked("pkg2").expect("is_marked"));
+
+        let events = db.get_queue_events("pkg1").expect("queue events");
+        assert_eq!(events[0].action, QueueAction::Reinstalled);
+        assert_eq!(events[0].origin, Some(Origin::Hook));
+
+        db.mark("pkg1", None, None).expect("mark");
+        assert!(
+            db.complete_uninstall("pkg1", Origin::Hook)
+                .expect("uninstall")
+        );
+        assert!(
+            !db.complete_uninstall("pkg2", Origin::Hook)
+                .expect("uninstall")
+        );
+        assert_eq!(
+            db.get_queue_events("pkg1").expect("queue events")[1].action,
+            QueueAction::Uninstalled
+        );
+    }
+
+    #[test]
+    fn expired_entries_are_pruned() {
+        let (dir, mut db) = temp_db();
+        let past = "2020-01-01T00:00:00.000Z";
+
+        for pkg in ["expired", "later", "pinned", "remarked"] {
+            db.mark(pkg, None, None).expect("mark");
+        }
+        db.add_tags("expired", &["a"]).expect("tag");
+        assert!(db.set_expiry("expired", past).expect("expire"));
+        assert!(
+            db.set_expiry("later", "2999-01-01T00:00:00.000Z")
+                .expect("expire")
+        );
+        assert!(db.set_expiry("pinned", past).expect("expire"));
+        db.set_pinned("pinned", true).expect("pin");
+        assert!(db.set_expiry("remarked", past).expect("expire"));
+        // Marking again drops the expiry
+        db.mark("remarked", None, None).expect("mark");
+        assert!(!db.set_expiry("missing", past).expect("expire"));
+        assert_eq!(db.expired_packages().expect("expired"), vec!["expired"]);
+        drop(db);
+
+        // Pruned when the database is next opened for writing
+        let db = Database::open_at(&dir.path().join("test.db"), 90).expect("open");
+        let queued: Vec<String> = db
+            .list()
+            .expect("list")
+            .into_iter()
+            .map(|e| e.package)
+            .collect();
+        assert_eq!(queued, vec!["later", "pinned", "remarked"]);
+        assert_eq!(
+            db.get_queue_events("expired").expect("queue events")[0].action,
+            QueueAction::Expired
+        );
+        assert!(db.get_tags("expired").expect("tags").is_empty());
+    }
+
     #[test]
     fn trigger_events() {
         let (_dir, mut db) = temp_db();
@@ -652,6 +3338,28 @@ mod tests {
         assert_eq!(events3[0].trigger_package, Some("gtk4".to_string()));
     }
 
+    #[test]
+    fn in_memory() {
+        let mut db = Database::open_in_memory(90).expect("open db");
+        assert!(db.mark("pkg1", Some("qt6-base"), None).expect("mark"));
+        assert!(db.is_marked("pkg1").expect("is_marked"));
+        assert_eq!(db.get_events("pkg1").expect("events").len(), 1);
+    }
+
+    #[test]
+    fn in_memory_path() {
+        let path = Path::new(MEMORY_DB_PATH);
+        assert!(is_writable(path));
+
+        let mut db = Database::open_at(path, 90).expect("open db");
+        db.mark("pkg1", None, None).expect("mark");
+        assert!(!path.exists());
+
+        // Each connection gets its own database
+        let db = Database::open_readonly(path).expect("open readonly");
+        assert!(db.list().expect("list").is_empty());
+    }
+
     #[test]
     fn readonly_mode() {
         let dir = tempfile::tempdir().expect("create temp dir");
@@ -671,34 +3379,6 @@ mod tests {
         assert_eq!(queue.len(), 1);
     }
 
-    #[test]
-    fn iso8601_format() {
-        let ts = now_iso8601();
-        // Basic format check: YYYY-MM-DDTHH:MM:SS.mmmZ
-        assert_eq!(ts.len(), 24);
-        assert_eq!(&ts[4..5], "-");
-        assert_eq!(&ts[7..8], "-");
-        assert_eq!(&ts[10..11], "T");
-        assert_eq!(&ts[13..14], ":");
-        assert_eq!(&ts[16..17], ":");
-        assert_eq!(&ts[19..20], ".");
-        assert_eq!(&ts[23..24], "Z");
-    }
-
-    #[test]
-    fn days_to_date_epoch() {
-        // 1970-01-01
-        assert_eq!(days_to_date(0), (1970, 1, 1));
-    }
-
-    #[test]
-    fn days_to_date_known_dates() {
-        // 2024-01-01 is 19723 days from epoch
-        assert_eq!(days_to_date(19723), (2024, 1, 1));
-        // 2000-01-01 is 10957 days from epoch
-        assert_eq!(days_to_date(10957), (2000, 1, 1));
-    }
-
     #[test]
     fn readonly_mode_strict() {
         use std::os::unix::fs::PermissionsExt;
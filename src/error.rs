@@ -0,0 +1,150 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! The error type of the commands.
+//!
+//! Each module has its own error type; [`Error`] wraps them so a front end
+//! driving the queue (the CLI, or another tool) handles one type and can
+//! still match on where a failure came from.
+
+use std::io;
+
+use crate::aur::AurError;
+use crate::backup::BackupError;
+use crate::config::ConfigError;
+use crate::db::{DbError, get_db_path};
+use crate::hooks::HookError;
+use crate::overrides::OverrideError;
+use crate::pacman::PacmanError;
+use crate::rebuild::RebuildError;
+use crate::tr;
+use crate::triggers::TriggerListError;
+
+/// Errors of the commands, wrapping those of each module.
+#[derive(Debug)]
+pub enum Error {
+    /// The config couldn't be read or is invalid.
+    Config(ConfigError),
+    /// A database operation failed.
+    Db(DbError),
+    /// pacman couldn't be queried.
+    Pacman(PacmanError),
+    /// A rebuild couldn't run or failed.
+    Rebuild(RebuildError),
+    /// The AUR couldn't be queried.
+    Aur(AurError),
+    /// A hook couldn't be run.
+    Hook(HookError),
+    /// An override file couldn't be read or written.
+    Override(OverrideError),
+    /// The trigger list couldn't be loaded or fetched.
+    TriggerList(TriggerListError),
+    /// A backup couldn't be written or restored.
+    Backup(BackupError),
+    /// Any other I/O failure.
+    Io(io::Error),
+    /// There is no database yet (read-only commands don't create it).
+    NoDatabase,
+    /// The command line or an interactive answer was invalid.
+    Usage(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Config(e) => write!(f, "{e}"),
+            Self::Db(e) => write!(f, "{e}"),
+            Self::Pacman(e) => write!(f, "{e}"),
+            Self::Rebuild(e) => write!(f, "{e}"),
+            Self::Aur(e) => write!(f, "{e}"),
+            Self::Hook(e) => write!(f, "{e}"),
+            Self::Override(e) => write!(f, "{e}"),
+            Self::TriggerList(e) => write!(f, "{e}"),
+            Self::Backup(e) => write!(f, "{e}"),
+            Self::Io(e) => write!(f, "{e}"),
+            Self::NoDatabase => f.write_str(&tr!(
+                "error-no-database",
+                path = get_db_path().display().to_string()
+            )),
+            Self::Usage(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Config(e) => Some(e),
+            Self::Db(e) => Some(e),
+            Self::Pacman(e) => Some(e),
+            Self::Rebuild(e) => Some(e),
+            Self::Aur(e) => Some(e),
+            Self::Hook(e) => Some(e),
+            Self::Override(e) => Some(e),
+            Self::TriggerList(e) => Some(e),
+            Self::Backup(e) => Some(e),
+            Self::Io(e) => Some(e),
+            Self::NoDatabase | Self::Usage(_) => None,
+        }
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(e: ConfigError) -> Self {
+        Self::Config(e)
+    }
+}
+
+impl From<BackupError> for Error {
+    fn from(e: BackupError) -> Self {
+        Self::Backup(e)
+    }
+}
+
+impl From<DbError> for Error {
+    fn from(e: DbError) -> Self {
+        Self::Db(e)
+    }
+}
+
+impl From<PacmanError> for Error {
+    fn from(e: PacmanError) -> Self {
+        Self::Pacman(e)
+    }
+}
+
+impl From<RebuildError> for Error {
+    fn from(e: RebuildError) -> Self {
+        Self::Rebuild(e)
+    }
+}
+
+impl From<AurError> for Error {
+    fn from(e: AurError) -> Self {
+        Self::Aur(e)
+    }
+}
+
+impl From<HookError> for Error {
+    fn from(e: HookError) -> Self {
+        Self::Hook(e)
+    }
+}
+
+impl From<OverrideError> for Error {
+    fn from(e: OverrideError) -> Self {
+        Self::Override(e)
+    }
+}
+
+impl From<TriggerListError> for Error {
+    fn from(e: TriggerListError) -> Self {
+        Self::TriggerList(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
@@ -0,0 +1,180 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! Pacman hook installation.
+//!
+//! The hooks from `contrib/` are embedded in the binary so `anneal setup` can
+//! wire `anneal trigger` (and `anneal capture-versions` before it) into
+//! pacman without the hook files being copied by hand. The installed hooks'
+//! `Exec` lines point at the running binary, so installs outside `/usr/bin`
+//! (e.g. `cargo install`) still work.
+//!
+//! Hooks are installed to `/etc/pacman.d/hooks` by default: files under
+//! `/usr/share/libalpm/hooks` belong to packages, and the anneal package
+//! already ships the hook there.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::tr;
+
+/// Directory for administrator-installed hooks.
+pub const HOOK_DIR: &str = "/etc/pacman.d/hooks";
+
+/// Directory for hooks shipped by packages.
+pub const PACKAGED_HOOK_DIR: &str = "/usr/share/libalpm/hooks";
+
+/// File name of the trigger hook.
+pub const TRIGGER_HOOK_NAME: &str = "anneal-trigger.hook";
+
+/// File name of the hook capturing versions before a transaction.
+pub const CAPTURE_HOOK_NAME: &str = "anneal-capture.hook";
+
+/// Trigger hook as shipped in `contrib/`.
+const TRIGGER_HOOK: &str = include_str!("../contrib/anneal-trigger.hook");
+
+/// Capture hook as shipped in `contrib/`.
+const CAPTURE_HOOK: &str = include_str!("../contrib/anneal-capture.hook");
+
+/// Binary path used by the shipped hook.
+const PACKAGED_EXEC: &str = "/usr/bin/anneal";
+
+/// Outcome of installing a hook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Install {
+    /// The hook didn't exist and was written.
+    Created,
+    /// The hook existed with different content and was overwritten.
+    Replaced,
+    /// The hook was already installed with the same content.
+    Unchanged,
+}
+
+/// Hook installation errors.
+#[derive(Debug)]
+pub enum HookError {
+    /// The hook file couldn't be read or written.
+    Io(PathBuf, io::Error),
+    /// A hook with different content exists and `force` wasn't given.
+    Modified(PathBuf),
+}
+
+impl std::fmt::Display for HookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(path, e) => f.write_str(&tr!(
+                "hook-io",
+                path = path.display().to_string(),
+                error = e.to_string()
+            )),
+            Self::Modified(path) => {
+                f.write_str(&tr!("hook-modified", path = path.display().to_string()))
+            }
+        }
+    }
+}
+
+impl std::error::Error for HookError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(_, e) => Some(e),
+            Self::Modified(_) => None,
+        }
+    }
+}
+
+/// Get the trigger hook, running the given binary.
+pub fn trigger_hook(exe: &Path) -> String {
+    with_exec(TRIGGER_HOOK, exe)
+}
+
+/// Get the capture hook, running the given binary.
+pub fn capture_hook(exe: &Path) -> String {
+    with_exec(CAPTURE_HOOK, exe)
+}
+
+/// Point a shipped hook at another binary.
+fn with_exec(hook: &str, exe: &Path) -> String {
+    hook.replace(
+        &format!("Exec = {PACKAGED_EXEC} "),
+        &format!("Exec = {} ", exe.display()),
+    )
+}
+
+/// Path of the trigger hook shipped by the anneal package.
+pub fn packaged_trigger_hook() -> PathBuf {
+    Path::new(PACKAGED_HOOK_DIR).join(TRIGGER_HOOK_NAME)
+}
+
+/// Install a hook file into a directory, creating the directory if needed.
+///
+/// An existing hook with different content is only replaced with `force`.
+///
+/// # Errors
+///
+/// Returns an error if the file can't be written, or if it was modified and
+/// `force` is false.
+pub fn install(dir: &Path, name: &str, content: &str, force: bool) -> Result<Install, HookError> {
+    let path = dir.join(name);
+
+    let outcome = match fs::read_to_string(&path) {
+        Ok(existing) if existing == content => return Ok(Install::Unchanged),
+        Ok(_) if !force => return Err(HookError::Modified(path)),
+        Ok(_) => Install::Replaced,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Install::Created,
+        Err(e) => return Err(HookError::Io(path, e)),
+    };
+
+    fs::create_dir_all(dir).map_err(|e| HookError::Io(dir.to_path_buf(), e))?;
+    fs::write(&path, content).map_err(|e| HookError::Io(path.clone(), e))?;
+    Ok(outcome)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hook_runs_given_binary() {
+        let hook = trigger_hook(Path::new("/home/me/.cargo/bin/anneal"));
+        assert!(hook.contains("Exec = /home/me/.cargo/bin/anneal trigger --hook\n"));
+        assert!(hook.contains("When = PostTransaction"));
+        assert!(hook.contains("NeedsTargets"));
+
+        assert_eq!(trigger_hook(Path::new(PACKAGED_EXEC)), TRIGGER_HOOK);
+
+        let hook = capture_hook(Path::new("/home/me/.cargo/bin/anneal"));
+        assert!(hook.contains("Exec = /home/me/.cargo/bin/anneal capture-versions\n"));
+        assert!(hook.contains("When = PreTransaction"));
+        assert!(hook.contains("NeedsTargets"));
+    }
+
+    #[test]
+    fn install_outcomes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let hooks = dir.path().join("hooks");
+
+        assert_eq!(
+            install(&hooks, TRIGGER_HOOK_NAME, "a", false).unwrap(),
+            Install::Created
+        );
+        assert_eq!(
+            install(&hooks, TRIGGER_HOOK_NAME, "a", false).unwrap(),
+            Install::Unchanged
+        );
+        assert!(matches!(
+            install(&hooks, TRIGGER_HOOK_NAME, "b", false),
+            Err(HookError::Modified(_))
+        ));
+        assert_eq!(
+            install(&hooks, TRIGGER_HOOK_NAME, "b", true).unwrap(),
+            Install::Replaced
+        );
+        assert_eq!(
+            fs::read_to_string(hooks.join(TRIGGER_HOOK_NAME)).unwrap(),
+            "b"
+        );
+    }
+}
@@ -0,0 +1,233 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! Translations of user-facing messages.
+//!
+//! Messages are looked up by id in [Fluent](https://projectfluent.org)
+//! catalogs embedded in the binary (`i18n/<lang>.ftl`), through the [`tr!`]
+//! macro:
+//!
+//! ```
+//! let msg = anneal::tr!("helper-not-found", name = "paru");
+//! assert_eq!(msg, "AUR helper 'paru' not found in PATH");
+//! ```
+//!
+//! The language comes from the usual locale variables (`LANGUAGE`, then
+//! `LC_ALL`, `LC_MESSAGES` and `LANG`). English is the fallback: a message
+//! missing from a translation, or a locale without one, uses the English
+//! catalog, which has every message.
+//!
+//! To add a language, copy `i18n/en.ftl` to `i18n/<lang>.ftl`, translate the
+//! values and add the file to [`CATALOGS`].
+//!
+//! [`tr!`]: crate::tr
+
+use std::sync::OnceLock;
+
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource};
+use unic_langid::LanguageIdentifier;
+
+pub use fluent_bundle::FluentValue;
+
+/// Catalogs built into the binary, by language tag. The first is the
+/// fallback and must have every message.
+pub const CATALOGS: &[(&str, &str)] = &[("en", include_str!("../i18n/en.ftl"))];
+
+/// Catalogs for the current locale.
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+/// Translate a message, with optional `name = value` arguments.
+///
+/// Values are strings or numbers (numbers select plural forms).
+#[macro_export]
+macro_rules! tr {
+    ($id:literal) => {
+        $crate::i18n::translate($id, &[])
+    };
+    ($id:literal, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::i18n::translate(
+            $id,
+            &[$((stringify!($name), $crate::i18n::FluentValue::from($value))),+],
+        )
+    };
+}
+
+/// A translation and the English fallback.
+struct Catalog {
+    /// Catalog of the user's language, if there is one besides English.
+    local: Option<FluentBundle<FluentResource>>,
+    /// The English catalog.
+    fallback: FluentBundle<FluentResource>,
+}
+
+impl Catalog {
+    /// Load the catalogs for a list of preferred languages.
+    fn new(languages: &[String]) -> Self {
+        let (fallback_tag, fallback_source) = CATALOGS[0];
+        let local = languages
+            .iter()
+            .find_map(|lang| find_catalog(lang))
+            .filter(|(tag, _)| *tag != fallback_tag)
+            .and_then(|(tag, source)| bundle(tag, source));
+        let fallback = bundle(fallback_tag, fallback_source).unwrap_or_else(|| empty(fallback_tag));
+        Self { local, fallback }
+    }
+
+    /// Format a message, falling back to English and then to the id.
+    fn format(&self, id: &str, args: &[(&str, FluentValue)]) -> String {
+        let mut fluent_args = FluentArgs::new();
+        for (name, value) in args {
+            fluent_args.set(*name, value.clone());
+        }
+        self.local
+            .iter()
+            .chain(std::iter::once(&self.fallback))
+            .find_map(|bundle| {
+                let pattern = bundle.get_message(id)?.value()?;
+                let mut errors = Vec::new();
+                Some(
+                    bundle
+                        .format_pattern(pattern, Some(&fluent_args), &mut errors)
+                        .into_owned(),
+                )
+            })
+            .unwrap_or_else(|| id.to_string())
+    }
+}
+
+/// Translate a message (see [`tr!`](crate::tr)).
+pub fn translate(id: &str, args: &[(&str, FluentValue)]) -> String {
+    CATALOG
+        .get_or_init(|| Catalog::new(&preferred_languages()))
+        .format(id, args)
+}
+
+/// Get the user's languages in order of preference from the environment.
+fn preferred_languages() -> Vec<String> {
+    let var = |name| std::env::var(name).ok().filter(|v| !v.is_empty());
+    // LANGUAGE is a list, and is ignored for the C locale like gettext does
+    let locale = var("LC_ALL")
+        .or_else(|| var("LC_MESSAGES"))
+        .or_else(|| var("LANG"));
+    let mut languages: Vec<String> = match (&locale, var("LANGUAGE")) {
+        (Some(locale), _) if is_c_locale(locale) => Vec::new(),
+        (_, Some(list)) => list.split(':').map(String::from).collect(),
+        _ => Vec::new(),
+    };
+    languages.extend(locale);
+    languages
+}
+
+/// Check for the C locale, which means untranslated messages.
+fn is_c_locale(locale: &str) -> bool {
+    matches!(locale, "C" | "POSIX") || locale.starts_with("C.")
+}
+
+/// Find the catalog for a locale name like `de_DE.UTF-8`: the exact
+/// language and region, else the language alone.
+fn find_catalog(locale: &str) -> Option<(&'static str, &'static str)> {
+    if is_c_locale(locale) {
+        return None;
+    }
+    let tag = locale
+        .split(['.', '@'])
+        .next()
+        .unwrap_or_default()
+        .replace('_', "-");
+    let language = tag.split('-').next().unwrap_or_default();
+    CATALOGS
+        .iter()
+        .find(|(t, _)| t.eq_ignore_ascii_case(&tag))
+        .or_else(|| CATALOGS.iter().find(|(t, _)| *t == language))
+        .copied()
+}
+
+/// Build a bundle from a catalog, or `None` if it doesn't parse.
+fn bundle(tag: &str, source: &str) -> Option<FluentBundle<FluentResource>> {
+    let resource = FluentResource::try_new(source.to_string()).ok()?;
+    let mut bundle = empty(tag);
+    bundle.add_resource(resource).ok()?;
+    Some(bundle)
+}
+
+/// Create a bundle without messages.
+fn empty(tag: &str) -> FluentBundle<FluentResource> {
+    let lang: LanguageIdentifier = tag.parse().unwrap_or_default();
+    let mut bundle = FluentBundle::new_concurrent(vec![lang]);
+    // Terminals don't need Unicode isolation marks around arguments
+    bundle.set_use_isolating(false);
+    bundle
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn english_catalog_parses() {
+        let (_, source) = CATALOGS[0];
+        assert!(FluentResource::try_new(source.to_string()).is_ok());
+        assert!(bundle(CATALOGS[0].0, source).is_some());
+    }
+
+    #[test]
+    fn every_message_is_in_english() {
+        let catalog = Catalog::new(&[]);
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let source = std::fs::read_to_string(&path).unwrap();
+            for (at, call) in source.match_indices("tr!(\"") {
+                // Not `include_str!(...)`
+                if source[..at].ends_with(|c: char| c.is_alphanumeric() || c == '_') {
+                    continue;
+                }
+                let rest = &source[at + call.len()..];
+                let id = rest.split('"').next().unwrap();
+                assert!(
+                    catalog.fallback.has_message(id),
+                    "{}: message '{id}' is missing from en.ftl",
+                    path.display()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn formats_arguments_and_plurals() {
+        let catalog = Catalog::new(&[]);
+        assert_eq!(
+            catalog.format("helper-not-found", &[("name", "paru".into())]),
+            "AUR helper 'paru' not found in PATH"
+        );
+        assert_eq!(
+            catalog.format("package-word", &[("count", 1.into())]),
+            "package"
+        );
+        assert_eq!(
+            catalog.format("package-word", &[("count", 3.into())]),
+            "packages"
+        );
+        assert_eq!(catalog.format("no-such-message", &[]), "no-such-message");
+    }
+
+    #[test]
+    fn translation_falls_back_to_english() {
+        let catalog = Catalog {
+            local: bundle("de", "label-warning = Warnung:\n"),
+            fallback: bundle(CATALOGS[0].0, CATALOGS[0].1).unwrap(),
+        };
+        assert_eq!(catalog.format("label-warning", &[]), "Warnung:");
+        assert_eq!(catalog.format("label-error", &[]), "error:");
+    }
+
+    #[test]
+    fn locale_names() {
+        assert_eq!(find_catalog("en_US.UTF-8").map(|c| c.0), Some("en"));
+        assert_eq!(find_catalog("en").map(|c| c.0), Some("en"));
+        assert_eq!(find_catalog("C.UTF-8"), None);
+        assert_eq!(find_catalog("xx_YY"), None);
+    }
+}
@@ -0,0 +1,121 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! Reading user input: packages piped on stdin and numbered selections
+//! from interactive prompts.
+
+use std::io::{self, BufRead, IsTerminal};
+
+/// Parse a numbered selection like `1 3 5-7` or `all` (1-based, inclusive).
+///
+/// Numbers may be separated by spaces or commas. Returns sorted, deduplicated
+/// indices.
+///
+/// # Errors
+///
+/// Returns a message naming the first invalid or out-of-range token.
+pub fn parse_selection(input: &str, max: usize) -> Result<Vec<usize>, String> {
+    let input = input.trim();
+    if input.eq_ignore_ascii_case("all") {
+        return Ok((1..=max).collect());
+    }
+
+    let mut selected = Vec::new();
+    for token in input.split([' ', ',']).filter(|t| !t.is_empty()) {
+        let (start, end) = match token.split_once('-') {
+            Some((start, end)) => (start, end),
+            None => (token, token),
+        };
+        let (Ok(start), Ok(end)) = (start.parse::<usize>(), end.parse::<usize>()) else {
+            return Err(format!("invalid selection '{token}'"));
+        };
+        if start == 0 || end > max || start > end {
+            return Err(format!("selection '{token}' out of range (1-{max})"));
+        }
+        selected.extend(start..=end);
+    }
+
+    selected.sort_unstable();
+    selected.dedup();
+    Ok(selected)
+}
+
+/// Parse a package list file: names separated by whitespace or newlines,
+/// with `#` starting a comment.
+pub fn parse_package_list(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .flat_map(|line| line.split('#').next().unwrap_or("").split_whitespace())
+        .map(String::from)
+        .collect()
+}
+
+/// Read packages from stdin (one per line).
+///
+/// Returns nothing if stdin is a terminal, rather than waiting for input.
+pub fn read_stdin_packages() -> Vec<String> {
+    let stdin = io::stdin();
+    if stdin.is_terminal() {
+        // Don't block waiting for input if stdin is a terminal
+        return Vec::new();
+    }
+
+    stdin
+        .lock()
+        .lines()
+        .map_while(Result::ok)
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn package_list() {
+        assert_eq!(
+            parse_package_list(
+                "# python 3.13 leftovers\npython-foo\n\n  python-bar python-baz # vendored\n"
+            ),
+            vec!["python-foo", "python-bar", "python-baz"]
+        );
+        assert!(parse_package_list("# nothing\n").is_empty());
+    }
+
+    #[test]
+    fn numbers_and_ranges() {
+        assert_eq!(parse_selection("1 3 5-7", 8).unwrap(), vec![1, 3, 5, 6, 7]);
+        assert_eq!(parse_selection("2,1", 3).unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn all() {
+        assert_eq!(parse_selection("all\n", 3).unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn empty_selects_nothing() {
+        assert!(parse_selection("\n", 3).unwrap().is_empty());
+    }
+
+    #[test]
+    fn duplicates_removed() {
+        assert_eq!(parse_selection("1 1-2 2", 3).unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert!(parse_selection("0", 3).is_err());
+        assert!(parse_selection("4", 3).is_err());
+        assert!(parse_selection("3-1", 3).is_err());
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(parse_selection("foo", 3).is_err());
+        assert!(parse_selection("1-", 3).is_err());
+    }
+}
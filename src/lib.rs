@@ -2,12 +2,53 @@
 // Copyright (C) 2026 Mark Wells Dev
 
 //! Anneal - Proactive AUR rebuild management for Arch Linux
+//!
+//! The `anneal` binary is a front end over these modules, which only parses
+//! arguments and renders results: the queue lives in [`db::Database`] and
+//! is marked, unmarked, pinned and cleaned through [`queue`], triggers are
+//! processed by [`trigger`] and applied after a pacman transaction by
+//! [`transaction`], and [`rebuild`] plans and runs rebuilds
+//! ([`rebuild::plan_rebuild`], [`rebuild::run_rebuild`]). Other tools (GUIs,
+//! bots) can drive the queue the same way; failures of any module convert
+//! into [`Error`].
+//!
+//! With the `serde` feature, the queue, history, trigger and config types
+//! ([`db::QueueEntry`], [`db::TriggerEvent`], [`trigger::TriggerResult`],
+//! [`trigger::MarkedPackage`], [`config::Config`], [`version::Threshold`])
+//! implement `Serialize` and `Deserialize`.
 
+pub mod audit;
+pub mod aur;
+pub mod aurgit;
+pub mod backup;
+pub mod buildlog;
+pub mod capture;
 pub mod cli;
 pub mod config;
 pub mod db;
+pub mod error;
+pub mod hooks;
+pub mod i18n;
+pub mod input;
+pub mod lock;
+pub mod net;
+pub mod notify;
 pub mod output;
 pub mod overrides;
+pub mod pacman;
+pub mod queue;
+pub mod rebuild;
+pub mod scan;
+pub mod soname;
+pub mod srcinfo;
+pub mod syslog;
+pub mod systemd;
+pub mod template;
+pub mod time;
+pub mod transaction;
 pub mod trigger;
 pub mod triggers;
+pub mod validate;
 pub mod version;
+
+pub use error::Error;
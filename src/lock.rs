@@ -0,0 +1,123 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! Rebuild lock.
+//!
+//! Two rebuilds of the same queue would both drive the AUR helper over the
+//! same packages and both unmark them. `rebuild` holds an exclusive `flock`
+//! on a lock file for its whole run, which the kernel releases when the
+//! process exits, however it exits. The lock file is never removed, and
+//! `flock` works on a read-only descriptor, so any user who can read the
+//! file can take the lock.
+//!
+//! The default database is guarded by [`REBUILD_LOCK`], created at boot by
+//! the packaged tmpfiles.d entry. Another database (`ANNEAL_DB_PATH`) gets
+//! its own lock file next to it.
+
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::db::{self, DEFAULT_DB_PATH};
+
+/// Lock file of the default database.
+pub const REBUILD_LOCK: &str = "/run/anneal/rebuild.lock";
+
+/// An exclusive rebuild lock, released when dropped.
+#[derive(Debug)]
+pub struct RebuildLock {
+    _file: File,
+}
+
+/// Get the lock file guarding rebuilds of a database.
+///
+/// Returns `None` for an in-memory database, which no other process can see.
+pub fn rebuild_lock_path(db_path: &Path) -> Option<PathBuf> {
+    if db::is_memory_path(db_path) {
+        None
+    } else if db_path == Path::new(DEFAULT_DB_PATH) {
+        Some(PathBuf::from(REBUILD_LOCK))
+    } else {
+        let mut path = db_path.as_os_str().to_owned();
+        path.push(".rebuild.lock");
+        Some(PathBuf::from(path))
+    }
+}
+
+/// Try to take the rebuild lock without waiting.
+///
+/// Creates the lock file (and, for [`REBUILD_LOCK`], its directory) if
+/// needed. Returns `None` if another process holds the lock.
+///
+/// # Errors
+///
+/// Returns an error if the lock file can't be opened or created.
+pub fn try_lock(path: &Path) -> io::Result<Option<RebuildLock>> {
+    let file = open(path)?;
+    // SAFETY: flock is called on a descriptor owned by `file`
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(Some(RebuildLock { _file: file }));
+    }
+    let err = io::Error::last_os_error();
+    if err.kind() == io::ErrorKind::WouldBlock {
+        Ok(None)
+    } else {
+        Err(err)
+    }
+}
+
+/// Open the lock file, creating it readable by everyone if it's missing.
+fn open(path: &Path) -> io::Result<File> {
+    match File::open(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        result => return result,
+    }
+    if path == Path::new(REBUILD_LOCK)
+        && let Some(parent) = path.parent()
+    {
+        fs::create_dir_all(parent)?;
+    }
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .mode(0o644)
+        .open(path)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lock_paths() {
+        assert_eq!(
+            rebuild_lock_path(Path::new(DEFAULT_DB_PATH)),
+            Some(PathBuf::from(REBUILD_LOCK))
+        );
+        assert_eq!(
+            rebuild_lock_path(Path::new("/tmp/test.db")),
+            Some(PathBuf::from("/tmp/test.db.rebuild.lock"))
+        );
+        assert_eq!(rebuild_lock_path(Path::new(db::MEMORY_DB_PATH)), None);
+    }
+
+    #[test]
+    fn lock_is_exclusive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rebuild.lock");
+
+        let lock = try_lock(&path).unwrap();
+        assert!(lock.is_some());
+        // flock locks belong to the open file, so a second open conflicts
+        // even within one process
+        assert!(try_lock(&path).unwrap().is_none());
+
+        drop(lock);
+        assert!(try_lock(&path).unwrap().is_some());
+    }
+}
@@ -3,39 +3,125 @@
 
 //! Anneal CLI - Proactive AUR rebuild management for Arch Linux.
 
-use std::collections::HashSet;
-use std::io::{self, BufRead, BufReader, IsTerminal, Write};
-use std::process::{Command as ProcessCommand, ExitCode, Stdio};
-
-use anneal::cli::{Cli, Command};
-use anneal::config::{Config, KNOWN_HELPERS};
-use anneal::db::{Database, DbError, get_db_path};
-use anneal::output;
-use anneal::overrides::Overrides;
-use anneal::trigger::{TriggerError, process_triggers};
-use anneal::triggers::{TRIGGER_LIST_VERSION, TRIGGERS};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::{Command as ProcessCommand, ExitCode};
+
+use anneal::audit;
+use anneal::aur::AurClient;
+use anneal::backup;
+use anneal::cli::{
+    Cli, Command, ConfigAction, DbAction, OverrideAction, SourceAction, TriggerDefAction,
+};
+use anneal::config::{CONFIG_PATH, Config};
+use anneal::db::{
+    self, Database, ListFilter, Origin, Priority, QueueAction, QueueEntry, QueueEvent, SortOrder,
+    get_db_path,
+};
+use anneal::hooks;
+use anneal::input::{parse_package_list, parse_selection, read_stdin_packages};
+use anneal::net::NetOptions;
+use anneal::overrides::{
+    self, OverrideFile, OverrideKind, Overrides, PackageOverride, TriggerOverride, matches_glob,
+};
+use anneal::pacman;
+use anneal::queue::{
+    self, Cleaned, ClearTarget, MarkOptions, expand_globs, open_readonly, resolve_foreign,
+    resolve_group, resolve_maintainer, stale_versions,
+};
+use anneal::rebuild::{
+    PlannedCommand, RebuildOptions, format_estimate, plan_rebuild, run_rebuild, shell_quote,
+    validate_rebuilt,
+};
+use anneal::scan;
+use anneal::syslog;
+use anneal::systemd;
+use anneal::template::Template;
+use anneal::time::{
+    age_days, cutoff_date, display_time, display_timestamp, format_age, format_duration,
+    format_elapsed,
+};
+use anneal::transaction::{self, Dependents, hook_log};
+use anneal::trigger::{MarkedPackage, list_all_triggers, process_triggers, with_versions};
+use anneal::triggers::{self, ListUpdate, curated, is_curated_trigger};
+use anneal::validate;
+use anneal::{Error, output, tr};
 use clap::{CommandFactory, Parser};
 use clap_complete::generate;
+use serde_json::{Value, json};
 
 /// Exit codes.
 mod exit {
     pub const SUCCESS: u8 = 0;
     pub const ERROR: u8 = 1;
     pub const NOT_FOUND: u8 = 2;
+    /// Some, but not all, of the given packages were found.
+    pub const PARTIAL: u8 = 3;
+    /// The queue has packages (`list --check`).
+    pub const PENDING: u8 = 2;
 }
 
+/// Fields available to `list --format`.
+const LIST_FORMAT_FIELDS: &[&str] = &[
+    "package",
+    "trigger",
+    "trigger_version",
+    "first_marked_at",
+    "age",
+    "tags",
+    "reason",
+    "priority",
+    "expires_at",
+];
+
+/// Number of busiest triggers shown by `stats` (JSON lists all of them).
+const STATS_TOP_TRIGGERS: usize = 5;
+
+/// pkexec exit code when the user is not authorized.
+const PKEXEC_NOT_AUTHORIZED: i32 = 127;
+
+/// pkexec exit code when the authentication dialog was dismissed.
+const PKEXEC_DISMISSED: i32 = 126;
+
 fn main() -> ExitCode {
     let cli = Cli::parse();
-
-    // Check root requirement
-    if cli.command.requires_root() && !is_root() {
-        output::error("Permission denied. This command requires root privileges.");
-        return ExitCode::from(exit::ERROR);
+    output::set_color(cli.color);
+    output::set_verbosity(cli.verbose);
+    anneal::time::set_absolute_time(cli.absolute_time);
+
+    match check_access(&cli.command, is_root(), || db::is_writable(&get_db_path())) {
+        Ok(()) => {}
+        Err(Denied::NotRoot) => {
+            output::error("Permission denied. This command requires root privileges.");
+            return ExitCode::from(exit::ERROR);
+        }
+        Err(Denied::DatabaseNotWritable) => {
+            if cli.command.allows_polkit() && Config::load().is_ok_and(|c| c.polkit) {
+                return escalate_with_pkexec();
+            }
+            output::error(&format!(
+                "Permission denied. This command requires root privileges or write access to {}.",
+                get_db_path().display()
+            ));
+            if cli.command.modifies_queue() {
+                output::info(
+                    "Members of the 'anneal' group have write access (usermod -aG anneal $USER).",
+                );
+            }
+            return ExitCode::from(exit::ERROR);
+        }
     }
 
     // Check quiet + confirmation conflict
-    if cli.quiet && needs_confirmation(&cli.command) && !has_force_flag(&cli.command) {
-        output::error("Cannot prompt for confirmation with --quiet. Use -f to force.");
+    if (cli.quiet || cli.json) && needs_confirmation(&cli.command) && !has_force_flag(&cli.command)
+    {
+        let flag = if cli.json { "--json" } else { "--quiet" };
+        output::error(&format!(
+            "Cannot prompt for confirmation with {flag}. Use -f to force."
+        ));
         return ExitCode::from(exit::ERROR);
     }
 
@@ -50,56 +136,333 @@ fn main() -> ExitCode {
 
 /// Run the CLI command.
 fn run(cli: Cli) -> Result<u8, Error> {
-    let config = Config::load()?;
+    // Users can tune their own commands, but not ones that change the system
+    let mut config = if cli.command.modifies_system() {
+        Config::load()?
+    } else {
+        Config::load_with_user()?
+    };
+    config.offline |= cli.offline;
+    db::set_journal_mode(config.journal_mode);
+
+    if let Some(dir) = cli.fixture_dir {
+        pacman::set_fixture_dir(dir);
+    }
+
+    triggers::load_lists();
+
+    if cli.porcelain && !cli.command.supports_porcelain() {
+        return Err(Error::Usage(
+            "--porcelain is only supported by list, query and history".into(),
+        ));
+    }
+
+    // stdout is reserved for the JSON document or porcelain records
+    let json = cli.json;
+    let porcelain = cli.porcelain;
+    let quiet = cli.quiet || json || porcelain;
 
     match cli.command {
         Command::Mark {
-            packages,
-            trigger,
+            mut packages,
+            foreign,
+            file,
+            group,
+            maintainer,
+            mut trigger,
             trigger_version,
-        } => cmd_mark(
+            allow_missing,
+            tags,
+            reason,
+            priority,
+            expires,
+        } => {
+            if let Some(group) = group {
+                let members = resolve_group(&group)?;
+                if members.is_empty() {
+                    output::warning(&format!(
+                        "Group '{group}' has no installed foreign packages"
+                    ));
+                    return Ok(exit::NOT_FOUND);
+                }
+                packages.extend(members);
+                trigger = Some(group);
+            }
+            if let Some(maintainer) = maintainer {
+                let maintained = resolve_maintainer(&config, &maintainer)?;
+                if maintained.is_empty() {
+                    output::warning(&format!(
+                        "No installed foreign packages are maintained by '{maintainer}'"
+                    ));
+                    return Ok(exit::NOT_FOUND);
+                }
+                packages.extend(maintained);
+            }
+            if foreign {
+                packages = resolve_foreign(&config)?;
+                if packages.is_empty() {
+                    output::warning("No installed foreign packages to mark");
+                    return Ok(exit::NOT_FOUND);
+                }
+            }
+            if let Some(file) = file {
+                let content = fs::read_to_string(&file)
+                    .map_err(|e| Error::Usage(format!("Cannot read {}: {e}", file.display())))?;
+                packages.extend(parse_package_list(&content));
+            }
+            let (packages, unmatched) = expand_globs(packages)?;
+            for pattern in &unmatched {
+                output::warning(&format!("No installed foreign packages match '{pattern}'"));
+            }
+            if packages.is_empty() {
+                if unmatched.is_empty() && !quiet {
+                    output::status("No packages specified");
+                }
+                return Ok(exit::NOT_FOUND);
+            }
+            let code = cmd_mark(
+                &config,
+                &packages,
+                &MarkOptions {
+                    trigger: trigger.as_deref(),
+                    trigger_version: trigger_version.as_deref(),
+                    allow_missing,
+                    tags: &tags,
+                    reason: reason.as_deref(),
+                    priority,
+                    expires_at: expires.as_deref(),
+                },
+                quiet,
+            )?;
+            Ok(if unmatched.is_empty() {
+                code
+            } else {
+                exit::NOT_FOUND
+            })
+        }
+
+        Command::Unmark {
+            packages,
+            strict,
+            interactive,
+            include_pinned,
+        } => cmd_unmark(
             &config,
-            &packages,
-            trigger.as_deref(),
-            trigger_version.as_deref(),
-            cli.quiet,
+            packages,
+            strict,
+            interactive,
+            include_pinned,
+            quiet,
         ),
 
-        Command::Unmark { packages, strict } => cmd_unmark(&config, packages, strict, cli.quiet),
+        Command::Pin { packages } => cmd_pin(&config, &packages, true, quiet),
+
+        Command::Unpin { packages } => cmd_pin(&config, &packages, false, quiet),
+
+        Command::Prioritize { priority, packages } => {
+            cmd_prioritize(&config, &packages, priority, quiet)
+        }
 
-        Command::List => cmd_list(cli.quiet),
+        Command::List {
+            sort,
+            reverse,
+            tag,
+            trigger,
+            since,
+            before,
+            format,
+            long,
+            null,
+            check,
+        } => {
+            let filter = ListFilter {
+                trigger,
+                since,
+                before,
+            };
+            let format = format
+                .map(|format| Template::parse(&format, LIST_FORMAT_FIELDS))
+                .transpose()
+                .map_err(|e| Error::Usage(format!("Invalid --format: {e}")))?;
+            let style = match (&format, json) {
+                (Some(_), true) => {
+                    return Err(Error::Usage("--format can't be used with --json".into()));
+                }
+                (Some(_), false) if porcelain => {
+                    return Err(Error::Usage(
+                        "--format can't be used with --porcelain".into(),
+                    ));
+                }
+                (Some(format), false) => ListStyle::Format(format),
+                (None, true) => ListStyle::Json,
+                (None, false) if porcelain => ListStyle::Porcelain,
+                (None, false) if null => ListStyle::Null,
+                (None, false) if cli.verbose > 0 => ListStyle::Verbose,
+                (None, false) if long => ListStyle::Long,
+                (None, false) => ListStyle::Plain,
+            };
+            cmd_list(sort, reverse, tag.as_deref(), &filter, &style, check, quiet)
+        }
 
-        Command::Clear { force, trigger } => {
-            cmd_clear(&config, force, trigger.as_deref(), cli.quiet)
+        Command::Clear {
+            force,
+            trigger,
+            tag,
+            include_pinned,
+            undo,
+        } => {
+            if undo {
+                cmd_clear_undo(&config, quiet)
+            } else {
+                cmd_clear(
+                    &config,
+                    force,
+                    trigger.as_deref(),
+                    tag.as_deref(),
+                    include_pinned,
+                    quiet,
+                )
+            }
         }
 
+        Command::Clean { dry_run } => cmd_clean(&config, dry_run, quiet, json),
+
         Command::Rebuild {
             force,
             checkrebuild,
             cmd,
+            skip_flagged,
+            skip_stale,
+            unmark_stale,
+            profile,
+            tag,
+            validate,
+            failed,
+            keep_going,
+            wait_for_pacman,
+            dry_run,
+            interactive,
+            exclude,
+            limit,
             packages,
             helper_args,
         } => cmd_rebuild(
             &config,
-            force,
-            checkrebuild,
-            cmd.as_deref(),
-            &packages,
-            &helper_args,
-            cli.quiet,
+            &RebuildOptions {
+                force,
+                checkrebuild,
+                cmd: cmd.as_deref(),
+                skip_flagged,
+                skip_stale,
+                unmark_stale,
+                profile: profile.as_deref(),
+                tag: tag.as_deref(),
+                validate,
+                failed,
+                keep_going,
+                wait_for_pacman,
+                dry_run,
+                interactive,
+                exclude: &exclude,
+                limit: limit.map(NonZeroUsize::get),
+                packages: &packages,
+                helper_args: &helper_args,
+            },
+            quiet,
+            json,
         ),
 
         Command::IsMarked { package } => cmd_ismarked(&package),
 
-        Command::Query { packages } => cmd_query(&packages, cli.quiet),
+        Command::Query { packages, null } => cmd_query(&packages, json, porcelain, null),
+
+        Command::Timeline { package } => cmd_timeline(&package, quiet),
+
+        Command::Why { package } => cmd_why(&package, quiet, json),
+
+        Command::History {
+            package,
+            rebuilds,
+            limit,
+            reverse,
+        } => {
+            let style = HistoryStyle::new(json, porcelain);
+            if rebuilds {
+                cmd_rebuild_history(&package, limit, reverse, quiet, style)
+            } else {
+                cmd_history(&package, limit, reverse, quiet, style)
+            }
+        }
+
+        Command::Count => cmd_count(quiet, json),
 
-        Command::Triggers => cmd_triggers(cli.quiet),
+        Command::Status { waybar } => {
+            if json && waybar {
+                return Err(Error::Usage("--waybar can't be used with --json".into()));
+            }
+            cmd_status(waybar, quiet, json)
+        }
+
+        Command::Stats => cmd_stats(&config, quiet, json),
+
+        Command::Backup { file } => cmd_backup(&file, quiet),
+
+        Command::Restore { force, file } => cmd_restore(&config, &file, force, quiet),
+
+        Command::Triggers => cmd_triggers(&config, quiet, json),
+
+        Command::UpdateTriggers => cmd_update_triggers(&config, quiet),
+
+        Command::Trigger {
+            dry_run,
+            null,
+            hook,
+            packages,
+        } => {
+            if hook {
+                cmd_trigger_hook(&config, quiet, json)
+            } else {
+                cmd_trigger(&config, dry_run, packages, null, false, quiet, json)
+            }
+        }
+
+        Command::CaptureVersions => cmd_capture_versions(),
+
+        Command::Scan {
+            dry_run,
+            checkrebuild,
+        } => cmd_scan(&config, dry_run, checkrebuild, quiet),
+
+        Command::Verify { package } => cmd_verify(&package, quiet, json),
 
-        Command::Trigger { dry_run, packages } => {
-            cmd_trigger(&config, dry_run, packages, cli.quiet)
+        Command::Preview { file } => cmd_preview(&config, file.as_deref(), quiet, json),
+
+        Command::Source { action } => cmd_source(&config, action, quiet),
+
+        Command::Ignore { packages, list } => cmd_ignore(&packages, list, quiet, json),
+
+        Command::Unignore { packages } => cmd_unignore(&packages, quiet),
+
+        Command::TriggerDef { action } => cmd_trigger_def(action, quiet),
+
+        Command::Override { action } => cmd_override(action, quiet, json),
+
+        Command::Setup {
+            dir,
+            force,
+            print,
+            systemd,
+        } => {
+            if systemd {
+                cmd_setup_systemd(&config, dir, force, print, quiet)
+            } else {
+                cmd_setup(dir, force, print, quiet)
+            }
         }
 
-        Command::Config => cmd_config(&config, cli.quiet),
+        Command::Config { action } => cmd_config(&config, action, quiet, json),
+
+        Command::Db { action } => cmd_db(&config, action, quiet, json),
 
         Command::Completions { shell } => {
             cmd_completions(shell);
@@ -108,86 +471,44 @@ fn run(cli: Cli) -> Result<u8, Error> {
     }
 }
 
-// ==================== Rebuild Types ====================
-
-/// Rebuild-specific errors.
-#[derive(Debug)]
-enum RebuildError {
-    /// No AUR helper found in PATH.
-    NoHelper,
-    /// Multiple AUR helpers found, user must configure one.
-    AmbiguousHelper(Vec<String>),
-    /// Specified helper not found in PATH.
-    HelperNotFound(String),
-    /// Helper process failed to start.
-    HelperSpawn(io::Error),
-    /// Helper exited with non-zero code.
-    HelperFailed(i32),
-    /// checkrebuild command failed.
-    CheckrebuildFailed(io::Error),
-    /// Package not in queue (without -f flag).
-    PackageNotInQueue(String),
-}
-
-impl std::fmt::Display for RebuildError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::NoHelper => write!(
-                f,
-                "No AUR helper detected. Set 'helper' in /etc/anneal/config.conf\nSupported helpers: {}",
-                KNOWN_HELPERS.join(", ")
-            ),
-            Self::AmbiguousHelper(helpers) => write!(
-                f,
-                "Multiple AUR helpers found: {}. Set 'helper' in /etc/anneal/config.conf",
-                helpers.join(", ")
-            ),
-            Self::HelperNotFound(name) => write!(f, "AUR helper '{name}' not found in PATH"),
-            Self::HelperSpawn(e) => write!(f, "Failed to start AUR helper: {e}"),
-            Self::HelperFailed(code) => write!(f, "AUR helper exited with code {code}"),
-            Self::CheckrebuildFailed(e) => write!(f, "Failed to run checkrebuild: {e}"),
-            Self::PackageNotInQueue(pkg) => {
-                write!(f, "Package '{pkg}' is not in the queue (use -f to force)")
-            }
-        }
-    }
+/// How `list` prints the queue.
+enum ListStyle<'a> {
+    /// One package per line with its trigger.
+    Plain,
+    /// With the mark time, tags and reason under each package (`--verbose`).
+    Verbose,
+    /// A table with triggers, mark dates and ages (`--long`).
+    Long,
+    /// One line per package from a template (`--format`).
+    Format(&'a Template),
+    /// A JSON array (`--json`).
+    Json,
+    /// One space-separated record per package (`--porcelain`).
+    Porcelain,
+    /// NUL-terminated package names (`-0`).
+    Null,
 }
 
-/// Information about how to invoke an AUR helper.
-struct HelperInvocation {
-    /// The command to run (e.g., "paru").
-    command: String,
-    /// Base arguments for rebuild (e.g., ["-S", "--rebuild"]).
-    base_args: Vec<String>,
+/// How `history` prints events.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum HistoryStyle {
+    /// One line per event with a readable timestamp.
+    Plain,
+    /// A JSON array (`--json`).
+    Json,
+    /// One space-separated record per event (`--porcelain`).
+    Porcelain,
 }
 
-impl HelperInvocation {
-    /// Create invocation for a known helper.
-    fn for_known_helper(name: &str) -> Self {
-        let base_args = match name {
-            "aura" => vec!["-A".to_string(), "--rebuild".to_string()],
-            _ => vec!["-S".to_string(), "--rebuild".to_string()],
-        };
-        Self {
-            command: name.to_string(),
-            base_args,
-        }
-    }
-
-    /// Create invocation from a custom command string.
-    fn from_custom(cmd: &str) -> Self {
-        let parts: Vec<&str> = cmd.split_whitespace().collect();
-        if parts.is_empty() {
-            // Shouldn't happen, but handle gracefully
-            Self {
-                command: cmd.to_string(),
-                base_args: vec![],
-            }
+impl HistoryStyle {
+    /// Pick the style from the global output flags.
+    fn new(json: bool, porcelain: bool) -> Self {
+        if json {
+            Self::Json
+        } else if porcelain {
+            Self::Porcelain
         } else {
-            Self {
-                command: parts[0].to_string(),
-                base_args: parts[1..].iter().map(|s| s.to_string()).collect(),
-            }
+            Self::Plain
         }
     }
 }
@@ -197,28 +518,32 @@ impl HelperInvocation {
 fn cmd_mark(
     config: &Config,
     packages: &[String],
-    trigger: Option<&str>,
-    trigger_version: Option<&str>,
+    opts: &MarkOptions,
     quiet: bool,
 ) -> Result<u8, Error> {
-    let mut db = Database::open(config.retention_days)?;
+    let result = queue::mark(config, packages, opts)?;
 
-    let mut newly_marked = 0;
-    for pkg in packages {
-        if db.mark(pkg, trigger, trigger_version)? {
-            newly_marked += 1;
-        }
+    if !result.missing.is_empty() {
+        output::warning(&format!(
+            "Not installed: {} (use --allow-missing to mark anyway)",
+            result.missing.join(", ")
+        ));
     }
 
     if !quiet {
-        match trigger {
+        match opts.trigger {
             Some(t) => output::status(&format!(
-                "Marked {newly_marked} package(s) for rebuild (trigger: {t})"
+                "Marked {} package(s) for rebuild (trigger: {t})",
+                result.newly_marked
             )),
-            None => output::success_count("Marked", newly_marked),
+            None => output::success_count("Marked", result.newly_marked),
         }
     }
 
+    if !result.missing.is_empty() {
+        return Ok(exit::NOT_FOUND);
+    }
+
     Ok(exit::SUCCESS)
 }
 
@@ -226,10 +551,22 @@ fn cmd_unmark(
     config: &Config,
     packages: Vec<String>,
     strict: bool,
+    interactive: bool,
+    include_pinned: bool,
     quiet: bool,
 ) -> Result<u8, Error> {
-    let packages = if packages.is_empty() {
-        read_stdin_packages()?
+    // Without arguments on a terminal there's no stdin to read, so offer the
+    // picker instead of silently doing nothing
+    let prompt = packages.is_empty() && !quiet && io::stdin().is_terminal();
+    let from_stdin = packages.is_empty() && !interactive && !prompt;
+
+    let packages = if interactive || prompt {
+        if !io::stdin().is_terminal() {
+            return Err(Error::Usage("--interactive requires a terminal".into()));
+        }
+        pick_queue_entries(config)?
+    } else if packages.is_empty() {
+        read_stdin_packages()
     } else {
         packages
     };
@@ -241,190 +578,361 @@ fn cmd_unmark(
         return Ok(exit::SUCCESS);
     }
 
-    let mut db = Database::open(config.retention_days)?;
-    let mut removed = 0;
-    let mut not_found = Vec::new();
+    // Packages from stdin come from the remove hook
+    let origin = if from_stdin {
+        Origin::Hook
+    } else {
+        Origin::Cli
+    };
+    let result = queue::unmark(config, &packages, origin, include_pinned)?;
 
-    for pkg in &packages {
-        if db.unmark(pkg)? {
-            removed += 1;
-        } else {
-            not_found.push(pkg.as_str());
+    if !quiet {
+        for (old, new) in &result.moved {
+            output::status(&format!("Moved {old} to {new} (replaced)"));
         }
     }
+    if !result.pinned.is_empty() {
+        output::warning(&format!(
+            "Pinned, kept in the queue: {} (use --include-pinned or unpin)",
+            result.pinned.join(", ")
+        ));
+    }
 
     if !quiet {
-        output::success_count("Removed", removed);
+        output::success_count("Removed", result.removed.len());
     }
 
-    if strict && !not_found.is_empty() {
-        output::warning(&format!("Not in queue: {}", not_found.join(", ")));
+    if strict && !result.not_queued.is_empty() {
+        output::warning(&format!("Not in queue: {}", result.not_queued.join(", ")));
         return Ok(exit::NOT_FOUND);
     }
 
     Ok(exit::SUCCESS)
 }
 
-fn cmd_list(quiet: bool) -> Result<u8, Error> {
-    let db = open_readonly()?;
-    let queue = db.list()?;
+/// Pin or unpin queued packages.
+fn cmd_pin(config: &Config, packages: &[String], pin: bool, quiet: bool) -> Result<u8, Error> {
+    let result = queue::set_pinned(config, packages, pin)?;
 
-    if queue.is_empty() {
-        if !quiet {
-            output::status("No packages in queue");
-        }
-        return Ok(exit::SUCCESS);
+    if !quiet {
+        output::success_count(
+            if pin { "Pinned" } else { "Unpinned" },
+            result.changed.len(),
+        );
     }
 
-    for entry in &queue {
-        // Get the most recent trigger event for context
-        if let Some(event) = db.get_latest_event(&entry.package)? {
-            match event.trigger_package {
-                Some(ref trigger) => output::package_with_trigger(&entry.package, trigger),
-                None => output::package_with_trigger(&entry.package, "external"),
-            }
-        } else {
-            output::package(&entry.package);
-        }
-    }
+    report_not_queued(&result.not_queued)
+}
+
+/// Set the priority of queued packages.
+fn cmd_prioritize(
+    config: &Config,
+    packages: &[String],
+    priority: Priority,
+    quiet: bool,
+) -> Result<u8, Error> {
+    let result = queue::prioritize(config, packages, priority)?;
 
     if !quiet {
-        output::info(&format!("{} package(s) in queue", queue.len()));
+        output::success_count(
+            &format!("Set {} priority on", priority.as_str()),
+            result.changed.len(),
+        );
     }
 
+    report_not_queued(&result.not_queued)
+}
+
+/// Warn about packages that aren't queued, which exits with 2.
+fn report_not_queued(not_queued: &[String]) -> Result<u8, Error> {
+    if !not_queued.is_empty() {
+        output::warning(&format!("Not in queue: {}", not_queued.join(", ")));
+        return Ok(exit::NOT_FOUND);
+    }
     Ok(exit::SUCCESS)
 }
 
-fn cmd_clear(
-    config: &Config,
-    force: bool,
-    trigger: Option<&str>,
+/// Prompt for queue entries to unmark from a numbered list.
+///
+/// Returns an empty list if the queue is empty or nothing was selected.
+fn pick_queue_entries(config: &Config) -> Result<Vec<String>, Error> {
+    let queue = Database::open(config.retention_days)?.list()?;
+    if queue.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let width = queue.len().to_string().len();
+    for (i, entry) in queue.iter().enumerate() {
+        eprintln!("  {:>width$} {}", i + 1, entry.package);
+    }
+    eprint!(":: Packages to unmark (e.g. 1 2 4-6, all): ");
+    io::stderr().flush().ok();
+
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+
+    let selected = parse_selection(&line, queue.len()).map_err(Error::Usage)?;
+    Ok(selected
+        .into_iter()
+        .map(|i| queue[i - 1].package.clone())
+        .collect())
+}
+
+/// Prompt for packages to leave out of a rebuild from a numbered list.
+///
+/// An empty answer keeps them all.
+fn pick_rebuild_exclusions(candidates: &[&str]) -> Result<Vec<String>, Error> {
+    if !io::stdin().is_terminal() {
+        return Err(Error::Usage("--interactive requires a terminal".into()));
+    }
+
+    let width = candidates.len().to_string().len();
+    for (i, pkg) in candidates.iter().enumerate() {
+        eprintln!("  {:>width$} {pkg}", i + 1);
+    }
+    eprint!(":: Packages to exclude (e.g. 1 2 4-6, empty for none): ");
+    io::stderr().flush().ok();
+
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+
+    let selected = parse_selection(&line, candidates.len()).map_err(Error::Usage)?;
+    Ok(selected
+        .into_iter()
+        .map(|i| candidates[i - 1].to_string())
+        .collect())
+}
+
+fn cmd_list(
+    sort: SortOrder,
+    reverse: bool,
+    tag: Option<&str>,
+    filter: &ListFilter,
+    style: &ListStyle,
+    check: bool,
     quiet: bool,
 ) -> Result<u8, Error> {
-    let mut db = Database::open(config.retention_days)?;
+    let db = match open_readonly() {
+        // Nothing has been marked yet if the database doesn't exist
+        Err(Error::NoDatabase) if check => return Ok(exit::SUCCESS),
+        result => result?,
+    };
+    let mut queue = db.list_filtered(sort, reverse, filter)?;
 
-    if let Some(trigger_name) = trigger {
-        // Clear events for a specific trigger
-        let count = db.clear_trigger_events(trigger_name)?;
-        if !quiet {
-            output::status(&format!(
-                "Cleared {count} event(s) for trigger '{trigger_name}'"
-            ));
-        }
+    if let Some(tag) = tag {
+        let tagged: HashSet<String> = db.tagged_packages(tag)?.into_iter().collect();
+        queue.retain(|entry| tagged.contains(&entry.package));
+    }
+
+    let code = if check && !queue.is_empty() {
+        exit::PENDING
     } else {
-        // Clear entire queue
-        let queue = db.list()?;
-        if queue.is_empty() {
-            if !quiet {
-                output::status("Queue is already empty");
-            }
-            return Ok(exit::SUCCESS);
-        }
+        exit::SUCCESS
+    };
 
-        if !force {
-            eprint!(":: Clear {} package(s) from queue? [y/N] ", queue.len());
-            io::stderr().flush().ok();
+    let stale = match style {
+        ListStyle::Format(_) | ListStyle::Porcelain | ListStyle::Null => HashMap::new(),
+        _ => stale_versions(&queue),
+    };
 
-            if !confirm()? {
-                if !quiet {
-                    output::status("Cancelled");
-                }
-                return Ok(exit::SUCCESS);
-            }
+    if let ListStyle::Json = style {
+        let mut entries = Vec::new();
+        for entry in &queue {
+            let event = db.get_latest_event(&entry.package)?;
+            entries.push(json!({
+                "package": entry.package,
+                "first_marked_at": entry.first_marked_at,
+                "trigger": event.as_ref().and_then(|e| e.trigger_package.as_deref()),
+                "trigger_version": event.as_ref().and_then(|e| e.trigger_version.as_deref()),
+                "tags": db.get_tags(&entry.package)?,
+                "reason": entry.reason,
+                "pinned": entry.pinned,
+                "priority": entry.priority.as_str(),
+                "expires_at": entry.expires_at,
+                "marked_version": entry.marked_version,
+                "stale": stale.contains_key(&entry.package),
+            }));
         }
+        output::json(&Value::Array(entries));
+        return Ok(code);
+    }
 
-        let count = db.clear()?;
-        if !quiet {
-            output::success_count("Cleared", count);
+    if let ListStyle::Null = style {
+        for entry in &queue {
+            output::null_terminated(&entry.package);
         }
+        return Ok(code);
     }
 
-    Ok(exit::SUCCESS)
-}
+    if let ListStyle::Porcelain = style {
+        for entry in &queue {
+            let event = db.get_latest_event(&entry.package)?;
+            let trigger = event.as_ref().and_then(|e| e.trigger_package.as_deref());
+            let version = event.as_ref().and_then(|e| e.trigger_version.as_deref());
+            output::porcelain(&[
+                &entry.package,
+                trigger.unwrap_or("-"),
+                version.unwrap_or("-"),
+                &entry.first_marked_at,
+            ]);
+        }
+        return Ok(code);
+    }
 
-fn cmd_rebuild(
-    config: &Config,
-    force: bool,
-    checkrebuild: bool,
-    cmd: Option<&str>,
-    packages: &[String],
-    helper_args: &[String],
-    quiet: bool,
-) -> Result<u8, Error> {
-    // Step 1: Detect helper
-    let helper = detect_helper(config, cmd)?;
+    if let ListStyle::Format(format) = style {
+        for entry in &queue {
+            let event = db.get_latest_event(&entry.package)?;
+            let tags = db.get_tags(&entry.package)?;
+            println!(
+                "{}",
+                format.render(|field| match field {
+                    "package" => entry.package.clone(),
+                    "trigger" => event
+                        .as_ref()
+                        .and_then(|e| e.trigger_package.clone())
+                        .unwrap_or_default(),
+                    "trigger_version" => event
+                        .as_ref()
+                        .and_then(|e| e.trigger_version.clone())
+                        .unwrap_or_default(),
+                    "first_marked_at" => entry.first_marked_at.clone(),
+                    "age" => format_age(&entry.first_marked_at).unwrap_or_default(),
+                    "tags" => tags.join(","),
+                    "reason" => entry.reason.clone().unwrap_or_default(),
+                    "priority" => entry.priority.as_str().to_string(),
+                    "expires_at" => entry.expires_at.clone().unwrap_or_default(),
+                    _ => String::new(),
+                })
+            );
+        }
+        return Ok(code);
+    }
 
-    // Step 2: Collect packages from queue
-    let db = open_readonly()?;
-    let queue = db.list()?;
-    let queue_set: HashSet<&str> = queue.iter().map(|e| e.package.as_str()).collect();
+    if queue.is_empty() {
+        if !quiet {
+            match tag {
+                _ if filter.is_active() => output::status("No matching packages in queue"),
+                Some(tag) => output::status(&format!("No packages tagged '{tag}'")),
+                None => output::status("No packages in queue"),
+            }
+        }
+        return Ok(exit::SUCCESS);
+    }
 
-    // Step 3: Determine which packages to rebuild
-    let from_queue: Vec<String> = if packages.is_empty() {
-        // Rebuild all queued packages
-        queue.iter().map(|e| e.package.clone()).collect()
+    if let ListStyle::Long = style {
+        print_list_table(&db, &queue)?;
     } else {
-        // Rebuild specified packages
-        let mut result = Vec::new();
-        for pkg in packages {
-            if queue_set.contains(pkg.as_str()) {
-                result.push(pkg.clone());
-            } else if !force {
-                return Err(RebuildError::PackageNotInQueue(pkg.clone()).into());
+        for entry in &queue {
+            // Get the most recent trigger event for context
+            if let Some(event) = db.get_latest_event(&entry.package)? {
+                output::package_with_trigger(&entry.package, event.trigger_package.as_deref());
             } else {
-                // With -f, allow packages not in queue
-                result.push(pkg.clone());
+                output::aur_package(&entry.package);
             }
-        }
-        result
-    };
-
-    // Step 4: Add checkrebuild packages if requested
-    let mut from_checkrebuild: Vec<String> = Vec::new();
-    if checkrebuild || config.include_checkrebuild {
-        match run_checkrebuild() {
-            Ok(pkgs) => {
-                for pkg in pkgs {
-                    // Only add if not already in the list
-                    if !from_queue.contains(&pkg) {
-                        from_checkrebuild.push(pkg);
-                    }
+            if let ListStyle::Verbose = style {
+                println!("  queued since {}", display_time(&entry.first_marked_at));
+                let tags = db.get_tags(&entry.package)?;
+                if !tags.is_empty() {
+                    println!("  tags: {}", tags.join(", "));
+                }
+                if let Some(reason) = &entry.reason {
+                    println!("  reason: {reason}");
+                }
+                if entry.priority != Priority::Normal {
+                    println!("  priority: {}", entry.priority.as_str());
+                }
+                if entry.pinned {
+                    println!("  pinned");
+                } else if let Some(expires_at) = &entry.expires_at {
+                    println!("  expires {}", display_time(expires_at));
+                }
+                if let (Some(marked), Some(installed)) =
+                    (&entry.marked_version, stale.get(&entry.package))
+                {
+                    println!("  marked at {marked}, now {installed} (likely rebuilt already)");
                 }
-            }
-            Err(e) => {
-                // Warn but don't fail if checkrebuild isn't available
-                output::warning(&e.to_string());
             }
         }
     }
 
-    // Step 5: Check if there's anything to rebuild
-    let total_count = from_queue.len() + from_checkrebuild.len();
-    if total_count == 0 {
-        if !quiet {
-            output::status("No packages to rebuild");
+    if !quiet {
+        output::info(&format!("{} package(s) in queue", queue.len()));
+        if !stale.is_empty() {
+            output::info(&format!(
+                "{} package(s) changed version since marked and may be rebuilt already (anneal rebuild --unmark-stale)",
+                stale.len()
+            ));
         }
-        return Ok(exit::SUCCESS);
     }
 
-    // Step 6: Show packages and confirm
-    if !quiet {
-        if !from_queue.is_empty() {
-            output::header("From queue:");
-            for pkg in &from_queue {
-                eprintln!("  {pkg}");
+    Ok(code)
+}
+
+/// Print the queue as a table (`list --long`).
+fn print_list_table(db: &Database, queue: &[QueueEntry]) -> Result<(), Error> {
+    let mut rows = Vec::new();
+    for entry in queue {
+        let event = db.get_latest_event(&entry.package)?;
+        let trigger = event.as_ref().and_then(|e| e.trigger_package.as_deref());
+        let version = event.as_ref().and_then(|e| e.trigger_version.as_deref());
+        rows.push(vec![
+            entry.package.clone(),
+            trigger.unwrap_or("external").to_string(),
+            version.unwrap_or("-").to_string(),
+            display_timestamp(&entry.first_marked_at),
+            age_days(&entry.first_marked_at).map_or_else(|| "-".into(), |d| format!("{d}d")),
+        ]);
+    }
+    output::table(&["PACKAGE", "TRIGGER", "VERSION", "MARKED", "AGE"], &rows);
+    Ok(())
+}
+
+fn cmd_clear(
+    config: &Config,
+    force: bool,
+    trigger: Option<&str>,
+    tag: Option<&str>,
+    include_pinned: bool,
+    quiet: bool,
+) -> Result<u8, Error> {
+    let target = match (tag, trigger) {
+        (Some(tag), _) => ClearTarget::Tag(tag),
+        (None, Some(trigger_name)) => {
+            // Clear events for a specific trigger
+            let count = queue::clear_trigger(config, trigger_name)?;
+            if !quiet {
+                output::status(&format!(
+                    "Cleared {count} event(s) for trigger '{trigger_name}'"
+                ));
             }
+            return Ok(exit::SUCCESS);
         }
-        if !from_checkrebuild.is_empty() {
-            output::header("From checkrebuild:");
-            for pkg in &from_checkrebuild {
-                eprintln!("  {pkg}");
+        (None, None) => ClearTarget::Queue,
+    };
+
+    let clearable = queue::clearable(config, target, include_pinned)?;
+    if clearable.packages.is_empty() {
+        if !quiet {
+            match (target, clearable.pinned) {
+                (ClearTarget::Tag(tag), _) => {
+                    output::status(&format!("No packages tagged '{tag}'"));
+                }
+                (ClearTarget::Queue, 0) => output::status("Queue is already empty"),
+                (ClearTarget::Queue, n) => output::status(&format!(
+                    "Only pinned packages in queue ({n}, use --include-pinned)"
+                )),
             }
         }
+        return Ok(exit::SUCCESS);
     }
 
     if !force {
-        eprint!(":: Rebuild {total_count} package(s)? [y/N] ");
+        let count = clearable.packages.len();
+        let prompt = match target {
+            ClearTarget::Tag(tag) => tr!("prompt-clear-tagged", count = count, tag = tag),
+            ClearTarget::Queue => tr!("prompt-clear", count = count),
+        };
+        eprint!("{prompt} ");
         io::stderr().flush().ok();
 
         if !confirm()? {
@@ -435,39 +943,336 @@ fn cmd_rebuild(
         }
     }
 
-    // Step 7: Build and execute the helper command
-    let all_packages: Vec<&str> = from_queue
-        .iter()
-        .chain(from_checkrebuild.iter())
-        .map(String::as_str)
-        .collect();
+    let cleared = queue::clear(config, target, include_pinned)?;
+    if !quiet {
+        output::success_count("Cleared", cleared.len());
+    }
 
-    let status = ProcessCommand::new(&helper.command)
-        .args(&helper.base_args)
-        .args(&all_packages)
-        .args(helper_args)
-        .status()
-        .map_err(RebuildError::HelperSpawn)?;
-
-    // Step 8: Handle result
-    if status.success() {
-        // Unmark packages that were in the queue
-        if !from_queue.is_empty() {
-            let mut db = Database::open(config.retention_days)?;
-            for pkg in &from_queue {
-                db.unmark(pkg)?;
-            }
-        }
+    Ok(exit::SUCCESS)
+}
 
+fn cmd_clear_undo(config: &Config, quiet: bool) -> Result<u8, Error> {
+    let Some(restored) = queue::undo_clear(config)? else {
         if !quiet {
-            output::success_count("Successfully rebuilt", total_count);
+            output::status("Nothing to undo");
         }
-        Ok(exit::SUCCESS)
-    } else {
-        let code = status.code().unwrap_or(-1);
-        Err(RebuildError::HelperFailed(code).into())
-    }
-}
+        return Ok(exit::NOT_FOUND);
+    };
+
+    if !quiet {
+        output::success_count("Restored", restored.len());
+    }
+    Ok(exit::SUCCESS)
+}
+
+fn cmd_clean(config: &Config, dry_run: bool, quiet: bool, json: bool) -> Result<u8, Error> {
+    let Cleaned {
+        expired,
+        uninstalled,
+        stale,
+        replaced,
+        pruned_events: pruned,
+    } = queue::clean(config, dry_run)?;
+
+    if json {
+        let replaced: Vec<Value> = replaced
+            .iter()
+            .map(|(old, new)| json!({ "package": old, "replaced_by": new }))
+            .collect();
+        output::json(&json!({
+            "dry_run": dry_run,
+            "expired": expired,
+            "uninstalled": uninstalled,
+            "stale": stale,
+            "replaced": replaced,
+            "pruned_events": pruned,
+        }));
+        return Ok(exit::SUCCESS);
+    }
+    if quiet {
+        return Ok(exit::SUCCESS);
+    }
+
+    let (unmark, moved, prune) = if dry_run {
+        ("Would unmark", "Would move", "Would prune")
+    } else {
+        ("Unmarked", "Moved", "Pruned")
+    };
+    for pkg in &expired {
+        output::status(&format!("{unmark} {pkg} (expired)"));
+    }
+    for pkg in &uninstalled {
+        output::status(&format!("{unmark} {pkg} (no longer installed)"));
+    }
+    for pkg in &stale {
+        output::status(&format!(
+            "{unmark} {pkg} (changed version since marked, likely rebuilt)"
+        ));
+    }
+    for (old, new) in &replaced {
+        output::status(&format!("{moved} {old} to {new} (replaced)"));
+    }
+    if pruned > 0 {
+        output::status(&format!(
+            "{prune} {pruned} history record(s) older than {} days",
+            config.retention_days
+        ));
+    }
+    if expired.is_empty()
+        && uninstalled.is_empty()
+        && stale.is_empty()
+        && replaced.is_empty()
+        && pruned == 0
+    {
+        output::status("Nothing to clean");
+    }
+    Ok(exit::SUCCESS)
+}
+
+fn cmd_rebuild(
+    config: &Config,
+    opts: &RebuildOptions,
+    quiet: bool,
+    json: bool,
+) -> Result<u8, Error> {
+    let plan = plan_rebuild(config, opts, pick_rebuild_exclusions, quiet)?;
+    let mut skipped: Vec<String> = plan
+        .skipped_flagged
+        .iter()
+        .chain(plan.skipped_stale.iter())
+        .cloned()
+        .collect();
+
+    if plan.is_empty() {
+        if !quiet {
+            output::status("No packages to rebuild");
+        }
+        if json {
+            output::json(&rebuild_json(&[], &[], &skipped, None, &[]));
+        }
+        return Ok(exit::SUCCESS);
+    }
+
+    if opts.dry_run {
+        print_rebuild_commands(&plan.commands()?, json);
+        return Ok(exit::SUCCESS);
+    }
+
+    // Show packages and confirm
+    if !quiet {
+        if !plan.from_queue.is_empty() {
+            output::header("From queue:");
+            for pkg in &plan.from_queue {
+                match plan.sources.get(pkg) {
+                    Some(path) => eprintln!("  {pkg} (local: {path})"),
+                    None => eprintln!("  {pkg}"),
+                }
+            }
+        }
+        if plan.excluded > 0 {
+            output::info(&format!(
+                "{} queued package(s) excluded (--exclude)",
+                plan.excluded
+            ));
+        }
+        if plan.held_back > 0 {
+            output::info(&format!(
+                "{} newer queued package(s) left for later (--limit)",
+                plan.held_back
+            ));
+        }
+        if !plan.from_checkrebuild.is_empty() {
+            output::header("With broken linkage:");
+            for pkg in &plan.from_checkrebuild {
+                eprintln!("  {pkg}");
+            }
+        }
+        if plan.stages.len() > 1 {
+            output::header("Build order:");
+            for (idx, stage) in plan.stages.iter().enumerate() {
+                eprintln!("  {}. {}", idx + 1, stage.join(" "));
+            }
+        }
+    }
+
+    if !opts.force {
+        let count = plan.packages().len();
+        match plan.estimate() {
+            Some(secs) => eprint!(
+                "{} ",
+                tr!(
+                    "prompt-rebuild-estimate",
+                    count = count,
+                    estimate = format_estimate(secs)
+                )
+            ),
+            None => eprint!("{} ", tr!("prompt-rebuild", count = count)),
+        }
+        io::stderr().flush().ok();
+
+        if !confirm()? {
+            if !quiet {
+                output::status("Cancelled");
+            }
+            return Ok(exit::SUCCESS);
+        }
+    }
+
+    let started = std::time::Instant::now();
+    let report = run_rebuild(config, &plan, quiet)?;
+    skipped.extend(report.skipped.iter().cloned());
+
+    if let Some(e) = report.stopped {
+        if !report.skipped.is_empty() {
+            output::warning(&format!(
+                "Skipped {} package(s) ordered after the failed build",
+                report.skipped.len()
+            ));
+        }
+        if !quiet {
+            print_rebuild_summary(&report.rebuilt, &report.failed, &skipped, started);
+        }
+        if json {
+            output::json(&rebuild_json(
+                &report.rebuilt,
+                &report.failed,
+                &skipped,
+                report.exit_code,
+                &[],
+            ));
+        }
+        return Err(e);
+    }
+
+    if !quiet && !report.rebuilt.is_empty() {
+        output::success_count("Successfully rebuilt", report.rebuilt.len());
+    }
+
+    let problems = if (opts.validate || config.validate_builds) && !report.rebuilt.is_empty() {
+        report_validation(config, &report.rebuilt, quiet)
+    } else {
+        Vec::new()
+    };
+    if !quiet {
+        print_rebuild_summary(&report.rebuilt, &report.failed, &skipped, started);
+    }
+    if json {
+        output::json(&rebuild_json(
+            &report.rebuilt,
+            &report.failed,
+            &skipped,
+            report.exit_code,
+            &problems,
+        ));
+    }
+
+    // Only reachable with --keep-going: failures are reported at the end
+    if !report.failed.is_empty() {
+        output::error(&format!(
+            "Failed to rebuild {} package(s): {}",
+            report.failed.len(),
+            report.failed.join(", ")
+        ));
+        return Ok(exit::ERROR);
+    }
+    Ok(exit::SUCCESS)
+}
+
+/// JSON result of `rebuild`.
+///
+/// `exit_code` is the failing helper's (or makepkg's) exit code, if a build
+/// failed.
+fn rebuild_json(
+    rebuilt: &[String],
+    failed: &[String],
+    skipped: &[String],
+    exit_code: Option<i32>,
+    problems: &[validate::Problem],
+) -> Value {
+    let problems: Vec<Value> = problems
+        .iter()
+        .map(|p| json!({ "package": p.package, "check": p.check.as_str(), "message": p.message }))
+        .collect();
+    json!({
+        "rebuilt": rebuilt,
+        "failed": failed,
+        "skipped": skipped,
+        "exit_code": exit_code,
+        "problems": problems,
+    })
+}
+
+/// Print the table summing up a rebuild that got to building.
+///
+/// Failed and skipped packages are listed by name; what's still queued is
+/// read back from the database.
+fn print_rebuild_summary(
+    rebuilt: &[String],
+    failed: &[String],
+    skipped: &[String],
+    started: std::time::Instant,
+) {
+    let still_queued = open_readonly()
+        .and_then(|db| Ok(db.list()?.len()))
+        .map_or_else(|_| "?".to_string(), |n| n.to_string());
+    let with_names = |packages: &[String]| {
+        if packages.is_empty() {
+            "0".to_string()
+        } else {
+            format!("{}  {}", packages.len(), packages.join(", "))
+        }
+    };
+
+    output::header("Rebuild summary:");
+    let rows = [
+        ("Rebuilt", rebuilt.len().to_string()),
+        ("Failed", with_names(failed)),
+        ("Skipped", with_names(skipped)),
+        ("Still queued", still_queued),
+        ("Time", format_elapsed(started.elapsed().as_secs())),
+    ];
+    for (label, value) in rows {
+        eprintln!("  {label:<14}{value}");
+    }
+}
+
+/// Validate rebuilt packages and report packages that are likely still
+/// broken.
+///
+/// Validation never fails the rebuild: the packages did build and install.
+fn report_validation(config: &Config, packages: &[String], quiet: bool) -> Vec<validate::Problem> {
+    if !quiet {
+        output::info("Validating rebuilt packages...");
+    }
+    let packages: Vec<&str> = packages.iter().map(String::as_str).collect();
+    let problems = match validate_rebuilt(config, &packages) {
+        Ok(problems) => problems,
+        Err(e) => {
+            output::warning(&format!("validation failed: {e}"));
+            return Vec::new();
+        }
+    };
+
+    if problems.is_empty() {
+        if !quiet {
+            output::status("No problems found");
+        }
+        return problems;
+    }
+
+    let broken: HashSet<&str> = problems.iter().map(|p| p.package.as_str()).collect();
+    output::warning(&format!(
+        "{} rebuilt package(s) may still be broken:",
+        broken.len()
+    ));
+    for problem in &problems {
+        eprintln!(
+            "  {}: [{}] {}",
+            problem.package, problem.check, problem.message
+        );
+    }
+    problems
+}
 
 fn cmd_ismarked(package: &str) -> Result<u8, Error> {
     let db = open_readonly()?;
@@ -479,121 +1284,1641 @@ fn cmd_ismarked(package: &str) -> Result<u8, Error> {
     }
 }
 
-fn cmd_query(packages: &[String], quiet: bool) -> Result<u8, Error> {
-    let db = open_readonly()?;
-    let pkg_refs: Vec<&str> = packages.iter().map(String::as_str).collect();
-    let found = db.query(&pkg_refs)?;
+fn cmd_query(packages: &[String], json: bool, porcelain: bool, null: bool) -> Result<u8, Error> {
+    let pkg_refs: Vec<&str> = packages.iter().map(String::as_str).collect();
+    // Nothing is queued if the database doesn't exist yet
+    let found = match open_readonly() {
+        Ok(db) => db.query(&pkg_refs)?,
+        Err(Error::NoDatabase) => Vec::new(),
+        Err(e) => return Err(e),
+    };
+
+    if json {
+        let queued: serde_json::Map<String, Value> = packages
+            .iter()
+            .map(|pkg| (pkg.clone(), json!(found.contains(pkg))))
+            .collect();
+        output::json(&Value::Object(queued));
+    } else if porcelain {
+        for pkg in packages {
+            let state = if found.contains(pkg) {
+                "queued"
+            } else {
+                "absent"
+            };
+            output::porcelain(&[pkg, state]);
+        }
+    } else {
+        for pkg in &found {
+            if null {
+                output::null_terminated(pkg);
+            } else {
+                println!("{pkg}");
+            }
+        }
+    }
+
+    let all = pkg_refs.iter().all(|pkg| found.iter().any(|f| f == pkg));
+    Ok(if all {
+        exit::SUCCESS
+    } else if found.is_empty() {
+        exit::NOT_FOUND
+    } else {
+        exit::PARTIAL
+    })
+}
+
+fn cmd_timeline(package: &str, quiet: bool) -> Result<u8, Error> {
+    let db = open_readonly()?;
+
+    // (timestamp, description); ISO8601 timestamps sort chronologically
+    let mut entries: Vec<(String, String)> = Vec::new();
+
+    for event in db.get_events(package)? {
+        let description = match (event.trigger_package, event.trigger_version) {
+            (Some(trigger), Some(version)) => format!("marked by {trigger} {version}"),
+            (Some(trigger), None) => format!("marked by {trigger}"),
+            (None, _) => "marked (external)".to_string(),
+        };
+        entries.push((event.marked_at, description));
+    }
+
+    for event in db.get_queue_events(package)? {
+        let description = describe_queue_event(&event);
+        entries.push((event.at, description));
+    }
+
+    if entries.is_empty() {
+        if !quiet {
+            output::status(&format!("No history for {package}"));
+        }
+        return Ok(exit::NOT_FOUND);
+    }
+
+    // Stable sort keeps marks before queue events recorded in the same instant
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    if !quiet {
+        output::header(package);
+    }
+    for (at, description) in &entries {
+        println!("{}  {description}", display_time(at));
+    }
+
+    Ok(exit::SUCCESS)
+}
+
+/// Describe a queue event for `timeline` and `why`.
+fn describe_queue_event(event: &QueueEvent) -> String {
+    let description = match (event.action, &event.detail) {
+        (QueueAction::Unmarked, _) => "unmarked".to_string(),
+        (QueueAction::Cleared, _) => "cleared from queue".to_string(),
+        (QueueAction::Rebuilt, _) => "rebuilt".to_string(),
+        (QueueAction::RebuildFailed, Some(code)) => {
+            format!("rebuild failed (exit code {code})")
+        }
+        (QueueAction::RebuildFailed, None) => "rebuild failed".to_string(),
+        (QueueAction::Reinstalled, _) => "reinstalled outside anneal".to_string(),
+        (QueueAction::Uninstalled, _) => "no longer installed".to_string(),
+        (QueueAction::Renamed, Some(old)) => format!("took over queue entry of {old}"),
+        (QueueAction::Renamed, None) => "renamed".to_string(),
+        (QueueAction::Restored, _) => "restored by clear --undo".to_string(),
+        (QueueAction::Expired, _) => "expired (mark --expires)".to_string(),
+    };
+    // Rebuild events can only come from rebuild
+    match event.origin {
+        Some(Origin::Cli) => format!("{description} (command line)"),
+        Some(Origin::Hook) => format!("{description} (pacman hook)"),
+        Some(Origin::Rebuild) | None => description,
+    }
+}
+
+fn cmd_why(package: &str, quiet: bool, json: bool) -> Result<u8, Error> {
+    let db = open_readonly()?;
+
+    let Some(entry) = db.list()?.into_iter().find(|e| e.package == package) else {
+        if json {
+            output::json(&Value::Null);
+        } else if !quiet {
+            // Say how it left the queue, if it ever was queued
+            let left = db
+                .get_queue_events(package)?
+                .into_iter()
+                .rfind(|e| e.action != QueueAction::RebuildFailed);
+            match left {
+                Some(event) => output::status(&format!(
+                    "{package} is not in the queue: {} at {}",
+                    describe_queue_event(&event),
+                    display_time(&event.at)
+                )),
+                None => output::status(&format!("{package} is not in the queue")),
+            }
+        }
+        return Ok(exit::NOT_FOUND);
+    };
+
+    // Only marks from the current stay are in the queue, oldest first
+    let mut events = db.get_events(package)?;
+    events.retain(|e| e.marked_at >= entry.first_marked_at);
+    events.reverse();
+    let tags = db.get_tags(package)?;
+
+    let overrides = Overrides::load();
+    let via = |trigger: &str| match overrides.trigger_override(trigger) {
+        Some(TriggerOverride::Patterns(patterns)) => {
+            let pattern = patterns.iter().find(|p| matches_glob(p, package));
+            match pattern {
+                Some(pattern) => format!(
+                    "trigger override {}/{trigger}.conf (pattern {pattern})",
+                    overrides::TRIGGERS_DIR
+                ),
+                None => format!(
+                    "trigger override {}/{trigger}.conf",
+                    overrides::TRIGGERS_DIR
+                ),
+            }
+        }
+        Some(TriggerOverride::Disabled) => format!(
+            "trigger since disabled by {}/{trigger}.conf",
+            overrides::TRIGGERS_DIR
+        ),
+        None => format!("{package} depends on {trigger}"),
+    };
+    let installed = stale_versions(std::slice::from_ref(&entry)).remove(package);
+    let package_override = overrides.package_override(package).map(|o| match o {
+        PackageOverride::OnlyTriggers(triggers) => format!(
+            "{}/{package}.conf only allows {}",
+            overrides::PACKAGES_DIR,
+            triggers.join(", ")
+        ),
+        PackageOverride::NeverMark => format!(
+            "{}/{package}.conf now blocks all triggers",
+            overrides::PACKAGES_DIR
+        ),
+    });
+
+    if json {
+        let marks: Vec<Value> = events
+            .iter()
+            .map(|e| {
+                json!({
+                    "trigger": e.trigger_package,
+                    "trigger_version": e.trigger_version,
+                    "marked_at": e.marked_at,
+                    "via": e.trigger_package.as_deref().map(via),
+                })
+            })
+            .collect();
+        output::json(&json!({
+            "package": package,
+            "first_marked_at": entry.first_marked_at,
+            "tags": tags,
+            "reason": entry.reason,
+            "pinned": entry.pinned,
+            "priority": entry.priority.as_str(),
+            "expires_at": entry.expires_at,
+            "marked_version": entry.marked_version,
+            "stale": installed.is_some(),
+            "marks": marks,
+            "package_override": package_override,
+        }));
+        return Ok(exit::SUCCESS);
+    }
+
+    if !quiet {
+        output::header(&format!(
+            "{package} queued since {}",
+            display_time(&entry.first_marked_at)
+        ));
+    }
+    if events.is_empty() {
+        // Events are pruned after retention_days
+        println!("  no trigger events recorded");
+    }
+    for event in &events {
+        let at = display_time(&event.marked_at);
+        match (&event.trigger_package, &event.trigger_version) {
+            (Some(trigger), version) => {
+                let version = version
+                    .as_deref()
+                    .map_or(String::new(), |v| format!(" {v}"));
+                println!("  {at}  {trigger}{version}: {}", via(trigger));
+            }
+            (None, _) => println!("  {at}  marked externally (anneal mark or a package hook)"),
+        }
+    }
+    if !tags.is_empty() {
+        println!("  tags: {}", tags.join(", "));
+    }
+    if let Some(reason) = &entry.reason {
+        println!("  reason: {reason}");
+    }
+    if entry.priority != Priority::Normal {
+        println!("  priority: {}", entry.priority.as_str());
+    }
+    if entry.pinned {
+        println!("  pinned: stays queued after rebuilds and clears (anneal unpin)");
+    } else if let Some(expires_at) = &entry.expires_at {
+        println!(
+            "  expires: {} (unless marked again)",
+            display_time(expires_at)
+        );
+    }
+    if let Some(marked_version) = &entry.marked_version {
+        match &installed {
+            Some(installed) => println!(
+                "  version: marked at {marked_version}, now {installed} (likely rebuilt already)"
+            ),
+            None => println!("  version: marked at {marked_version}"),
+        }
+    }
+    if let Some(package_override) = package_override {
+        println!("  package override: {package_override}");
+    }
+
+    Ok(exit::SUCCESS)
+}
+
+fn cmd_history(
+    package: &str,
+    limit: Option<usize>,
+    reverse: bool,
+    quiet: bool,
+    style: HistoryStyle,
+) -> Result<u8, Error> {
+    let db = open_readonly()?;
+
+    // Newest first; the limit applies before reversing so `-n 5 -r` shows
+    // the five most recent events in chronological order
+    let mut events = db.get_events(package)?;
+    if let Some(limit) = limit {
+        events.truncate(limit);
+    }
+    if reverse {
+        events.reverse();
+    }
+
+    if style == HistoryStyle::Json {
+        let code = if events.is_empty() {
+            exit::NOT_FOUND
+        } else {
+            exit::SUCCESS
+        };
+        let events: Vec<Value> = events
+            .iter()
+            .map(|e| {
+                json!({
+                    "trigger": e.trigger_package,
+                    "trigger_version": e.trigger_version,
+                    "marked_at": e.marked_at,
+                })
+            })
+            .collect();
+        output::json(&Value::Array(events));
+        return Ok(code);
+    }
+
+    if events.is_empty() {
+        if !quiet {
+            output::status(&format!("No trigger events for {package}"));
+        }
+        return Ok(exit::NOT_FOUND);
+    }
+
+    if style == HistoryStyle::Porcelain {
+        for event in &events {
+            output::porcelain(&[
+                &event.marked_at,
+                event.trigger_package.as_deref().unwrap_or("-"),
+                event.trigger_version.as_deref().unwrap_or("-"),
+            ]);
+        }
+        return Ok(exit::SUCCESS);
+    }
+
+    for event in &events {
+        let trigger = match (&event.trigger_package, &event.trigger_version) {
+            (Some(trigger), Some(version)) => format!("{trigger} {version}"),
+            (Some(trigger), None) => trigger.clone(),
+            (None, _) => "external".to_string(),
+        };
+        println!("{}  {trigger}", display_time(&event.marked_at));
+    }
+
+    Ok(exit::SUCCESS)
+}
+
+fn cmd_rebuild_history(
+    package: &str,
+    limit: Option<usize>,
+    reverse: bool,
+    quiet: bool,
+    style: HistoryStyle,
+) -> Result<u8, Error> {
+    let db = open_readonly()?;
+
+    let mut records = db.get_rebuilds(package)?;
+    if let Some(limit) = limit {
+        records.truncate(limit);
+    }
+    if reverse {
+        records.reverse();
+    }
+
+    if style == HistoryStyle::Json {
+        let code = if records.is_empty() {
+            exit::NOT_FOUND
+        } else {
+            exit::SUCCESS
+        };
+        let records: Vec<Value> = records
+            .iter()
+            .map(|r| {
+                json!({
+                    "started_at": r.started_at,
+                    "finished_at": r.finished_at,
+                    "exit_code": r.exit_code,
+                    "helper": r.helper,
+                })
+            })
+            .collect();
+        output::json(&Value::Array(records));
+        return Ok(code);
+    }
+
+    if records.is_empty() {
+        if !quiet {
+            output::status(&format!("No rebuild attempts for {package}"));
+        }
+        return Ok(exit::NOT_FOUND);
+    }
+
+    if style == HistoryStyle::Porcelain {
+        for record in &records {
+            let exit_code = record
+                .exit_code
+                .map_or_else(|| "-".to_string(), |code| code.to_string());
+            output::porcelain(&[
+                &record.started_at,
+                &record.finished_at,
+                &exit_code,
+                &record.helper,
+            ]);
+        }
+        return Ok(exit::SUCCESS);
+    }
+
+    for record in &records {
+        let outcome = match record.exit_code {
+            Some(0) => "ok".to_string(),
+            Some(code) => format!("failed (exit {code})"),
+            None => "killed".to_string(),
+        };
+        println!(
+            "{}  {}  {outcome}",
+            display_time(&record.started_at),
+            record.helper
+        );
+    }
+
+    Ok(exit::SUCCESS)
+}
+
+fn cmd_count(quiet: bool, json: bool) -> Result<u8, Error> {
+    // Nothing has been marked yet if the database doesn't exist
+    let count = match open_readonly() {
+        Ok(db) => db.count()?,
+        Err(Error::NoDatabase) => 0,
+        Err(e) => return Err(e),
+    };
+
+    if json {
+        output::json(&json!(count));
+    } else if !quiet {
+        println!("{count}");
+    }
+
+    Ok(if count == 0 {
+        exit::NOT_FOUND
+    } else {
+        exit::SUCCESS
+    })
+}
+
+fn cmd_status(waybar: bool, quiet: bool, json: bool) -> Result<u8, Error> {
+    // The database is created by the first mark
+    let queue = match open_readonly() {
+        Ok(db) => db.list()?,
+        Err(Error::NoDatabase) => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    // Higher priorities are listed first, so look for the oldest
+    let oldest = queue
+        .iter()
+        .min_by(|a, b| a.first_marked_at.cmp(&b.first_marked_at));
+
+    let summary = match oldest {
+        None => "Queue empty".to_string(),
+        Some(entry) => {
+            let age =
+                format_age(&entry.first_marked_at).unwrap_or_else(|| entry.first_marked_at.clone());
+            match queue.len() {
+                1 => format!("1 package queued for {age}"),
+                n => format!("{n} packages queued, oldest for {age}"),
+            }
+        }
+    };
+
+    if waybar {
+        let (tooltip, class) = if queue.is_empty() {
+            (summary, "empty")
+        } else {
+            let packages: Vec<&str> = queue.iter().map(|e| e.package.as_str()).collect();
+            (format!("{summary}\n{}", packages.join("\n")), "pending")
+        };
+        // Waybar reads one JSON object per line, even with --quiet
+        output::json(&json!({
+            "text": queue.len().to_string(),
+            "tooltip": tooltip,
+            "class": class,
+        }));
+        return Ok(exit::SUCCESS);
+    }
+
+    if json {
+        output::json(&json!({
+            "queued": queue.len(),
+            "oldest": oldest.map(|entry| json!({
+                "package": entry.package,
+                "first_marked_at": entry.first_marked_at,
+            })),
+        }));
+    } else if !quiet {
+        println!("{summary}");
+    }
+
+    Ok(exit::SUCCESS)
+}
+
+fn cmd_stats(config: &Config, quiet: bool, json: bool) -> Result<u8, Error> {
+    let db = open_readonly()?;
+
+    // History older than the retention period is pruned on the next write
+    let since = if config.retention_days == 0 {
+        String::new()
+    } else {
+        cutoff_date(config.retention_days)
+    };
+    let stats = db.stats(&since)?;
+
+    if json {
+        let queued_by_trigger: Vec<Value> = stats
+            .queued_by_trigger
+            .iter()
+            .map(|(trigger, count)| json!({ "trigger": trigger, "count": count }))
+            .collect();
+        let busiest: Vec<Value> = stats
+            .busiest_triggers
+            .iter()
+            .map(|(trigger, marks)| json!({ "trigger": trigger, "marks": marks }))
+            .collect();
+        output::json(&json!({
+            "queued": stats.queued,
+            "queued_by_trigger": queued_by_trigger,
+            "retention_days": config.retention_days,
+            "busiest_triggers": busiest,
+            "external_marks": stats.external_marks,
+            "dequeued": stats.dequeued,
+            "average_wait_secs": stats.average_wait_secs,
+        }));
+        return Ok(exit::SUCCESS);
+    }
+
+    if quiet {
+        return Ok(exit::SUCCESS);
+    }
+
+    let noun = |n: usize| if n == 1 { "package" } else { "packages" };
+
+    output::header(&format!("Queue: {} {}", stats.queued, noun(stats.queued)));
+    let width = stats
+        .queued_by_trigger
+        .iter()
+        .map(|(trigger, _)| trigger.as_deref().map_or(8, str::len))
+        .max()
+        .unwrap_or(0);
+    for (trigger, count) in &stats.queued_by_trigger {
+        println!(
+            "{:<width$}  {count}",
+            trigger.as_deref().unwrap_or("external")
+        );
+    }
+
+    if config.retention_days == 0 {
+        output::header("All history");
+    } else {
+        output::header(&format!("Last {} days", config.retention_days));
+    }
+    if stats.busiest_triggers.is_empty() {
+        println!("Busiest triggers: none");
+    } else {
+        println!("Busiest triggers:");
+        let top = &stats.busiest_triggers[..stats.busiest_triggers.len().min(STATS_TOP_TRIGGERS)];
+        let width = top
+            .iter()
+            .map(|(trigger, _)| trigger.len())
+            .max()
+            .unwrap_or(0);
+        for (trigger, marks) in top {
+            let word = if *marks == 1 { "mark" } else { "marks" };
+            println!("  {trigger:<width$}  {marks} {word}");
+        }
+    }
+    println!("External marks: {}", stats.external_marks);
+    match stats.average_wait_secs {
+        Some(secs) => println!(
+            "Average time in queue: {} ({} {} left the queue)",
+            format_duration(secs),
+            stats.dequeued,
+            noun(stats.dequeued)
+        ),
+        None => println!("Average time in queue: no packages left the queue"),
+    }
+
+    Ok(exit::SUCCESS)
+}
+
+fn cmd_backup(file: &Path, quiet: bool) -> Result<u8, Error> {
+    let db = open_readonly()?;
+    let queued = backup::write(&db, file)?;
+
+    if !quiet {
+        output::status(&format!(
+            "Saved {queued} queued package(s) and history to {}",
+            file.display()
+        ));
+    }
+    Ok(exit::SUCCESS)
+}
+
+fn cmd_restore(config: &Config, file: &Path, force: bool, quiet: bool) -> Result<u8, Error> {
+    let backup = backup::read(file)?;
+    let restored = backup.queued();
+    let mut db = Database::open(config.retention_days)?;
+
+    if !force {
+        let queued = db.count()?;
+        eprint!(
+            "{} ",
+            tr!(
+                "prompt-restore",
+                queued = queued,
+                created = display_timestamp(&backup.created_at),
+                count = restored.len()
+            )
+        );
+        io::stderr().flush().ok();
+
+        if !confirm()? {
+            if !quiet {
+                output::status("Cancelled");
+            }
+            return Ok(exit::SUCCESS);
+        }
+    }
+
+    backup::restore(&mut db, &backup)?;
+    let names: Vec<&str> = restored.iter().map(String::as_str).collect();
+    audit::record(config, "restore", &names);
+    if !quiet {
+        output::success_count("Restored", restored.len());
+    }
+    Ok(exit::SUCCESS)
+}
+
+fn cmd_triggers(config: &Config, quiet: bool, json: bool) -> Result<u8, Error> {
+    let overrides = Overrides::load();
+    let triggers = list_all_triggers(&overrides, config.version_threshold);
+    let disabled = |name: &str| {
+        matches!(
+            overrides.trigger_override(name),
+            Some(TriggerOverride::Disabled)
+        )
+    };
+
+    if json {
+        let triggers: Vec<Value> = triggers
+            .iter()
+            .map(|(name, threshold)| {
+                json!({
+                    "name": name,
+                    "threshold": threshold.as_str(),
+                    "user": !is_curated_trigger(name),
+                    "disabled": disabled(name),
+                })
+            })
+            .collect();
+        output::json(&json!({ "version": curated().version, "triggers": triggers }));
+        return Ok(exit::SUCCESS);
+    }
+
+    if !quiet {
+        output::header(&format!(
+            "Triggers (curated list v{} and user triggers)",
+            curated().version
+        ));
+    }
+
+    for (name, threshold) in &triggers {
+        if quiet {
+            output::package(name);
+            continue;
+        }
+        let mut notes = vec![threshold.as_str()];
+        if !is_curated_trigger(name) {
+            notes.push("user");
+        } else if disabled(name) {
+            notes.push("disabled");
+        } else if overrides.is_user_trigger(name) {
+            notes.push("overridden");
+        }
+        output::package(&format!("{name} ({})", notes.join(", ")));
+    }
+
+    Ok(exit::SUCCESS)
+}
+
+fn cmd_ignore(packages: &[String], list: bool, quiet: bool, json: bool) -> Result<u8, Error> {
+    let dir = PathBuf::from(overrides::PACKAGES_DIR);
+
+    if list {
+        let ignored: Vec<String> = overrides::list_overrides(&dir)?
+            .into_iter()
+            .filter(|file| file.entries.is_empty())
+            .map(|file| file.name)
+            .collect();
+        if json {
+            output::json(&json!(ignored));
+        } else if ignored.is_empty() {
+            if !quiet {
+                output::status("No ignored packages");
+            }
+        } else {
+            for pkg in &ignored {
+                output::package(pkg);
+            }
+        }
+        return Ok(exit::SUCCESS);
+    }
+
+    // Check everything first so a bad name doesn't leave half the list ignored
+    let mut new = Vec::new();
+    for pkg in packages {
+        overrides::validate_name(pkg)?;
+        match overrides::read_override(&dir, pkg)? {
+            Some(file) if file.entries.is_empty() => {}
+            Some(_) => {
+                return Err(Error::Usage(format!(
+                    "{pkg} has a package override. Remove it first with 'anneal override remove package {pkg}'."
+                )));
+            }
+            None => new.push(pkg.as_str()),
+        }
+    }
+
+    for pkg in &new {
+        overrides::add_override(&dir, pkg, &[])?;
+    }
+
+    if !quiet {
+        output::success_count("Ignored", new.len());
+    }
+    Ok(exit::SUCCESS)
+}
+
+fn cmd_unignore(packages: &[String], quiet: bool) -> Result<u8, Error> {
+    let dir = PathBuf::from(overrides::PACKAGES_DIR);
+    let mut removed = 0;
+    let mut not_ignored = Vec::new();
+
+    for pkg in packages {
+        // Only empty overrides are ignores; leave ones with entries alone
+        match overrides::read_override(&dir, pkg)? {
+            Some(file) if file.entries.is_empty() => {
+                overrides::remove_override(&dir, pkg, &[])?;
+                removed += 1;
+            }
+            _ => not_ignored.push(pkg.as_str()),
+        }
+    }
+
+    if !quiet {
+        output::success_count("Unignored", removed);
+    }
+
+    if !not_ignored.is_empty() {
+        output::warning(&format!("Not ignored: {}", not_ignored.join(", ")));
+        return Ok(exit::NOT_FOUND);
+    }
+    Ok(exit::SUCCESS)
+}
+
+fn cmd_update_triggers(config: &Config, quiet: bool) -> Result<u8, Error> {
+    let update = triggers::update_list(&config.trigger_list_url, &NetOptions::from_config(config))?;
+    if quiet {
+        return Ok(exit::SUCCESS);
+    }
+    match update {
+        ListUpdate::UpToDate(version) => {
+            output::status(&format!("Trigger list is up to date (v{version})"));
+        }
+        ListUpdate::Updated {
+            from,
+            to,
+            added,
+            removed,
+            changed,
+        } => {
+            output::status(&format!("Updated trigger list from v{from} to v{to}"));
+            let changed: Vec<String> = changed
+                .iter()
+                .map(|(name, old, new)| format!("{name} ({} -> {})", old.as_str(), new.as_str()))
+                .collect();
+            for (label, names) in [
+                ("Added", added.join(", ")),
+                ("Removed", removed.join(", ")),
+                ("Changed", changed.join(", ")),
+            ] {
+                if !names.is_empty() {
+                    output::status(&format!("{label}: {names}"));
+                }
+            }
+        }
+    }
+    Ok(exit::SUCCESS)
+}
+
+fn cmd_trigger_def(action: TriggerDefAction, quiet: bool) -> Result<u8, Error> {
+    let dir = PathBuf::from(overrides::TRIGGERS_DIR);
+    let curated_error = |name: &str| {
+        Error::Usage(format!(
+            "{name} is a curated trigger. Use 'anneal override' to change what it marks."
+        ))
+    };
+
+    match action {
+        TriggerDefAction::Add { name, patterns } => {
+            if is_curated_trigger(&name) {
+                return Err(curated_error(&name));
+            }
+            let existed = overrides::read_override(&dir, &name)?.is_some();
+            let added = overrides::add_override(&dir, &name, &patterns)?;
+            if !quiet {
+                if !existed {
+                    output::status(&format!(
+                        "Defined trigger {name}: marks {}",
+                        added.join(", ")
+                    ));
+                } else if added.is_empty() {
+                    output::status("Nothing to add");
+                } else {
+                    output::status(&format!("Added to trigger {name}: {}", added.join(", ")));
+                }
+            }
+            Ok(exit::SUCCESS)
+        }
+
+        TriggerDefAction::Remove { name } => {
+            if is_curated_trigger(&name) {
+                return Err(curated_error(&name));
+            }
+            if overrides::remove_override(&dir, &name, &[])?.is_none() {
+                if !quiet {
+                    output::status(&format!("{name} is not a user trigger"));
+                }
+                return Ok(exit::NOT_FOUND);
+            }
+            if !quiet {
+                output::status(&format!("Removed trigger {name}"));
+            }
+            Ok(exit::SUCCESS)
+        }
+    }
+}
+
+/// Run as the pacman hook (`trigger --hook`).
+///
+/// Old versions come from `capture-versions`, if the PreTransaction hook ran
+/// it. Failures also go to the system log, since the hook's output is easily
+/// missed in a long transaction.
+fn cmd_trigger_hook(config: &Config, quiet: bool, json: bool) -> Result<u8, Error> {
+    let captured = transaction::take_captured_versions();
+    let result = with_versions(&read_stdin_packages(), &captured, &Overrides::load())
+        .map_err(Error::from)
+        .and_then(|packages| cmd_trigger(config, false, packages, false, true, quiet, json));
+    if let Err(e) = &result {
+        hook_log(syslog::Level::Error, &e.to_string());
+    }
+    result
+}
+
+/// Save the installed versions of triggers about to be upgraded
+/// (`capture-versions`, run by the PreTransaction hook).
+///
+/// Failures also go to the system log, like `trigger --hook`.
+fn cmd_capture_versions() -> Result<u8, Error> {
+    let result = transaction::capture_versions(&read_stdin_packages());
+    if let Err(e) = &result {
+        hook_log(syslog::Level::Error, &e.to_string());
+    }
+    result.map(|()| exit::SUCCESS)
+}
+
+fn cmd_trigger(
+    config: &Config,
+    dry_run: bool,
+    packages: Vec<String>,
+    null: bool,
+    hook: bool,
+    quiet: bool,
+    json: bool,
+) -> Result<u8, Error> {
+    let packages = if packages.is_empty() {
+        read_stdin_packages()
+    } else {
+        packages
+    };
+
+    if packages.is_empty() {
+        if json {
+            output::json(&trigger_json(dry_run, &[], &HashSet::new()));
+        }
+        return Ok(exit::SUCCESS);
+    }
+
+    if !dry_run {
+        let update = transaction::update_queue(config, &packages)?;
+        if !quiet {
+            for (old, new) in &update.moved {
+                output::status(&format!("Moved {old} to {new} (replaced)"));
+            }
+            for pkg in &update.uninstalled {
+                output::status(&format!("Unmarked {pkg} (no longer installed)"));
+            }
+            for pkg in &update.reinstalled {
+                output::status(&format!("Unmarked {pkg} (reinstalled)"));
+            }
+        }
+    }
+
+    let Dependents {
+        result,
+        newly_marked,
+    } = transaction::mark_dependents(config, &packages, dry_run, quiet)?;
+
+    // Report packages skipped due to version threshold
+    if !quiet && !result.below_threshold.is_empty() {
+        output::info(&format!(
+            "Skipped {} trigger(s) below threshold",
+            result.below_threshold.len(),
+        ));
+    }
+
+    // Report triggers that kept their sonames
+    if !quiet && !result.same_sonames.is_empty() {
+        output::info(&format!(
+            "Skipped {} trigger(s) with unchanged sonames",
+            result.same_sonames.len(),
+        ));
+    }
+
+    // Report architecture-independent dependents
+    if !quiet && !result.skipped_any_arch.is_empty() {
+        output::info(&format!(
+            "Skipped {} architecture-independent package(s)",
+            result.skipped_any_arch.len(),
+        ));
+    }
+
+    if result.marked.is_empty() {
+        if !quiet {
+            output::info("No packages to mark");
+        }
+        if json {
+            output::json(&trigger_json(dry_run, &[], &HashSet::new()));
+        }
+        return Ok(exit::SUCCESS);
+    }
+
+    if dry_run {
+        // Just print what would be marked
+        if json {
+            output::json(&trigger_json(true, &result.marked, &HashSet::new()));
+        } else if null {
+            for m in &result.marked {
+                output::null_terminated(&m.package);
+            }
+        } else {
+            for m in &result.marked {
+                output::package_with_trigger(&m.package, Some(&m.trigger));
+            }
+        }
+        if !quiet {
+            output::info(&format!(
+                "Would mark {} package(s) for rebuild",
+                result.marked.len()
+            ));
+        }
+        return Ok(exit::SUCCESS);
+    }
+
+    for m in &newly_marked {
+        let msg = format!("Marked {} (triggered by {})", m.package, m.trigger);
+        if hook {
+            hook_log(syslog::Level::Info, &msg);
+        }
+        if !quiet {
+            output::status(&msg);
+        }
+    }
+    if !quiet {
+        output::info(&format!(
+            "Marked {} package(s) for rebuild",
+            newly_marked.len()
+        ));
+    }
+    transaction::announce(config, &newly_marked, quiet);
+    if json {
+        let new: HashSet<&str> = newly_marked.iter().map(|m| m.package.as_str()).collect();
+        output::json(&trigger_json(false, &result.marked, &new));
+    }
+
+    Ok(exit::SUCCESS)
+}
+
+fn cmd_preview(config: &Config, file: Option<&Path>, quiet: bool, json: bool) -> Result<u8, Error> {
+    let updates = match file {
+        Some(path) if path == Path::new("-") => {
+            pacman::parse_updates(&io::read_to_string(io::stdin())?)
+        }
+        Some(path) => {
+            let content = fs::read_to_string(path)
+                .map_err(|e| Error::Usage(format!("Cannot read {}: {e}", path.display())))?;
+            pacman::parse_updates(&content)
+        }
+        None => pacman::pending_updates()?,
+    };
+
+    if updates.is_empty() {
+        if !quiet {
+            output::status("No pending updates");
+        }
+        if json {
+            output::json(&preview_json(0, &[]));
+        }
+        return Ok(exit::SUCCESS);
+    }
+
+    // The new packages aren't downloaded yet, so their sonames can't be
+    // compared and only the version threshold decides
+    let overrides = Overrides::load();
+    let mut result = process_triggers(
+        &updates,
+        config.version_threshold,
+        &overrides,
+        config.skip_any_arch,
+        &config.skip_packages,
+        false,
+    )?;
+    if config.confirm_srcinfo && !result.marked.is_empty() {
+        let aur = AurClient::from_config(config);
+        transaction::confirm_with_srcinfo(&aur, &mut result.marked, quiet);
+    }
+
+    if json {
+        output::json(&preview_json(updates.len(), &result.marked));
+        return Ok(exit::SUCCESS);
+    }
+
+    for m in &result.marked {
+        output::package_with_trigger(&m.package, Some(&m.trigger));
+    }
+    if !quiet {
+        if !result.below_threshold.is_empty() {
+            output::info(&format!(
+                "{} trigger(s) below threshold would be skipped",
+                result.below_threshold.len()
+            ));
+        }
+        output::info(&format!(
+            "Upgrading {} package(s) would mark {} package(s) for rebuild",
+            updates.len(),
+            result.marked.len()
+        ));
+    }
+
+    Ok(exit::SUCCESS)
+}
+
+/// Build the `--json` document of `preview`.
+fn preview_json(updates: usize, marked: &[MarkedPackage]) -> Value {
+    let marked: Vec<Value> = marked
+        .iter()
+        .map(|m| json!({ "package": m.package, "trigger": m.trigger }))
+        .collect();
+    json!({ "updates": updates, "marked": marked })
+}
+
+/// JSON result of `trigger`: the (would-be) marks, and for real runs whether
+/// each package was newly added to the queue.
+fn trigger_json(dry_run: bool, marked: &[MarkedPackage], new: &HashSet<&str>) -> Value {
+    let marked: Vec<Value> = marked
+        .iter()
+        .map(|m| {
+            let mut value = json!({ "package": m.package, "trigger": m.trigger });
+            if !dry_run {
+                value["new"] = json!(new.contains(m.package.as_str()));
+            }
+            value
+        })
+        .collect();
+    json!({ "dry_run": dry_run, "marked": marked })
+}
+
+fn cmd_scan(config: &Config, dry_run: bool, checkrebuild: bool, quiet: bool) -> Result<u8, Error> {
+    // Each finding with the trigger and trigger version it is marked with
+    let mut findings: Vec<(scan::Finding, &str, Option<String>)> = Vec::new();
+
+    let python = scan::scan_python()?;
+    match python.python_version {
+        None => {
+            if !quiet {
+                output::info("Python is not installed");
+            }
+        }
+        Some(version) => {
+            if python.stale.is_empty() && !quiet {
+                output::info(&format!("No packages left behind by python {version}"));
+            }
+            findings.extend(
+                python
+                    .stale
+                    .into_iter()
+                    .map(|finding| (finding, scan::PYTHON_PACKAGE, Some(version.clone()))),
+            );
+        }
+    }
+
+    if checkrebuild {
+        let broken = scan::scan_linkage(config, &Overrides::load())?;
+        if broken.is_empty() && !quiet {
+            output::info("No packages with broken linkage");
+        }
+        for finding in broken {
+            if findings
+                .iter()
+                .any(|(f, _, _)| f.package == finding.package)
+            {
+                continue;
+            }
+            findings.push((finding, scan::CHECKREBUILD_TRIGGER, None));
+        }
+    }
+
+    if findings.is_empty() {
+        return Ok(exit::SUCCESS);
+    }
+
+    if dry_run {
+        for (finding, _, _) in &findings {
+            output::package(&format!("{} ({})", finding.package, finding.reason));
+        }
+        if !quiet {
+            output::info(&format!(
+                "Would mark {} package(s) for rebuild",
+                findings.len()
+            ));
+        }
+        return Ok(exit::SUCCESS);
+    }
+
+    let found: Vec<(&str, &str, Option<&str>)> = findings
+        .iter()
+        .map(|(finding, trigger, version)| (finding.package.as_str(), *trigger, version.as_deref()))
+        .collect();
+    let newly_marked = queue::mark_found(config, &found)?;
+
+    if !quiet {
+        for (finding, _, _) in &findings {
+            if newly_marked.contains(&finding.package) {
+                output::status(&format!("Marked {} ({})", finding.package, finding.reason));
+            }
+        }
+        output::info(&format!(
+            "Marked {} package(s) for rebuild",
+            newly_marked.len()
+        ));
+    }
+
+    Ok(exit::SUCCESS)
+}
+
+/// Check the linkage of an installed package (`verify`).
+///
+/// Exits with 1 if there are problems, so scripts can tell whether the
+/// package needs a rebuild.
+fn cmd_verify(package: &str, quiet: bool, json: bool) -> Result<u8, Error> {
+    // Nothing is queued if the database doesn't exist yet
+    let queued = match open_readonly() {
+        Ok(db) => !db.query(&[package])?.is_empty(),
+        Err(Error::NoDatabase) => false,
+        Err(e) => return Err(e),
+    };
+    if pacman::installed_packages(&[package])?.is_empty() {
+        if json {
+            output::json(&verify_json(package, false, queued, &[]));
+        }
+        output::warning(&format!("{package} is not installed"));
+        return Ok(exit::NOT_FOUND);
+    }
+
+    let problems = scan::verify_linkage(package)?;
+    if json {
+        output::json(&verify_json(package, true, queued, &problems));
+    } else {
+        for problem in &problems {
+            println!("{}: {problem}", problem.file);
+        }
+        if !quiet {
+            let summary = if !problems.is_empty() {
+                format!(
+                    "{package} has {} linkage problem(s) and needs a rebuild",
+                    problems.len()
+                )
+            } else if queued {
+                format!(
+                    "No linkage problems in {package}, but it is queued (an ABI break that keeps the sonames doesn't show up here)"
+                )
+            } else {
+                format!("No linkage problems in {package}")
+            };
+            output::info(&summary);
+        }
+    }
+
+    Ok(if problems.is_empty() {
+        exit::SUCCESS
+    } else {
+        exit::ERROR
+    })
+}
+
+/// JSON output of `verify`.
+fn verify_json(
+    package: &str,
+    installed: bool,
+    queued: bool,
+    problems: &[pacman::LinkProblem],
+) -> Value {
+    let problems: Vec<Value> = problems
+        .iter()
+        .map(|p| json!({ "file": p.file, "library": p.library, "version": p.version }))
+        .collect();
+    json!({
+        "package": package,
+        "installed": installed,
+        "queued": queued,
+        "problems": problems,
+    })
+}
+
+fn cmd_source(config: &Config, action: SourceAction, quiet: bool) -> Result<u8, Error> {
+    match action {
+        SourceAction::Add { package, path } => {
+            let added = queue::add_source(config, &package, &path)?;
+            // Only a hint: the package may be registered before its first build
+            if added.not_installed {
+                output::warning(&format!("{package} is not installed"));
+            }
+            if !quiet {
+                let verb = if added.moved { "Moved" } else { "Registered" };
+                output::status(&format!("{verb} {package} (local: {})", added.path));
+            }
+            Ok(exit::SUCCESS)
+        }
+        SourceAction::Remove { package } => {
+            let mut db = Database::open(config.retention_days)?;
+            if db.remove_source(&package)? {
+                if !quiet {
+                    output::status(&format!("{package} is no longer built locally"));
+                }
+                Ok(exit::SUCCESS)
+            } else {
+                output::warning(&format!("{package} has no registered local source"));
+                Ok(exit::NOT_FOUND)
+            }
+        }
+        SourceAction::List => {
+            let sources = open_readonly()?.list_sources()?;
+            if sources.is_empty() {
+                if !quiet {
+                    output::status("No local sources registered");
+                }
+                return Ok(exit::SUCCESS);
+            }
+            for source in &sources {
+                println!("{} {}", source.package, source.path);
+            }
+            Ok(exit::SUCCESS)
+        }
+    }
+}
+
+fn cmd_db(config: &Config, action: Option<DbAction>, quiet: bool, json: bool) -> Result<u8, Error> {
+    let path = get_db_path();
+
+    match action {
+        None => {
+            let db = open_readonly()?;
+            let size = db_size(&path);
+            let version = db.schema_version()?;
+            let counts = db.table_counts()?;
+
+            if json {
+                let tables: serde_json::Map<String, Value> = counts
+                    .iter()
+                    .map(|(table, n)| (table.to_string(), json!(n)))
+                    .collect();
+                output::json(&json!({
+                    "path": path.display().to_string(),
+                    "size_bytes": size,
+                    "schema_version": version,
+                    "tables": tables,
+                }));
+                return Ok(exit::SUCCESS);
+            }
 
-    for pkg in &found {
-        println!("{pkg}");
-    }
+            if !quiet {
+                output::header(&path.display().to_string());
+            }
+            println!("Size: {}", format_size(size));
+            println!("Schema version: {version}");
+            let width = counts.iter().map(|(t, _)| t.len()).max().unwrap_or(0);
+            for (table, n) in &counts {
+                println!("{table:<width$}  {n}");
+            }
+        }
+
+        Some(DbAction::Check) => {
+            let problems = open_readonly()?.integrity_check()?;
+            if json {
+                output::json(&json!({ "ok": problems.is_empty(), "problems": problems }));
+            } else if problems.is_empty() {
+                if !quiet {
+                    output::status("Database is intact");
+                }
+            } else {
+                output::error(&format!("Database is damaged ({}):", path.display()));
+                for problem in &problems {
+                    eprintln!("  {problem}");
+                }
+            }
+            if !problems.is_empty() {
+                return Ok(exit::ERROR);
+            }
+        }
 
-    if !quiet && found.is_empty() {
-        // Silent for scripting, but show feedback when interactive
+        Some(DbAction::Vacuum) => {
+            let db = Database::open(config.retention_days)?;
+            let before = db_size(&path);
+            db.vacuum()?;
+            let after = db_size(&path);
+
+            if json {
+                output::json(&json!({ "size_before": before, "size_after": after }));
+            } else if !quiet {
+                output::status(&format!(
+                    "Vacuumed database: {} -> {}",
+                    format_size(before),
+                    format_size(after)
+                ));
+            }
+        }
     }
 
     Ok(exit::SUCCESS)
 }
 
-fn cmd_triggers(quiet: bool) -> Result<u8, Error> {
-    if !quiet {
-        output::header(&format!("Curated triggers (v{TRIGGER_LIST_VERSION})"));
-    }
+/// Size of the database file in bytes (0 for an in-memory database).
+fn db_size(path: &Path) -> u64 {
+    std::fs::metadata(path).map_or(0, |m| m.len())
+}
 
-    for (name, threshold) in TRIGGERS {
-        if quiet {
-            output::package(name);
-        } else {
-            output::package(&format!(
-                "{name} ({threshold})",
-                threshold = threshold.as_str()
-            ));
-        }
+/// Format a byte count for display (`512 B`, `1.5 KiB`, `12.0 MiB`).
+fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
     }
-
-    Ok(exit::SUCCESS)
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
 }
 
-fn cmd_trigger(
+fn cmd_config(
     config: &Config,
-    dry_run: bool,
-    packages: Vec<String>,
+    action: Option<ConfigAction>,
     quiet: bool,
+    json: bool,
 ) -> Result<u8, Error> {
-    let packages = if packages.is_empty() {
-        read_stdin_packages()?
-    } else {
-        packages
-    };
-
-    if packages.is_empty() {
-        return Ok(exit::SUCCESS);
-    }
-
-    // Load user overrides
-    let overrides = Overrides::load();
+    match action {
+        None => {
+            if json {
+                output::json(&config.to_json());
+            } else if !quiet {
+                print!("{}", config.to_conf());
+            }
+        }
 
-    // Process triggers to find AUR dependents
-    let result = process_triggers(&packages, config.version_threshold, &overrides)?;
+        Some(ConfigAction::Get { key }) => {
+            let value = config
+                .get(&key)
+                .ok_or_else(|| Error::Usage(format!("Unknown config key '{key}'")))?;
+            if json {
+                output::json(&value);
+            } else {
+                // Same form as in the config file; unset options print nothing
+                match value {
+                    Value::Null => {}
+                    Value::String(s) => println!("{s}"),
+                    Value::Array(items) => {
+                        let items: Vec<String> = items
+                            .iter()
+                            .map(|item| {
+                                item.as_str().map_or_else(|| item.to_string(), String::from)
+                            })
+                            .collect();
+                        println!("{}", items.join(" "));
+                    }
+                    other => println!("{other}"),
+                }
+            }
+        }
 
-    // Report packages skipped due to version threshold
-    if !quiet && !result.below_threshold.is_empty() {
-        output::info(&format!(
-            "Skipped {} trigger(s) below threshold",
-            result.below_threshold.len(),
-        ));
+        Some(ConfigAction::Set { key, value }) => {
+            let value = value.join(" ");
+            Config::set_in_file(Path::new(CONFIG_PATH), &key, &value)?;
+            if !quiet {
+                output::status(&format!("Set {key} = {value}"));
+            }
+        }
     }
+    Ok(exit::SUCCESS)
+}
 
-    if result.marked.is_empty() {
-        if !quiet {
-            output::info("No packages to mark");
+fn cmd_override(action: OverrideAction, quiet: bool, json: bool) -> Result<u8, Error> {
+    let dir = |kind: OverrideKind| PathBuf::from(kind.dir());
+
+    match action {
+        OverrideAction::List => {
+            let mut files: Vec<(OverrideKind, OverrideFile)> = Vec::new();
+            for kind in [OverrideKind::Trigger, OverrideKind::Package] {
+                files.extend(
+                    overrides::list_overrides(&dir(kind))?
+                        .into_iter()
+                        .map(|file| (kind, file)),
+                );
+            }
+
+            if json {
+                let files: Vec<Value> = files
+                    .iter()
+                    .map(|(kind, file)| {
+                        json!({ "kind": kind.as_str(), "name": file.name, "entries": file.entries })
+                    })
+                    .collect();
+                output::json(&Value::Array(files));
+            } else if files.is_empty() {
+                if !quiet {
+                    output::status("No overrides");
+                }
+            } else {
+                for (kind, file) in &files {
+                    println!(
+                        "{:<8} {}: {}",
+                        kind.as_str(),
+                        file.name,
+                        describe_override(*kind, &file.entries)
+                    );
+                }
+            }
+            Ok(exit::SUCCESS)
         }
-        return Ok(exit::SUCCESS);
-    }
 
-    if dry_run {
-        // Just print what would be marked
-        for m in &result.marked {
-            output::package_with_trigger(&m.package, &m.trigger);
+        OverrideAction::Show { kind, name } => {
+            let Some(file) = overrides::read_override(&dir(kind), &name)? else {
+                if json {
+                    output::json(&Value::Null);
+                } else if !quiet {
+                    output::status(&format!("No {} override for {name}", kind.as_str()));
+                }
+                return Ok(exit::NOT_FOUND);
+            };
+
+            if json {
+                output::json(&json!({
+                    "kind": kind.as_str(),
+                    "name": file.name,
+                    "path": file.path,
+                    "entries": file.entries,
+                }));
+                return Ok(exit::SUCCESS);
+            }
+            if !quiet {
+                output::header(&format!(
+                    "{} ({})",
+                    file.path.display(),
+                    describe_override(kind, &file.entries)
+                ));
+            }
+            for entry in &file.entries {
+                output::package(entry);
+            }
+            Ok(exit::SUCCESS)
         }
-        if !quiet {
-            output::info(&format!(
-                "Would mark {} package(s) for rebuild",
-                result.marked.len()
-            ));
+
+        OverrideAction::Add {
+            kind,
+            name,
+            entries,
+        } => {
+            let existed = overrides::read_override(&dir(kind), &name)?.is_some();
+            if existed && entries.is_empty() {
+                return Err(Error::Usage(format!(
+                    "The {} override for {name} already exists. Give entries to add, or remove it first.",
+                    kind.as_str()
+                )));
+            }
+            let added = overrides::add_override(&dir(kind), &name, &entries)?;
+            if !quiet {
+                if !existed && entries.is_empty() {
+                    output::status(&format!(
+                        "Created {} override for {name}: {}",
+                        kind.as_str(),
+                        describe_override(kind, &[])
+                    ));
+                } else if added.is_empty() {
+                    output::status("Nothing to add");
+                } else {
+                    output::status(&format!(
+                        "Added to {} override for {name}: {}",
+                        kind.as_str(),
+                        added.join(", ")
+                    ));
+                }
+            }
+            Ok(exit::SUCCESS)
         }
-    } else {
-        // Actually mark the packages
-        let mut db = Database::open(config.retention_days)?;
-        let mut newly_marked = 0;
 
-        for m in &result.marked {
-            if db.mark(&m.package, Some(&m.trigger), None)? {
-                newly_marked += 1;
+        OverrideAction::Remove {
+            kind,
+            name,
+            entries,
+        } => {
+            let Some(removed) = overrides::remove_override(&dir(kind), &name, &entries)? else {
                 if !quiet {
+                    output::status(&format!("No {} override for {name}", kind.as_str()));
+                }
+                return Ok(exit::NOT_FOUND);
+            };
+            if !quiet {
+                if entries.is_empty() {
+                    output::status(&format!("Removed {} override for {name}", kind.as_str()));
+                } else if removed.is_empty() {
+                    output::status("Nothing to remove");
+                } else {
                     output::status(&format!(
-                        "Marked {} (triggered by {})",
-                        m.package, m.trigger
+                        "Removed from {} override for {name}: {}",
+                        kind.as_str(),
+                        removed.join(", ")
                     ));
                 }
             }
+            Ok(exit::SUCCESS)
+        }
+    }
+}
+
+/// Describe what an override's entries do.
+fn describe_override(kind: OverrideKind, entries: &[String]) -> String {
+    match (kind, entries.is_empty()) {
+        (OverrideKind::Trigger, true) => "disabled".to_string(),
+        (OverrideKind::Trigger, false) => format!("marks {}", entries.join(" ")),
+        (OverrideKind::Package, true) => "never marked".to_string(),
+        (OverrideKind::Package, false) => format!("only marked by {}", entries.join(" ")),
+    }
+}
+
+fn cmd_setup(dir: Option<PathBuf>, force: bool, print: bool, quiet: bool) -> Result<u8, Error> {
+    // Point the hooks at this binary, wherever it was installed
+    let exe = std::env::current_exe().unwrap_or_else(|_| PathBuf::from("/usr/bin/anneal"));
+    let hooks = [
+        (hooks::TRIGGER_HOOK_NAME, hooks::trigger_hook(&exe)),
+        (hooks::CAPTURE_HOOK_NAME, hooks::capture_hook(&exe)),
+    ];
+
+    if print {
+        for (i, (name, content)) in hooks.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            println!("# {name}");
+            print!("{content}");
         }
+        return Ok(exit::SUCCESS);
+    }
 
+    // The package ships the hook already; a copy in /etc would shadow it
+    let packaged = hooks::packaged_trigger_hook();
+    if dir.is_none() && packaged.exists() && !force {
         if !quiet {
-            output::info(&format!("Marked {newly_marked} package(s) for rebuild"));
+            output::status(&format!(
+                "Hook already installed by the package at {}",
+                packaged.display()
+            ));
         }
+        return Ok(exit::SUCCESS);
     }
 
+    let dir = dir.unwrap_or_else(|| PathBuf::from(hooks::HOOK_DIR));
+    for (name, content) in &hooks {
+        let outcome = hooks::install(&dir, name, content, force)?;
+        if !quiet {
+            report_install(&dir.join(name), outcome);
+        }
+    }
     Ok(exit::SUCCESS)
 }
 
-fn cmd_config(config: &Config, quiet: bool) -> Result<u8, Error> {
-    if !quiet {
-        print!("{}", config.to_conf());
+fn cmd_setup_systemd(
+    config: &Config,
+    dir: Option<PathBuf>,
+    force: bool,
+    print: bool,
+    quiet: bool,
+) -> Result<u8, Error> {
+    let exe = std::env::current_exe().unwrap_or_else(|_| PathBuf::from("/usr/bin/anneal"));
+    let units = [
+        (systemd::SCAN_SERVICE_NAME, systemd::scan_service(&exe)),
+        (
+            systemd::SCAN_TIMER_NAME,
+            systemd::scan_timer(&config.scan_interval),
+        ),
+    ];
+
+    if print {
+        for (i, (name, content)) in units.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            println!("# {name}");
+            print!("{content}");
+        }
+        return Ok(exit::SUCCESS);
+    }
+
+    let dir = dir.unwrap_or_else(|| PathBuf::from(systemd::UNIT_DIR));
+    let mut changed = false;
+    for (name, content) in &units {
+        let outcome = hooks::install(&dir, name, content, force)?;
+        changed |= outcome != hooks::Install::Unchanged;
+        if !quiet {
+            report_install(&dir.join(name), outcome);
+        }
+    }
+    if changed && !quiet {
+        output::status(&format!(
+            "Enable with: systemctl daemon-reload && systemctl enable --now {}",
+            systemd::SCAN_TIMER_NAME
+        ));
     }
     Ok(exit::SUCCESS)
 }
 
+/// Report the outcome of installing a file with `setup`.
+fn report_install(path: &Path, outcome: hooks::Install) {
+    let message = match outcome {
+        hooks::Install::Created => format!("Installed {}", path.display()),
+        hooks::Install::Replaced => format!("Replaced {}", path.display()),
+        hooks::Install::Unchanged => format!("{} is up to date", path.display()),
+    };
+    output::status(&message);
+}
+
 fn cmd_completions(shell: clap_complete::Shell) {
     let mut cmd = Cli::command();
     generate(shell, &mut cmd, "anneal", &mut io::stdout());
@@ -607,14 +2932,84 @@ fn is_root() -> bool {
     unsafe { libc::getuid() == 0 }
 }
 
+/// Why the current user may not run a command.
+#[derive(Debug, PartialEq, Eq)]
+enum Denied {
+    /// The command writes system files, which only root may do.
+    NotRoot,
+    /// The command writes the database, which isn't writable for the user.
+    DatabaseNotWritable,
+}
+
+/// Check that the current user may run a command.
+///
+/// Root always passes. Other users may write the database if it is writable
+/// for them (e.g., through the group-writable directory), but never files
+/// outside it.
+fn check_access(
+    command: &Command,
+    root: bool,
+    db_writable: impl FnOnce() -> bool,
+) -> Result<(), Denied> {
+    if root {
+        Ok(())
+    } else if command.writes_system_files() {
+        Err(Denied::NotRoot)
+    } else if command.writes_database() && !db_writable() {
+        Err(Denied::DatabaseNotWritable)
+    } else {
+        Ok(())
+    }
+}
+
+/// Re-run the current command as root through pkexec.
+///
+/// pkexec asks polkit for authorization (prompting through the session's
+/// authentication agent) and exits with 126/127 if it's dismissed or denied.
+fn escalate_with_pkexec() -> ExitCode {
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            output::error(&format!("Cannot locate the anneal executable: {e}"));
+            return ExitCode::from(exit::ERROR);
+        }
+    };
+
+    let status = ProcessCommand::new("pkexec")
+        .arg(exe)
+        .args(std::env::args_os().skip(1))
+        .status();
+
+    match status {
+        Ok(status) => match status.code() {
+            Some(PKEXEC_NOT_AUTHORIZED | PKEXEC_DISMISSED) => {
+                output::error("Permission denied. Authorization was not granted.");
+                ExitCode::from(exit::ERROR)
+            }
+            Some(code) => ExitCode::from(u8::try_from(code).unwrap_or(exit::ERROR)),
+            None => ExitCode::from(exit::ERROR),
+        },
+        Err(e) => {
+            output::error(&format!("Failed to run pkexec: {e}"));
+            ExitCode::from(exit::ERROR)
+        }
+    }
+}
+
 /// Check if a command needs confirmation.
 fn needs_confirmation(cmd: &Command) -> bool {
     matches!(
         cmd,
         Command::Clear {
             force: false,
-            trigger: None
-        } | Command::Rebuild { force: false, .. }
+            trigger: None,
+            undo: false,
+            ..
+        } | Command::Rebuild {
+            force: false,
+            dry_run: false,
+            ..
+        } | Command::Restore { force: false, .. }
     )
 }
 
@@ -622,42 +3017,12 @@ fn needs_confirmation(cmd: &Command) -> bool {
 fn has_force_flag(cmd: &Command) -> bool {
     matches!(
         cmd,
-        Command::Clear { force: true, .. } | Command::Rebuild { force: true, .. }
+        Command::Clear { force: true, .. }
+            | Command::Rebuild { force: true, .. }
+            | Command::Restore { force: true, .. }
     )
 }
 
-/// Open the database in read-only mode, with a helpful error if it doesn't exist.
-fn open_readonly() -> Result<Database, Error> {
-    Database::open_readonly(&get_db_path()).map_err(|e| {
-        if matches!(&e, DbError::Sqlite(rusqlite::Error::SqliteFailure(err, _))
-            if err.code == rusqlite::ErrorCode::CannotOpen)
-        {
-            Error::NoDatabase
-        } else {
-            e.into()
-        }
-    })
-}
-
-/// Read packages from stdin (one per line).
-fn read_stdin_packages() -> Result<Vec<String>, Error> {
-    let stdin = io::stdin();
-    if stdin.is_terminal() {
-        // Don't block waiting for input if stdin is a terminal
-        return Ok(Vec::new());
-    }
-
-    let packages: Vec<String> = stdin
-        .lock()
-        .lines()
-        .map_while(Result::ok)
-        .map(|line| line.trim().to_string())
-        .filter(|line| !line.is_empty())
-        .collect();
-
-    Ok(packages)
-}
-
 /// Read confirmation from user.
 fn confirm() -> Result<bool, Error> {
     let stdin = io::stdin();
@@ -666,261 +3031,95 @@ fn confirm() -> Result<bool, Error> {
     Ok(line.trim().eq_ignore_ascii_case("y") || line.trim().eq_ignore_ascii_case("yes"))
 }
 
-// ==================== Rebuild Helpers ====================
-
-/// Detect which AUR helper to use.
-fn detect_helper(
-    config: &Config,
-    cmd_override: Option<&str>,
-) -> Result<HelperInvocation, RebuildError> {
-    // Priority 1: Command-line override
-    if let Some(cmd) = cmd_override {
-        return resolve_helper(cmd);
-    }
-
-    // Priority 2: Config file
-    if let Some(ref helper) = config.helper {
-        return resolve_helper(helper);
-    }
-
-    // Priority 3: Auto-detect from PATH
-    let found: Vec<&str> = KNOWN_HELPERS
-        .iter()
-        .copied()
-        .filter(|h| is_in_path(h))
-        .collect();
-
-    match found.len() {
-        0 => Err(RebuildError::NoHelper),
-        1 => Ok(HelperInvocation::for_known_helper(found[0])),
-        _ => Err(RebuildError::AmbiguousHelper(
-            found.into_iter().map(String::from).collect(),
-        )),
-    }
-}
-
-/// Resolve a helper string to an invocation.
-fn resolve_helper(helper: &str) -> Result<HelperInvocation, RebuildError> {
-    // Check if it's a known helper name
-    if Config::is_known_helper(helper) {
-        if !is_in_path(helper) {
-            return Err(RebuildError::HelperNotFound(helper.to_string()));
-        }
-        return Ok(HelperInvocation::for_known_helper(helper));
-    }
-
-    // Custom command - extract first word to verify it exists
-    let cmd_name = helper.split_whitespace().next().unwrap_or(helper);
-    if !is_in_path(cmd_name) {
-        return Err(RebuildError::HelperNotFound(cmd_name.to_string()));
+/// Print the commands a rebuild would run, in order (`rebuild --dry-run`).
+fn print_rebuild_commands(commands: &[PlannedCommand], json: bool) {
+    if json {
+        let commands: Vec<Value> = commands
+            .iter()
+            .map(|c| json!({ "packages": c.packages, "command": c.argv, "directory": c.directory }))
+            .collect();
+        output::json(&json!({ "commands": commands }));
+        return;
     }
-
-    Ok(HelperInvocation::from_custom(helper))
-}
-
-/// Check if a command exists in PATH.
-fn is_in_path(cmd: &str) -> bool {
-    ProcessCommand::new("which")
-        .arg(cmd)
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status()
-        .map(|s| s.success())
-        .unwrap_or(false)
-}
-
-/// Run checkrebuild and return the list of packages needing rebuild.
-fn run_checkrebuild() -> Result<Vec<String>, RebuildError> {
-    let output = ProcessCommand::new("checkrebuild")
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .output()
-        .map_err(RebuildError::CheckrebuildFailed)?;
-
-    // checkrebuild exits 0 regardless of whether packages need rebuild
-    let packages: Vec<String> = BufReader::new(&output.stdout[..])
-        .lines()
-        .map_while(Result::ok)
-        .map(|line| {
-            // checkrebuild output format: "package_name dependency_that_changed"
-            // We only want the package name (first field)
-            line.split_whitespace().next().unwrap_or(&line).to_string()
-        })
-        .filter(|line| !line.is_empty())
-        .collect();
-
-    Ok(packages)
-}
-
-// ==================== Error Handling ====================
-
-/// Application errors.
-#[derive(Debug)]
-enum Error {
-    Config(anneal::config::ConfigError),
-    Db(anneal::db::DbError),
-    Trigger(TriggerError),
-    Rebuild(RebuildError),
-    Io(io::Error),
-    NoDatabase,
-}
-
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Config(e) => write!(f, "{e}"),
-            Self::Db(e) => write!(f, "{e}"),
-            Self::Trigger(e) => write!(f, "{e}"),
-            Self::Rebuild(e) => write!(f, "{e}"),
-            Self::Io(e) => write!(f, "{e}"),
-            Self::NoDatabase => write!(
-                f,
-                "No database found at {}. Run a command as root first to create it.",
-                get_db_path().display()
-            ),
+    for command in commands {
+        let line: Vec<String> = command.argv.iter().map(|arg| shell_quote(arg)).collect();
+        match &command.directory {
+            Some(dir) => println!("cd {} && {}", shell_quote(dir), line.join(" ")),
+            None => println!("{}", line.join(" ")),
         }
     }
 }
 
-impl From<anneal::config::ConfigError> for Error {
-    fn from(e: anneal::config::ConfigError) -> Self {
-        Self::Config(e)
-    }
-}
-
-impl From<anneal::db::DbError> for Error {
-    fn from(e: anneal::db::DbError) -> Self {
-        Self::Db(e)
-    }
-}
-
-impl From<TriggerError> for Error {
-    fn from(e: TriggerError) -> Self {
-        Self::Trigger(e)
-    }
-}
-
-impl From<RebuildError> for Error {
-    fn from(e: RebuildError) -> Self {
-        Self::Rebuild(e)
-    }
-}
-
-impl From<io::Error> for Error {
-    fn from(e: io::Error) -> Self {
-        Self::Io(e)
-    }
-}
-
 #[cfg(test)]
 #[allow(clippy::unwrap_used, clippy::expect_used)]
 mod tests {
     use super::*;
 
-    mod helper_invocation {
+    mod access {
         use super::*;
 
-        #[test]
-        fn known_helper_paru() {
-            let inv = HelperInvocation::for_known_helper("paru");
-            assert_eq!(inv.command, "paru");
-            assert_eq!(inv.base_args, vec!["-S", "--rebuild"]);
-        }
-
-        #[test]
-        fn known_helper_yay() {
-            let inv = HelperInvocation::for_known_helper("yay");
-            assert_eq!(inv.command, "yay");
-            assert_eq!(inv.base_args, vec!["-S", "--rebuild"]);
-        }
-
-        #[test]
-        fn known_helper_pikaur() {
-            let inv = HelperInvocation::for_known_helper("pikaur");
-            assert_eq!(inv.command, "pikaur");
-            assert_eq!(inv.base_args, vec!["-S", "--rebuild"]);
-        }
-
-        #[test]
-        fn known_helper_aura() {
-            // aura uses -A instead of -S
-            let inv = HelperInvocation::for_known_helper("aura");
-            assert_eq!(inv.command, "aura");
-            assert_eq!(inv.base_args, vec!["-A", "--rebuild"]);
+        fn command(args: &[&str]) -> Command {
+            Cli::parse_from(std::iter::once("anneal").chain(args.iter().copied())).command
         }
 
         #[test]
-        fn known_helper_trizen() {
-            let inv = HelperInvocation::for_known_helper("trizen");
-            assert_eq!(inv.command, "trizen");
-            assert_eq!(inv.base_args, vec!["-S", "--rebuild"]);
+        fn root_runs_anything() {
+            assert_eq!(check_access(&command(&["setup"]), true, || false), Ok(()));
+            assert_eq!(
+                check_access(&command(&["mark", "foo"]), true, || false),
+                Ok(())
+            );
         }
 
         #[test]
-        fn custom_command_simple() {
-            let inv = HelperInvocation::from_custom("my-helper");
-            assert_eq!(inv.command, "my-helper");
-            assert!(inv.base_args.is_empty());
+        fn group_member_writes_database() {
+            assert_eq!(
+                check_access(&command(&["mark", "foo"]), false, || true),
+                Ok(())
+            );
+            assert_eq!(
+                check_access(&command(&["mark", "foo"]), false, || false),
+                Err(Denied::DatabaseNotWritable)
+            );
+            assert_eq!(check_access(&command(&["list"]), false, || false), Ok(()));
         }
 
         #[test]
-        fn custom_command_with_args() {
-            let inv = HelperInvocation::from_custom("my-helper -S --rebuild --custom");
-            assert_eq!(inv.command, "my-helper");
-            assert_eq!(inv.base_args, vec!["-S", "--rebuild", "--custom"]);
-        }
-
-        #[test]
-        fn custom_command_extra_whitespace() {
-            let inv = HelperInvocation::from_custom("  my-helper   -S   --rebuild  ");
-            assert_eq!(inv.command, "my-helper");
-            assert_eq!(inv.base_args, vec!["-S", "--rebuild"]);
+        fn group_member_needs_root_for_system_files() {
+            assert_eq!(
+                check_access(&command(&["setup"]), false, || true),
+                Err(Denied::NotRoot)
+            );
+            assert_eq!(
+                check_access(&command(&["setup", "--print"]), false, || false),
+                Ok(())
+            );
+
+            // These write root-owned files outside the database, so a
+            // writable database doesn't help
+            for args in [
+                &["override", "add", "trigger", "my-lib", "app-*"][..],
+                &["override", "remove", "package", "my-app", "qt6-base"],
+                &["trigger-def", "add", "my-lib", "my-app"],
+                &["ignore", "foo"],
+                &["unignore", "foo"],
+                &["update-triggers"],
+                &["config", "set", "helper", "paru"],
+            ] {
+                assert_eq!(
+                    check_access(&command(args), false, || true),
+                    Err(Denied::NotRoot)
+                );
+            }
         }
     }
 
-    mod rebuild_error_display {
-        use super::*;
-
-        #[test]
-        fn no_helper() {
-            let err = RebuildError::NoHelper;
-            let msg = err.to_string();
-            assert!(msg.contains("No AUR helper detected"));
-            assert!(msg.contains("paru"));
-            assert!(msg.contains("yay"));
-        }
-
-        #[test]
-        fn ambiguous_helper() {
-            let err = RebuildError::AmbiguousHelper(vec!["paru".into(), "yay".into()]);
-            let msg = err.to_string();
-            assert!(msg.contains("Multiple AUR helpers found"));
-            assert!(msg.contains("paru"));
-            assert!(msg.contains("yay"));
-        }
-
-        #[test]
-        fn helper_not_found() {
-            let err = RebuildError::HelperNotFound("nonexistent".into());
-            let msg = err.to_string();
-            assert!(msg.contains("nonexistent"));
-            assert!(msg.contains("not found"));
-        }
-
-        #[test]
-        fn helper_failed() {
-            let err = RebuildError::HelperFailed(1);
-            let msg = err.to_string();
-            assert!(msg.contains("exited with code 1"));
-        }
-
-        #[test]
-        fn package_not_in_queue() {
-            let err = RebuildError::PackageNotInQueue("my-pkg".into());
-            let msg = err.to_string();
-            assert!(msg.contains("my-pkg"));
-            assert!(msg.contains("not in the queue"));
-            assert!(msg.contains("-f"));
-        }
+    #[test]
+    fn format_sizes() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(12 * 1024 * 1024), "12.0 MiB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024 * 1024), "3072.0 GiB");
     }
 }
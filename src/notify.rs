@@ -0,0 +1,163 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! Desktop notifications for newly queued packages (`notify = true`).
+//!
+//! `anneal trigger` runs as root from the pacman hook, outside any desktop
+//! session, so a plain `notify-send` would go nowhere. As root, the
+//! notification is sent to every user with a session bus under `/run/user`,
+//! running `notify-send` as that user through `setpriv`. Other users just
+//! run `notify-send` themselves.
+//!
+//! Notifications are best-effort: a missing `notify-send`, no session, or a
+//! failed send are silently ignored so the pacman hook is never affected.
+
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::trigger::MarkedPackage;
+
+/// Directory holding the per-user runtime directories.
+const RUNTIME_DIR: &str = "/run/user";
+
+/// Application name shown by the notification daemon.
+const APP_NAME: &str = "anneal";
+
+/// Icon of the notification.
+const ICON: &str = "system-software-update";
+
+/// Build the notification for newly queued packages.
+///
+/// Packages are grouped by trigger, in the order they were marked. Returns
+/// the summary and body, or `None` if nothing was queued.
+pub fn message(marked: &[&MarkedPackage]) -> Option<(String, String)> {
+    if marked.is_empty() {
+        return None;
+    }
+
+    let mut groups: Vec<(&str, Vec<&str>)> = Vec::new();
+    for m in marked {
+        match groups.iter_mut().find(|(trigger, _)| *trigger == m.trigger) {
+            Some((_, packages)) => packages.push(&m.package),
+            None => groups.push((&m.trigger, vec![&m.package])),
+        }
+    }
+
+    let summary = match marked.len() {
+        1 => "1 AUR package queued for rebuild".to_string(),
+        n => format!("{n} AUR packages queued for rebuild"),
+    };
+    let body = groups
+        .iter()
+        .map(|(trigger, packages)| format!("{trigger}: {}", packages.join(", ")))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Some((summary, body))
+}
+
+/// Send a desktop notification to the users with a graphical session.
+pub fn send(summary: &str, body: &str) {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    if unsafe { libc::geteuid() } != 0 {
+        run(notify_send(summary, body));
+        return;
+    }
+
+    let Ok(entries) = fs::read_dir(RUNTIME_DIR) else {
+        return;
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let dir = entry.path();
+        let Some((uid, gid)) = session_owner(&dir) else {
+            continue;
+        };
+
+        let mut cmd = Command::new("setpriv");
+        cmd.arg(format!("--reuid={uid}"))
+            .arg(format!("--regid={gid}"))
+            .arg("--clear-groups")
+            .arg("notify-send")
+            .args(notify_send_args(summary, body))
+            .env("XDG_RUNTIME_DIR", &dir)
+            .env(
+                "DBUS_SESSION_BUS_ADDRESS",
+                format!("unix:path={}", dir.join("bus").display()),
+            );
+        run(cmd);
+    }
+}
+
+/// Owner of a runtime directory with a session bus, skipping root's.
+fn session_owner(dir: &Path) -> Option<(u32, u32)> {
+    let uid: u32 = dir.file_name()?.to_str()?.parse().ok()?;
+    if uid == 0 || !dir.join("bus").exists() {
+        return None;
+    }
+    let metadata = fs::metadata(dir).ok()?;
+    (metadata.uid() == uid).then(|| (uid, metadata.gid()))
+}
+
+/// `notify-send` invocation for the current user.
+fn notify_send(summary: &str, body: &str) -> Command {
+    let mut cmd = Command::new("notify-send");
+    cmd.args(notify_send_args(summary, body));
+    cmd
+}
+
+/// Arguments to `notify-send`.
+fn notify_send_args(summary: &str, body: &str) -> Vec<String> {
+    vec![
+        format!("--app-name={APP_NAME}"),
+        format!("--icon={ICON}"),
+        // notify-send treats a leading '-' as an option
+        "--".to_string(),
+        summary.to_string(),
+        body.to_string(),
+    ]
+}
+
+/// Run a notification command, ignoring failures.
+fn run(mut cmd: Command) {
+    let _ = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marked(package: &str, trigger: &str) -> MarkedPackage {
+        MarkedPackage {
+            package: package.to_string(),
+            trigger: trigger.to_string(),
+        }
+    }
+
+    #[test]
+    fn message_groups_by_trigger() {
+        let packages = [
+            marked("qt6ct", "qt6-base"),
+            marked("python-foo", "python"),
+            marked("qt6gtk2", "qt6-base"),
+        ];
+        let refs: Vec<&MarkedPackage> = packages.iter().collect();
+
+        assert_eq!(
+            message(&refs),
+            Some((
+                "3 AUR packages queued for rebuild".to_string(),
+                "qt6-base: qt6ct, qt6gtk2\npython: python-foo".to_string()
+            ))
+        );
+        assert_eq!(
+            message(&refs[1..2]).map(|(summary, _)| summary),
+            Some("1 AUR package queued for rebuild".to_string())
+        );
+        assert_eq!(message(&[]), None);
+    }
+}
@@ -10,20 +10,115 @@
 //! - Warnings in yellow
 //! - Errors in red
 //!
-//! Colors are automatically disabled when stdout/stderr is not a TTY.
+//! Colors are automatically disabled when stdout/stderr is not a TTY, or
+//! when the `NO_COLOR` environment variable is set (to anything but an empty
+//! string). `--color always` or `never` overrides both (see [`set_color`]).
+//!
+//! Details for debugging (`-v`, `-vv`) go to stderr through [`debug`].
+//!
+//! With `--json`, stdout carries a single JSON document instead (see
+//! [`json`]); warnings and errors still go to stderr as text.
+//!
+//! On terminals that support them, queued package names are OSC 8 hyperlinks
+//! to their AUR page, and triggers link to the Arch package search.
 
 use std::io::{self, IsTerminal, Write};
+use std::sync::OnceLock;
 
 use owo_colors::OwoColorize;
 
+use crate::tr;
+
+/// AUR package page URL prefix.
+const AUR_PACKAGE_URL: &str = "https://aur.archlinux.org/packages/";
+
+/// Arch package search URL prefix (the repository isn't known here).
+const ARCH_PACKAGE_URL: &str = "https://archlinux.org/packages/?name=";
+
+/// Terminals known not to understand OSC 8 escape sequences.
+const NO_HYPERLINK_TERMS: &[&str] = &["dumb", "linux"];
+
+/// When to color output (`--color`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorChoice {
+    /// Color terminals, unless `NO_COLOR` is set.
+    #[default]
+    Auto,
+    /// Always color, even when piped.
+    Always,
+    /// Never color.
+    Never,
+}
+
+impl ColorChoice {
+    /// Decide whether to color a stream, given whether it's a terminal and
+    /// the value of `NO_COLOR`.
+    fn enabled(self, is_terminal: bool, no_color: Option<&str>) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto => is_terminal && no_color.is_none_or(str::is_empty),
+        }
+    }
+}
+
+/// Color choice set on the command line.
+static COLOR: OnceLock<ColorChoice> = OnceLock::new();
+
+/// Verbosity set on the command line (the number of `-v`).
+static VERBOSITY: OnceLock<u8> = OnceLock::new();
+
+/// Set when to color output. Only the first call has an effect.
+pub fn set_color(choice: ColorChoice) {
+    let _ = COLOR.set(choice);
+}
+
+/// Set how many details [`debug`] prints. Only the first call has an
+/// effect.
+pub fn set_verbosity(level: u8) {
+    let _ = VERBOSITY.set(level);
+}
+
+/// Check if details of a verbosity level (1 for `-v`, 2 for `-vv`) are
+/// printed.
+pub fn is_verbose(level: u8) -> bool {
+    VERBOSITY.get().copied().unwrap_or(0) >= level
+}
+
+/// Check if a stream should be colored.
+fn use_color(is_terminal: bool) -> bool {
+    let no_color = std::env::var("NO_COLOR").ok();
+    COLOR
+        .get()
+        .copied()
+        .unwrap_or_default()
+        .enabled(is_terminal, no_color.as_deref())
+}
+
 /// Check if stdout supports colors.
 fn stdout_supports_color() -> bool {
-    io::stdout().is_terminal()
+    use_color(io::stdout().is_terminal())
+}
+
+/// Check if stdout supports OSC 8 hyperlinks.
+///
+/// Terminals that don't understand OSC 8 ignore it, so only terminals known
+/// to print the raw sequence are excluded. Pagers show the raw sequence
+/// too, so `--color always` doesn't add links to piped output.
+fn stdout_supports_hyperlinks() -> bool {
+    stdout_supports_color()
+        && io::stdout().is_terminal()
+        && std::env::var("TERM").map_or(true, |term| !NO_HYPERLINK_TERMS.contains(&term.as_str()))
+}
+
+/// Wrap text in an OSC 8 hyperlink.
+fn hyperlink(text: &str, url: &str) -> String {
+    format!("\x1b]8;;{url}\x1b\\{text}\x1b]8;;\x1b\\")
 }
 
 /// Check if stderr supports colors.
 fn stderr_supports_color() -> bool {
-    io::stderr().is_terminal()
+    use_color(io::stderr().is_terminal())
 }
 
 /// Print a header line in pacman style.
@@ -57,12 +152,35 @@ pub fn package(name: &str) {
     }
 }
 
-/// Print a package with trigger info.
-pub fn package_with_trigger(name: &str, trigger: &str) {
-    if stdout_supports_color() {
+/// Print an AUR package name, linked to its AUR page when supported.
+pub fn aur_package(name: &str) {
+    if stdout_supports_hyperlinks() {
+        let link = hyperlink(name, &format!("{AUR_PACKAGE_URL}{name}"));
+        println!("{}", link.bold().white());
+    } else {
+        package(name);
+    }
+}
+
+/// Print an AUR package with the trigger that marked it (`None` for
+/// external marks).
+///
+/// Links the package to its AUR page and the trigger to the Arch package
+/// search when supported.
+pub fn package_with_trigger(name: &str, trigger: Option<&str>) {
+    let external = tr!("trigger-external");
+    let trigger_text = trigger.unwrap_or(&external);
+    if stdout_supports_hyperlinks() {
+        let name = hyperlink(name, &format!("{AUR_PACKAGE_URL}{name}"));
+        let trigger = match trigger {
+            Some(t) => hyperlink(t, &format!("{ARCH_PACKAGE_URL}{t}")),
+            None => trigger_text.to_string(),
+        };
         println!("{} ({trigger})", name.bold().white());
+    } else if stdout_supports_color() {
+        println!("{} ({trigger_text})", name.bold().white());
     } else {
-        println!("{name} ({trigger})");
+        println!("{name} ({trigger_text})");
     }
 }
 
@@ -70,10 +188,11 @@ pub fn package_with_trigger(name: &str, trigger: &str) {
 ///
 /// Format: `warning: <message>`
 pub fn warning(msg: &str) {
+    let label = tr!("label-warning");
     if stderr_supports_color() {
-        eprintln!("{} {msg}", "warning:".yellow());
+        eprintln!("{} {msg}", label.yellow());
     } else {
-        eprintln!("warning: {msg}");
+        eprintln!("{label} {msg}");
     }
 }
 
@@ -81,10 +200,11 @@ pub fn warning(msg: &str) {
 ///
 /// Format: `error: <message>`
 pub fn error(msg: &str) {
+    let label = tr!("label-error");
     if stderr_supports_color() {
-        eprintln!("{} {msg}", "error:".bold().red());
+        eprintln!("{} {msg}", label.bold().red());
     } else {
-        eprintln!("error: {msg}");
+        eprintln!("{label} {msg}");
     }
 }
 
@@ -92,7 +212,7 @@ pub fn error(msg: &str) {
 ///
 /// Format: `-> <action> <count> package(s)`
 pub fn success_count(action: &str, count: usize) {
-    let pkg_word = if count == 1 { "package" } else { "packages" };
+    let pkg_word = tr!("package-word", count = count);
     if stdout_supports_color() {
         println!(
             "{} {action} {} {pkg_word}",
@@ -113,7 +233,165 @@ pub fn info(msg: &str) {
     }
 }
 
+/// Print a detail to stderr if the verbosity is at least `level`.
+///
+/// Format: `debug: <message>`
+pub fn debug(level: u8, msg: &str) {
+    if !is_verbose(level) {
+        return;
+    }
+    let label = tr!("label-debug");
+    if stderr_supports_color() {
+        eprintln!("{} {msg}", label.dimmed());
+    } else {
+        eprintln!("{label} {msg}");
+    }
+}
+
+/// A progress line on stderr, redrawn in place and erased when dropped.
+///
+/// Only drawn when stderr is a terminal that understands the escape
+/// sequence to erase a line, so hooks and logs never see it, and not with
+/// `-v`, whose debug lines would break it up.
+#[derive(Debug)]
+pub struct Progress {
+    enabled: bool,
+    shown: bool,
+}
+
+impl Progress {
+    /// Start a progress line (nothing is drawn until [`update`](Self::update)).
+    pub fn new() -> Self {
+        Self {
+            enabled: io::stderr().is_terminal()
+                && std::env::var("TERM").map_or(true, |term| term != "dumb")
+                && !is_verbose(1),
+            shown: false,
+        }
+    }
+
+    /// Replace the progress line with a message.
+    pub fn update(&mut self, msg: &str) {
+        if self.enabled {
+            eprint!("\r\x1b[K{msg}");
+            let _ = io::stderr().flush();
+            self.shown = true;
+        }
+    }
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if self.shown {
+            eprint!("\r\x1b[K");
+            let _ = io::stderr().flush();
+        }
+    }
+}
+
+/// Print rows as a table with aligned columns, under a header line.
+///
+/// Columns are two spaces apart and the last one isn't padded. The header
+/// is bold on a color terminal.
+pub fn table(headers: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let header = align_row(headers, &widths);
+    if stdout_supports_color() {
+        println!("{}", header.bold());
+    } else {
+        println!("{header}");
+    }
+    for row in rows {
+        println!("{}", align_row(row, &widths));
+    }
+}
+
+/// Pad the cells of a row to the column widths, leaving the last one as is.
+fn align_row(cells: &[impl AsRef<str>], widths: &[usize]) -> String {
+    let last = cells.len().saturating_sub(1);
+    cells
+        .iter()
+        .zip(widths)
+        .enumerate()
+        .map(|(i, (cell, width))| {
+            let cell = cell.as_ref();
+            if i == last {
+                cell.to_string()
+            } else {
+                let pad = width.saturating_sub(cell.chars().count());
+                format!("{cell}{}", " ".repeat(pad))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("  ")
+}
+
+/// Print a JSON document to stdout on a single line.
+pub fn json(value: &serde_json::Value) {
+    println!("{value}");
+}
+
+/// Print a `--porcelain` record: fields separated by single spaces.
+///
+/// Fields must not contain whitespace; callers print `-` for missing values.
+pub fn porcelain(fields: &[&str]) {
+    println!("{}", fields.join(" "));
+}
+
+/// Print a package name followed by a NUL byte (`-0`).
+pub fn null_terminated(name: &str) {
+    print!("{name}\0");
+}
+
 /// Flush stdout.
 pub fn flush() {
     let _ = io::stdout().flush();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_choice() {
+        assert!(ColorChoice::Auto.enabled(true, None));
+        assert!(ColorChoice::Auto.enabled(true, Some("")));
+        assert!(!ColorChoice::Auto.enabled(true, Some("1")));
+        assert!(!ColorChoice::Auto.enabled(false, None));
+        assert!(ColorChoice::Always.enabled(false, Some("1")));
+        assert!(!ColorChoice::Never.enabled(true, None));
+    }
+
+    #[test]
+    fn table_alignment() {
+        let widths = [7, 8, 3];
+        assert_eq!(
+            align_row(&["qt6gtk2", "qt6-base", "3d"], &widths),
+            "qt6gtk2  qt6-base  3d"
+        );
+        assert_eq!(
+            align_row(&["foo", "-", "12d"], &widths),
+            "foo      -         12d"
+        );
+    }
+
+    #[test]
+    fn hyperlink_format() {
+        assert_eq!(
+            hyperlink("qt6gtk2", "https://aur.archlinux.org/packages/qt6gtk2"),
+            "\x1b]8;;https://aur.archlinux.org/packages/qt6gtk2\x1b\\qt6gtk2\x1b]8;;\x1b\\"
+        );
+    }
+}
@@ -6,6 +6,8 @@
 //! Allows users to customize trigger behavior via config files:
 //! - `/etc/anneal/triggers/<trigger>.conf` - Override what packages a trigger marks
 //! - `/etc/anneal/packages/<package>.conf` - Override what triggers can mark a package
+//! - `/etc/anneal/groups/<group>.conf` - Define a package group for `mark --group`
+//! - `/etc/anneal/build-order.conf` - Ordering hints for `rebuild`
 //!
 //! ## File Format
 //!
@@ -17,11 +19,28 @@
 //! ```
 //!
 //! Empty file = disable trigger / never mark package.
+//!
+//! `anneal override` edits these files: entries are validated before they
+//! are written, new entries are appended (keeping comments), and removing
+//! entries never leaves an empty file behind, since that would silently
+//! change the override's meaning.
+//!
+//! ## Build Order
+//!
+//! `build-order.conf` holds one rule per line, with globs allowed on either
+//! side:
+//! ```text
+//! # foo-libs installs a host tool foo-plugin's build uses
+//! foo-libs before foo-plugin
+//! *-git after my-toolchain-git
+//! ```
 
 use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+use crate::tr;
 
 /// Directory containing trigger override files.
 pub const TRIGGERS_DIR: &str = "/etc/anneal/triggers";
@@ -29,6 +48,12 @@ pub const TRIGGERS_DIR: &str = "/etc/anneal/triggers";
 /// Directory containing package override files.
 pub const PACKAGES_DIR: &str = "/etc/anneal/packages";
 
+/// Directory containing user-defined group files.
+pub const GROUPS_DIR: &str = "/etc/anneal/groups";
+
+/// File containing user-declared build ordering rules.
+pub const BUILD_ORDER_FILE: &str = "/etc/anneal/build-order.conf";
+
 /// Loaded user overrides.
 #[derive(Debug, Default)]
 pub struct Overrides {
@@ -170,6 +195,16 @@ impl Overrides {
         }
     }
 
+    /// Get the override for a trigger, if there is one.
+    pub fn trigger_override(&self, trigger: &str) -> Option<&TriggerOverride> {
+        self.triggers.get(trigger)
+    }
+
+    /// Get the override for a package, if there is one.
+    pub fn package_override(&self, package: &str) -> Option<&PackageOverride> {
+        self.packages.get(package)
+    }
+
     /// List all user-defined trigger names.
     pub fn user_triggers(&self) -> impl Iterator<Item = &str> {
         self.triggers.keys().map(String::as_str)
@@ -200,6 +235,479 @@ impl PackageOverride {
     }
 }
 
+/// Load a user-defined group from the system groups directory.
+///
+/// Returns the group's package patterns, or None if no group file exists.
+pub fn load_group(name: &str) -> Option<Vec<String>> {
+    load_group_from(Path::new(GROUPS_DIR), name)
+}
+
+/// Load a user-defined group from a custom directory.
+pub fn load_group_from(groups_dir: &Path, name: &str) -> Option<Vec<String>> {
+    if name.is_empty() || name.starts_with('.') || name.contains('/') {
+        return None;
+    }
+    parse_override_file(&groups_dir.join(format!("{name}.conf"))).ok()
+}
+
+/// Kind of override file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OverrideKind {
+    /// `/etc/anneal/triggers/<trigger>.conf`: packages the trigger marks.
+    Trigger,
+    /// `/etc/anneal/packages/<package>.conf`: triggers that may mark it.
+    Package,
+}
+
+impl OverrideKind {
+    /// Directory holding overrides of this kind.
+    pub const fn dir(self) -> &'static str {
+        match self {
+            Self::Trigger => TRIGGERS_DIR,
+            Self::Package => PACKAGES_DIR,
+        }
+    }
+
+    /// Name shown in listings.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Trigger => "trigger",
+            Self::Package => "package",
+        }
+    }
+}
+
+/// An override file and its entries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverrideFile {
+    /// Trigger or package the override applies to.
+    pub name: String,
+    /// Path of the file.
+    pub path: PathBuf,
+    /// Patterns (trigger overrides) or trigger names (package overrides).
+    pub entries: Vec<String>,
+}
+
+/// Errors from editing override files.
+#[derive(Debug)]
+pub enum OverrideError {
+    /// Failed to read or write a file.
+    Io(PathBuf, io::Error),
+    /// Not a valid trigger or package name.
+    InvalidName(String),
+    /// Not a valid entry pattern.
+    InvalidPattern(String),
+    /// Removing the entries would leave the file empty, which means
+    /// "disabled" or "never mark".
+    WouldEmpty(PathBuf),
+}
+
+impl std::fmt::Display for OverrideError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "{}: {e}", path.display()),
+            Self::InvalidName(name) => {
+                f.write_str(&tr!("override-invalid-name", name = name.as_str()))
+            }
+            Self::InvalidPattern(pattern) => {
+                f.write_str(&tr!("override-invalid-pattern", pattern = pattern.as_str()))
+            }
+            Self::WouldEmpty(path) => f.write_str(&tr!(
+                "override-would-empty",
+                path = path.display().to_string()
+            )),
+        }
+    }
+}
+
+impl std::error::Error for OverrideError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(_, e) => Some(e),
+            Self::InvalidName(_) | Self::InvalidPattern(_) | Self::WouldEmpty(_) => None,
+        }
+    }
+}
+
+/// Check whether a character can appear in a package name.
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '@' | '.' | '_' | '+' | '-')
+}
+
+/// Validate a trigger or package name used as an override file name.
+///
+/// # Errors
+///
+/// Returns an error if the name isn't a valid package name.
+pub fn validate_name(name: &str) -> Result<(), OverrideError> {
+    if name.is_empty() || name.starts_with(['.', '-']) || !name.chars().all(is_name_char) {
+        return Err(OverrideError::InvalidName(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Validate an override entry: a package name with optional `*` and `?`
+/// wildcards.
+///
+/// # Errors
+///
+/// Returns an error if the pattern contains anything else.
+pub fn validate_pattern(pattern: &str) -> Result<(), OverrideError> {
+    if pattern.is_empty()
+        || !pattern
+            .chars()
+            .all(|c| is_name_char(c) || c == '*' || c == '?')
+    {
+        return Err(OverrideError::InvalidPattern(pattern.to_string()));
+    }
+    Ok(())
+}
+
+/// Path of an override file.
+fn override_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}.conf"))
+}
+
+/// Read an override file, if it exists.
+///
+/// # Errors
+///
+/// Returns an error if the name is invalid or the file can't be read.
+pub fn read_override(dir: &Path, name: &str) -> Result<Option<OverrideFile>, OverrideError> {
+    validate_name(name)?;
+    let path = override_path(dir, name);
+    match parse_override_file(&path) {
+        Ok(entries) => Ok(Some(OverrideFile {
+            name: name.to_string(),
+            path,
+            entries,
+        })),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(OverrideError::Io(path, e)),
+    }
+}
+
+/// List the override files in a directory, sorted by name.
+///
+/// A missing directory has no overrides.
+///
+/// # Errors
+///
+/// Returns an error if the directory or a file can't be read.
+pub fn list_overrides(dir: &Path) -> Result<Vec<OverrideFile>, OverrideError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(OverrideError::Io(dir.to_path_buf(), e)),
+    };
+
+    let mut files = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != "conf") {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        let entries = parse_override_file(&path).map_err(|e| OverrideError::Io(path.clone(), e))?;
+        files.push(OverrideFile {
+            name: name.to_string(),
+            path,
+            entries,
+        });
+    }
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(files)
+}
+
+/// Add entries to an override, creating the file (and directory) if needed.
+///
+/// Entries already present are skipped. Returns the entries that were added.
+/// With no entries, an empty file is created if none exists.
+///
+/// # Errors
+///
+/// Returns an error if the name or an entry is invalid, or the file can't be
+/// written.
+pub fn add_override(
+    dir: &Path,
+    name: &str,
+    entries: &[String],
+) -> Result<Vec<String>, OverrideError> {
+    validate_name(name)?;
+    for entry in entries {
+        validate_pattern(entry)?;
+    }
+
+    let path = override_path(dir, name);
+    let mut content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(OverrideError::Io(path, e)),
+    };
+    let existing: Vec<&str> = content.lines().map(str::trim).collect();
+
+    let mut added: Vec<String> = Vec::new();
+    for entry in entries {
+        if !existing.contains(&entry.as_str()) && !added.contains(entry) {
+            added.push(entry.clone());
+        }
+    }
+
+    if path.exists() && added.is_empty() {
+        return Ok(added);
+    }
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    for entry in &added {
+        content.push_str(entry);
+        content.push('\n');
+    }
+
+    fs::create_dir_all(dir).map_err(|e| OverrideError::Io(dir.to_path_buf(), e))?;
+    fs::write(&path, content).map_err(|e| OverrideError::Io(path.clone(), e))?;
+    Ok(added)
+}
+
+/// Remove entries from an override, or the whole file if `entries` is empty.
+///
+/// Returns the entries that were removed (for a whole file, its entries).
+/// Returns `None` if the override doesn't exist.
+///
+/// # Errors
+///
+/// Returns an error if the name is invalid, the file can't be written, or
+/// removing the entries would leave no entries.
+pub fn remove_override(
+    dir: &Path,
+    name: &str,
+    entries: &[String],
+) -> Result<Option<Vec<String>>, OverrideError> {
+    let Some(file) = read_override(dir, name)? else {
+        return Ok(None);
+    };
+
+    if entries.is_empty() {
+        fs::remove_file(&file.path).map_err(|e| OverrideError::Io(file.path.clone(), e))?;
+        return Ok(Some(file.entries));
+    }
+
+    let removed: Vec<String> = file
+        .entries
+        .iter()
+        .filter(|e| entries.contains(e))
+        .cloned()
+        .collect();
+    if removed.is_empty() {
+        return Ok(Some(removed));
+    }
+    if removed.len() == file.entries.len() {
+        return Err(OverrideError::WouldEmpty(file.path));
+    }
+
+    let content =
+        fs::read_to_string(&file.path).map_err(|e| OverrideError::Io(file.path.clone(), e))?;
+    let kept: String = content
+        .lines()
+        .filter(|line| !removed.iter().any(|r| r == line.trim()))
+        .map(|line| format!("{line}\n"))
+        .collect();
+    fs::write(&file.path, kept).map_err(|e| OverrideError::Io(file.path.clone(), e))?;
+    Ok(Some(removed))
+}
+
+/// A user-declared ordering rule: packages matching `first` are built before
+/// packages matching `then`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderRule {
+    /// Pattern of packages built first.
+    pub first: String,
+    /// Pattern of packages built afterwards.
+    pub then: String,
+}
+
+/// Build ordering rules from `build-order.conf`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildOrder {
+    /// Rules in file order.
+    rules: Vec<OrderRule>,
+}
+
+/// Errors from loading build ordering rules.
+#[derive(Debug)]
+pub enum BuildOrderError {
+    /// Failed to read the file.
+    Io(io::Error),
+    /// A line isn't `<a> before <b>` or `<a> after <b>`.
+    Parse {
+        /// Line number (1-based).
+        line: usize,
+        /// The offending line.
+        content: String,
+    },
+    /// The rules order the selected packages in a cycle.
+    Cycle(Vec<String>),
+}
+
+impl std::fmt::Display for BuildOrderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => f.write_str(&tr!(
+                "build-order-io",
+                file = BUILD_ORDER_FILE,
+                error = e.to_string()
+            )),
+            Self::Parse { line, content } => f.write_str(&tr!(
+                "build-order-parse",
+                file = BUILD_ORDER_FILE,
+                line = *line,
+                content = content.as_str()
+            )),
+            Self::Cycle(packages) => f.write_str(&tr!(
+                "build-order-cycle",
+                file = BUILD_ORDER_FILE,
+                packages = packages.join(", ")
+            )),
+        }
+    }
+}
+
+impl std::error::Error for BuildOrderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Parse { .. } | Self::Cycle(_) => None,
+        }
+    }
+}
+
+impl BuildOrder {
+    /// Load rules from the system file.
+    ///
+    /// A missing file means no rules.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be read or has a malformed line.
+    pub fn load() -> Result<Self, BuildOrderError> {
+        Self::load_from(Path::new(BUILD_ORDER_FILE))
+    }
+
+    /// Load rules from a custom path.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be read or has a malformed line.
+    pub fn load_from(path: &Path) -> Result<Self, BuildOrderError> {
+        match fs::read_to_string(path) {
+            Ok(content) => Self::parse(&content),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(BuildOrderError::Io(e)),
+        }
+    }
+
+    /// Parse rules (`<a> before <b>` or `<b> after <a>`, `#` comments).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for a malformed line.
+    pub fn parse(content: &str) -> Result<Self, BuildOrderError> {
+        let mut rules = Vec::new();
+
+        for (idx, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let rule = match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [a, "before", b] => OrderRule {
+                    first: a.to_string(),
+                    then: b.to_string(),
+                },
+                [a, "after", b] => OrderRule {
+                    first: b.to_string(),
+                    then: a.to_string(),
+                },
+                _ => {
+                    return Err(BuildOrderError::Parse {
+                        line: idx + 1,
+                        content: line.to_string(),
+                    });
+                }
+            };
+            rules.push(rule);
+        }
+
+        Ok(Self { rules })
+    }
+
+    /// Returns true if there are no rules.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Split packages into build stages that satisfy the rules.
+    ///
+    /// Each stage only depends on earlier stages; packages keep their given
+    /// order within a stage. Without applicable rules, everything is a
+    /// single stage.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BuildOrderError::Cycle`] with the packages involved if the
+    /// rules contradict each other.
+    pub fn stages<'a>(&self, packages: &[&'a str]) -> Result<Vec<Vec<&'a str>>, BuildOrderError> {
+        // must_follow[j] = packages that have to be built before j
+        let must_follow: Vec<Vec<usize>> = packages
+            .iter()
+            .map(|then| {
+                (0..packages.len())
+                    .filter(|&i| {
+                        packages[i] != *then
+                            && self.rules.iter().any(|rule| {
+                                matches_glob(&rule.first, packages[i])
+                                    && matches_glob(&rule.then, then)
+                            })
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut stage_of: Vec<Option<usize>> = vec![None; packages.len()];
+        let mut stages: Vec<Vec<&str>> = Vec::new();
+
+        while stage_of.iter().any(Option::is_none) {
+            let current = stages.len();
+            let ready: Vec<usize> = (0..packages.len())
+                .filter(|&j| {
+                    stage_of[j].is_none()
+                        && must_follow[j]
+                            .iter()
+                            .all(|&i| stage_of[i].is_some_and(|stage| stage < current))
+                })
+                .collect();
+
+            if ready.is_empty() {
+                return Err(BuildOrderError::Cycle(
+                    (0..packages.len())
+                        .filter(|&j| stage_of[j].is_none())
+                        .map(|j| packages[j].to_string())
+                        .collect(),
+                ));
+            }
+            for &j in &ready {
+                stage_of[j] = Some(current);
+            }
+            stages.push(ready.into_iter().map(|j| packages[j]).collect());
+        }
+
+        Ok(stages)
+    }
+}
+
 /// Parse an override file into a list of patterns.
 ///
 /// - Skips empty lines
@@ -218,6 +726,11 @@ fn parse_override_file(path: &Path) -> io::Result<Vec<String>> {
     Ok(patterns)
 }
 
+/// Returns true if a name is a glob pattern (contains `*` or `?`).
+pub fn is_glob(name: &str) -> bool {
+    name.contains(['*', '?'])
+}
+
 /// Match a glob pattern against a string.
 ///
 /// Supports:
@@ -271,6 +784,13 @@ mod tests {
     mod glob_matching {
         use super::*;
 
+        #[test]
+        fn glob_detection() {
+            assert!(is_glob("python-*"));
+            assert!(is_glob("lib?"));
+            assert!(!is_glob("python-foo"));
+        }
+
         #[test]
         fn exact_match() {
             assert!(matches_glob("hello", "hello"));
@@ -574,5 +1094,207 @@ mod tests {
             assert!(!overrides.should_mark_package("restricted-pkg", "gtk4"));
             assert!(!overrides.should_mark_package("restricted-pkg", "other"));
         }
+
+        #[test]
+        fn lookup_overrides() {
+            let overrides = make_overrides();
+            assert_eq!(
+                overrides.trigger_override("disabled-trigger"),
+                Some(&TriggerOverride::Disabled)
+            );
+            assert_eq!(overrides.trigger_override("qt6-base"), None);
+            assert_eq!(
+                overrides.package_override("never-pkg"),
+                Some(&PackageOverride::NeverMark)
+            );
+            assert_eq!(overrides.package_override("normal-pkg"), None);
+        }
+    }
+    mod editing {
+        use super::*;
+
+        fn entries(items: &[&str]) -> Vec<String> {
+            items.iter().map(|s| (*s).to_string()).collect()
+        }
+
+        #[test]
+        fn validation() {
+            assert!(validate_name("qt6-base").is_ok());
+            assert!(validate_name("libc++").is_ok());
+            assert!(validate_name("../etc").is_err());
+            assert!(validate_name("-x").is_err());
+            assert!(validate_name("").is_err());
+
+            assert!(validate_pattern("qt6-*").is_ok());
+            assert!(validate_pattern("foo?").is_ok());
+            assert!(validate_pattern("foo bar").is_err());
+            assert!(validate_pattern("foo#").is_err());
+            assert!(validate_pattern("a/b").is_err());
+        }
+
+        #[test]
+        fn add_and_remove_entries() {
+            let dir = tempfile::TempDir::new().unwrap();
+            let triggers = dir.path().join("triggers");
+
+            let added = add_override(&triggers, "my-lib", &entries(&["app-*", "tool"])).unwrap();
+            assert_eq!(added, entries(&["app-*", "tool"]));
+
+            // Existing entries are skipped, comments kept
+            let path = triggers.join("my-lib.conf");
+            let content = fs::read_to_string(&path).unwrap();
+            fs::write(&path, format!("# keep me\n{content}")).unwrap();
+            let added = add_override(&triggers, "my-lib", &entries(&["tool", "other"])).unwrap();
+            assert_eq!(added, entries(&["other"]));
+
+            let file = read_override(&triggers, "my-lib").unwrap().unwrap();
+            assert_eq!(file.entries, entries(&["app-*", "tool", "other"]));
+
+            let removed = remove_override(&triggers, "my-lib", &entries(&["tool"]))
+                .unwrap()
+                .unwrap();
+            assert_eq!(removed, entries(&["tool"]));
+            let content = fs::read_to_string(&path).unwrap();
+            assert_eq!(content, "# keep me\napp-*\nother\n");
+
+            // Never empties a file by removing entries
+            assert!(matches!(
+                remove_override(&triggers, "my-lib", &entries(&["app-*", "other"])),
+                Err(OverrideError::WouldEmpty(_))
+            ));
+
+            assert!(remove_override(&triggers, "my-lib", &[]).unwrap().is_some());
+            assert!(read_override(&triggers, "my-lib").unwrap().is_none());
+            assert!(remove_override(&triggers, "my-lib", &[]).unwrap().is_none());
+        }
+
+        #[test]
+        fn add_empty_creates_disabling_file() {
+            let dir = tempfile::TempDir::new().unwrap();
+            assert!(add_override(dir.path(), "noisy", &[]).unwrap().is_empty());
+            let file = read_override(dir.path(), "noisy").unwrap().unwrap();
+            assert!(file.entries.is_empty());
+        }
+
+        #[test]
+        fn list_sorted() {
+            let dir = tempfile::TempDir::new().unwrap();
+            add_override(dir.path(), "b", &entries(&["x"])).unwrap();
+            add_override(dir.path(), "a", &[]).unwrap();
+            fs::write(dir.path().join("README"), "not an override").unwrap();
+
+            let names: Vec<String> = list_overrides(dir.path())
+                .unwrap()
+                .into_iter()
+                .map(|f| f.name)
+                .collect();
+            assert_eq!(names, entries(&["a", "b"]));
+            assert!(
+                list_overrides(&dir.path().join("missing"))
+                    .unwrap()
+                    .is_empty()
+            );
+        }
+    }
+
+    mod build_order {
+        use super::*;
+
+        #[test]
+        fn parse_rules() {
+            let order =
+                BuildOrder::parse("# comment\nfoo before bar\n\nbaz after qux # trailing\n")
+                    .unwrap();
+            assert_eq!(
+                order.rules,
+                vec![
+                    OrderRule {
+                        first: "foo".into(),
+                        then: "bar".into()
+                    },
+                    OrderRule {
+                        first: "qux".into(),
+                        then: "baz".into()
+                    },
+                ]
+            );
+        }
+
+        #[test]
+        fn parse_error() {
+            let err = BuildOrder::parse("foo before bar\nfoo then bar\n").unwrap_err();
+            assert!(matches!(err, BuildOrderError::Parse { line: 2, .. }));
+        }
+
+        #[test]
+        fn missing_file_is_empty() {
+            let dir = tempfile::tempdir().unwrap();
+            let order = BuildOrder::load_from(&dir.path().join("build-order.conf")).unwrap();
+            assert!(order.is_empty());
+        }
+
+        #[test]
+        fn no_rules_single_stage() {
+            let order = BuildOrder::default();
+            assert_eq!(
+                order.stages(&["a", "b", "c"]).unwrap(),
+                vec![vec!["a", "b", "c"]]
+            );
+        }
+
+        #[test]
+        fn stages_follow_rules() {
+            let order = BuildOrder::parse("c before a\nlib-* before c\n").unwrap();
+            assert_eq!(
+                order.stages(&["a", "b", "c", "lib-x"]).unwrap(),
+                vec![vec!["b", "lib-x"], vec!["c"], vec!["a"]]
+            );
+        }
+
+        #[test]
+        fn rules_for_unselected_packages_ignored() {
+            let order = BuildOrder::parse("missing before a\n").unwrap();
+            assert_eq!(order.stages(&["a", "b"]).unwrap(), vec![vec!["a", "b"]]);
+        }
+
+        #[test]
+        #[allow(clippy::panic)]
+        fn cycle_is_error() {
+            let order = BuildOrder::parse("a before b\nb before a\n").unwrap();
+            match order.stages(&["a", "b", "c"]).unwrap_err() {
+                BuildOrderError::Cycle(packages) => assert_eq!(packages, vec!["a", "b"]),
+                e => panic!("expected cycle, got {e}"),
+            }
+        }
+    }
+
+    mod groups {
+        use super::*;
+
+        #[test]
+        fn load_group_file() {
+            let dir = tempfile::tempdir().unwrap();
+            fs::write(
+                dir.path().join("kde-extras.conf"),
+                "# KDE extras\nkf6-*\nplasma-foo\n",
+            )
+            .unwrap();
+
+            let group = load_group_from(dir.path(), "kde-extras").unwrap();
+            assert_eq!(group, vec!["kf6-*", "plasma-foo"]);
+        }
+
+        #[test]
+        fn load_missing_group() {
+            let dir = tempfile::tempdir().unwrap();
+            assert!(load_group_from(dir.path(), "nope").is_none());
+        }
+
+        #[test]
+        fn load_group_rejects_paths() {
+            let dir = tempfile::tempdir().unwrap();
+            fs::write(dir.path().join("x.conf"), "pkg\n").unwrap();
+            assert!(load_group_from(&dir.path().join("sub"), "../x").is_none());
+        }
     }
 }
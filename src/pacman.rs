@@ -0,0 +1,963 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! Queries against the local package database.
+//!
+//! All package database access goes through this module, which shells out to
+//! `pacman` and `pactree` (and `bsdtar`, `checkupdates` and `ldd` for what
+//! the database doesn't say).
+//!
+//! ## Fixture Mode
+//!
+//! For development and tests on machines without pacman, a fixture directory
+//! can be set with the hidden `--fixture-dir` flag or the `ANNEAL_FIXTURE_DIR`
+//! environment variable. Queries then read files instead of running commands:
+//!
+//! ```text
+//! <dir>/foreign          # pacman -Qmq output
+//! <dir>/pactree/<pkg>    # pactree -r -u <pkg> output
+//! <dir>/info/<pkg>       # pacman -Qi <pkg> output
+//! <dir>/groups/<group>   # pacman -Qgq <group> output
+//! <dir>/files/<pkg>      # pacman -Qlq <pkg> output
+//! <dir>/check/<pkg>      # pacman -Qkk <pkg> warnings
+//! <dir>/cache/<file>     # bsdtar -tf output for a cached package file
+//! <dir>/ldd/<pkg>        # ldd output for the ELF files of <pkg>
+//! <dir>/checkupdates     # checkupdates output
+//! ```
+//!
+//! A missing file behaves like the package not being installed, and a package
+//! counts as installed if it has an `info` file.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+use crate::tr;
+
+/// Fixture directory set on the command line.
+static FIXTURE_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Use a fixture directory instead of the system package database.
+///
+/// Takes precedence over `ANNEAL_FIXTURE_DIR`. Only the first call has an
+/// effect.
+pub fn set_fixture_dir(dir: PathBuf) {
+    let _ = FIXTURE_DIR.set(dir);
+}
+
+/// Get the fixture directory, if fixture mode is enabled.
+pub fn fixture_dir() -> Option<PathBuf> {
+    FIXTURE_DIR
+        .get()
+        .cloned()
+        .or_else(|| std::env::var_os("ANNEAL_FIXTURE_DIR").map(PathBuf::from))
+}
+
+/// Default pacman package cache directory.
+pub const CACHE_DIR: &str = "/var/cache/pacman/pkg";
+
+/// Lock file pacman holds for the duration of a transaction.
+pub const DB_LOCK: &str = "/var/lib/pacman/db.lck";
+
+/// Files passed to one ldd run.
+const LDD_BATCH: usize = 256;
+
+/// Installed package details from `pacman -Qi`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageInfo {
+    /// Package name.
+    pub name: String,
+    /// Installed version (e.g., "1:2.0.0-1").
+    pub version: String,
+    /// Package architecture (e.g., "x86_64" or "any").
+    pub arch: String,
+    /// Packages this one replaces (version constraints stripped).
+    pub replaces: Vec<String>,
+}
+
+/// A shared library problem of an ELF file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkProblem {
+    /// File needing the library.
+    pub file: String,
+    /// Library soname (e.g., "libicuuc.so.74").
+    pub library: String,
+    /// Symbol version the library lacks; `None` if the library is missing.
+    pub version: Option<String>,
+}
+
+impl std::fmt::Display for LinkProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.version {
+            None => write!(f, "{} not found", self.library),
+            Some(version) => write!(f, "{} lacks {version}", self.library),
+        }
+    }
+}
+
+/// Errors from querying the package database.
+#[derive(Debug)]
+pub enum PacmanError {
+    /// Failed to run pactree.
+    Pactree(io::Error),
+    /// Failed to run pacman.
+    Pacman(io::Error),
+    /// pactree returned non-zero exit code.
+    PactreeExitCode(i32),
+    /// pacman returned non-zero exit code.
+    PacmanExitCode(i32),
+    /// Failed to run checkupdates.
+    Checkupdates(io::Error),
+    /// checkupdates failed (exit code 1, e.g., no network).
+    CheckupdatesExitCode(i32),
+    /// Failed to run ldd.
+    Ldd(io::Error),
+    /// Failed to read a fixture file.
+    Fixture(PathBuf, io::Error),
+}
+
+impl std::fmt::Display for PacmanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pactree(e) => f.write_str(&tr!("pacman-pactree", error = e.to_string())),
+            Self::Pacman(e) => f.write_str(&tr!("pacman-pacman", error = e.to_string())),
+            Self::PactreeExitCode(code) => f.write_str(&tr!("pacman-pactree-exit", code = *code)),
+            Self::PacmanExitCode(code) => f.write_str(&tr!("pacman-pacman-exit", code = *code)),
+            Self::Checkupdates(e) => {
+                f.write_str(&tr!("pacman-checkupdates", error = e.to_string()))
+            }
+            Self::CheckupdatesExitCode(code) => {
+                f.write_str(&tr!("pacman-checkupdates-exit", code = *code))
+            }
+            Self::Ldd(e) => f.write_str(&tr!("pacman-ldd", error = e.to_string())),
+            Self::Fixture(path, e) => f.write_str(&tr!(
+                "pacman-fixture",
+                path = path.display().to_string(),
+                error = e.to_string()
+            )),
+        }
+    }
+}
+
+impl std::error::Error for PacmanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Pactree(e)
+            | Self::Pacman(e)
+            | Self::Checkupdates(e)
+            | Self::Ldd(e)
+            | Self::Fixture(_, e) => Some(e),
+            Self::PactreeExitCode(_) | Self::PacmanExitCode(_) | Self::CheckupdatesExitCode(_) => {
+                None
+            }
+        }
+    }
+}
+
+/// Get the list of foreign (AUR) packages.
+///
+/// # Errors
+///
+/// Returns an error if pacman fails.
+pub fn foreign_packages() -> Result<HashSet<String>, PacmanError> {
+    if let Some(dir) = fixture_dir() {
+        return Ok(parse_lines(&read_fixture(&dir.join("foreign"))?)
+            .into_iter()
+            .collect());
+    }
+
+    let output = Command::new("pacman")
+        .args(["-Qmq"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+        .map_err(PacmanError::Pacman)?;
+
+    if !output.status.success() {
+        let code = output.status.code().unwrap_or(-1);
+        // Exit code 1 with no output means no foreign packages
+        if code == 1 && output.stdout.is_empty() {
+            return Ok(HashSet::new());
+        }
+        return Err(PacmanError::PacmanExitCode(code));
+    }
+
+    Ok(read_lines(&output.stdout).into_iter().collect())
+}
+
+/// Get the reverse dependencies of a package using pactree.
+///
+/// Returns an empty list if the package isn't installed.
+///
+/// # Errors
+///
+/// Returns an error if pactree can't be run.
+pub fn reverse_deps(package: &str) -> Result<Vec<String>, PacmanError> {
+    let deps = if let Some(dir) = fixture_dir() {
+        let Some(path) = fixture_path(&dir, "pactree", package) else {
+            return Ok(Vec::new());
+        };
+        parse_lines(&read_fixture(&path)?)
+    } else {
+        let output = Command::new("pactree")
+            .args(["-r", "-u", package])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .output()
+            .map_err(PacmanError::Pactree)?;
+
+        if !output.status.success() {
+            // pactree returns 1 if package not found, which is fine
+            // (package might have been removed or not installed)
+            return Ok(Vec::new());
+        }
+
+        read_lines(&output.stdout)
+    };
+
+    Ok(deps.into_iter().filter(|line| line != package).collect())
+}
+
+/// Get the pending repository updates from `checkupdates` (pacman-contrib),
+/// as trigger inputs (`name:oldver:newver`).
+///
+/// checkupdates syncs a temporary copy of the package databases, so this
+/// works without root and leaves pacman's own databases alone.
+///
+/// # Errors
+///
+/// Returns an error if checkupdates can't be run or fails.
+pub fn pending_updates() -> Result<Vec<String>, PacmanError> {
+    if let Some(dir) = fixture_dir() {
+        return Ok(parse_updates(&read_fixture(&dir.join("checkupdates"))?));
+    }
+
+    let output = Command::new("checkupdates")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(PacmanError::Checkupdates)?;
+
+    // Exit code 2 means there are no updates
+    match output.status.code() {
+        Some(0 | 2) => Ok(parse_updates(&String::from_utf8_lossy(&output.stdout))),
+        code => Err(PacmanError::CheckupdatesExitCode(code.unwrap_or(-1))),
+    }
+}
+
+/// Parse an update list as printed by checkupdates or `pacman -Qu`
+/// (`<name> <old> -> <new>`) into trigger inputs.
+///
+/// Updates pacman is told to ignore (`[ignored]`) won't be installed and are
+/// left out. A line with only a name is kept without versions.
+pub fn parse_updates(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [] | [.., "[ignored]"] => None,
+                [name, old, "->", new] => Some(format!("{name}:{old}:{new}")),
+                [name] => Some((*name).to_string()),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Get the installed members of a package group.
+///
+/// Returns an empty list if the group doesn't exist or has no installed members.
+///
+/// # Errors
+///
+/// Returns an error if pacman can't be run.
+pub fn group_members(group: &str) -> Result<Vec<String>, PacmanError> {
+    if let Some(dir) = fixture_dir() {
+        let Some(path) = fixture_path(&dir, "groups", group) else {
+            return Ok(Vec::new());
+        };
+        return Ok(parse_lines(&read_fixture(&path)?));
+    }
+
+    let output = Command::new("pacman")
+        .args(["-Qgq", group])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+        .map_err(PacmanError::Pacman)?;
+
+    if !output.status.success() {
+        // pacman returns 1 if the group isn't found
+        return Ok(Vec::new());
+    }
+
+    Ok(read_lines(&output.stdout))
+}
+
+/// Filter a list of package names to those that are installed.
+///
+/// # Errors
+///
+/// Returns an error if pacman can't be run.
+pub fn installed_packages(packages: &[&str]) -> Result<HashSet<String>, PacmanError> {
+    if packages.is_empty() {
+        return Ok(HashSet::new());
+    }
+
+    if let Some(dir) = fixture_dir() {
+        return Ok(packages
+            .iter()
+            .filter(|pkg| fixture_path(&dir, "info", pkg).is_some_and(|path| path.is_file()))
+            .map(|pkg| (*pkg).to_string())
+            .collect());
+    }
+
+    let output = Command::new("pacman")
+        .arg("-Qq")
+        .args(packages)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+        .map_err(PacmanError::Pacman)?;
+
+    // pacman exits non-zero if any package is missing, but still lists the
+    // installed ones, so the exit status is ignored
+    Ok(read_lines(&output.stdout).into_iter().collect())
+}
+
+/// Get details of installed packages using `pacman -Qi`.
+///
+/// Packages that aren't installed are absent from the result.
+///
+/// # Errors
+///
+/// Returns an error if pacman can't be run.
+pub fn package_info(packages: &[&str]) -> Result<Vec<PackageInfo>, PacmanError> {
+    if packages.is_empty() {
+        // pacman -Qi without arguments lists every installed package
+        return Ok(Vec::new());
+    }
+
+    if let Some(dir) = fixture_dir() {
+        let mut info = Vec::new();
+        for pkg in packages {
+            if let Some(path) = fixture_path(&dir, "info", pkg) {
+                info.extend(parse_package_info(&read_fixture(&path)?));
+            }
+        }
+        return Ok(info);
+    }
+
+    let output = Command::new("pacman")
+        .arg("-Qi")
+        .args(packages)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+        .map_err(PacmanError::Pacman)?;
+
+    // pacman exits non-zero if any package is missing, but still prints the
+    // rest, so the exit status is ignored
+    Ok(parse_package_info(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Get the files owned by installed packages using `pacman -Ql`.
+///
+/// Returns `(package, path)` pairs. Directories end with `/`.
+///
+/// # Errors
+///
+/// Returns an error if pacman can't be run.
+pub fn package_files(packages: &[&str]) -> Result<Vec<(String, String)>, PacmanError> {
+    if packages.is_empty() {
+        // pacman -Ql without arguments lists every installed file
+        return Ok(Vec::new());
+    }
+
+    if let Some(dir) = fixture_dir() {
+        let mut files = Vec::new();
+        for pkg in packages {
+            if let Some(path) = fixture_path(&dir, "files", pkg) {
+                files.extend(
+                    parse_lines(&read_fixture(&path)?)
+                        .into_iter()
+                        .map(|file| ((*pkg).to_string(), file)),
+                );
+            }
+        }
+        return Ok(files);
+    }
+
+    let output = Command::new("pacman")
+        .arg("-Ql")
+        .args(packages)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+        .map_err(PacmanError::Pacman)?;
+
+    // As with -Qi, missing packages don't prevent the rest from being listed
+    Ok(parse_file_list(&read_lines(&output.stdout)))
+}
+
+/// Check the installed files of packages against the package database using
+/// `pacman -Qkk`.
+///
+/// Returns `(package, problem)` pairs, e.g. `("foo", "/usr/bin/foo (Size
+/// mismatch)")`.
+///
+/// # Errors
+///
+/// Returns an error if pacman can't be run.
+pub fn check_files(packages: &[&str]) -> Result<Vec<(String, String)>, PacmanError> {
+    if packages.is_empty() {
+        // pacman -Qkk without arguments checks every installed package
+        return Ok(Vec::new());
+    }
+
+    if let Some(dir) = fixture_dir() {
+        let mut problems = Vec::new();
+        for pkg in packages {
+            if let Some(path) = fixture_path(&dir, "check", pkg) {
+                problems.extend(parse_file_check(&parse_lines(&read_fixture(&path)?)));
+            }
+        }
+        return Ok(problems);
+    }
+
+    let output = Command::new("pacman")
+        .arg("-Qkk")
+        .args(packages)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(PacmanError::Pacman)?;
+
+    // Problems are reported as warnings on stderr and make pacman exit
+    // non-zero, so the exit status is ignored
+    Ok(parse_file_check(&read_lines(&output.stderr)))
+}
+
+/// Find installed foreign packages that replace the given (removed) packages.
+///
+/// Returns `(old, new)` pairs, e.g. after an AUR package was renamed and the
+/// new package declares `replaces=(old)`.
+///
+/// # Errors
+///
+/// Returns an error if pacman can't be run.
+pub fn find_replacements(packages: &[&str]) -> Result<Vec<(String, String)>, PacmanError> {
+    if packages.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let foreign = foreign_packages()?;
+    let mut foreign: Vec<&str> = foreign.iter().map(String::as_str).collect();
+    foreign.sort_unstable();
+
+    let mut replacements = Vec::new();
+    for info in package_info(&foreign)? {
+        for old in &info.replaces {
+            if packages.contains(&old.as_str()) && *old != info.name {
+                replacements.push((old.clone(), info.name.clone()));
+            }
+        }
+    }
+
+    Ok(replacements)
+}
+
+/// Find the package files of a package in the pacman cache.
+///
+/// Returns `(version, path)` pairs in no particular order. An unreadable
+/// cache counts as empty.
+pub fn cached_packages(package: &str) -> Vec<(String, PathBuf)> {
+    let dir = match fixture_dir() {
+        Some(dir) => dir.join("cache"),
+        None => PathBuf::from(CACHE_DIR),
+    };
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Vec::new();
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let version = package_file_version(name.to_str()?, package)?;
+            Some((version, entry.path()))
+        })
+        .collect()
+}
+
+/// List the files in a package file using `bsdtar -tf`.
+///
+/// Paths are relative (`usr/lib/libfoo.so.1`) and include the package
+/// metadata files. Returns `None` if the archive can't be read.
+pub fn archive_files(path: &Path) -> Option<Vec<String>> {
+    if fixture_dir().is_some() {
+        return fs::read_to_string(path).ok().map(|c| parse_lines(&c));
+    }
+
+    let output = Command::new("bsdtar")
+        .arg("-tf")
+        .arg(path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output.status.success().then(|| read_lines(&output.stdout))
+}
+
+/// Find the shared library problems of an installed package's files, using
+/// `ldd`: libraries the dynamic loader can't find, and libraries lacking a
+/// symbol version the file needs.
+///
+/// `files` are the package's files as `pacman -Ql` lists them; only regular
+/// files starting with the ELF magic are checked. Problems are in the order
+/// ldd reports them.
+///
+/// # Errors
+///
+/// Returns an error if ldd can't be run.
+pub fn link_problems(package: &str, files: &[&str]) -> Result<Vec<LinkProblem>, PacmanError> {
+    if let Some(dir) = fixture_dir() {
+        return match fixture_path(&dir, "ldd", package) {
+            Some(path) => Ok(parse_ldd(&read_fixture(&path)?)),
+            None => Ok(Vec::new()),
+        };
+    }
+
+    let elf: Vec<&str> = files
+        .iter()
+        .copied()
+        .filter(|path| is_elf(Path::new(path)))
+        .collect();
+    let mut output = String::new();
+    // Batched to stay well below the argument length limit
+    for batch in elf.chunks(LDD_BATCH) {
+        let result = Command::new("ldd")
+            .args(batch)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .map_err(PacmanError::Ldd)?;
+        // ldd only names the file when given several
+        if let [file] = batch {
+            output.push_str(&format!("{file}:\n"));
+        }
+        // ldd exits non-zero if any file isn't dynamically linked, but still
+        // lists the others. Missing symbol versions go to stderr.
+        output.push_str(&String::from_utf8_lossy(&result.stdout));
+        output.push_str(&String::from_utf8_lossy(&result.stderr));
+    }
+    Ok(parse_ldd(&output))
+}
+
+/// Check whether a path is a regular file starting with the ELF magic.
+///
+/// Symlinks are skipped, since they point at files checked on their own.
+fn is_elf(path: &Path) -> bool {
+    if !fs::symlink_metadata(path).is_ok_and(|meta| meta.is_file()) {
+        return false;
+    }
+    let mut magic = [0; 4];
+    fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok_and(|()| magic == *b"\x7fELF")
+}
+
+/// Parse the problems in ldd output.
+///
+/// Files are introduced by a `<path>:` line. Missing libraries are listed
+/// under their file (`libfoo.so.1 => not found`), and missing symbol
+/// versions name it (`<path>: /usr/lib/libfoo.so.1: version `FOO_1.2' not
+/// found (required by <path>)`).
+fn parse_ldd(output: &str) -> Vec<LinkProblem> {
+    let mut problems = Vec::new();
+    let mut file = "";
+    for line in output.lines() {
+        if let Some((library, _)) = line.trim().split_once(" => not found") {
+            problems.push(LinkProblem {
+                file: file.to_string(),
+                library: library.to_string(),
+                version: None,
+            });
+        } else if let Some((_, rest)) = line.split_once(": /")
+            && let Some((library, rest)) = rest.split_once(": version `")
+            && let Some((version, rest)) = rest.split_once("' not found")
+        {
+            let required_by = rest
+                .trim()
+                .strip_prefix("(required by ")
+                .and_then(|rest| rest.strip_suffix(')'))
+                .unwrap_or(file);
+            let library = library.rsplit('/').next().unwrap_or(library);
+            problems.push(LinkProblem {
+                file: required_by.to_string(),
+                library: library.to_string(),
+                version: Some(version.to_string()),
+            });
+        } else if !line.starts_with(char::is_whitespace)
+            && let Some(path) = line.strip_suffix(':')
+        {
+            file = path;
+        }
+    }
+    problems
+}
+
+/// Get the version from a package file name for `package`
+/// (`<name>-<pkgver>-<pkgrel>-<arch>.pkg.tar.<ext>`).
+///
+/// Package names can contain dashes, so `foo-git-1.0-1-x86_64` is a package
+/// file for `foo-git` but not for `foo`. Signatures aren't package files.
+pub fn package_file_version(file_name: &str, package: &str) -> Option<String> {
+    if file_name.ends_with(".sig") {
+        return None;
+    }
+    let (stem, _) = file_name.split_once(".pkg.tar")?;
+    let rest = stem.strip_prefix(package)?.strip_prefix('-')?;
+    let (version, arch) = rest.rsplit_once('-')?;
+    let (pkgver, pkgrel) = version.split_once('-')?;
+    if pkgver.is_empty() || pkgrel.is_empty() || pkgrel.contains('-') || arch.is_empty() {
+        return None;
+    }
+    Some(version.to_string())
+}
+
+/// Path of a per-package fixture file.
+///
+/// Returns None for names that can't be file names.
+fn fixture_path(dir: &Path, kind: &str, package: &str) -> Option<PathBuf> {
+    if package.is_empty() || package.starts_with('.') || package.contains('/') {
+        return None;
+    }
+    Some(dir.join(kind).join(package))
+}
+
+/// Read a fixture file, treating a missing file as empty.
+fn read_fixture(path: &Path) -> Result<String, PacmanError> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(contents),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(PacmanError::Fixture(path.to_path_buf(), e)),
+    }
+}
+
+/// Split command output into trimmed, non-empty lines.
+fn read_lines(output: &[u8]) -> Vec<String> {
+    BufReader::new(output)
+        .lines()
+        .map_while(Result::ok)
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Split fixture contents into trimmed, non-empty lines.
+fn parse_lines(contents: &str) -> Vec<String> {
+    read_lines(contents.as_bytes())
+}
+
+/// Parse `pacman -Ql` output lines (`<package> <path>`).
+fn parse_file_list(lines: &[String]) -> Vec<(String, String)> {
+    lines
+        .iter()
+        .filter_map(|line| {
+            let (pkg, path) = line.split_once(' ')?;
+            Some((pkg.to_string(), path.to_string()))
+        })
+        .collect()
+}
+
+/// Parse `pacman -Qkk` warnings (`warning: <package>: <problem>`).
+fn parse_file_check(lines: &[String]) -> Vec<(String, String)> {
+    lines
+        .iter()
+        .filter_map(|line| {
+            let (pkg, problem) = line.strip_prefix("warning: ")?.split_once(": ")?;
+            Some((pkg.to_string(), problem.to_string()))
+        })
+        .collect()
+}
+
+/// Parse `pacman -Qi` output.
+///
+/// Entries missing a version or architecture are ignored.
+fn parse_package_info(output: &str) -> Vec<PackageInfo> {
+    let mut packages = Vec::new();
+
+    // Entries are separated by blank lines
+    for entry in output.split("\n\n") {
+        let mut name = None;
+        let mut version = None;
+        let mut arch = None;
+        let mut replaces = Vec::new();
+
+        for line in entry.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim().to_string();
+            match key.trim() {
+                "Name" => name = Some(value),
+                "Version" => version = Some(value),
+                "Architecture" => arch = Some(value),
+                "Replaces" if value != "None" => {
+                    replaces = value.split_whitespace().map(strip_constraint).collect();
+                }
+                _ => {}
+            }
+        }
+
+        if let (Some(name), Some(version), Some(arch)) = (name, version, arch) {
+            packages.push(PackageInfo {
+                name,
+                version,
+                arch,
+                replaces,
+            });
+        }
+    }
+
+    packages
+}
+
+/// Strip a version constraint from a dependency (`foo>=1.0` becomes `foo`).
+fn strip_constraint(dep: &str) -> String {
+    dep.split(['<', '>', '=']).next().unwrap_or(dep).to_string()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_list() {
+        let output = "\
+qt6-base 6.7.0-1 -> 6.8.0-1
+linux 6.9.1.arch1-1 -> 6.9.2.arch1-1 [ignored]
+ffmpeg
+
+garbage line here
+";
+        assert_eq!(
+            parse_updates(output),
+            vec!["qt6-base:6.7.0-1:6.8.0-1", "ffmpeg"]
+        );
+    }
+
+    #[test]
+    fn parse_info() {
+        let output = "\
+Name            : foo-git
+Version         : 1:1.0-1
+Architecture    : x86_64
+Depends On      : qt6-base
+
+Name            : bar-theme
+Version         : 2.0-1
+Architecture    : any
+";
+        let info = parse_package_info(output);
+        assert_eq!(info.len(), 2);
+        assert_eq!(
+            info[0],
+            PackageInfo {
+                name: "foo-git".into(),
+                version: "1:1.0-1".into(),
+                arch: "x86_64".into(),
+                replaces: vec![],
+            }
+        );
+        assert_eq!(info[1].name, "bar-theme");
+        assert_eq!(info[1].arch, "any");
+    }
+
+    #[test]
+    fn parse_info_replaces() {
+        let output = "\
+Name            : foo-ng
+Version         : 2.0-1
+Architecture    : x86_64
+Replaces        : foo  foo-git<2.0
+";
+        let info = parse_package_info(output);
+        assert_eq!(info[0].replaces, vec!["foo", "foo-git"]);
+    }
+
+    #[test]
+    fn parse_info_replaces_none() {
+        let output = "\
+Name            : foo
+Version         : 1.0-1
+Architecture    : x86_64
+Replaces        : None
+";
+        assert!(parse_package_info(output)[0].replaces.is_empty());
+    }
+
+    #[test]
+    fn strip_version_constraints() {
+        assert_eq!(strip_constraint("foo"), "foo");
+        assert_eq!(strip_constraint("foo>=1.0"), "foo");
+        assert_eq!(strip_constraint("foo<2"), "foo");
+        assert_eq!(strip_constraint("foo=1.0-1"), "foo");
+    }
+
+    #[test]
+    fn parse_info_empty() {
+        assert!(parse_package_info("").is_empty());
+    }
+
+    #[test]
+    fn parse_info_skips_incomplete_entries() {
+        let output = "Name            : foo\nVersion         : 1.0-1\n";
+        assert!(parse_package_info(output).is_empty());
+    }
+
+    #[test]
+    fn parse_files() {
+        let lines = vec![
+            "python-foo /usr/lib/python3.12/".to_string(),
+            "python-foo /usr/lib/python3.12/site-packages/foo file.py".to_string(),
+        ];
+        assert_eq!(
+            parse_file_list(&lines),
+            vec![
+                ("python-foo".into(), "/usr/lib/python3.12/".into()),
+                (
+                    "python-foo".into(),
+                    "/usr/lib/python3.12/site-packages/foo file.py".into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_check() {
+        let lines = vec![
+            "warning: foo: /usr/lib/libfoo.so.1 (No such file or directory)".to_string(),
+            "foo: 12 total files, 1 missing file".to_string(),
+            "error: package 'bar' was not found".to_string(),
+        ];
+        assert_eq!(
+            parse_file_check(&lines),
+            vec![(
+                "foo".into(),
+                "/usr/lib/libfoo.so.1 (No such file or directory)".into()
+            )]
+        );
+    }
+
+    #[test]
+    fn lines_are_trimmed() {
+        assert_eq!(parse_lines("  foo \n\nbar\n"), vec!["foo", "bar"]);
+    }
+
+    #[test]
+    fn fixture_path_rejects_unsafe_names() {
+        let dir = Path::new("/fixtures");
+        assert_eq!(
+            fixture_path(dir, "info", "foo"),
+            Some(PathBuf::from("/fixtures/info/foo"))
+        );
+        assert_eq!(fixture_path(dir, "info", "../foo"), None);
+        assert_eq!(fixture_path(dir, "info", ".hidden"), None);
+        assert_eq!(fixture_path(dir, "info", ""), None);
+    }
+
+    #[test]
+    fn ldd_output() {
+        let output = "\
+/opt/foo/bin/foo:
+\tlinux-vdso.so.1 (0x00007ffc)
+\tlibicuuc.so.74 => not found
+\tlibc.so.6 => /usr/lib/libc.so.6 (0x00007f00)
+/opt/foo/lib/libbar.so:
+\tlibicuuc.so.74 => not found
+\tlibboost_regex.so.1.83.0 => not found
+/opt/foo/lib/libbar.so: /usr/lib/libstdc++.so.6: version `GLIBCXX_3.4.32' not found (required by /opt/foo/lib/libbar.so)
+";
+        let problems = parse_ldd(output);
+        let described: Vec<(&str, String)> = problems
+            .iter()
+            .map(|p| (p.file.as_str(), p.to_string()))
+            .collect();
+        assert_eq!(
+            described,
+            vec![
+                ("/opt/foo/bin/foo", "libicuuc.so.74 not found".to_string()),
+                (
+                    "/opt/foo/lib/libbar.so",
+                    "libicuuc.so.74 not found".to_string()
+                ),
+                (
+                    "/opt/foo/lib/libbar.so",
+                    "libboost_regex.so.1.83.0 not found".to_string()
+                ),
+                (
+                    "/opt/foo/lib/libbar.so",
+                    "libstdc++.so.6 lacks GLIBCXX_3.4.32".to_string()
+                ),
+            ]
+        );
+        assert!(parse_ldd("/usr/bin/foo:\n\tstatically linked\n").is_empty());
+    }
+
+    #[test]
+    fn elf_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let elf = dir.path().join("foo");
+        fs::write(&elf, b"\x7fELF\x02\x01\x01").unwrap();
+        fs::write(dir.path().join("script"), "#!/bin/sh\n").unwrap();
+        fs::write(dir.path().join("tiny"), "x").unwrap();
+        std::os::unix::fs::symlink(&elf, dir.path().join("link")).unwrap();
+
+        assert!(is_elf(&elf));
+        assert!(!is_elf(&dir.path().join("script")));
+        assert!(!is_elf(&dir.path().join("tiny")));
+        assert!(!is_elf(&dir.path().join("link")));
+        assert!(!is_elf(dir.path()));
+        assert!(!is_elf(&dir.path().join("missing")));
+    }
+
+    #[test]
+    fn package_file_versions() {
+        assert_eq!(
+            package_file_version("openssl-3.3.2-1-x86_64.pkg.tar.zst", "openssl"),
+            Some("3.3.2-1".into())
+        );
+        assert_eq!(
+            package_file_version("foo-1:1.0-2-any.pkg.tar.xz", "foo"),
+            Some("1:1.0-2".into())
+        );
+        assert_eq!(
+            package_file_version("foo-git-r12.abc-1-x86_64.pkg.tar.zst", "foo"),
+            None
+        );
+        assert_eq!(
+            package_file_version("foo-1.0-1-x86_64.pkg.tar.zst.sig", "foo"),
+            None
+        );
+        assert_eq!(package_file_version("foo-1.0.tar.gz", "foo"), None);
+    }
+
+    #[test]
+    fn empty_queries_run_nothing() {
+        assert!(installed_packages(&[]).unwrap().is_empty());
+        assert!(package_info(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn missing_fixture_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(read_fixture(&dir.path().join("foreign")).unwrap(), "");
+    }
+}
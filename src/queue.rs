@@ -0,0 +1,759 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! Changing the queue: marking, unmarking, pinning, clearing and cleaning,
+//! and registering local sources.
+//!
+//! These are the flows behind `mark`, `unmark`, `pin`, `prioritize`,
+//! `clear`, `clean` and `source add`. Each opens the database, applies the change, records
+//! it in the audit log and returns what happened; printing it, and prompting
+//! before destructive changes, is up to the caller.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use crate::audit;
+use crate::aur::AurClient;
+use crate::config::Config;
+use crate::db::{Database, DbError, Origin, Priority, QueueEntry, get_db_path};
+use crate::error::Error;
+use crate::overrides::{self, Overrides, PackageOverride, is_glob, matches_glob};
+use crate::pacman;
+use crate::time::cutoff_date;
+use crate::triggers::ecosystem_tag;
+
+/// Options for [`mark`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MarkOptions<'a> {
+    /// Trigger package that caused the mark.
+    pub trigger: Option<&'a str>,
+    /// Version of the trigger package.
+    pub trigger_version: Option<&'a str>,
+    /// Mark packages even if they aren't installed.
+    pub allow_missing: bool,
+    /// Tags for the marked packages.
+    pub tags: &'a [String],
+    /// Why the packages are marked.
+    pub reason: Option<&'a str>,
+    /// Rebuild priority of the packages.
+    pub priority: Option<Priority>,
+    /// When the packages leave the queue on their own.
+    pub expires_at: Option<&'a str>,
+}
+
+/// Result of [`mark`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Marked {
+    /// Packages that are now queued.
+    pub marked: Vec<String>,
+    /// How many of them weren't queued before.
+    pub newly_marked: usize,
+    /// Packages left out because they aren't installed.
+    pub missing: Vec<String>,
+}
+
+/// Result of [`unmark`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Unmarked {
+    /// Packages removed from the queue.
+    pub removed: Vec<String>,
+    /// Packages that weren't queued.
+    pub not_queued: Vec<String>,
+    /// Pinned packages kept in the queue.
+    pub pinned: Vec<String>,
+    /// Entries moved to the package that replaced them, as `(old, new)`.
+    pub moved: Vec<(String, String)>,
+}
+
+/// Result of [`set_pinned`] and [`prioritize`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Changed {
+    /// Queued packages that were changed.
+    pub changed: Vec<String>,
+    /// Packages that aren't queued.
+    pub not_queued: Vec<String>,
+}
+
+/// What [`clear`] removes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClearTarget<'a> {
+    /// The whole queue.
+    Queue,
+    /// Queued packages carrying a tag.
+    Tag(&'a str),
+}
+
+/// Packages [`clear`] would remove, see [`clearable`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Clearable {
+    /// Packages that would be removed.
+    pub packages: Vec<String>,
+    /// How many pinned packages would be kept.
+    pub pinned: usize,
+}
+
+/// Result of [`clean`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cleaned {
+    /// Entries past their expiry.
+    pub expired: Vec<String>,
+    /// Entries of packages that are no longer installed.
+    pub uninstalled: Vec<String>,
+    /// Entries whose package changed version since it was marked.
+    pub stale: Vec<String>,
+    /// Entries moved to the package that replaced them, as `(old, new)`.
+    pub replaced: Vec<(String, String)>,
+    /// History records older than `retention_days`.
+    pub pruned_events: usize,
+}
+
+/// Result of [`add_source`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceAdded {
+    /// Absolute path of the checkout, as stored.
+    pub path: String,
+    /// Whether the package had a checkout registered already.
+    pub moved: bool,
+    /// Whether pacman reports the package as not installed.
+    pub not_installed: bool,
+}
+
+/// Open the database read-only.
+///
+/// # Errors
+///
+/// Returns [`Error::NoDatabase`] if it doesn't exist yet, or the database
+/// error if it can't be opened.
+pub fn open_readonly() -> Result<Database, Error> {
+    Database::open_readonly(&get_db_path()).map_err(|e| {
+        if matches!(&e, DbError::Sqlite(rusqlite::Error::SqliteFailure(err, _))
+            if err.code == rusqlite::ErrorCode::CannotOpen)
+        {
+            Error::NoDatabase
+        } else {
+            e.into()
+        }
+    })
+}
+
+/// Mark packages for rebuild.
+///
+/// Names that aren't installed are refused (typos would otherwise sit in
+/// the queue forever), unless `allow_missing` is set.
+///
+/// # Errors
+///
+/// Returns an error if pacman can't be queried or the database can't be
+/// written.
+pub fn mark(config: &Config, packages: &[String], opts: &MarkOptions) -> Result<Marked, Error> {
+    let mut missing = Vec::new();
+    if !opts.allow_missing {
+        let names: Vec<&str> = packages.iter().map(String::as_str).collect();
+        let installed = pacman::installed_packages(&names)?;
+        missing = packages
+            .iter()
+            .filter(|pkg| !installed.contains(pkg.as_str()))
+            .cloned()
+            .collect();
+    }
+
+    let mut db = Database::open(config.retention_days)?;
+
+    let marked: Vec<&str> = packages
+        .iter()
+        .map(String::as_str)
+        .filter(|pkg| !missing.iter().any(|m| m == pkg))
+        .collect();
+    let newly_marked = db.mark_all(&marked, opts.trigger, opts.trigger_version)?;
+    for pkg in &marked {
+        db.add_tags(pkg, &mark_tags(opts.tags, opts.trigger))?;
+        if let Some(reason) = opts.reason {
+            db.set_reason(pkg, reason)?;
+        }
+        if let Some(priority) = opts.priority {
+            db.set_priority(pkg, priority)?;
+        }
+        if let Some(expires_at) = opts.expires_at {
+            db.set_expiry(pkg, expires_at)?;
+        }
+    }
+    record_marked_versions(&mut db, &marked)?;
+    audit::record(config, "mark", &marked);
+
+    Ok(Marked {
+        marked: marked.into_iter().map(String::from).collect(),
+        newly_marked,
+        missing,
+    })
+}
+
+/// Mark packages found by a scan, each with its own trigger and trigger
+/// version.
+///
+/// Returns the packages that weren't queued before.
+///
+/// # Errors
+///
+/// Returns an error if the database can't be written.
+pub fn mark_found(
+    config: &Config,
+    found: &[(&str, &str, Option<&str>)],
+) -> Result<Vec<String>, Error> {
+    let mut db = Database::open(config.retention_days)?;
+    let mut newly_marked = Vec::new();
+
+    for &(package, trigger, version) in found {
+        if db.mark(package, Some(trigger), version)? {
+            newly_marked.push(package.to_string());
+        }
+        db.add_tags(package, &mark_tags(&[], Some(trigger)))?;
+    }
+
+    let marked: Vec<&str> = found.iter().map(|&(package, _, _)| package).collect();
+    record_marked_versions(&mut db, &marked)?;
+    audit::record(config, "mark", &marked);
+    Ok(newly_marked)
+}
+
+/// Record the installed version of marked packages, so an entry rebuilt
+/// outside anneal can be told apart later (see [`stale_versions`]).
+///
+/// Best-effort: if pacman can't be run, the versions stay unknown.
+///
+/// # Errors
+///
+/// Returns an error if the database can't be written.
+pub fn record_marked_versions(db: &mut Database, packages: &[&str]) -> Result<(), Error> {
+    let Ok(info) = pacman::package_info(packages) else {
+        return Ok(());
+    };
+    for pkg in info {
+        db.set_marked_version(&pkg.name, &pkg.version)?;
+    }
+    Ok(())
+}
+
+/// Find queued packages whose installed version changed since they were
+/// marked (likely rebuilt outside anneal), with the version now installed.
+///
+/// Pinned packages are left out, since rebuilding them doesn't unmark them.
+pub fn stale_versions(queue: &[QueueEntry]) -> HashMap<String, String> {
+    let known: Vec<&str> = queue
+        .iter()
+        .filter(|e| e.marked_version.is_some() && !e.pinned)
+        .map(|e| e.package.as_str())
+        .collect();
+    if known.is_empty() {
+        return HashMap::new();
+    }
+    let Ok(info) = pacman::package_info(&known) else {
+        return HashMap::new();
+    };
+    let installed: HashMap<String, String> = info
+        .into_iter()
+        .map(|pkg| (pkg.name, pkg.version))
+        .collect();
+    queue
+        .iter()
+        .filter(|e| !e.pinned)
+        .filter_map(|e| {
+            let marked = e.marked_version.as_ref()?;
+            let version = installed.get(&e.package)?;
+            (marked != version).then(|| (e.package.clone(), version.clone()))
+        })
+        .collect()
+}
+
+/// Tags for a marked package: the given tags plus the trigger's ecosystem tag.
+pub fn mark_tags<'a>(tags: &'a [String], trigger: Option<&str>) -> Vec<&'a str> {
+    let mut all: Vec<&str> = tags.iter().map(String::as_str).collect();
+    if let Some(tag) = trigger.and_then(ecosystem_tag) {
+        all.push(tag);
+    }
+    all
+}
+
+/// Expand a group to its installed foreign members.
+///
+/// A user-defined group in `/etc/anneal/groups` takes precedence over a
+/// pacman group of the same name.
+///
+/// # Errors
+///
+/// Returns an error if pacman can't be queried.
+pub fn resolve_group(group: &str) -> Result<Vec<String>, Error> {
+    let foreign = pacman::foreign_packages()?;
+
+    let mut members: Vec<String> = match overrides::load_group(group) {
+        Some(patterns) => foreign
+            .into_iter()
+            .filter(|pkg| patterns.iter().any(|pattern| matches_glob(pattern, pkg)))
+            .collect(),
+        None => pacman::group_members(group)?
+            .into_iter()
+            .filter(|pkg| foreign.contains(pkg))
+            .collect(),
+    };
+
+    members.sort();
+    Ok(members)
+}
+
+/// Expand glob patterns among package names against the installed foreign
+/// packages, dropping duplicates.
+///
+/// Returns the packages and the patterns that matched nothing. pacman is
+/// only queried if there is a pattern.
+///
+/// # Errors
+///
+/// Returns an error if pacman can't be queried.
+pub fn expand_globs(names: Vec<String>) -> Result<(Vec<String>, Vec<String>), Error> {
+    let foreign = if names.iter().any(|name| is_glob(name)) {
+        let mut foreign: Vec<String> = pacman::foreign_packages()?.into_iter().collect();
+        foreign.sort();
+        foreign
+    } else {
+        Vec::new()
+    };
+
+    let mut seen = HashSet::new();
+    let mut packages = Vec::new();
+    let mut unmatched = Vec::new();
+    for name in names {
+        if is_glob(&name) {
+            let matches: Vec<&String> = foreign
+                .iter()
+                .filter(|pkg| matches_glob(&name, pkg))
+                .collect();
+            if matches.is_empty() {
+                unmatched.push(name);
+            }
+            for pkg in matches {
+                if seen.insert(pkg.clone()) {
+                    packages.push(pkg.clone());
+                }
+            }
+        } else if seen.insert(name.clone()) {
+            packages.push(name);
+        }
+    }
+    Ok((packages, unmatched))
+}
+
+/// Find the installed foreign packages `mark --foreign` marks: all of them
+/// except ignored packages, `skip_packages` and -bin packages (rebuilding
+/// those just downloads the same binary).
+///
+/// # Errors
+///
+/// Returns an error if pacman can't be queried.
+pub fn resolve_foreign(config: &Config) -> Result<Vec<String>, Error> {
+    let overrides = Overrides::load();
+    let mut packages: Vec<String> = pacman::foreign_packages()?
+        .into_iter()
+        .filter(|pkg| {
+            !pkg.ends_with("-bin")
+                && !config.is_skipped_package(pkg)
+                && overrides.package_override(pkg) != Some(&PackageOverride::NeverMark)
+        })
+        .collect();
+
+    packages.sort();
+    Ok(packages)
+}
+
+/// Find the installed foreign packages maintained by an AUR user.
+///
+/// # Errors
+///
+/// Returns an error if pacman or the AUR can't be queried.
+pub fn resolve_maintainer(config: &Config, maintainer: &str) -> Result<Vec<String>, Error> {
+    let foreign = pacman::foreign_packages()?;
+    let mut packages: Vec<String> = AurClient::from_config(config)
+        .by_maintainer(maintainer)?
+        .into_iter()
+        .map(|pkg| pkg.name)
+        .filter(|name| foreign.contains(name))
+        .collect();
+
+    packages.sort();
+    Ok(packages)
+}
+
+/// Remove packages from the queue.
+///
+/// Pinned packages are kept unless `include_pinned` is set. The remove hook
+/// ([`Origin::Hook`]) also fires for packages removed because another package
+/// replaced them, in which case the entry follows the new name instead.
+///
+/// # Errors
+///
+/// Returns an error if the database can't be written, or pacman can't be
+/// queried for replacements.
+pub fn unmark(
+    config: &Config,
+    packages: &[String],
+    origin: Origin,
+    include_pinned: bool,
+) -> Result<Unmarked, Error> {
+    let mut db = Database::open(config.retention_days)?;
+    let mut packages = packages.to_vec();
+
+    let mut moved = Vec::new();
+    if origin == Origin::Hook {
+        let names: Vec<&str> = packages.iter().map(String::as_str).collect();
+        moved = migrate_replaced(config, &mut db, &names, origin)?;
+        packages.retain(|pkg| !moved.iter().any(|(old, _)| old == pkg));
+    }
+
+    let mut pinned = Vec::new();
+    if !include_pinned {
+        pinned = db
+            .pinned_packages()?
+            .into_iter()
+            .filter(|pkg| packages.contains(pkg))
+            .collect();
+        packages.retain(|pkg| !pinned.contains(pkg));
+    }
+
+    let mut removed = Vec::new();
+    let mut not_queued = Vec::new();
+    for pkg in packages {
+        if db.unmark(&pkg, origin)? {
+            removed.push(pkg);
+        } else {
+            not_queued.push(pkg);
+        }
+    }
+    let names: Vec<&str> = removed.iter().map(String::as_str).collect();
+    audit::record(config, "unmark", &names);
+
+    Ok(Unmarked {
+        removed,
+        not_queued,
+        pinned,
+        moved,
+    })
+}
+
+/// Move queue entries of replaced packages to their replacements.
+///
+/// `removed` are package names that are no longer installed. Returns the
+/// entries that were moved, as `(old, new)`.
+///
+/// # Errors
+///
+/// Returns an error if pacman can't be queried or the database can't be
+/// written.
+pub fn migrate_replaced(
+    config: &Config,
+    db: &mut Database,
+    removed: &[&str],
+    origin: Origin,
+) -> Result<Vec<(String, String)>, Error> {
+    let mut moved = Vec::new();
+
+    for (old, new) in pacman::find_replacements(removed)? {
+        if db.rename(&old, &new, origin)? {
+            audit::record(config, "rename", &[&old, &new]);
+            moved.push((old, new));
+        }
+    }
+
+    Ok(moved)
+}
+
+/// Pin or unpin queued packages.
+///
+/// # Errors
+///
+/// Returns an error if the database can't be written.
+pub fn set_pinned(config: &Config, packages: &[String], pin: bool) -> Result<Changed, Error> {
+    let mut db = Database::open(config.retention_days)?;
+    let result = apply(packages, |pkg| db.set_pinned(pkg, pin))?;
+    audit::record(config, if pin { "pin" } else { "unpin" }, &names(&result));
+    Ok(result)
+}
+
+/// Set the priority of queued packages.
+///
+/// # Errors
+///
+/// Returns an error if the database can't be written.
+pub fn prioritize(
+    config: &Config,
+    packages: &[String],
+    priority: Priority,
+) -> Result<Changed, Error> {
+    let mut db = Database::open(config.retention_days)?;
+    let result = apply(packages, |pkg| db.set_priority(pkg, priority))?;
+    audit::record(config, "prioritize", &names(&result));
+    Ok(result)
+}
+
+/// Apply a change to each package, splitting them by whether it was queued.
+fn apply(
+    packages: &[String],
+    mut change: impl FnMut(&str) -> Result<bool, DbError>,
+) -> Result<Changed, Error> {
+    let mut result = Changed::default();
+    for pkg in packages {
+        if change(pkg)? {
+            result.changed.push(pkg.clone());
+        } else {
+            result.not_queued.push(pkg.clone());
+        }
+    }
+    Ok(result)
+}
+
+/// Names of the changed packages, for the audit log.
+fn names(result: &Changed) -> Vec<&str> {
+    result.changed.iter().map(String::as_str).collect()
+}
+
+/// Find the packages [`clear`] would remove.
+///
+/// # Errors
+///
+/// Returns an error if the database can't be read.
+pub fn clearable(
+    config: &Config,
+    target: ClearTarget,
+    include_pinned: bool,
+) -> Result<Clearable, Error> {
+    let db = Database::open(config.retention_days)?;
+    let pinned = if include_pinned {
+        Vec::new()
+    } else {
+        db.pinned_packages()?
+    };
+
+    let mut packages = match target {
+        ClearTarget::Queue => db.list()?.into_iter().map(|e| e.package).collect(),
+        ClearTarget::Tag(tag) => db.tagged_packages(tag)?,
+    };
+    let queued = packages.len();
+    packages.retain(|pkg| !pinned.contains(pkg));
+
+    Ok(Clearable {
+        pinned: queued - packages.len(),
+        packages,
+    })
+}
+
+/// Remove the whole queue, or the packages carrying a tag.
+///
+/// Pinned packages are kept unless `include_pinned` is set. A cleared queue
+/// can be brought back with [`undo_clear`]. Returns the removed packages.
+///
+/// # Errors
+///
+/// Returns an error if the database can't be written.
+pub fn clear(
+    config: &Config,
+    target: ClearTarget,
+    include_pinned: bool,
+) -> Result<Vec<String>, Error> {
+    let mut db = Database::open(config.retention_days)?;
+    let cleared = match target {
+        ClearTarget::Queue => {
+            let pinned = if include_pinned {
+                Vec::new()
+            } else {
+                db.pinned_packages()?
+            };
+            let queue: Vec<String> = db
+                .list()?
+                .into_iter()
+                .map(|e| e.package)
+                .filter(|pkg| !pinned.contains(pkg))
+                .collect();
+            db.clear(include_pinned)?;
+            queue
+        }
+        ClearTarget::Tag(tag) => db.clear_tag(tag, include_pinned)?,
+    };
+    let names: Vec<&str> = cleared.iter().map(String::as_str).collect();
+    audit::record(config, "clear", &names);
+    Ok(cleared)
+}
+
+/// Clear the trigger history of a trigger package.
+///
+/// Returns the number of events removed.
+///
+/// # Errors
+///
+/// Returns an error if the database can't be written.
+pub fn clear_trigger(config: &Config, trigger: &str) -> Result<usize, Error> {
+    let count = Database::open(config.retention_days)?.clear_trigger_events(trigger)?;
+    audit::record(config, "clear-trigger", &[trigger]);
+    Ok(count)
+}
+
+/// Bring back the packages removed by the last [`clear`].
+///
+/// Returns `None` if there is nothing to undo.
+///
+/// # Errors
+///
+/// Returns an error if the database can't be written.
+pub fn undo_clear(config: &Config) -> Result<Option<Vec<String>>, Error> {
+    let restored = Database::open(config.retention_days)?.undo_clear()?;
+    if let Some(restored) = &restored {
+        let names: Vec<&str> = restored.iter().map(String::as_str).collect();
+        audit::record(config, "undo-clear", &names);
+    }
+    Ok(restored)
+}
+
+/// Drop queue entries that no longer apply, and prune old history.
+///
+/// Removes expired entries, entries of packages that are no longer
+/// installed (or moves them to the package that replaced them) and entries
+/// whose package changed version since it was marked. Pinned packages are
+/// always kept. With `dry_run`, nothing is changed and the result says what
+/// would be.
+///
+/// # Errors
+///
+/// Returns an error if the database can't be read or written, or pacman
+/// can't be queried.
+pub fn clean(config: &Config, dry_run: bool) -> Result<Cleaned, Error> {
+    // Read everything first: opening the database for writing drops the
+    // expired entries right away
+    let db = open_readonly()?;
+    let queue = db.list()?;
+    let expired = db.expired_packages()?;
+    let old_events = match config.retention_days {
+        0 => 0,
+        days => db.count_events_before(&cutoff_date(days))?,
+    };
+    drop(db);
+
+    let queued: Vec<&str> = queue
+        .iter()
+        .map(|e| e.package.as_str())
+        .filter(|pkg| !expired.iter().any(|e| e == pkg))
+        .collect();
+    let installed = pacman::installed_packages(&queued)?;
+    let removed: Vec<&str> = queued
+        .into_iter()
+        .filter(|pkg| !installed.contains(*pkg))
+        .collect();
+    // Replaced packages move to the replacing package rather than being
+    // dropped, like in `trigger`
+    let replaced = pacman::find_replacements(&removed)?;
+    let pinned: HashSet<&str> = queue
+        .iter()
+        .filter(|e| e.pinned)
+        .map(|e| e.package.as_str())
+        .collect();
+    let uninstalled: Vec<String> = removed
+        .into_iter()
+        .filter(|pkg| !pinned.contains(pkg) && !replaced.iter().any(|(old, _)| old == pkg))
+        .map(String::from)
+        .collect();
+    let mut stale: Vec<String> = stale_versions(&queue)
+        .into_keys()
+        .filter(|pkg| !expired.contains(pkg))
+        .collect();
+    stale.sort_unstable();
+
+    let mut pruned_events = old_events;
+    if !dry_run {
+        let mut db = Database::open(config.retention_days)?;
+        for (old, new) in &replaced {
+            if db.rename(old, new, Origin::Cli)? {
+                audit::record(config, "rename", &[old, new]);
+            }
+        }
+        for pkg in &uninstalled {
+            db.complete_uninstall(pkg, Origin::Cli)?;
+        }
+        for pkg in &stale {
+            db.complete_reinstall(pkg, Origin::Cli)?;
+        }
+        pruned_events = db.prune_old_events()?;
+
+        let unmarked: Vec<&str> = expired
+            .iter()
+            .chain(uninstalled.iter())
+            .chain(stale.iter())
+            .map(String::as_str)
+            .collect();
+        audit::record(config, "unmark", &unmarked);
+    }
+
+    Ok(Cleaned {
+        expired,
+        uninstalled,
+        stale,
+        replaced,
+        pruned_events,
+    })
+}
+
+/// Register (or move) the local PKGBUILD checkout a package is rebuilt
+/// from (`source add`).
+///
+/// The directory is stored as an absolute path, since rebuilds may run from
+/// anywhere. A package that isn't installed can still be registered before
+/// its first build.
+///
+/// # Errors
+///
+/// Returns [`Error::Usage`] if the directory doesn't exist or has no
+/// `PKGBUILD`, or the database error if it can't be written.
+pub fn add_source(config: &Config, package: &str, dir: &Path) -> Result<SourceAdded, Error> {
+    let path = fs::canonicalize(dir)
+        .map_err(|e| Error::Usage(format!("Cannot use {}: {e}", dir.display())))?;
+    if !path.join("PKGBUILD").is_file() {
+        return Err(Error::Usage(format!("No PKGBUILD in {}", path.display())));
+    }
+    let not_installed =
+        pacman::installed_packages(&[package]).is_ok_and(|installed| installed.is_empty());
+
+    let path = path.to_string_lossy().into_owned();
+    let mut db = Database::open(config.retention_days)?;
+    let moved = !db.add_source(package, &path)?;
+    Ok(SourceAdded {
+        path,
+        moved,
+        not_installed,
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mark_tags_add_ecosystem() {
+        let tags = vec!["manual".to_string()];
+        assert_eq!(mark_tags(&tags, Some("qt6-base")), vec!["manual", "qt"]);
+        assert_eq!(mark_tags(&tags, Some("my-lib")), vec!["manual"]);
+        assert_eq!(mark_tags(&[], None), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn expand_globs_without_patterns() {
+        // Plain names don't need pacman, and duplicates are dropped
+        let names = vec!["foo".to_string(), "bar".to_string(), "foo".to_string()];
+        let (packages, unmatched) = expand_globs(names).unwrap();
+        assert_eq!(packages, vec!["foo", "bar"]);
+        assert!(unmatched.is_empty());
+    }
+
+    #[test]
+    fn apply_splits_by_queued() {
+        let packages = vec!["queued".to_string(), "other".to_string()];
+        let result = apply(&packages, |pkg| Ok(pkg == "queued")).unwrap();
+        assert_eq!(result.changed, vec!["queued"]);
+        assert_eq!(result.not_queued, vec!["other"]);
+    }
+}
@@ -0,0 +1,1830 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! Running rebuilds: deciding what to build, finding the AUR helper and
+//! the commands that build each stage, and running them.
+//!
+//! [`plan_rebuild`] selects the packages and splits them into stages,
+//! [`run_rebuild`] builds them, unmarking rebuilt queued packages and
+//! recording each build in the history. Confirming the rebuild and
+//! reporting the outcome are up to the caller.
+//!
+//! Underneath, [`detect_helper`] resolves the configured (or installed)
+//! helper to a [`HelperInvocation`], and [`stage_runs`] turns a stage of the
+//! build order into [`BuildRun`]s: helper invocations for AUR packages,
+//! makepkg for local sources and, with the built-in backend, makepkg in each
+//! package base's AUR checkout.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::audit;
+use crate::aur::{AurClient, AurPackage};
+use crate::aurgit::{self, GitError, Synced};
+use crate::buildlog;
+use crate::config::{AutoRebuild, Config, FALLBACK_HELPERS, KNOWN_HELPERS, MAKEPKG_HELPER};
+use crate::db::{Database, Origin, get_db_path};
+use crate::error::Error;
+use crate::lock::{self, RebuildLock};
+use crate::net::NetOptions;
+use crate::output;
+use crate::overrides::{BuildOrder, BuildOrderError, Overrides, matches_glob};
+use crate::pacman::{self, PacmanError};
+use crate::queue::{open_readonly, stale_versions};
+use crate::scan;
+use crate::template::Template;
+use crate::time::now_iso8601;
+use crate::tr;
+use crate::validate::{self, Problem};
+
+/// How long `rebuild --wait-for-pacman` waits for pacman's lock (and for
+/// another rebuild to finish).
+pub const PACMAN_WAIT: Duration = Duration::from_secs(30 * 60);
+
+/// makepkg arguments for rebuilding a local source: install missing
+/// dependencies, overwrite the previous build, install the result, and clean
+/// up the build directory.
+const MAKEPKG_ARGS: &[&str] = &["--syncdeps", "--force", "--install", "--clean"];
+
+/// Profile added to unattended rebuilds when it is defined.
+const AUTO_REBUILD_PROFILE: &str = "auto";
+
+/// Name of the transient unit for `auto_rebuild = systemd`.
+const AUTO_REBUILD_UNIT: &str = "anneal-auto-rebuild";
+
+/// Rebuild-specific errors.
+#[derive(Debug)]
+pub enum RebuildError {
+    /// No AUR helper found in PATH.
+    NoHelper,
+    /// Multiple AUR helpers found, user must configure one.
+    AmbiguousHelper(Vec<String>),
+    /// Specified helper not found in PATH.
+    HelperNotFound(String),
+    /// Custom helper command has an invalid placeholder.
+    HelperTemplate(String),
+    /// Helper process failed to start.
+    HelperSpawn(io::Error),
+    /// Helper exited with non-zero code.
+    HelperFailed(i32),
+    /// Package not in queue (without -f flag).
+    PackageNotInQueue(String),
+    /// Profile not defined in the config.
+    UnknownProfile(String),
+    /// Build order hints couldn't be loaded or contradict each other.
+    BuildOrder(BuildOrderError),
+    /// makepkg failed to start for a local source.
+    MakepkgSpawn(io::Error),
+    /// makepkg exited with non-zero code for a local source or the built-in
+    /// backend.
+    MakepkgFailed(String, i32),
+    /// The AUR checkout of a package base couldn't be cloned or updated.
+    Checkout(String, GitError),
+    /// pacman's lock was still held after waiting for it.
+    PacmanBusy,
+    /// Another rebuild holds the rebuild lock.
+    AlreadyRunning(PathBuf),
+}
+
+impl std::fmt::Display for RebuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoHelper => f.write_str(&tr!(
+                "no-helper",
+                helpers = KNOWN_HELPERS.join(", "),
+                makepkg = MAKEPKG_HELPER
+            )),
+            Self::AmbiguousHelper(helpers) => {
+                f.write_str(&tr!("ambiguous-helper", helpers = helpers.join(", ")))
+            }
+            Self::HelperNotFound(name) => {
+                f.write_str(&tr!("helper-not-found", name = name.as_str()))
+            }
+            Self::HelperTemplate(msg) => {
+                f.write_str(&tr!("helper-template", message = msg.as_str()))
+            }
+            Self::HelperSpawn(e) => f.write_str(&tr!("helper-spawn", error = e.to_string())),
+            Self::HelperFailed(code) => f.write_str(&tr!("helper-failed", code = *code)),
+            Self::PackageNotInQueue(pkg) => {
+                f.write_str(&tr!("package-not-in-queue", package = pkg.as_str()))
+            }
+            Self::UnknownProfile(name) => {
+                f.write_str(&tr!("unknown-profile", name = name.as_str()))
+            }
+            Self::BuildOrder(e) => write!(f, "{e}"),
+            Self::MakepkgSpawn(e) => f.write_str(&tr!("makepkg-spawn", error = e.to_string())),
+            Self::MakepkgFailed(pkg, code) => {
+                f.write_str(&tr!("makepkg-failed", code = *code, package = pkg.as_str()))
+            }
+            Self::Checkout(base, e) => f.write_str(&tr!(
+                "checkout-failed",
+                base = base.as_str(),
+                error = e.to_string()
+            )),
+            Self::PacmanBusy => f.write_str(&tr!(
+                "pacman-busy",
+                minutes = PACMAN_WAIT.as_secs() / 60,
+                lock = pacman::DB_LOCK
+            )),
+            Self::AlreadyRunning(path) => {
+                f.write_str(&tr!("rebuild-running", lock = path.display().to_string()))
+            }
+        }
+    }
+}
+
+impl std::error::Error for RebuildError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::HelperSpawn(e) | Self::MakepkgSpawn(e) => Some(e),
+            Self::BuildOrder(e) => Some(e),
+            Self::Checkout(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Placeholders in a custom helper command.
+const HELPER_PLACEHOLDERS: &[&str] = &["packages", "package", "logdir", "pkgdest"];
+
+/// Information about how to invoke an AUR helper.
+#[derive(Debug)]
+pub struct HelperInvocation {
+    /// The command to run (e.g., "paru").
+    command: String,
+    /// Base arguments for rebuild (e.g., ["-S", "--rebuild"]).
+    base_args: Vec<String>,
+    /// Arguments of a custom command with placeholders, replacing
+    /// `base_args`.
+    template: Option<Vec<HelperArg>>,
+    /// Value of `{logdir}` (`build_log_dir`).
+    log_dir: Option<String>,
+    /// Value of `{pkgdest}` (`package_dir`).
+    pkgdest: Option<String>,
+    /// The helper only builds, into a local repository (aurutils); the
+    /// packages are installed with pacman afterwards.
+    install_after: bool,
+    /// The built-in backend: makepkg in a checkout of each package base.
+    builtin: bool,
+    /// Package bases of the AUR packages, for the built-in backend. Packages
+    /// missing here are their own base.
+    package_bases: HashMap<String, String>,
+}
+
+/// A command building some packages of a stage.
+#[derive(Debug)]
+pub struct BuildRun<'a> {
+    /// Packages the command builds.
+    pub packages: Vec<&'a str>,
+    /// The helper or makepkg command.
+    pub command: Command,
+    /// AUR package base to clone or update before running makepkg (built-in
+    /// backend, see [`sync_checkout`]).
+    pub checkout: Option<String>,
+    /// pacman command installing the packages once built.
+    pub install: Option<Command>,
+}
+
+/// An argument of a custom helper command with placeholders.
+#[derive(Debug)]
+enum HelperArg {
+    /// `{packages}` as a whole argument: one argument per package.
+    Packages,
+    /// Any other argument, with its placeholders filled in.
+    Word(Template),
+}
+
+impl HelperInvocation {
+    /// Create invocation for a known helper.
+    pub fn for_known_helper(name: &str) -> Self {
+        let base_args: &[&str] = match name {
+            "aura" => &["-A", "--rebuild"],
+            // pamac always builds AUR packages, installed or not
+            "pamac" => &["build"],
+            // pakku reinstalls like pacman; --build makes it build from source
+            "pakku" => &["-S", "--build"],
+            "aur" => &["sync", "--rebuild"],
+            _ => &["-S", "--rebuild"],
+        };
+        Self {
+            command: name.to_string(),
+            base_args: base_args.iter().map(|arg| arg.to_string()).collect(),
+            template: None,
+            log_dir: None,
+            pkgdest: None,
+            install_after: name == "aur",
+            builtin: false,
+            package_bases: HashMap::new(),
+        }
+    }
+
+    /// Create invocation for aura of a major version.
+    ///
+    /// aura 4 (the rewrite) has no `--rebuild`; `--force` rebuilds installed
+    /// packages there. An unknown version gets the 3.x flags.
+    pub fn for_aura(major: Option<u32>) -> Self {
+        let mut helper = Self::for_known_helper("aura");
+        if major.is_some_and(|major| major >= 4) {
+            helper.base_args = vec!["-A".to_string(), "--force".to_string()];
+        }
+        helper
+    }
+
+    /// Create invocation for the built-in makepkg backend.
+    pub fn builtin_makepkg(flags: &[String]) -> Self {
+        Self {
+            command: MAKEPKG_HELPER.to_string(),
+            base_args: flags.to_vec(),
+            template: None,
+            log_dir: None,
+            pkgdest: None,
+            install_after: false,
+            builtin: true,
+            package_bases: HashMap::new(),
+        }
+    }
+
+    /// Create invocation from a custom command string.
+    ///
+    /// Arguments may contain placeholders (`{packages}`, `{package}`,
+    /// `{logdir}`, `{pkgdest}`).
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if a placeholder is unknown or malformed.
+    pub fn from_custom(cmd: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = cmd.split_whitespace().collect();
+        let Some((command, args)) = parts.split_first() else {
+            // Shouldn't happen, but handle gracefully
+            return Ok(Self {
+                command: cmd.to_string(),
+                base_args: vec![],
+                template: None,
+                log_dir: None,
+                pkgdest: None,
+                install_after: false,
+                builtin: false,
+                package_bases: HashMap::new(),
+            });
+        };
+
+        let template = if args.iter().any(|arg| arg.contains(['{', '}', '\\'])) {
+            let args = args
+                .iter()
+                .map(|arg| match *arg {
+                    "{packages}" => Ok(HelperArg::Packages),
+                    arg => Template::parse(arg, HELPER_PLACEHOLDERS).map(HelperArg::Word),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Some(args)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            command: command.to_string(),
+            base_args: args.iter().map(|s| s.to_string()).collect(),
+            template,
+            log_dir: None,
+            pkgdest: None,
+            install_after: false,
+            builtin: false,
+            package_bases: HashMap::new(),
+        })
+    }
+
+    /// Returns true if this is the built-in makepkg backend.
+    pub fn is_builtin(&self) -> bool {
+        self.builtin
+    }
+
+    /// Look up the package bases of AUR packages for the built-in backend.
+    ///
+    /// Most packages are their own base, so when the AUR can't be queried the
+    /// packages are assumed to be. Being offline is expected and silent.
+    pub fn lookup_package_bases(&mut self, aur: &AurClient, packages: &[&str]) {
+        self.package_bases = match aur.info(packages) {
+            Ok(info) => info
+                .into_iter()
+                .map(|pkg| (pkg.name, pkg.package_base))
+                .collect(),
+            Err(e) if e.is_offline() => HashMap::new(),
+            Err(e) => {
+                output::warning(&format!("Could not look up package bases on the AUR: {e}"));
+                HashMap::new()
+            }
+        };
+    }
+
+    /// Returns true if the helper takes one package per invocation
+    /// (`{package}`).
+    pub fn per_package(&self) -> bool {
+        self.template.iter().flatten().any(|arg| match arg {
+            HelperArg::Packages => false,
+            HelperArg::Word(word) => word.uses("package"),
+        })
+    }
+
+    /// Get the arguments building some packages.
+    ///
+    /// Without a placeholder for them, packages are appended.
+    pub fn args(&self, packages: &[&str]) -> Vec<String> {
+        let Some(template) = &self.template else {
+            let mut args = self.base_args.clone();
+            args.extend(packages.iter().map(|pkg| pkg.to_string()));
+            return args;
+        };
+
+        let mut args = Vec::new();
+        let mut placed = false;
+        for arg in template {
+            match arg {
+                HelperArg::Packages => {
+                    args.extend(packages.iter().map(|pkg| pkg.to_string()));
+                    placed = true;
+                }
+                HelperArg::Word(word) => {
+                    placed |= word.uses("packages") || word.uses("package");
+                    args.push(word.render(|name| match name {
+                        "logdir" => self.log_dir.clone().unwrap_or_default(),
+                        "pkgdest" => self.pkgdest.clone().unwrap_or_default(),
+                        _ => packages.join(" "),
+                    }));
+                }
+            }
+        }
+        if !placed {
+            args.extend(packages.iter().map(|pkg| pkg.to_string()));
+        }
+        args
+    }
+}
+
+/// Estimate the total time of a rebuild from past build durations.
+///
+/// Packages that never built successfully count as the average of those
+/// that did. Returns `None` if none of the packages has built before.
+pub fn estimate_rebuild(durations: &HashMap<String, u64>, packages: &[&str]) -> Option<u64> {
+    let known: Vec<u64> = packages
+        .iter()
+        .filter_map(|pkg| durations.get(*pkg).copied())
+        .collect();
+    if known.is_empty() {
+        return None;
+    }
+    let total: u64 = known.iter().sum();
+    let unknown = (packages.len() - known.len()) as u64;
+    Some(total + total / known.len() as u64 * unknown)
+}
+
+/// Format a time estimate in whole minutes, rounded up (`~48 min`).
+pub fn format_estimate(secs: u64) -> String {
+    let minutes = secs.div_ceil(60).max(1);
+    if minutes < 60 {
+        format!("~{minutes} min")
+    } else {
+        format!("~{}h {:02} min", minutes / 60, minutes % 60)
+    }
+}
+
+/// Detect which AUR helper to use.
+///
+/// The command-line override wins over the configured `helper`; without
+/// either, the one known helper in PATH is used.
+///
+/// # Errors
+///
+/// Returns an error if the helper isn't installed, or if none or several
+/// are found when detecting it.
+pub fn detect_helper(
+    config: &Config,
+    cmd_override: Option<&str>,
+) -> Result<HelperInvocation, RebuildError> {
+    let with_config = |mut helper: HelperInvocation| {
+        helper.log_dir = config.build_log_dir.clone();
+        helper.pkgdest = config.package_dir.clone();
+        helper
+    };
+
+    // Priority 1: Command-line override
+    if let Some(cmd) = cmd_override {
+        output::debug(1, &format!("Using helper '{cmd}' (--cmd)"));
+        return resolve_helper(cmd, config).map(with_config);
+    }
+
+    // Priority 2: Config file
+    if let Some(ref helper) = config.helper {
+        output::debug(1, &format!("Using helper '{helper}' (config)"));
+        return resolve_helper(helper, config).map(with_config);
+    }
+
+    // Priority 3: Auto-detect from PATH, falling back to helpers that are
+    // often installed for other reasons
+    let installed: Vec<&str> = KNOWN_HELPERS
+        .iter()
+        .copied()
+        .filter(|h| is_in_path(h))
+        .collect();
+    let (fallback, found): (Vec<&str>, Vec<&str>) = installed
+        .into_iter()
+        .partition(|h| FALLBACK_HELPERS.contains(h));
+    output::debug(
+        2,
+        &format!(
+            "Helpers in PATH: {} (fallbacks: {})",
+            list_or_none(&found),
+            list_or_none(&fallback)
+        ),
+    );
+    let found = if found.is_empty() { fallback } else { found };
+
+    match found.len() {
+        0 => Err(RebuildError::NoHelper),
+        1 => {
+            output::debug(1, &format!("Using helper '{}' (auto-detected)", found[0]));
+            Ok(known_helper(found[0]))
+        }
+        _ => Err(RebuildError::AmbiguousHelper(
+            found.into_iter().map(String::from).collect(),
+        )),
+    }
+}
+
+/// Join names for a debug message, or "none".
+fn list_or_none(names: &[&str]) -> String {
+    if names.is_empty() {
+        "none".to_string()
+    } else {
+        names.join(", ")
+    }
+}
+
+/// Resolve a helper string to an invocation.
+fn resolve_helper(helper: &str, config: &Config) -> Result<HelperInvocation, RebuildError> {
+    // The built-in backend needs git for the checkouts as well
+    if helper == MAKEPKG_HELPER {
+        if let Some(missing) = [MAKEPKG_HELPER, "git"].into_iter().find(|c| !is_in_path(c)) {
+            return Err(RebuildError::HelperNotFound(missing.to_string()));
+        }
+        return Ok(HelperInvocation::builtin_makepkg(&config.makepkg_flags));
+    }
+
+    // Check if it's a known helper name
+    if Config::is_known_helper(helper) {
+        if !is_in_path(helper) {
+            return Err(RebuildError::HelperNotFound(helper.to_string()));
+        }
+        return Ok(known_helper(helper));
+    }
+
+    // Custom command - extract first word to verify it exists
+    let cmd_name = helper.split_whitespace().next().unwrap_or(helper);
+    if !is_in_path(cmd_name) {
+        return Err(RebuildError::HelperNotFound(cmd_name.to_string()));
+    }
+
+    HelperInvocation::from_custom(helper).map_err(RebuildError::HelperTemplate)
+}
+
+/// Create the invocation of a known helper in PATH.
+fn known_helper(name: &str) -> HelperInvocation {
+    if name == "aura" {
+        HelperInvocation::for_aura(aura_major_version())
+    } else {
+        HelperInvocation::for_known_helper(name)
+    }
+}
+
+/// Get the major version of the installed aura.
+fn aura_major_version() -> Option<u32> {
+    let output = Command::new("aura")
+        .arg("--version")
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    parse_major_version(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Find the major version in `--version` output (e.g. `aura 4.0.8`).
+fn parse_major_version(output: &str) -> Option<u32> {
+    output.split_whitespace().find_map(|word| {
+        let word = word.strip_prefix('v').unwrap_or(word);
+        let (major, _) = word.split_once('.')?;
+        major.parse().ok()
+    })
+}
+
+/// Check if a command exists in PATH.
+pub fn is_in_path(cmd: &str) -> bool {
+    find_in_path(cmd, &std::env::var_os("PATH").unwrap_or_default()).is_some()
+}
+
+/// Find the executable a command runs, searching a PATH value.
+///
+/// A command containing a slash is a path and isn't searched for, as with
+/// the shell.
+pub fn find_in_path(cmd: &str, path: &std::ffi::OsStr) -> Option<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let is_executable = |file: &Path| {
+        fs::metadata(file)
+            .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+    };
+    if cmd.contains('/') {
+        return is_executable(Path::new(cmd)).then(|| PathBuf::from(cmd));
+    }
+    std::env::split_paths(path)
+        // An empty entry means the current directory
+        .map(|dir| {
+            if dir.as_os_str().is_empty() {
+                PathBuf::from(".")
+            } else {
+                dir
+            }
+        })
+        .map(|dir| dir.join(cmd))
+        .find(|file| is_executable(file))
+}
+
+/// Query the AUR for packages flagged out-of-date.
+///
+/// The flag check is advisory, so failures are reported and treated as
+/// "nothing flagged". Being offline is expected and only gets a notice.
+pub fn find_flagged(aur: &AurClient, packages: &[&str], quiet: bool) -> HashSet<String> {
+    match aur.info(packages) {
+        Ok(info) => info
+            .into_iter()
+            .filter(AurPackage::is_flagged)
+            .map(|pkg| pkg.name)
+            .collect(),
+        Err(e) if e.is_offline() => {
+            if !quiet {
+                output::info(&format!("Skipping AUR out-of-date check ({e})"));
+            }
+            HashSet::new()
+        }
+        Err(e) => {
+            output::warning(&format!("Could not check AUR out-of-date flags: {e}"));
+            HashSet::new()
+        }
+    }
+}
+
+/// Wait until no pacman transaction holds the database lock.
+///
+/// # Errors
+///
+/// Returns [`RebuildError::PacmanBusy`] after waiting for [`PACMAN_WAIT`].
+pub fn wait_for_pacman() -> Result<(), RebuildError> {
+    let start = Instant::now();
+    while Path::new(pacman::DB_LOCK).exists() {
+        if start.elapsed() >= PACMAN_WAIT {
+            return Err(RebuildError::PacmanBusy);
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+    Ok(())
+}
+
+/// Get the commands that build a stage, with the packages each one builds.
+///
+/// AUR packages go to the helper in one invocation (one per package with
+/// `--keep-going` or a `{package}` placeholder), local checkouts each through
+/// makepkg in their directory. The built-in backend runs makepkg once per
+/// package base, in its AUR checkout.
+///
+/// # Errors
+///
+/// Returns [`RebuildError::NoHelper`] if the stage has AUR packages but no
+/// helper was found.
+pub fn stage_runs<'a>(
+    stage: &[&'a str],
+    sources: &HashMap<String, String>,
+    helper: Option<&HelperInvocation>,
+    helper_args: &[String],
+    keep_going: bool,
+) -> Result<Vec<BuildRun<'a>>, RebuildError> {
+    let (local, aur): (Vec<&str>, Vec<&str>) =
+        stage.iter().partition(|pkg| sources.contains_key(**pkg));
+
+    let mut runs = Vec::new();
+    if !aur.is_empty() {
+        let helper = helper.ok_or(RebuildError::NoHelper)?;
+        if helper.builtin {
+            runs.extend(checkout_runs(&aur, helper, helper_args));
+        } else {
+            let batches: Vec<Vec<&str>> = if keep_going || helper.per_package() {
+                aur.iter().map(|pkg| vec![*pkg]).collect()
+            } else {
+                vec![aur]
+            };
+            for packages in batches {
+                let mut command = Command::new(&helper.command);
+                command.args(helper.args(&packages)).args(helper_args);
+                let install = helper.install_after.then(|| {
+                    // The rebuild was confirmed already
+                    let mut install = Command::new("sudo");
+                    install
+                        .args(["pacman", "-S", "--noconfirm"])
+                        .args(&packages);
+                    install
+                });
+                runs.push(BuildRun {
+                    packages,
+                    command,
+                    checkout: None,
+                    install,
+                });
+            }
+        }
+    }
+    for pkg in local {
+        let mut command = Command::new("makepkg");
+        command.args(MAKEPKG_ARGS).current_dir(&sources[pkg]);
+        runs.push(BuildRun {
+            packages: vec![pkg],
+            command,
+            checkout: None,
+            install: None,
+        });
+    }
+    Ok(runs)
+}
+
+/// Get the makepkg runs of the built-in backend, one per package base.
+///
+/// Split packages of one base build together; when they aren't exactly the
+/// base, `--pkg` keeps makepkg from building (and installing) the others.
+fn checkout_runs<'a>(
+    packages: &[&'a str],
+    helper: &HelperInvocation,
+    helper_args: &[String],
+) -> Vec<BuildRun<'a>> {
+    let mut bases: Vec<(&str, Vec<&'a str>)> = Vec::new();
+    for pkg in packages {
+        let base = helper.package_bases.get(*pkg).map_or(*pkg, String::as_str);
+        match bases.iter_mut().find(|(b, _)| *b == base) {
+            Some((_, split)) => split.push(pkg),
+            None => bases.push((base, vec![pkg])),
+        }
+    }
+
+    let clone_dir = aurgit::clone_dir();
+    bases
+        .into_iter()
+        .map(|(base, packages)| {
+            let mut command = Command::new(&helper.command);
+            command.args(&helper.base_args);
+            if packages != [base] {
+                command.arg("--pkg").arg(packages.join(","));
+            }
+            command.args(helper_args).current_dir(clone_dir.join(base));
+            BuildRun {
+                packages,
+                command,
+                checkout: Some(base.to_string()),
+                install: None,
+            }
+        })
+        .collect()
+}
+
+/// Clone or update the AUR checkout of a package base before building it.
+///
+/// # Errors
+///
+/// Returns an error if the checkout can't be cloned or updated.
+pub fn sync_checkout(config: &Config, base: &str, quiet: bool) -> Result<(), RebuildError> {
+    let dir = aurgit::clone_dir().join(base);
+    let synced = aurgit::sync(
+        &aurgit::repo_url(base),
+        &dir,
+        &NetOptions::from_config(config),
+    )
+    .map_err(|e| RebuildError::Checkout(base.to_string(), e))?;
+    if synced == Synced::Offline && !quiet {
+        output::info(&format!(
+            "Offline, building {base} from the existing checkout in {}",
+            dir.display()
+        ));
+    }
+    Ok(())
+}
+
+/// Quote a word for a POSIX shell, if it needs it.
+pub fn shell_quote(word: &str) -> String {
+    let plain = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:+@,%".contains(c));
+    if plain {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+/// Take the rebuild lock of the database in use, optionally waiting for it.
+///
+/// A lock file that can't be opened (e.g., `/run/anneal` is missing and the
+/// user can't create it) only prints a warning, as the lock is a safeguard.
+///
+/// # Errors
+///
+/// Returns [`RebuildError::AlreadyRunning`] if another rebuild holds the
+/// lock (after [`PACMAN_WAIT`] when waiting).
+pub fn lock_rebuild(wait: bool, quiet: bool) -> Result<Option<RebuildLock>, RebuildError> {
+    // Without a database there's nothing to rebuild, which is reported next
+    let db_path = get_db_path();
+    if !db_path.exists() {
+        return Ok(None);
+    }
+    let Some(path) = lock::rebuild_lock_path(&db_path) else {
+        return Ok(None);
+    };
+
+    let start = Instant::now();
+    let mut waiting = false;
+    loop {
+        match lock::try_lock(&path) {
+            Ok(Some(lock)) => return Ok(Some(lock)),
+            Ok(None) if !wait || start.elapsed() >= PACMAN_WAIT => {
+                return Err(RebuildError::AlreadyRunning(path));
+            }
+            Ok(None) => {
+                if !waiting && !quiet {
+                    output::info("Waiting for another rebuild to finish");
+                }
+                waiting = true;
+                thread::sleep(Duration::from_secs(1));
+            }
+            Err(e) => {
+                output::warning(&format!(
+                    "Cannot take the rebuild lock {}: {e}",
+                    path.display()
+                ));
+                return Ok(None);
+            }
+        }
+    }
+}
+
+/// Check whether a rebuild of the database in use is running.
+///
+/// A lock file that can't be opened counts as no rebuild running.
+pub fn rebuild_running() -> bool {
+    let Some(path) = lock::rebuild_lock_path(&get_db_path()) else {
+        return false;
+    };
+    if !path.exists() {
+        return false;
+    }
+    matches!(lock::try_lock(&path), Ok(None))
+}
+
+/// Start an unattended rebuild of the queue (`auto_rebuild`).
+///
+/// The rebuild runs as `auto_rebuild_user`, outlives this process, and waits
+/// for the pacman transaction that ran the hook to finish. Failing to start
+/// it only warns, so the hook still succeeds.
+pub fn start_auto_rebuild(config: &Config, quiet: bool) {
+    let Some(user) = &config.auto_rebuild_user else {
+        output::warning("auto_rebuild needs auto_rebuild_user to be set, not rebuilding");
+        return;
+    };
+    let exe = std::env::current_exe().unwrap_or_else(|_| PathBuf::from("/usr/bin/anneal"));
+    let profile = config.profiles.contains_key(AUTO_REBUILD_PROFILE);
+    let mut cmd = auto_rebuild_command(config.auto_rebuild, user, &exe, profile);
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+
+    let started = match config.auto_rebuild {
+        // systemd-run returns once the unit is queued
+        AutoRebuild::Systemd => cmd.status().is_ok_and(|status| status.success()),
+        // Own process group, so it isn't caught by signals meant for pacman
+        _ => {
+            use std::os::unix::process::CommandExt;
+            cmd.process_group(0).spawn().is_ok()
+        }
+    };
+
+    if !started {
+        output::warning("Failed to start the automatic rebuild");
+    } else if !quiet {
+        output::info(&format!("Started an automatic rebuild as {user}"));
+    }
+}
+
+/// Command starting an unattended `anneal rebuild` as a user.
+fn auto_rebuild_command(mode: AutoRebuild, user: &str, exe: &Path, profile: bool) -> Command {
+    let mut args = vec!["--quiet", "rebuild", "--force", "--wait-for-pacman"];
+    if profile {
+        args.extend(["--profile", AUTO_REBUILD_PROFILE]);
+    }
+
+    let mut cmd = if mode == AutoRebuild::Systemd {
+        let mut cmd = Command::new("systemd-run");
+        cmd.arg(format!("--unit={AUTO_REBUILD_UNIT}"))
+            .arg(format!("--uid={user}"))
+            .args(["--collect", "--no-block", "--quiet", "--"]);
+        cmd
+    } else {
+        let mut cmd = Command::new("runuser");
+        cmd.args(["-u", user, "--"]);
+        cmd
+    };
+    cmd.arg(exe).args(args);
+    cmd
+}
+
+/// Options for a rebuild run, collected from the command line.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RebuildOptions<'a> {
+    /// Skip confirmation and allow packages not in the queue.
+    pub force: bool,
+    /// Include foreign packages with broken linkage.
+    pub checkrebuild: bool,
+    /// Helper override from `--cmd`.
+    pub cmd: Option<&'a str>,
+    /// Leave AUR-flagged packages out of the rebuild.
+    pub skip_flagged: bool,
+    /// Leave packages whose version changed since marking out of the rebuild.
+    pub skip_stale: bool,
+    /// Unmark packages whose version changed since marking instead.
+    pub unmark_stale: bool,
+    /// Print the commands instead of running them (`--dry-run`).
+    pub dry_run: bool,
+    /// Prompt for packages to leave out (`--interactive`).
+    pub interactive: bool,
+    /// Glob patterns of packages left out of the rebuild (`--exclude`).
+    pub exclude: &'a [String],
+    /// Only rebuild this many of the first selected queued packages (by
+    /// priority, then age).
+    pub limit: Option<usize>,
+    /// Explicit packages to rebuild (empty = whole queue).
+    pub packages: &'a [String],
+    /// Only rebuild queued packages with this tag (`--tag`).
+    pub tag: Option<&'a str>,
+    /// Validate the packages after a successful rebuild.
+    pub validate: bool,
+    /// Only rebuild queued packages whose last rebuild failed (`--failed`).
+    pub failed: bool,
+    /// Build packages one at a time and continue past failures.
+    pub keep_going: bool,
+    /// Wait for pacman's lock (and another rebuild) to be released first
+    /// (`--wait-for-pacman`).
+    pub wait_for_pacman: bool,
+    /// Helper-argument profile from `--profile`.
+    pub profile: Option<&'a str>,
+    /// Extra arguments passed through to the helper.
+    pub helper_args: &'a [String],
+}
+
+/// What a rebuild will build, from [`plan_rebuild`].
+///
+/// Holds the rebuild lock (unless planned for a dry run) until dropped.
+#[derive(Debug)]
+pub struct RebuildPlan {
+    /// Selected queued packages, in queue order.
+    pub from_queue: Vec<String>,
+    /// Foreign packages with broken linkage, not already in `from_queue`.
+    pub from_checkrebuild: Vec<String>,
+    /// Local checkout of each package built from one, by package.
+    pub sources: HashMap<String, String>,
+    /// Packages to build, in stages that follow the build order hints.
+    /// Empty if there's nothing to build.
+    pub stages: Vec<Vec<String>>,
+    /// Number of selected queued packages left out by `exclude`.
+    pub excluded: usize,
+    /// Number of selected queued packages left for later by `limit`.
+    pub held_back: usize,
+    /// Packages left out for being flagged out-of-date on the AUR.
+    pub skipped_flagged: Vec<String>,
+    /// Queued packages left out for having changed version since marked.
+    pub skipped_stale: Vec<String>,
+    helper: Option<HelperInvocation>,
+    helper_args: Vec<String>,
+    keep_going: bool,
+    _lock: Option<RebuildLock>,
+}
+
+/// A command [`RebuildPlan::commands`] would run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedCommand {
+    /// Packages it builds (or installs).
+    pub packages: Vec<String>,
+    /// Program and arguments.
+    pub argv: Vec<String>,
+    /// Directory it runs in, if not the current one.
+    pub directory: Option<String>,
+}
+
+/// Outcome of [`run_rebuild`].
+#[derive(Debug, Default)]
+pub struct RebuildReport {
+    /// Packages built and installed.
+    pub rebuilt: Vec<String>,
+    /// Packages whose build failed.
+    pub failed: Vec<String>,
+    /// Packages not built because they were ordered after the failure that
+    /// stopped the rebuild.
+    pub skipped: Vec<String>,
+    /// Exit code of the first failing helper (or makepkg) run.
+    pub exit_code: Option<i32>,
+    /// The failure that stopped the rebuild (without `keep_going`).
+    pub stopped: Option<Error>,
+}
+
+impl RebuildPlan {
+    /// All packages to build: the queued ones, then those with broken
+    /// linkage.
+    pub fn packages(&self) -> Vec<&str> {
+        self.from_queue
+            .iter()
+            .chain(self.from_checkrebuild.iter())
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Whether there's nothing to build.
+    pub fn is_empty(&self) -> bool {
+        self.from_queue.is_empty() && self.from_checkrebuild.is_empty()
+    }
+
+    /// Estimate how long the rebuild takes, from past build durations.
+    pub fn estimate(&self) -> Option<u64> {
+        let durations = open_readonly()
+            .and_then(|db| Ok(db.build_durations()?))
+            .unwrap_or_default();
+        estimate_rebuild(&durations, &self.packages())
+    }
+
+    /// Get the commands the rebuild runs, in order (`rebuild --dry-run`).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RebuildError::NoHelper`] if there are AUR packages but no
+    /// helper was found.
+    pub fn commands(&self) -> Result<Vec<PlannedCommand>, RebuildError> {
+        let mut commands = Vec::new();
+        for stage in &self.stages {
+            let stage: Vec<&str> = stage.iter().map(String::as_str).collect();
+            for BuildRun {
+                packages,
+                command,
+                install,
+                ..
+            } in self.stage_runs(&stage)?
+            {
+                for command in std::iter::once(command).chain(install) {
+                    commands.push(PlannedCommand {
+                        packages: packages.iter().map(|pkg| pkg.to_string()).collect(),
+                        argv: std::iter::once(command.get_program())
+                            .chain(command.get_args())
+                            .map(|arg| arg.to_string_lossy().into_owned())
+                            .collect(),
+                        directory: command
+                            .get_current_dir()
+                            .map(|dir| dir.to_string_lossy().into_owned()),
+                    });
+                }
+            }
+        }
+        Ok(commands)
+    }
+
+    /// Get the runs of one stage.
+    fn stage_runs<'a>(&self, stage: &[&'a str]) -> Result<Vec<BuildRun<'a>>, RebuildError> {
+        stage_runs(
+            stage,
+            &self.sources,
+            self.helper.as_ref(),
+            &self.helper_args,
+            self.keep_going,
+        )
+    }
+}
+
+/// Decide what a rebuild builds.
+///
+/// Selects queued packages (all, `packages`, `tag` or `failed`), leaves out
+/// `exclude`d, stale (per `skip_stale` and `unmark_stale`, which unmarks
+/// them) and held back (`limit`) ones, adds packages with broken linkage
+/// (`checkrebuild`), warns about (or, with `skip_flagged`, leaves out)
+/// packages flagged out-of-date on the AUR, and splits the rest into stages.
+/// With `interactive`, `pick` is given the packages and returns the ones to
+/// leave out.
+///
+/// Unless planning a dry run, this takes the rebuild lock, which the plan
+/// holds.
+///
+/// # Errors
+///
+/// Returns an error if another rebuild is running, the profile is unknown,
+/// an explicit package isn't queued (without `force`), no helper is found,
+/// `pick` fails, or the database or build order can't be read.
+pub fn plan_rebuild(
+    config: &Config,
+    opts: &RebuildOptions,
+    pick: impl FnOnce(&[&str]) -> Result<Vec<String>, Error>,
+    quiet: bool,
+) -> Result<RebuildPlan, Error> {
+    // Only one rebuild at a time. A rebuild started from the pacman hook may
+    // well be waiting on a transaction of the running one, so it queues up
+    // behind it instead of giving up.
+    let lock = if opts.dry_run {
+        None
+    } else {
+        lock_rebuild(opts.wait_for_pacman, quiet)?
+    };
+
+    // The helper can't install while a transaction holds the lock, e.g.
+    // when started from the pacman hook
+    if opts.wait_for_pacman && !opts.dry_run {
+        wait_for_pacman()?;
+    }
+
+    // Detect helper and resolve the profile's arguments
+    let helper = detect_helper(config, opts.cmd);
+    let mut helper_args = match opts.profile {
+        Some(name) => config
+            .profile_args(name)
+            .ok_or_else(|| RebuildError::UnknownProfile(name.to_string()))?,
+        None => Vec::new(),
+    };
+    helper_args.extend(opts.helper_args.iter().cloned());
+
+    let db = open_readonly()?;
+    let queue = db.list()?;
+    let sources: HashMap<String, String> = db
+        .list_sources()?
+        .into_iter()
+        .map(|source| (source.package, source.path))
+        .collect();
+    let queue_set: HashSet<&str> = queue.iter().map(|e| e.package.as_str()).collect();
+
+    // Determine which queued packages to rebuild
+    let mut from_queue: Vec<String> = if let Some(tag) = opts.tag {
+        // Rebuild queued packages with the tag
+        let tagged: HashSet<String> = db.tagged_packages(tag)?.into_iter().collect();
+        queue
+            .iter()
+            .filter(|e| tagged.contains(&e.package))
+            .map(|e| e.package.clone())
+            .collect()
+    } else if opts.failed {
+        // Retry queued packages whose last attempt failed
+        let failed: HashSet<String> = db.failed_rebuilds()?.into_iter().collect();
+        queue
+            .iter()
+            .filter(|e| failed.contains(&e.package))
+            .map(|e| e.package.clone())
+            .collect()
+    } else if opts.packages.is_empty() {
+        // Rebuild all queued packages
+        queue.iter().map(|e| e.package.clone()).collect()
+    } else {
+        // Rebuild specified packages
+        let mut result = Vec::new();
+        for pkg in opts.packages {
+            if queue_set.contains(pkg.as_str()) {
+                result.push(pkg.clone());
+            } else if !opts.force {
+                return Err(RebuildError::PackageNotInQueue(pkg.clone()).into());
+            } else {
+                // With -f, allow packages not in queue
+                result.push(pkg.clone());
+            }
+        }
+        result
+    };
+
+    let excluded = |pkg: &str| {
+        opts.exclude
+            .iter()
+            .any(|pattern| matches_glob(pattern, pkg))
+    };
+    let selected = from_queue.len();
+    from_queue.retain(|pkg| !excluded(pkg));
+    let excluded_count = selected - from_queue.len();
+
+    // Entries whose package changed version since they were marked were
+    // most likely rebuilt some other way
+    let stale = stale_versions(&queue);
+    let mut skipped_stale: Vec<String> = Vec::new();
+    let mut unmarked_stale: Vec<&str> = Vec::new();
+    for entry in queue.iter().filter(|e| from_queue.contains(&e.package)) {
+        let Some(installed) = stale.get(&entry.package) else {
+            continue;
+        };
+        let change = format!(
+            "{} was marked at {} and is now {installed}",
+            entry.package,
+            entry.marked_version.as_deref().unwrap_or_default()
+        );
+        if opts.unmark_stale {
+            if !quiet {
+                let verb = if opts.dry_run {
+                    "would unmark"
+                } else {
+                    "unmarking"
+                };
+                output::info(&format!("{change}, {verb}"));
+            }
+            unmarked_stale.push(&entry.package);
+        } else if opts.skip_stale {
+            output::warning(&format!("{change}, skipping"));
+            skipped_stale.push(entry.package.clone());
+        } else {
+            output::warning(&format!(
+                "{change}, it may be rebuilt already (--skip-stale, --unmark-stale)"
+            ));
+        }
+    }
+    if opts.skip_stale || opts.unmark_stale {
+        from_queue.retain(|pkg| !stale.contains_key(pkg));
+    }
+    if !unmarked_stale.is_empty() && !opts.dry_run {
+        let mut db = Database::open(config.retention_days)?;
+        for pkg in &unmarked_stale {
+            db.complete_reinstall(pkg, Origin::Rebuild)?;
+        }
+        audit::record(config, "unmark", &unmarked_stale);
+    }
+
+    // Chip away at a large queue: the queue is listed by priority, then
+    // oldest first
+    let mut held_back = 0;
+    if let Some(limit) = opts.limit
+        && from_queue.len() > limit
+    {
+        held_back = from_queue.len() - limit;
+        from_queue.truncate(limit);
+    }
+
+    // Packages built from local checkouts don't need a helper
+    let all_local = !from_queue.is_empty()
+        && from_queue.iter().all(|pkg| sources.contains_key(pkg))
+        && !(opts.checkrebuild || config.include_checkrebuild);
+    let mut helper = match helper {
+        Ok(helper) => Some(helper),
+        Err(_) if all_local => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    // Add packages with broken linkage if requested
+    let mut from_checkrebuild: Vec<String> = Vec::new();
+    if opts.checkrebuild || config.include_checkrebuild {
+        match scan::scan_linkage(config, &Overrides::load()) {
+            Ok(broken) => {
+                for finding in broken {
+                    // Only add if not already in the list
+                    if !from_queue.contains(&finding.package) && !excluded(&finding.package) {
+                        from_checkrebuild.push(finding.package);
+                    }
+                }
+            }
+            Err(e) => {
+                // Warn but don't fail the queued rebuilds
+                output::warning(&e.to_string());
+            }
+        }
+    }
+
+    // Warn about (or skip) packages flagged out-of-date on the AUR
+    let candidates: Vec<&str> = from_queue
+        .iter()
+        .chain(from_checkrebuild.iter())
+        .filter(|pkg| !sources.contains_key(*pkg))
+        .map(String::as_str)
+        .collect();
+    let aur = AurClient::from_config(config);
+    let flagged = find_flagged(&aur, &candidates, quiet);
+    for pkg in &candidates {
+        if flagged.contains(*pkg) {
+            if opts.skip_flagged {
+                output::warning(&format!(
+                    "{pkg} is flagged out-of-date on the AUR, skipping"
+                ));
+            } else {
+                output::warning(&format!(
+                    "{pkg} is flagged out-of-date on the AUR, the build may fail"
+                ));
+            }
+        }
+    }
+    let mut skipped_flagged: Vec<String> = Vec::new();
+    if opts.skip_flagged && !flagged.is_empty() {
+        skipped_flagged = candidates
+            .iter()
+            .filter(|pkg| flagged.contains(**pkg))
+            .map(|pkg| pkg.to_string())
+            .collect();
+        from_queue.retain(|pkg| !flagged.contains(pkg));
+        from_checkrebuild.retain(|pkg| !flagged.contains(pkg));
+    }
+
+    // Let the user drop packages
+    if opts.interactive && !(from_queue.is_empty() && from_checkrebuild.is_empty()) {
+        let candidates: Vec<&str> = from_queue
+            .iter()
+            .chain(from_checkrebuild.iter())
+            .map(String::as_str)
+            .collect();
+        let dropped = pick(&candidates)?;
+        from_queue.retain(|pkg| !dropped.contains(pkg));
+        from_checkrebuild.retain(|pkg| !dropped.contains(pkg));
+    }
+
+    // The built-in backend clones by package base, not package name
+    if let Some(helper) = helper.as_mut()
+        && helper.is_builtin()
+    {
+        let packages: Vec<&str> = from_queue
+            .iter()
+            .chain(from_checkrebuild.iter())
+            .filter(|pkg| !sources.contains_key(*pkg))
+            .map(String::as_str)
+            .collect();
+        if !packages.is_empty() {
+            helper.lookup_package_bases(&aur, &packages);
+        }
+    }
+
+    // Split into stages that follow the user's build order hints
+    let all_packages: Vec<&str> = from_queue
+        .iter()
+        .chain(from_checkrebuild.iter())
+        .map(String::as_str)
+        .collect();
+    let stages = if all_packages.is_empty() {
+        Vec::new()
+    } else {
+        BuildOrder::load()
+            .and_then(|order| order.stages(&all_packages))
+            .map_err(RebuildError::BuildOrder)?
+            .into_iter()
+            .map(|stage| stage.into_iter().map(str::to_string).collect())
+            .collect()
+    };
+
+    Ok(RebuildPlan {
+        from_queue,
+        from_checkrebuild,
+        sources,
+        stages,
+        excluded: excluded_count,
+        held_back,
+        skipped_flagged,
+        skipped_stale,
+        helper,
+        helper_args,
+        keep_going: opts.keep_going,
+        _lock: lock,
+    })
+}
+
+/// Build each stage of a plan, stopping at the first failure.
+///
+/// AUR packages go to the helper in one invocation, local checkouts through
+/// makepkg. With `keep_going` every package gets its own helper run, so a
+/// failure only costs that package. Rebuilt queued packages are unmarked,
+/// and every run is recorded in the rebuild history.
+///
+/// A failed build is part of the report, with the failure in
+/// [`RebuildReport::stopped`] unless the plan keeps going.
+///
+/// # Errors
+///
+/// Returns an error if a checkout can't be synced (without `keep_going`),
+/// the helper or makepkg can't be started, or the database can't be written.
+pub fn run_rebuild(
+    config: &Config,
+    plan: &RebuildPlan,
+    quiet: bool,
+) -> Result<RebuildReport, Error> {
+    let mut report = RebuildReport::default();
+    let mut log_dir = config.build_log_dir.as_deref().map(PathBuf::from);
+    for stage in &plan.stages {
+        let stage: Vec<&str> = stage.iter().map(String::as_str).collect();
+        for BuildRun {
+            packages,
+            mut command,
+            checkout,
+            install,
+        } in plan.stage_runs(&stage)?
+        {
+            let names = || packages.iter().map(|pkg| pkg.to_string());
+            let is_makepkg = checkout.is_some() || plan.sources.contains_key(packages[0]);
+            if let Some(base) = &checkout
+                && let Err(e) = sync_checkout(config, base, quiet)
+            {
+                if !plan.keep_going {
+                    return Err(e.into());
+                }
+                output::warning(&format!("Failed to rebuild {}: {e}", packages.join(", ")));
+                report.exit_code.get_or_insert(-1);
+                report.failed.extend(names());
+                continue;
+            }
+            let started_at = now_iso8601();
+            let (log_path, log) = open_build_log(&mut log_dir, &packages, &started_at).unzip();
+            let mut status = match log {
+                Some(log) => buildlog::run(&mut command, log),
+                None => command.status(),
+            }
+            .map_err(|e| {
+                if is_makepkg {
+                    RebuildError::MakepkgSpawn(e)
+                } else {
+                    RebuildError::HelperSpawn(e)
+                }
+            })?;
+            if status.success()
+                && let Some(mut install) = install
+            {
+                status = install.status().map_err(RebuildError::HelperSpawn)?;
+            }
+            record_attempt(config, &packages, &command, &started_at, status);
+            if let Err(e) = finish_build(config, &packages, &plan.from_queue, status, is_makepkg) {
+                if let Some(path) = &log_path {
+                    output::info(&format!("Build log: {}", path.display()));
+                }
+                report.exit_code.get_or_insert(status.code().unwrap_or(-1));
+                report.failed.extend(names());
+                if plan.keep_going {
+                    output::warning(&format!("Failed to rebuild {}: {e}", packages.join(", ")));
+                    continue;
+                }
+                report.skipped = plan
+                    .packages()
+                    .into_iter()
+                    .filter(|pkg| {
+                        !report.rebuilt.iter().any(|r| r == pkg) && !packages.contains(pkg)
+                    })
+                    .map(str::to_string)
+                    .collect();
+                report.stopped = Some(e);
+                return Ok(report);
+            }
+            report.rebuilt.extend(names());
+        }
+    }
+    Ok(report)
+}
+
+/// Record the outcome of one helper or makepkg run.
+///
+/// Queued packages are unmarked on success; on failure the attempt is
+/// recorded and the failure returned.
+fn finish_build(
+    config: &Config,
+    packages: &[&str],
+    from_queue: &[String],
+    status: ExitStatus,
+    is_makepkg: bool,
+) -> Result<(), Error> {
+    let queued: Vec<&str> = packages
+        .iter()
+        .copied()
+        .filter(|pkg| from_queue.iter().any(|q| q == pkg))
+        .collect();
+
+    if status.success() {
+        // Unmark packages that were in the queue
+        if !queued.is_empty() {
+            let mut db = Database::open(config.retention_days)?;
+            for pkg in &queued {
+                db.complete_rebuild(pkg)?;
+            }
+            audit::record(config, "rebuild", &queued);
+        }
+        return Ok(());
+    }
+
+    let code = status.code().unwrap_or(-1);
+
+    // Keep a record of the attempt for `timeline`. Best-effort: the failure
+    // itself is what matters to the caller.
+    if let Ok(mut db) = Database::open(config.retention_days) {
+        for pkg in &queued {
+            let _ = db.record_rebuild_failure(pkg, code);
+        }
+    }
+
+    if is_makepkg {
+        Err(RebuildError::MakepkgFailed(packages[0].to_string(), code).into())
+    } else {
+        Err(RebuildError::HelperFailed(code).into())
+    }
+}
+
+/// Record a build in the rebuild history, one row per package.
+///
+/// Best-effort: a history write failing shouldn't turn into a build failure.
+fn record_attempt(
+    config: &Config,
+    packages: &[&str],
+    command: &Command,
+    started_at: &str,
+    status: ExitStatus,
+) {
+    let helper = command.get_program().to_string_lossy();
+    if let Ok(mut db) = Database::open(config.retention_days) {
+        for pkg in packages {
+            let _ = db.record_rebuild(pkg, &helper, started_at, status.code());
+        }
+    }
+}
+
+/// Create the log of a build, if it builds a single package and build logs
+/// are enabled.
+///
+/// If the log can't be created, build logs are turned off for the rest of
+/// the rebuild (with one warning) and the build runs without one.
+fn open_build_log(
+    log_dir: &mut Option<PathBuf>,
+    packages: &[&str],
+    started_at: &str,
+) -> Option<(PathBuf, fs::File)> {
+    let [package] = packages else {
+        return None;
+    };
+    let path = buildlog::log_path(log_dir.as_deref()?, package, started_at);
+    match buildlog::create(&path) {
+        Ok(file) => Some((path, file)),
+        Err(e) => {
+            output::warning(&format!(
+                "Cannot write build log {}: {e} (build logs disabled for this rebuild)",
+                path.display()
+            ));
+            *log_dir = None;
+            None
+        }
+    }
+}
+
+/// Validate rebuilt packages (see [`validate::validate`]).
+///
+/// The built package files are looked for in `package_dir`, or `PKGDEST`.
+///
+/// # Errors
+///
+/// Returns an error if pacman can't be run.
+pub fn validate_rebuilt(config: &Config, packages: &[&str]) -> Result<Vec<Problem>, PacmanError> {
+    let package_dir = config
+        .package_dir
+        .clone()
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("PKGDEST").map(PathBuf::from));
+    validate::validate(packages, package_dir.as_deref())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shell_quoting() {
+        assert_eq!(shell_quote("--noconfirm"), "--noconfirm");
+        assert_eq!(shell_quote("/home/me/src/pkg"), "/home/me/src/pkg");
+        assert_eq!(shell_quote("my dir"), "'my dir'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn path_search() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let (first, second) = (temp.path().join("a"), temp.path().join("b"));
+        fs::create_dir(&first).unwrap();
+        fs::create_dir(&second).unwrap();
+        for (dir, name, mode) in [
+            (&first, "paru", 0o644),
+            (&second, "paru", 0o755),
+            (&first, "yay", 0o755),
+        ] {
+            fs::write(dir.join(name), "").unwrap();
+            fs::set_permissions(dir.join(name), fs::Permissions::from_mode(mode)).unwrap();
+        }
+        fs::create_dir(first.join("aura")).unwrap();
+        let path = std::env::join_paths([&first, &second]).unwrap();
+
+        // Directories and files that aren't executable are skipped
+        assert_eq!(find_in_path("paru", &path), Some(second.join("paru")));
+        assert_eq!(find_in_path("yay", &path), Some(first.join("yay")));
+        assert_eq!(find_in_path("pikaur", &path), None);
+        assert_eq!(find_in_path("aura", &path), None);
+
+        let yay = first.join("yay");
+        let yay = yay.to_str().unwrap();
+        assert_eq!(find_in_path(yay, "".as_ref()), Some(PathBuf::from(yay)));
+    }
+
+    mod estimate {
+        use super::*;
+
+        #[test]
+        fn unknown_packages_count_as_average() {
+            let durations = HashMap::from([("a".to_string(), 600), ("b".to_string(), 1200)]);
+            assert_eq!(estimate_rebuild(&durations, &["a", "b"]), Some(1800));
+            assert_eq!(estimate_rebuild(&durations, &["a", "b", "c"]), Some(2700));
+            assert_eq!(estimate_rebuild(&durations, &["c"]), None);
+        }
+
+        #[test]
+        fn format_minutes() {
+            assert_eq!(format_estimate(10), "~1 min");
+            assert_eq!(format_estimate(48 * 60 - 30), "~48 min");
+            assert_eq!(format_estimate(2 * 3600 + 5 * 60), "~2h 05 min");
+        }
+    }
+
+    mod helper_invocation {
+        use super::*;
+
+        #[test]
+        fn known_helper_paru() {
+            let inv = HelperInvocation::for_known_helper("paru");
+            assert_eq!(inv.command, "paru");
+            assert_eq!(inv.base_args, vec!["-S", "--rebuild"]);
+        }
+
+        #[test]
+        fn known_helper_yay() {
+            let inv = HelperInvocation::for_known_helper("yay");
+            assert_eq!(inv.command, "yay");
+            assert_eq!(inv.base_args, vec!["-S", "--rebuild"]);
+        }
+
+        #[test]
+        fn known_helper_pikaur() {
+            let inv = HelperInvocation::for_known_helper("pikaur");
+            assert_eq!(inv.command, "pikaur");
+            assert_eq!(inv.base_args, vec!["-S", "--rebuild"]);
+        }
+
+        #[test]
+        fn known_helper_aura() {
+            // aura uses -A instead of -S
+            let inv = HelperInvocation::for_known_helper("aura");
+            assert_eq!(inv.command, "aura");
+            assert_eq!(inv.base_args, vec!["-A", "--rebuild"]);
+        }
+
+        #[test]
+        fn aura_by_major_version() {
+            assert_eq!(
+                HelperInvocation::for_aura(Some(3)).base_args,
+                vec!["-A", "--rebuild"]
+            );
+            assert_eq!(
+                HelperInvocation::for_aura(Some(4)).base_args,
+                vec!["-A", "--force"]
+            );
+            assert_eq!(
+                HelperInvocation::for_aura(None).base_args,
+                vec!["-A", "--rebuild"]
+            );
+
+            assert_eq!(parse_major_version("aura 4.0.8\n"), Some(4));
+            assert_eq!(parse_major_version("Version: v3.2.9"), Some(3));
+            assert_eq!(parse_major_version("aura (unknown)"), None);
+        }
+
+        #[test]
+        fn known_helper_trizen() {
+            let inv = HelperInvocation::for_known_helper("trizen");
+            assert_eq!(inv.command, "trizen");
+            assert_eq!(inv.base_args, vec!["-S", "--rebuild"]);
+        }
+
+        #[test]
+        fn known_helper_pamac() {
+            let inv = HelperInvocation::for_known_helper("pamac");
+            assert_eq!(inv.command, "pamac");
+            assert_eq!(inv.base_args, vec!["build"]);
+        }
+
+        #[test]
+        fn known_helper_pakku() {
+            let inv = HelperInvocation::for_known_helper("pakku");
+            assert_eq!(inv.command, "pakku");
+            assert_eq!(inv.base_args, vec!["-S", "--build"]);
+        }
+
+        #[test]
+        fn known_helper_aurutils() {
+            // aur sync builds into a local repository, pacman installs
+            let inv = HelperInvocation::for_known_helper("aur");
+            assert_eq!(inv.command, "aur");
+            assert_eq!(inv.base_args, vec!["sync", "--rebuild"]);
+            assert!(inv.install_after);
+
+            let runs =
+                stage_runs(&["foo", "bar"], &HashMap::new(), Some(&inv), &[], false).unwrap();
+            let install = runs[0].install.as_ref().unwrap();
+            assert_eq!(install.get_program(), "sudo");
+            assert_eq!(
+                install.get_args().collect::<Vec<_>>(),
+                ["pacman", "-S", "--noconfirm", "foo", "bar"]
+            );
+        }
+
+        #[test]
+        fn custom_command_simple() {
+            let inv = HelperInvocation::from_custom("my-helper").unwrap();
+            assert_eq!(inv.command, "my-helper");
+            assert!(inv.base_args.is_empty());
+        }
+
+        #[test]
+        fn custom_command_with_args() {
+            let inv = HelperInvocation::from_custom("my-helper -S --rebuild --custom").unwrap();
+            assert_eq!(inv.command, "my-helper");
+            assert_eq!(inv.base_args, vec!["-S", "--rebuild", "--custom"]);
+            assert_eq!(
+                inv.args(&["foo", "bar"]),
+                vec!["-S", "--rebuild", "--custom", "foo", "bar"]
+            );
+            assert!(!inv.per_package());
+        }
+
+        #[test]
+        fn custom_command_extra_whitespace() {
+            let inv = HelperInvocation::from_custom("  my-helper   -S   --rebuild  ").unwrap();
+            assert_eq!(inv.command, "my-helper");
+            assert_eq!(inv.base_args, vec!["-S", "--rebuild"]);
+        }
+
+        #[test]
+        fn template_placeholders() {
+            let mut inv =
+                HelperInvocation::from_custom("my-helper --rebuild {packages} --log {logdir}")
+                    .unwrap();
+            inv.log_dir = Some("/var/log/anneal".into());
+            assert_eq!(
+                inv.args(&["foo", "bar"]),
+                vec!["--rebuild", "foo", "bar", "--log", "/var/log/anneal"]
+            );
+            assert!(!inv.per_package());
+
+            // Without a package placeholder, packages are still appended
+            let inv = HelperInvocation::from_custom("my-helper --dest={pkgdest}").unwrap();
+            assert_eq!(inv.args(&["foo"]), vec!["--dest=", "foo"]);
+
+            let inv = HelperInvocation::from_custom("build-one --name={package} -i").unwrap();
+            assert!(inv.per_package());
+            assert_eq!(inv.args(&["foo"]), vec!["--name=foo", "-i"]);
+
+            assert!(HelperInvocation::from_custom("my-helper {pkgs}").is_err());
+            assert!(HelperInvocation::from_custom("my-helper {packages").is_err());
+        }
+    }
+
+    mod rebuild_error_display {
+        use super::*;
+
+        #[test]
+        fn no_helper() {
+            let err = RebuildError::NoHelper;
+            let msg = err.to_string();
+            assert!(msg.contains("No AUR helper detected"));
+            assert!(msg.contains("paru"));
+            assert!(msg.contains("yay"));
+        }
+
+        #[test]
+        fn ambiguous_helper() {
+            let err = RebuildError::AmbiguousHelper(vec!["paru".into(), "yay".into()]);
+            let msg = err.to_string();
+            assert!(msg.contains("Multiple AUR helpers found"));
+            assert!(msg.contains("paru"));
+            assert!(msg.contains("yay"));
+        }
+
+        #[test]
+        fn unknown_profile() {
+            let err = RebuildError::UnknownProfile("fast".into());
+            let msg = err.to_string();
+            assert!(msg.contains("'fast'"));
+            assert!(msg.contains("not defined"));
+        }
+
+        #[test]
+        fn helper_not_found() {
+            let err = RebuildError::HelperNotFound("nonexistent".into());
+            let msg = err.to_string();
+            assert!(msg.contains("nonexistent"));
+            assert!(msg.contains("not found"));
+        }
+
+        #[test]
+        fn helper_failed() {
+            let err = RebuildError::HelperFailed(1);
+            let msg = err.to_string();
+            assert!(msg.contains("exited with code 1"));
+        }
+
+        #[test]
+        fn package_not_in_queue() {
+            let err = RebuildError::PackageNotInQueue("my-pkg".into());
+            let msg = err.to_string();
+            assert!(msg.contains("my-pkg"));
+            assert!(msg.contains("not in the queue"));
+            assert!(msg.contains("-f"));
+        }
+    }
+
+    #[test]
+    fn plan_commands_in_stage_order() {
+        let plan = RebuildPlan {
+            from_queue: vec!["foo".to_string(), "bar".to_string()],
+            from_checkrebuild: Vec::new(),
+            sources: HashMap::from([("bar".to_string(), "/src/bar".to_string())]),
+            stages: vec![vec!["bar".to_string()], vec!["foo".to_string()]],
+            excluded: 0,
+            held_back: 0,
+            skipped_flagged: Vec::new(),
+            skipped_stale: Vec::new(),
+            helper: Some(HelperInvocation::for_known_helper("paru")),
+            helper_args: vec!["--nocheck".to_string()],
+            keep_going: false,
+            _lock: None,
+        };
+        let commands = plan.commands().unwrap();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].packages, ["bar"]);
+        assert_eq!(commands[0].argv[0], "makepkg");
+        assert_eq!(commands[0].directory.as_deref(), Some("/src/bar"));
+        assert_eq!(commands[1].packages, ["foo"]);
+        assert_eq!(commands[1].argv[0], "paru");
+        assert_eq!(
+            commands[1].argv.last().map(String::as_str),
+            Some("--nocheck")
+        );
+        assert_eq!(commands[1].directory, None);
+    }
+
+    mod auto_rebuild {
+        use super::*;
+
+        fn args(cmd: &Command) -> Vec<String> {
+            std::iter::once(cmd.get_program())
+                .chain(cmd.get_args())
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect()
+        }
+
+        #[test]
+        fn detached() {
+            let cmd = auto_rebuild_command(
+                AutoRebuild::Detached,
+                "builder",
+                Path::new("/usr/bin/anneal"),
+                false,
+            );
+            assert_eq!(
+                args(&cmd),
+                vec![
+                    "runuser",
+                    "-u",
+                    "builder",
+                    "--",
+                    "/usr/bin/anneal",
+                    "--quiet",
+                    "rebuild",
+                    "--force",
+                    "--wait-for-pacman"
+                ]
+            );
+        }
+
+        #[test]
+        fn systemd_with_profile() {
+            let cmd = auto_rebuild_command(
+                AutoRebuild::Systemd,
+                "builder",
+                Path::new("/usr/bin/anneal"),
+                true,
+            );
+            let args = args(&cmd);
+            assert_eq!(args[0], "systemd-run");
+            assert!(args.contains(&"--uid=builder".to_string()));
+            assert!(args.ends_with(&[
+                "--wait-for-pacman".to_string(),
+                "--profile".to_string(),
+                "auto".to_string()
+            ]));
+        }
+    }
+}
@@ -0,0 +1,356 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! Scanners for breakage that dependency information doesn't reveal.
+//!
+//! ## Python
+//!
+//! A Python minor upgrade (e.g., 3.12 to 3.13) moves the module search path
+//! to `/usr/lib/python3.13`. Foreign packages built against the old version
+//! still have their files under `/usr/lib/python3.12` or ship compiled
+//! extensions tagged for the old ABI (`*.cpython-312-*.so`), so they silently
+//! stop importing. Packages that don't declare a dependency on `python`
+//! (vendored interpreters, optional Python bindings) are missed by pactree,
+//! and nothing is wrong at the ELF level, so ldd-based checks miss them too.
+//!
+//! The scanner compares the installed files of every foreign package against
+//! the installed Python version.
+//!
+//! ## Broken linkage
+//!
+//! A library upgrade that changes a soname breaks the foreign packages
+//! linked against the old one, whether or not a trigger predicted it. The
+//! linkage scanner runs `ldd` on the ELF files of every foreign package and
+//! flags the ones needing a library the loader can't find, or a symbol
+//! version the installed library lacks, so a periodic run (see `anneal setup
+//! --systemd`) catches what the triggers missed. `anneal verify` runs the
+//! same check on one package.
+//!
+//! A missing library the package ships itself doesn't count: applications
+//! bundled under `/opt` often find their own libraries through a wrapper
+//! script rather than an rpath. Packages a trigger wouldn't mark aren't
+//! flagged either: `-bin` packages, `skip_packages`, and packages whose
+//! override doesn't allow the [`CHECKREBUILD_TRIGGER`].
+//!
+//! This replaces running `checkrebuild` from rebuild-detector, whose name the
+//! `--checkrebuild` flags and the recorded trigger keep.
+
+use std::collections::{BTreeMap, HashSet};
+
+use crate::config::Config;
+use crate::output;
+use crate::overrides::Overrides;
+use crate::pacman::{self, LinkProblem, PacmanError};
+
+/// Name of the Python package (recorded as the trigger when marking).
+pub const PYTHON_PACKAGE: &str = "python";
+
+/// Trigger recorded for packages marked for broken linkage.
+pub const CHECKREBUILD_TRIGGER: &str = "checkrebuild";
+
+/// A package found by a scanner.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// Package that needs a rebuild.
+    pub package: String,
+    /// Why it was flagged (e.g., "files under python3.12").
+    pub reason: String,
+}
+
+/// Result of scanning for packages left behind by a Python upgrade.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PythonScan {
+    /// Installed Python version (full pacman version), if Python is installed.
+    pub python_version: Option<String>,
+    /// Foreign packages built for another Python version.
+    pub stale: Vec<Finding>,
+}
+
+/// Find foreign packages built for a different Python minor version than the
+/// installed one.
+///
+/// Returns an empty scan if Python isn't installed.
+///
+/// # Errors
+///
+/// Returns an error if pacman can't be run.
+pub fn scan_python() -> Result<PythonScan, PacmanError> {
+    let Some(info) = pacman::package_info(&[PYTHON_PACKAGE])?.into_iter().next() else {
+        return Ok(PythonScan::default());
+    };
+    let Some(current) = parse_minor(&info.version) else {
+        return Ok(PythonScan::default());
+    };
+
+    let foreign = pacman::foreign_packages()?;
+    let mut foreign: Vec<&str> = foreign.iter().map(String::as_str).collect();
+    foreign.sort_unstable();
+
+    // First reason per package, in name order
+    let mut stale: BTreeMap<String, String> = BTreeMap::new();
+    for (package, path) in pacman::package_files(&foreign)? {
+        if stale.contains_key(&package) {
+            continue;
+        }
+        if let Some(reason) = stale_python_file(&path, current) {
+            stale.insert(package, reason);
+        }
+    }
+
+    Ok(PythonScan {
+        python_version: Some(info.version),
+        stale: stale
+            .into_iter()
+            .map(|(package, reason)| Finding { package, reason })
+            .collect(),
+    })
+}
+
+/// Find foreign packages needing shared libraries that aren't installed, in
+/// name order.
+///
+/// # Errors
+///
+/// Returns an error if pacman or ldd can't be run.
+pub fn scan_linkage(config: &Config, overrides: &Overrides) -> Result<Vec<Finding>, PacmanError> {
+    let mut candidates: Vec<String> = pacman::foreign_packages()?
+        .into_iter()
+        .filter(|package| is_linkage_candidate(package, config, overrides))
+        .collect();
+    candidates.sort_unstable();
+    let names: Vec<&str> = candidates.iter().map(String::as_str).collect();
+
+    let mut files: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (package, path) in pacman::package_files(&names)? {
+        files.entry(package).or_default().push(path);
+    }
+
+    let mut broken = Vec::new();
+    for package in candidates {
+        let files: Vec<&str> = files
+            .get(&package)
+            .map(|files| files.iter().map(String::as_str).collect())
+            .unwrap_or_default();
+        let problems = describe(&package_problems(&package, &files)?);
+        if let Some(reason) = problems_reason(&problems) {
+            output::debug(1, &format!("{package}: {}", problems.join(", ")));
+            broken.push(Finding { package, reason });
+        }
+    }
+    Ok(broken)
+}
+
+/// Check the linkage of one installed package, of any origin.
+///
+/// # Errors
+///
+/// Returns an error if pacman or ldd can't be run.
+pub fn verify_linkage(package: &str) -> Result<Vec<LinkProblem>, PacmanError> {
+    let files = pacman::package_files(&[package])?;
+    let files: Vec<&str> = files.iter().map(|(_, path)| path.as_str()).collect();
+    package_problems(package, &files)
+}
+
+/// Get the linkage problems of a package, except for libraries it ships.
+fn package_problems(package: &str, files: &[&str]) -> Result<Vec<LinkProblem>, PacmanError> {
+    Ok(not_shipped(pacman::link_problems(package, files)?, files))
+}
+
+/// Check whether a foreign package would be marked for broken linkage,
+/// explaining a refusal with `-vv`.
+fn is_linkage_candidate(package: &str, config: &Config, overrides: &Overrides) -> bool {
+    let skipped = if package.ends_with("-bin") {
+        // Rebuilding just re-downloads the same binary
+        "-bin package"
+    } else if config.is_skipped_package(package) {
+        "in skip_packages"
+    } else if !overrides.should_mark_package(package, CHECKREBUILD_TRIGGER) {
+        "package override"
+    } else {
+        return true;
+    };
+    output::debug(2, &format!("{package}: linkage not checked ({skipped})"));
+    false
+}
+
+/// Drop problems with libraries that the package ships itself.
+fn not_shipped(problems: Vec<LinkProblem>, files: &[&str]) -> Vec<LinkProblem> {
+    let shipped: HashSet<&str> = files
+        .iter()
+        .filter_map(|path| path.rsplit('/').next())
+        .collect();
+    problems
+        .into_iter()
+        .filter(|problem| !shipped.contains(problem.library.as_str()))
+        .collect()
+}
+
+/// Describe linkage problems once each, whichever files they're in.
+fn describe(problems: &[LinkProblem]) -> Vec<String> {
+    let mut described: Vec<String> = Vec::new();
+    for problem in problems {
+        let description = problem.to_string();
+        if !described.contains(&description) {
+            described.push(description);
+        }
+    }
+    described
+}
+
+/// Summarize the problems of a package (`libfoo.so.1 not found`).
+fn problems_reason(problems: &[String]) -> Option<String> {
+    match problems {
+        [] => None,
+        [problem] => Some(problem.clone()),
+        [problem, rest @ ..] => Some(format!("{problem} and {} more", rest.len())),
+    }
+}
+
+/// Parse the Python minor version from a pacman version (`3.13.1-1` is 13).
+fn parse_minor(version: &str) -> Option<u32> {
+    let mut parts = version.split(['.', '-']);
+    if parts.next()? != "3" {
+        return None;
+    }
+    parts.next()?.parse().ok()
+}
+
+/// Check whether a file belongs to a Python 3 version other than `current`.
+///
+/// Returns the reason if so.
+fn stale_python_file(path: &str, current: u32) -> Option<String> {
+    // Module directory: /usr/lib/python3.X/...
+    if let Some(rest) = path.strip_prefix("/usr/lib/python3.") {
+        let minor = rest.split('/').next()?;
+        if minor.parse::<u32>().is_ok_and(|minor| minor != current) {
+            return Some(format!("files under python3.{minor}"));
+        }
+    }
+
+    // Compiled extension: foo.cpython-3XY-<platform>.so (anywhere, e.g. /opt)
+    let file_name = path.rsplit('/').next()?;
+    let (_, tag) = file_name.split_once(".cpython-3")?;
+    let minor: String = tag.chars().take_while(char::is_ascii_digit).collect();
+    if file_name.ends_with(".so") && minor.parse::<u32>().is_ok_and(|minor| minor != current) {
+        return Some(format!("extension built for cpython-3{minor}"));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn missing(file: &str, library: &str) -> LinkProblem {
+        LinkProblem {
+            file: file.into(),
+            library: library.into(),
+            version: None,
+        }
+    }
+
+    #[test]
+    fn shipped_libraries_are_not_missing() {
+        let files = ["/opt/foo/", "/opt/foo/lib/libfoo.so.1", "/opt/foo/bin/foo"];
+        let problems = vec![
+            missing("/opt/foo/bin/foo", "libfoo.so.1"),
+            missing("/opt/foo/bin/foo", "libicuuc.so.74"),
+        ];
+        assert_eq!(
+            not_shipped(problems, &files),
+            vec![missing("/opt/foo/bin/foo", "libicuuc.so.74")]
+        );
+    }
+
+    #[test]
+    fn problem_reasons() {
+        let problems = [
+            missing("/usr/bin/foo", "libicuuc.so.74"),
+            missing("/usr/lib/libfoo.so", "libicuuc.so.74"),
+            LinkProblem {
+                file: "/usr/bin/foo".into(),
+                library: "libstdc++.so.6".into(),
+                version: Some("GLIBCXX_3.4.32".into()),
+            },
+        ];
+        let described = describe(&problems);
+        assert_eq!(
+            described,
+            vec![
+                "libicuuc.so.74 not found",
+                "libstdc++.so.6 lacks GLIBCXX_3.4.32"
+            ]
+        );
+        assert_eq!(
+            problems_reason(&described),
+            Some("libicuuc.so.74 not found and 1 more".into())
+        );
+        assert_eq!(problems_reason(&described[..1]), Some(described[0].clone()));
+        assert_eq!(problems_reason(&[]), None);
+    }
+
+    #[test]
+    fn linkage_candidates() {
+        let config = Config::default();
+        let overrides = Overrides::default();
+        assert!(is_linkage_candidate("qt6gtk2", &config, &overrides));
+        assert!(!is_linkage_candidate("discord-bin", &config, &overrides));
+        assert!(!is_linkage_candidate("qt6gtk2-debug", &config, &overrides));
+    }
+
+    #[test]
+    fn minor_version() {
+        assert_eq!(parse_minor("3.13.1-1"), Some(13));
+        assert_eq!(parse_minor("3.12-2"), Some(12));
+        assert_eq!(parse_minor("2.7.18-1"), None);
+        assert_eq!(parse_minor("garbage"), None);
+    }
+
+    #[test]
+    fn old_module_directory() {
+        assert_eq!(
+            stale_python_file("/usr/lib/python3.12/site-packages/foo/__init__.py", 13),
+            Some("files under python3.12".into())
+        );
+        assert_eq!(
+            stale_python_file("/usr/lib/python3.12/", 13),
+            Some("files under python3.12".into())
+        );
+    }
+
+    #[test]
+    fn current_module_directory() {
+        assert_eq!(
+            stale_python_file("/usr/lib/python3.13/site-packages/foo/__init__.py", 13),
+            None
+        );
+    }
+
+    #[test]
+    fn old_extension_tag() {
+        assert_eq!(
+            stale_python_file("/opt/foo/lib/_speedups.cpython-312-x86_64-linux-gnu.so", 13),
+            Some("extension built for cpython-312".into())
+        );
+    }
+
+    #[test]
+    fn current_and_stable_abi_extensions() {
+        assert_eq!(
+            stale_python_file("/opt/foo/_speedups.cpython-313-x86_64-linux-gnu.so", 13),
+            None
+        );
+        assert_eq!(stale_python_file("/opt/foo/_speedups.abi3.so", 13), None);
+    }
+
+    #[test]
+    fn unrelated_files() {
+        assert_eq!(stale_python_file("/usr/bin/foo", 13), None);
+        assert_eq!(stale_python_file("/usr/lib/python2.7/foo.py", 13), None);
+        assert_eq!(
+            stale_python_file("/usr/share/doc/foo.cpython-312.txt", 13),
+            None
+        );
+    }
+}
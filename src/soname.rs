@@ -0,0 +1,169 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! Soname-based trigger detection.
+//!
+//! Version thresholds are a guess at ABI compatibility: openssl, for one,
+//! bumps minor versions without breaking anything. With `soname_detection`
+//! enabled, a library trigger instead fires only if the set of sonames it
+//! ships changed between the old and new package.
+//!
+//! The new package's files come from the local database; the old package is
+//! read from the pacman cache. Sonames are taken from the names of the
+//! versioned shared libraries directly in `/usr/lib` and `/usr/lib32`, cut
+//! to their major version (`libssl.so.3.0.1` -> `libssl.so.3`).
+//!
+//! When the old package isn't cached, or ships no shared libraries, the
+//! comparison says nothing and the caller falls back to the threshold.
+
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+
+use crate::pacman::{self, PacmanError};
+use crate::version::Version;
+
+/// Library directories whose sonames are compared.
+const LIB_DIRS: &[&str] = &["usr/lib", "usr/lib32"];
+
+/// Check whether the sonames shipped by a package changed in an upgrade.
+///
+/// `old_version` is the version before the upgrade. Without it, the newest
+/// cached version older than the installed one is used.
+///
+/// Returns `None` if the sonames can't be compared.
+///
+/// # Errors
+///
+/// Returns an error if pacman can't be run.
+pub fn changed(package: &str, old_version: Option<&str>) -> Result<Option<bool>, PacmanError> {
+    let Some(installed) = pacman::package_info(&[package])?.into_iter().next() else {
+        return Ok(None);
+    };
+
+    let cached = pacman::cached_packages(package);
+    let old_file = match old_version {
+        Some(old) => cached.into_iter().find(|(version, _)| version == old),
+        None => newest_older(cached, &installed.version),
+    };
+    let Some(old_files) = old_file.and_then(|(_, path)| pacman::archive_files(&path)) else {
+        return Ok(None);
+    };
+
+    let old = sonames(old_files.iter().map(String::as_str));
+    if old.is_empty() {
+        return Ok(None);
+    }
+    let new_files = pacman::package_files(&[package])?;
+    let new = sonames(new_files.iter().map(|(_, path)| path.as_str()));
+
+    Ok(Some(old != new))
+}
+
+/// Pick the newest cached package older than the installed version.
+///
+/// A lower pkgrel of the same version counts as older, so a rebuild is
+/// compared with the build it replaced.
+pub fn newest_older<T>(cached: Vec<(String, T)>, installed: &str) -> Option<(String, T)> {
+    let installed = Version::parse(installed)?;
+    cached
+        .into_iter()
+        .filter_map(|(version, item)| Some((Version::parse(&version)?, version, item)))
+        .filter(|(parsed, _, _)| cmp_builds(parsed, &installed) == Ordering::Less)
+        .max_by(|(a, _, _), (b, _, _)| cmp_builds(a, b))
+        .map(|(_, version, item)| (version, item))
+}
+
+/// Compare versions, then pkgrels.
+fn cmp_builds(a: &Version, b: &Version) -> Ordering {
+    let pkgrel = |v: &Version| v.pkgrel.as_deref().and_then(Version::parse);
+    a.cmp_to(b).then_with(|| match (pkgrel(a), pkgrel(b)) {
+        (Some(a), Some(b)) => a.cmp_to(&b),
+        (a, b) => a.is_some().cmp(&b.is_some()),
+    })
+}
+
+/// Collect the sonames of the shared libraries in a file list.
+///
+/// Paths may be absolute (`pacman -Ql`) or relative (archive listings).
+pub fn sonames<'a>(files: impl IntoIterator<Item = &'a str>) -> BTreeSet<String> {
+    files
+        .into_iter()
+        .filter_map(|path| {
+            let (dir, name) = path.trim_start_matches('/').rsplit_once('/')?;
+            if !LIB_DIRS.contains(&dir) {
+                return None;
+            }
+            soname(name)
+        })
+        .collect()
+}
+
+/// Cut a versioned shared library name to its major version.
+fn soname(name: &str) -> Option<String> {
+    let (stem, version) = name.split_once(".so.")?;
+    let major = version.split('.').next()?;
+    if stem.is_empty() || major.is_empty() || !major.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(format!("{stem}.so.{major}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sonames_from_file_lists() {
+        let files = [
+            "/usr/lib/libssl.so",
+            "/usr/lib/libssl.so.3",
+            "/usr/lib/libcrypto.so.3",
+            "/usr/lib/engines-3/afalg.so",
+            "/usr/lib/ossl-modules/legacy.so",
+            "usr/lib32/libz.so.1.3.1",
+            "/usr/share/doc/libfoo.so.1",
+            "/usr/lib/",
+        ];
+        let expected: BTreeSet<String> = ["libcrypto.so.3", "libssl.so.3", "libz.so.1"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(sonames(files), expected);
+    }
+
+    #[test]
+    fn soname_major() {
+        assert_eq!(
+            soname("libQt6Core.so.6.7.0"),
+            Some("libQt6Core.so.6".into())
+        );
+        assert_eq!(
+            soname("libpython3.12.so.1.0"),
+            Some("libpython3.12.so.1".into())
+        );
+        assert_eq!(soname("libfoo.so"), None);
+        assert_eq!(soname("libfoo.so.abc"), None);
+    }
+
+    #[test]
+    fn newest_older_skips_installed_and_newer() {
+        let cached = vec![
+            ("3.2.1-1".to_string(), 1),
+            ("3.3.2-1".to_string(), 2),
+            ("3.3.1-1".to_string(), 3),
+            ("3.4.0-1".to_string(), 4),
+        ];
+        assert_eq!(
+            newest_older(cached, "3.3.2-1"),
+            Some(("3.3.1-1".to_string(), 3))
+        );
+        assert_eq!(newest_older(Vec::<(String, ())>::new(), "1.0-1"), None);
+
+        // A rebuild of the same version
+        let cached = vec![("3.3.2-1".to_string(), 1), ("3.3.1-2".to_string(), 2)];
+        assert_eq!(
+            newest_older(cached, "3.3.2-2"),
+            Some(("3.3.2-1".to_string(), 1))
+        );
+    }
+}
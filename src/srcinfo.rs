@@ -0,0 +1,188 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! `.SRCINFO` parsing.
+//!
+//! A `.SRCINFO` file has a `pkgbase` section followed by one section per
+//! split package (`pkgname = ...`). Values in the `pkgbase` section apply to
+//! every package unless a package section sets the same key, which replaces
+//! them. Only dependency keys are extracted.
+
+use std::collections::HashMap;
+
+/// Keys listing dependencies that tie a package to a trigger.
+///
+/// Architecture-specific variants (`depends_x86_64`) are matched by prefix.
+const DEPENDENCY_KEYS: &[&str] = &["depends", "makedepends", "checkdepends"];
+
+/// Parsed dependency information from a `.SRCINFO` file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Srcinfo {
+    /// Dependency keys set in the `pkgbase` section.
+    base: HashMap<String, Vec<String>>,
+    /// Dependency keys set in each package section.
+    packages: HashMap<String, HashMap<String, Vec<String>>>,
+}
+
+impl Srcinfo {
+    /// Parse a `.SRCINFO` file.
+    ///
+    /// Unknown keys and malformed lines are ignored.
+    pub fn parse(contents: &str) -> Self {
+        let mut srcinfo = Self::default();
+        let mut current: Option<String> = None;
+
+        for line in contents.lines() {
+            let line = line.trim();
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let key = key.trim();
+            let value = value.trim();
+
+            match key {
+                "pkgbase" => current = None,
+                "pkgname" => {
+                    srcinfo.packages.entry(value.to_string()).or_default();
+                    current = Some(value.to_string());
+                }
+                _ if is_dependency_key(key) => {
+                    let section = match &current {
+                        Some(name) => srcinfo.packages.entry(name.clone()).or_default(),
+                        None => &mut srcinfo.base,
+                    };
+                    section
+                        .entry(key.to_string())
+                        .or_default()
+                        .push(strip_constraint(value));
+                }
+                _ => {}
+            }
+        }
+
+        srcinfo
+    }
+
+    /// Returns true if the file defines the given package.
+    pub fn has_package(&self, package: &str) -> bool {
+        self.packages.contains_key(package)
+    }
+
+    /// All build, check, and runtime dependencies of a package.
+    pub fn dependencies(&self, package: &str) -> Vec<&str> {
+        let overrides = self.packages.get(package);
+        let mut keys: Vec<&String> = self.base.keys().collect();
+        if let Some(overrides) = overrides {
+            keys.extend(overrides.keys().filter(|key| !self.base.contains_key(*key)));
+        }
+
+        keys.into_iter()
+            .flat_map(|key| {
+                overrides
+                    .and_then(|section| section.get(key))
+                    .or_else(|| self.base.get(key))
+                    .into_iter()
+                    .flatten()
+            })
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Returns true if a package depends on `dependency` in any way.
+    pub fn depends_on(&self, package: &str, dependency: &str) -> bool {
+        self.dependencies(package).contains(&dependency)
+    }
+}
+
+/// Returns true for dependency keys, including architecture-specific ones.
+fn is_dependency_key(key: &str) -> bool {
+    DEPENDENCY_KEYS.iter().any(|dep_key| {
+        key == *dep_key
+            || key
+                .strip_prefix(dep_key)
+                .is_some_and(|arch| arch.starts_with('_'))
+    })
+}
+
+/// Strip a version constraint from a dependency (`foo>=1.0` becomes `foo`).
+fn strip_constraint(dep: &str) -> String {
+    dep.split(['<', '>', '=']).next().unwrap_or(dep).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRCINFO: &str = "\
+pkgbase = qt6gtk2
+\tpkgdesc = GTK+2.0 integration plugins for Qt6
+\tpkgver = 0.2
+\tpkgrel = 5
+\tarch = x86_64
+\tmakedepends = cmake
+\tdepends = gtk2
+\tdepends = qt6-base>=6.7
+
+pkgname = qt6gtk2
+";
+
+    #[test]
+    fn base_dependencies() {
+        let srcinfo = Srcinfo::parse(SRCINFO);
+        assert!(srcinfo.has_package("qt6gtk2"));
+        assert!(srcinfo.depends_on("qt6gtk2", "qt6-base"));
+        assert!(srcinfo.depends_on("qt6gtk2", "cmake"));
+        assert!(!srcinfo.depends_on("qt6gtk2", "qt5-base"));
+    }
+
+    #[test]
+    fn package_section_overrides_base() {
+        let srcinfo = Srcinfo::parse(
+            "\
+pkgbase = foo
+\tdepends = qt6-base
+\tmakedepends = cmake
+
+pkgname = foo
+\tdepends = libfoo
+
+pkgname = foo-qt
+",
+        );
+
+        // foo replaces depends but inherits makedepends
+        assert!(!srcinfo.depends_on("foo", "qt6-base"));
+        assert!(srcinfo.depends_on("foo", "libfoo"));
+        assert!(srcinfo.depends_on("foo", "cmake"));
+        // foo-qt inherits everything
+        assert!(srcinfo.depends_on("foo-qt", "qt6-base"));
+    }
+
+    #[test]
+    fn arch_specific_dependencies() {
+        let srcinfo = Srcinfo::parse(
+            "\
+pkgbase = foo
+\tdepends_x86_64 = lib32-glibc
+
+pkgname = foo
+",
+        );
+        assert!(srcinfo.depends_on("foo", "lib32-glibc"));
+    }
+
+    #[test]
+    fn dependency_keys() {
+        assert!(is_dependency_key("depends"));
+        assert!(is_dependency_key("makedepends_aarch64"));
+        assert!(!is_dependency_key("optdepends"));
+        assert!(!is_dependency_key("dependsfoo"));
+    }
+
+    #[test]
+    fn parse_empty() {
+        let srcinfo = Srcinfo::parse("");
+        assert!(!srcinfo.has_package("foo"));
+        assert!(srcinfo.dependencies("foo").is_empty());
+    }
+}
@@ -0,0 +1,68 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! Messages to the system log.
+//!
+//! `trigger --hook` runs inside a pacman transaction, whose hook output
+//! scrolls away with the rest of it (and only partly reaches
+//! `/var/log/pacman.log`). What it marks is also sent to the system log
+//! through the [`SOCKET`] datagram socket, where journald or a syslog daemon
+//! picks it up (`journalctl -t anneal`).
+//!
+//! Logging is best effort: without a socket, messages are dropped.
+
+use std::os::unix::net::UnixDatagram;
+
+/// Socket of the system log.
+pub const SOCKET: &str = "/dev/log";
+
+/// Program name the messages are tagged with.
+const IDENTIFIER: &str = "anneal";
+
+/// The `user` facility, shifted into place.
+const FACILITY_USER: u8 = 1 << 3;
+
+/// Severity of a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// Something failed.
+    Error = 3,
+    /// Something may need attention.
+    Warning = 4,
+    /// Normal operation.
+    Info = 6,
+}
+
+/// Send a message to the system log, ignoring failures.
+pub fn log(level: Level, msg: &str) {
+    let Ok(socket) = UnixDatagram::unbound() else {
+        return;
+    };
+    let line = format_message(level, std::process::id(), msg);
+    let _ = socket.send_to(line.as_bytes(), SOCKET);
+}
+
+/// Format a message in the traditional syslog form journald also accepts.
+fn format_message(level: Level, pid: u32, msg: &str) -> String {
+    format!(
+        "<{}>{IDENTIFIER}[{pid}]: {msg}",
+        FACILITY_USER | level as u8
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_format() {
+        assert_eq!(
+            format_message(Level::Info, 42, "Marked qt6gtk2"),
+            "<14>anneal[42]: Marked qt6gtk2"
+        );
+        assert_eq!(
+            format_message(Level::Error, 1, "failed"),
+            "<11>anneal[1]: failed"
+        );
+    }
+}
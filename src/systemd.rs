@@ -0,0 +1,76 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! systemd units for periodic scans.
+//!
+//! Triggers only catch breakage anneal knows to expect. `anneal setup
+//! --systemd` installs a timer that runs `anneal scan --checkrebuild`, so
+//! packages broken some other way are queued too. The units are generated
+//! rather than shipped: `ExecStart` points at the running binary, and the
+//! schedule comes from `scan_interval` in the config.
+
+use std::path::Path;
+
+/// Directory for administrator-installed units.
+pub const UNIT_DIR: &str = "/etc/systemd/system";
+
+/// File name of the scan service.
+pub const SCAN_SERVICE_NAME: &str = "anneal-scan.service";
+
+/// File name of the scan timer.
+pub const SCAN_TIMER_NAME: &str = "anneal-scan.timer";
+
+/// Get the scan service, running the given binary.
+pub fn scan_service(exe: &Path) -> String {
+    format!(
+        "\
+# Generated by anneal setup --systemd
+[Unit]
+Description=Mark AUR packages with broken linkage for rebuild
+Documentation=https://github.com/MarkWells-Dev/Anneal
+
+[Service]
+Type=oneshot
+ExecStart={} --quiet scan --checkrebuild
+",
+        exe.display()
+    )
+}
+
+/// Get the scan timer, firing on a systemd calendar expression.
+pub fn scan_timer(interval: &str) -> String {
+    format!(
+        "\
+# Generated by anneal setup --systemd
+[Unit]
+Description=Periodically mark AUR packages with broken linkage for rebuild
+Documentation=https://github.com/MarkWells-Dev/Anneal
+
+[Timer]
+OnCalendar={interval}
+Persistent=true
+RandomizedDelaySec=15min
+
+[Install]
+WantedBy=timers.target
+"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn units_use_binary_and_interval() {
+        let service = scan_service(Path::new("/home/me/.cargo/bin/anneal"));
+        assert!(
+            service.contains("ExecStart=/home/me/.cargo/bin/anneal --quiet scan --checkrebuild\n")
+        );
+        assert!(service.contains("Type=oneshot"));
+
+        let timer = scan_timer("weekly");
+        assert!(timer.contains("OnCalendar=weekly\n"));
+        assert!(timer.contains("WantedBy=timers.target"));
+    }
+}
@@ -0,0 +1,141 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! Format strings for script-friendly output (`list --format`).
+//!
+//! A template is literal text with `{field}` placeholders, similar to
+//! `git log --format`. `{{` and `}}` stand for literal braces, and the
+//! escapes `\t`, `\n` and `\\` are expanded so tabs and newlines can be
+//! given on the command line without shell quoting tricks.
+
+/// A piece of a parsed template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    /// Text printed as is.
+    Literal(String),
+    /// A placeholder replaced by a field value.
+    Field(String),
+}
+
+/// A parsed format string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    /// Parse a format string, checking its placeholders against the known
+    /// field names.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message describing an unknown field, an unclosed or stray
+    /// brace, or an unknown escape.
+    pub fn parse(format: &str, fields: &[&str]) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = format.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(format!("unclosed '{{{name}' in format")),
+                        }
+                    }
+                    if !fields.contains(&name.as_str()) {
+                        return Err(format!(
+                            "unknown field '{{{name}}}' in format, expected one of: {}",
+                            fields.join(", ")
+                        ));
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Field(name));
+                }
+                '}' => return Err("unmatched '}' in format (use '}}' for a brace)".into()),
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(c) => return Err(format!("unknown escape '\\{c}' in format")),
+                    None => return Err("format ends with '\\'".into()),
+                },
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Self { segments })
+    }
+
+    /// Returns true if the template has a placeholder for the field.
+    pub fn uses(&self, field: &str) -> bool {
+        self.segments
+            .iter()
+            .any(|segment| matches!(segment, Segment::Field(name) if name == field))
+    }
+
+    /// Render the template, looking up each placeholder's value.
+    pub fn render(&self, value: impl Fn(&str) -> String) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(text) => text.clone(),
+                Segment::Field(name) => value(name),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    const FIELDS: &[&str] = &["package", "trigger"];
+
+    #[test]
+    fn render_fields_and_escapes() {
+        let template = Template::parse("{package}\\t{trigger} {{x}}\\\\", FIELDS).unwrap();
+        let rendered = template.render(|name| match name {
+            "package" => "qt6ct".into(),
+            _ => "qt6-base".into(),
+        });
+        assert_eq!(rendered, "qt6ct\tqt6-base {x}\\");
+        assert!(template.uses("trigger"));
+        assert!(
+            !Template::parse("{{trigger}}", FIELDS)
+                .unwrap()
+                .uses("trigger")
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(
+            Template::parse("{size}", FIELDS)
+                .unwrap_err()
+                .contains("unknown field '{size}'")
+        );
+        assert!(Template::parse("{package", FIELDS).is_err());
+        assert!(Template::parse("package}", FIELDS).is_err());
+        assert!(Template::parse("\\x", FIELDS).is_err());
+        assert!(Template::parse("trailing\\", FIELDS).is_err());
+    }
+}
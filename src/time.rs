@@ -0,0 +1,405 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! Timestamp helpers.
+//!
+//! Timestamps are stored and logged as UTC ISO8601 strings, which sort
+//! lexicographically in chronological order. People see them through
+//! [`display_time`], with how long ago they were unless `--absolute-time`
+//! is given.
+
+use std::sync::OnceLock;
+
+/// Whether to leave relative times out of displayed timestamps.
+static ABSOLUTE_TIME: OnceLock<bool> = OnceLock::new();
+
+/// Get current time as ISO8601 string with millisecond precision.
+pub fn now_iso8601() -> String {
+    let duration = now_since_epoch();
+    format_iso8601(duration.as_secs(), duration.subsec_millis())
+}
+
+/// Time elapsed since the Unix epoch.
+fn now_since_epoch() -> std::time::Duration {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// Format seconds since the Unix epoch as an ISO8601 string.
+fn format_iso8601(secs: u64, millis: u32) -> String {
+    // Convert to date components (simplified - doesn't handle leap seconds)
+    let days = secs / 86400;
+    let time_secs = secs % 86400;
+    let hours = time_secs / 3600;
+    let minutes = (time_secs % 3600) / 60;
+    let seconds = time_secs % 60;
+
+    // Calculate date from days since epoch (1970-01-01)
+    let (year, month, day) = days_to_date(days);
+
+    format!("{year:04}-{month:02}-{day:02}T{hours:02}:{minutes:02}:{seconds:02}.{millis:03}Z")
+}
+
+/// Calculate cutoff date for retention period.
+pub fn cutoff_date(retention_days: u32) -> String {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    let cutoff_secs = now
+        .as_secs()
+        .saturating_sub(u64::from(retention_days) * 86400);
+
+    let days = cutoff_secs / 86400;
+    let (year, month, day) = days_to_date(days);
+
+    format!("{year:04}-{month:02}-{day:02}T00:00:00Z")
+}
+
+/// Parse a point in time given on the command line into an ISO8601 string.
+///
+/// Accepts a relative age (`30m`, `12h`, `7d`, `2w`: that long ago), a date
+/// (`2026-01-15`, midnight UTC), or a UTC timestamp (`2026-01-15T10:32:01`,
+/// optionally with milliseconds and `Z`).
+pub fn parse_time_spec(spec: &str) -> Option<String> {
+    if let Some(duration) = parse_duration(spec) {
+        let secs = now_since_epoch().as_secs().saturating_sub(duration);
+        return Some(format_iso8601(secs, 0));
+    }
+
+    let (date, time) = spec.split_once('T').unwrap_or((spec, "00:00:00"));
+    let time = time.strip_suffix('Z').unwrap_or(time);
+    let (time, millis) = time.split_once('.').unwrap_or((time, "000"));
+
+    let digits_at = |s: &str, pattern: &str| {
+        s.len() == pattern.len()
+            && s.bytes().zip(pattern.bytes()).all(|(c, p)| {
+                if p == b'0' {
+                    c.is_ascii_digit()
+                } else {
+                    c == p
+                }
+            })
+    };
+    if !digits_at(date, "0000-00-00") || !digits_at(time, "00:00:00") || !digits_at(millis, "000") {
+        return None;
+    }
+    Some(format!("{date}T{time}.{millis}Z"))
+}
+
+/// Parse a duration (`30m`, `12h`, `7d`, `2w`) into seconds.
+pub fn parse_duration(spec: &str) -> Option<u64> {
+    let (index, unit) = spec.char_indices().last()?;
+    if index == 0 || !spec[..index].bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let count: u64 = spec[..index].parse().ok()?;
+    let unit_secs = match unit {
+        'm' => 60,
+        'h' => 3600,
+        'd' => 86400,
+        'w' => 7 * 86400,
+        _ => return None,
+    };
+    Some(count.saturating_mul(unit_secs))
+}
+
+/// Get the ISO8601 time a number of seconds from now.
+pub fn time_after(secs: u64) -> String {
+    let secs = now_since_epoch().as_secs().saturating_add(secs);
+    format_iso8601(secs, 0)
+}
+
+/// Parse one of our ISO8601 timestamps into seconds since the Unix epoch.
+pub fn parse_iso8601(ts: &str) -> Option<u64> {
+    let field = |range: std::ops::Range<usize>| -> Option<u64> {
+        let text = ts.get(range)?;
+        text.bytes()
+            .all(|b| b.is_ascii_digit())
+            .then(|| text.parse().ok())?
+    };
+    if ts.as_bytes().get(10) != Some(&b'T') {
+        return None;
+    }
+    let days = days_from_date(
+        i64::try_from(field(0..4)?).ok()?,
+        u32::try_from(field(5..7)?).ok()?,
+        u32::try_from(field(8..10)?).ok()?,
+    )?;
+    Some(days * 86400 + field(11..13)? * 3600 + field(14..16)? * 60 + field(17..19)?)
+}
+
+/// Format how long ago a timestamp was, in its largest whole unit (`45s`,
+/// `12m`, `5h`, `3d`, `2w`).
+///
+/// Returns `None` if the timestamp can't be parsed.
+pub fn format_age(ts: &str) -> Option<String> {
+    let secs = now_since_epoch()
+        .as_secs()
+        .saturating_sub(parse_iso8601(ts)?);
+    Some(format_duration(secs))
+}
+
+/// Get how many whole days ago a timestamp was.
+///
+/// Returns `None` if the timestamp can't be parsed.
+pub fn age_days(ts: &str) -> Option<u64> {
+    let secs = now_since_epoch()
+        .as_secs()
+        .saturating_sub(parse_iso8601(ts)?);
+    Some(secs / 86400)
+}
+
+/// Format a duration in seconds in its largest whole unit, as
+/// [`format_age`] does.
+pub fn format_duration(secs: u64) -> String {
+    match secs {
+        s if s < 60 => format!("{s}s"),
+        s if s < 3600 => format!("{}m", s / 60),
+        s if s < 86400 => format!("{}h", s / 3600),
+        s if s < 14 * 86400 => format!("{}d", s / 86400),
+        s => format!("{}w", s / (7 * 86400)),
+    }
+}
+
+/// Format an elapsed time in seconds with its two largest units
+/// (`45s`, `3m 12s`, `1h 05m`).
+pub fn format_elapsed(secs: u64) -> String {
+    match secs {
+        s if s < 60 => format!("{s}s"),
+        s if s < 3600 => format!("{}m {:02}s", s / 60, s % 60),
+        s => format!("{}h {:02}m", s / 3600, s % 3600 / 60),
+    }
+}
+
+/// Convert a date to days since the Unix epoch (the inverse of
+/// [`days_to_date`]).
+fn days_from_date(year: i64, month: u32, day: u32) -> Option<u64> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let mp = i64::from(if month > 2 { month - 3 } else { month + 9 });
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    u64::try_from(era * 146097 + doe - 719468).ok()
+}
+
+/// Format an ISO8601 timestamp for display (`YYYY-MM-DD HH:MM:SS`, UTC).
+///
+/// Timestamps that don't look like ours are returned unchanged.
+pub fn display_timestamp(ts: &str) -> String {
+    match ts.get(..19) {
+        Some(prefix) if ts.as_bytes().get(10) == Some(&b'T') => prefix.replacen('T', " ", 1),
+        _ => ts.to_string(),
+    }
+}
+
+/// Leave relative times out of [`display_time`] (`--absolute-time`). Only
+/// the first call has an effect.
+pub fn set_absolute_time(absolute: bool) {
+    let _ = ABSOLUTE_TIME.set(absolute);
+}
+
+/// Format how long ago (or from now) a timestamp is in words: `just now`,
+/// `5 minutes ago`, `1 day ago`, `in 2 weeks`.
+///
+/// Returns `None` if the timestamp can't be parsed.
+pub fn format_relative(ts: &str) -> Option<String> {
+    let then = parse_iso8601(ts)?;
+    let now = now_since_epoch().as_secs();
+    let (secs, future) = if then > now {
+        (then - now, true)
+    } else {
+        (now - then, false)
+    };
+    let (count, unit) = match secs {
+        s if s < 60 => return Some("just now".to_string()),
+        s if s < 3600 => (s / 60, "minute"),
+        s if s < 86400 => (s / 3600, "hour"),
+        s if s < 14 * 86400 => (s / 86400, "day"),
+        s => (s / (7 * 86400), "week"),
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    Some(if future {
+        format!("in {count} {unit}{plural}")
+    } else {
+        format!("{count} {unit}{plural} ago")
+    })
+}
+
+/// Format a timestamp for people: [`display_timestamp`], followed by
+/// [`format_relative`] in parentheses unless absolute times were asked for.
+pub fn display_time(ts: &str) -> String {
+    let absolute = display_timestamp(ts);
+    if ABSOLUTE_TIME.get().copied().unwrap_or(false) {
+        return absolute;
+    }
+    match format_relative(ts) {
+        Some(relative) => format!("{absolute} ({relative})"),
+        None => absolute,
+    }
+}
+
+/// Convert days since Unix epoch to (year, month, day).
+pub fn days_to_date(days: u64) -> (i32, u32, u32) {
+    // Algorithm from https://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = (z - era * 146097) as u32;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let y = yoe as i64 + era * 400;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = if m <= 2 { y + 1 } else { y };
+
+    (y as i32, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iso8601_format() {
+        let ts = now_iso8601();
+        // Basic format check: YYYY-MM-DDTHH:MM:SS.mmmZ
+        assert_eq!(ts.len(), 24);
+        assert_eq!(&ts[4..5], "-");
+        assert_eq!(&ts[7..8], "-");
+        assert_eq!(&ts[10..11], "T");
+        assert_eq!(&ts[13..14], ":");
+        assert_eq!(&ts[16..17], ":");
+        assert_eq!(&ts[19..20], ".");
+        assert_eq!(&ts[23..24], "Z");
+    }
+
+    #[test]
+    fn time_spec_absolute() {
+        assert_eq!(
+            parse_time_spec("2026-01-15").as_deref(),
+            Some("2026-01-15T00:00:00.000Z")
+        );
+        assert_eq!(
+            parse_time_spec("2026-01-15T10:32:01").as_deref(),
+            Some("2026-01-15T10:32:01.000Z")
+        );
+        assert_eq!(
+            parse_time_spec("2026-01-15T10:32:01.123Z").as_deref(),
+            Some("2026-01-15T10:32:01.123Z")
+        );
+        assert_eq!(parse_time_spec("2026-1-15"), None);
+        assert_eq!(parse_time_spec("yesterday"), None);
+        assert_eq!(parse_time_spec(""), None);
+    }
+
+    #[test]
+    fn time_spec_relative() {
+        let now = now_iso8601();
+        let week_ago = parse_time_spec("1w").unwrap_or_default();
+        let day_ago = parse_time_spec("1d").unwrap_or_default();
+        assert!(week_ago < day_ago && day_ago < now, "{week_ago} {day_ago}");
+        assert_eq!(parse_time_spec("0h").map(|t| t.len()), Some(now.len()));
+        assert_eq!(parse_time_spec("7y"), None);
+        assert_eq!(parse_time_spec("d"), None);
+        assert_eq!(parse_time_spec("-1d"), None);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("30m"), Some(1800));
+        assert_eq!(parse_duration("30d"), Some(30 * 86400));
+        assert_eq!(parse_duration("2w"), Some(14 * 86400));
+        assert_eq!(parse_duration("30"), None);
+        assert_eq!(parse_duration("2026-01-15"), None);
+        assert!(time_after(3600) > now_iso8601());
+    }
+
+    #[test]
+    fn iso8601_roundtrip() {
+        assert_eq!(parse_iso8601("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(
+            parse_iso8601("2024-01-01T01:02:03.456Z"),
+            Some(19723 * 86400 + 3723)
+        );
+        let now = now_iso8601();
+        let secs = parse_iso8601(&now).unwrap_or_default();
+        assert_eq!(format_iso8601(secs, 0)[..19], now[..19]);
+        assert_eq!(parse_iso8601("2024-13-01T00:00:00Z"), None);
+        assert_eq!(parse_iso8601("garbage"), None);
+    }
+
+    #[test]
+    fn age_units() {
+        let age = format_age(&now_iso8601()).unwrap_or_default();
+        assert!(age == "0s" || age == "1s", "{age}");
+        let ten_days = parse_time_spec("10d").unwrap_or_default();
+        assert_eq!(format_age(&ten_days).as_deref(), Some("10d"));
+        let three_weeks = parse_time_spec("3w").unwrap_or_default();
+        assert_eq!(format_age(&three_weeks).as_deref(), Some("3w"));
+        assert_eq!(format_age("garbage"), None);
+
+        assert_eq!(age_days(&now_iso8601()), Some(0));
+        assert_eq!(age_days(&three_weeks), Some(21));
+        assert_eq!(age_days("garbage"), None);
+    }
+
+    #[test]
+    fn relative_times() {
+        assert_eq!(format_relative(&now_iso8601()).as_deref(), Some("just now"));
+        let ago = |spec| format_relative(&parse_time_spec(spec).unwrap_or_default());
+        assert_eq!(ago("1d").as_deref(), Some("1 day ago"));
+        assert_eq!(ago("3d").as_deref(), Some("3 days ago"));
+        assert_eq!(ago("5h").as_deref(), Some("5 hours ago"));
+        assert_eq!(ago("3w").as_deref(), Some("3 weeks ago"));
+        assert_eq!(
+            format_relative(&time_after(2 * 3600 + 30)).as_deref(),
+            Some("in 2 hours")
+        );
+        assert_eq!(format_relative("garbage"), None);
+
+        let ts = parse_time_spec("3d").unwrap_or_default();
+        assert!(display_time(&ts).ends_with(" (3 days ago)"));
+        assert_eq!(display_time("garbage"), "garbage");
+    }
+
+    #[test]
+    fn elapsed_units() {
+        assert_eq!(format_elapsed(45), "45s");
+        assert_eq!(format_elapsed(192), "3m 12s");
+        assert_eq!(format_elapsed(3900), "1h 05m");
+        assert_eq!(format_elapsed(30 * 3600), "30h 00m");
+    }
+
+    #[test]
+    fn display_timestamp_format() {
+        assert_eq!(
+            display_timestamp("2026-01-15T10:32:01.123Z"),
+            "2026-01-15 10:32:01"
+        );
+        assert_eq!(display_timestamp("garbage"), "garbage");
+    }
+
+    #[test]
+    fn days_to_date_epoch() {
+        // 1970-01-01
+        assert_eq!(days_to_date(0), (1970, 1, 1));
+    }
+
+    #[test]
+    fn days_to_date_known_dates() {
+        // 2024-01-01 is 19723 days from epoch
+        assert_eq!(days_to_date(19723), (2024, 1, 1));
+        // 2000-01-01 is 10957 days from epoch
+        assert_eq!(days_to_date(10957), (2000, 1, 1));
+    }
+}
@@ -0,0 +1,321 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! Reacting to a pacman transaction: the flows behind `trigger` and
+//! `capture-versions`.
+//!
+//! After a transaction, [`update_queue`] brings the queue up to date with
+//! what changed (replaced, removed and reinstalled packages), and
+//! [`mark_dependents`] marks the AUR dependents of upgraded triggers.
+//! [`announce`] then sends the notification and starts the automatic
+//! rebuild, if configured. Before a transaction, [`capture_versions`] saves
+//! the versions about to be replaced for [`take_captured_versions`].
+
+use std::collections::HashMap;
+use std::io;
+
+use crate::audit;
+use crate::aur::{AurClient, AurError};
+use crate::capture;
+use crate::config::{AutoRebuild, Config};
+use crate::db::{Database, Origin, get_db_path};
+use crate::error::Error;
+use crate::notify;
+use crate::output;
+use crate::overrides::Overrides;
+use crate::pacman;
+use crate::queue::{mark_tags, migrate_replaced, record_marked_versions};
+use crate::rebuild::{rebuild_running, start_auto_rebuild};
+use crate::srcinfo::Srcinfo;
+use crate::syslog;
+use crate::trigger::{
+    MarkedPackage, TriggerInput, TriggerResult, installed_versions, process_triggers,
+};
+
+/// Result of [`update_queue`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueueUpdate {
+    /// Entries moved to the package that replaced them, as `(old, new)`.
+    pub moved: Vec<(String, String)>,
+    /// Packages unmarked because they are no longer installed.
+    pub uninstalled: Vec<String>,
+    /// Packages unmarked because the transaction reinstalled them.
+    pub reinstalled: Vec<String>,
+}
+
+/// Result of [`mark_dependents`].
+#[derive(Debug, Default)]
+pub struct Dependents {
+    /// Trigger processing, without the marks dropped by the `.SRCINFO`
+    /// check. These are marked (or would be, for a dry run).
+    pub result: TriggerResult,
+    /// Marks that weren't queued before, in order (empty for a dry run).
+    pub newly_marked: Vec<MarkedPackage>,
+}
+
+/// Bring the queue up to date with a transaction.
+///
+/// Queued packages that were replaced (e.g., renamed) since they were
+/// marked follow their replacement; the rest of those no longer installed
+/// are unmarked. Queued packages among `packages` were rebuilt some other
+/// way (the user's AUR helper) and are unmarked too, unless an anneal
+/// rebuild is running, which unmarks its packages itself.
+///
+/// # Errors
+///
+/// Returns an error if pacman can't be queried or the database can't be
+/// written.
+pub fn update_queue(config: &Config, packages: &[String]) -> Result<QueueUpdate, Error> {
+    let mut db = Database::open(config.retention_days)?;
+    let queue = db.list()?;
+    let queued: Vec<&str> = queue.iter().map(|e| e.package.as_str()).collect();
+    let installed = pacman::installed_packages(&queued)?;
+    let removed: Vec<&str> = queued
+        .into_iter()
+        .filter(|pkg| !installed.contains(*pkg))
+        .collect();
+    let moved = migrate_replaced(config, &mut db, &removed, Origin::Hook)?;
+
+    // The rest were removed from the system without being unmarked, or
+    // never installed (mark --allow-missing)
+    let mut uninstalled = Vec::new();
+    for pkg in removed {
+        if !moved.iter().any(|(old, _)| old == pkg) && db.complete_uninstall(pkg, Origin::Hook)? {
+            uninstalled.push(pkg.to_string());
+        }
+    }
+    audit::record(config, "unmark", &names(&uninstalled));
+
+    // A trigger in the same transaction still marks reinstalled packages
+    // again in [`mark_dependents`], since they were built against the old
+    // version
+    let mut reinstalled = Vec::new();
+    if !rebuild_running() {
+        for pkg in packages {
+            if db.complete_reinstall(pkg, Origin::Hook)? {
+                reinstalled.push(pkg.clone());
+            }
+        }
+        audit::record(config, "unmark", &names(&reinstalled));
+    }
+
+    Ok(QueueUpdate {
+        moved,
+        uninstalled,
+        reinstalled,
+    })
+}
+
+/// Mark the AUR dependents of the upgraded triggers among `packages`.
+///
+/// `packages` are `name` or `name:oldver:newver` (see [`TriggerInput`]).
+/// With `confirm_srcinfo`, dependents whose AUR package no longer depends on
+/// the trigger are dropped. With `dry_run`, nothing is marked.
+///
+/// # Errors
+///
+/// Returns an error if trigger processing fails or the database can't be
+/// written.
+pub fn mark_dependents(
+    config: &Config,
+    packages: &[String],
+    dry_run: bool,
+    quiet: bool,
+) -> Result<Dependents, Error> {
+    let mut result = process_triggers(
+        packages,
+        config.version_threshold,
+        &Overrides::load(),
+        config.skip_any_arch,
+        &config.skip_packages,
+        config.soname_detection,
+    )?;
+
+    // Drop dependents whose AUR package no longer depends on the trigger
+    // (e.g., switched to a bundled copy)
+    if config.confirm_srcinfo && !result.marked.is_empty() {
+        let aur = AurClient::from_config(config);
+        confirm_with_srcinfo(&aur, &mut result.marked, quiet);
+    }
+
+    if dry_run || result.marked.is_empty() {
+        return Ok(Dependents {
+            result,
+            newly_marked: Vec::new(),
+        });
+    }
+
+    // Record the version each trigger was upgraded to, when known
+    let versions: HashMap<String, String> = packages
+        .iter()
+        .map(|pkg| TriggerInput::parse(pkg))
+        .filter_map(|input| Some((input.name, input.new_version?)))
+        .collect();
+
+    let mut db = Database::open(config.retention_days)?;
+    let mut newly_marked = Vec::new();
+    for m in &result.marked {
+        let version = versions.get(&m.trigger).map(String::as_str);
+        if db.mark(&m.package, Some(&m.trigger), version)? {
+            newly_marked.push(m.clone());
+        }
+        db.add_tags(&m.package, &mark_tags(&[], Some(&m.trigger)))?;
+    }
+
+    let marked: Vec<&str> = result.marked.iter().map(|m| m.package.as_str()).collect();
+    record_marked_versions(&mut db, &marked)?;
+    audit::record(config, "mark", &marked);
+
+    Ok(Dependents {
+        result,
+        newly_marked,
+    })
+}
+
+/// Tell users about newly marked packages and start the automatic rebuild,
+/// as configured (`notify`, `auto_rebuild`).
+///
+/// Both are best-effort and never fail.
+pub fn announce(config: &Config, newly_marked: &[MarkedPackage], quiet: bool) {
+    if newly_marked.is_empty() {
+        return;
+    }
+    if config.notify {
+        let marked: Vec<&MarkedPackage> = newly_marked.iter().collect();
+        if let Some((summary, body)) = notify::message(&marked) {
+            notify::send(&summary, &body);
+        }
+    }
+    if config.auto_rebuild != AutoRebuild::Off {
+        start_auto_rebuild(config, quiet);
+    }
+}
+
+/// Keep only marks whose dependent's current `.SRCINFO` still depends on the
+/// trigger.
+///
+/// Anything that can't be checked (not on the AUR, network errors) is kept:
+/// a missed rebuild is worse than an unnecessary one.
+pub fn confirm_with_srcinfo(aur: &AurClient, marked: &mut Vec<MarkedPackage>, quiet: bool) {
+    let mut names: Vec<&str> = marked.iter().map(|m| m.package.as_str()).collect();
+    names.sort_unstable();
+    names.dedup();
+
+    let bases: HashMap<String, String> = match aur.info(&names) {
+        Ok(info) => info
+            .into_iter()
+            .map(|pkg| (pkg.name, pkg.package_base))
+            .collect(),
+        Err(e) => {
+            report_srcinfo_error(&e, quiet);
+            return;
+        }
+    };
+
+    // Split packages share a .SRCINFO, fetch each base once
+    let mut srcinfos: HashMap<String, Option<Srcinfo>> = HashMap::new();
+    let mut skipped = Vec::new();
+
+    for m in marked.iter() {
+        let Some(base) = bases.get(&m.package) else {
+            continue;
+        };
+        if !srcinfos.contains_key(base) {
+            let srcinfo = match aur.srcinfo(base) {
+                Ok(srcinfo) => Some(srcinfo),
+                Err(e) => {
+                    report_srcinfo_error(&e, quiet);
+                    if e.is_offline() {
+                        return;
+                    }
+                    None
+                }
+            };
+            srcinfos.insert(base.clone(), srcinfo);
+        }
+
+        if let Some(Some(srcinfo)) = srcinfos.get(base)
+            && srcinfo.has_package(&m.package)
+            && !srcinfo.depends_on(&m.package, &m.trigger)
+        {
+            skipped.push((m.package.clone(), m.trigger.clone()));
+        }
+    }
+
+    for (package, trigger) in &skipped {
+        if !quiet {
+            output::info(&format!(
+                "Skipped {package}: AUR package no longer depends on {trigger}"
+            ));
+        }
+    }
+    marked.retain(|m| {
+        !skipped
+            .iter()
+            .any(|(package, trigger)| *package == m.package && *trigger == m.trigger)
+    });
+}
+
+/// Report why the `.SRCINFO` check couldn't be done.
+fn report_srcinfo_error(e: &AurError, quiet: bool) {
+    if e.is_offline() {
+        if !quiet {
+            output::info(&format!("Skipping .SRCINFO check ({e})"));
+        }
+    } else {
+        output::warning(&format!("Could not check .SRCINFO: {e}"));
+    }
+}
+
+/// Save the installed versions of the triggers among `packages`, which are
+/// about to be upgraded.
+///
+/// # Errors
+///
+/// Returns an error if pacman can't be queried or the capture can't be
+/// written.
+pub fn capture_versions(packages: &[String]) -> Result<(), Error> {
+    let versions = installed_versions(packages, &Overrides::load())?;
+    for (name, version) in &versions {
+        output::debug(1, &format!("{name}: captured {version}"));
+    }
+    let Some(path) = capture::capture_path(&get_db_path()) else {
+        return Ok(());
+    };
+    capture::save(&path, &versions).map_err(|e| {
+        Error::Io(io::Error::new(
+            e.kind(),
+            format!("Cannot write {}: {e}", path.display()),
+        ))
+    })
+}
+
+/// Read and remove the versions captured before the transaction.
+///
+/// A capture that can't be read is only a warning, since the pacman cache
+/// is the fallback.
+pub fn take_captured_versions() -> HashMap<String, String> {
+    let Some(path) = capture::capture_path(&get_db_path()) else {
+        return HashMap::new();
+    };
+    capture::take(&path).unwrap_or_else(|e| {
+        let msg = format!("Cannot read captured versions from {}: {e}", path.display());
+        output::warning(&msg);
+        hook_log(syslog::Level::Warning, &msg);
+        HashMap::new()
+    })
+}
+
+/// Log a message of a pacman hook command to the system log.
+///
+/// Fixture mode is for development, so nothing is logged there.
+pub fn hook_log(level: syslog::Level, msg: &str) {
+    if pacman::fixture_dir().is_none() {
+        syslog::log(level, msg);
+    }
+}
+
+/// Package names, for the audit log.
+fn names(packages: &[String]) -> Vec<&str> {
+    packages.iter().map(String::as_str).collect()
+}
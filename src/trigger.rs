@@ -11,13 +11,27 @@
 //! Packages can be specified with version info: `name:oldver:newver`
 //! When version info is provided, the threshold is checked before triggering.
 //! Without version info, triggers always fire.
-
-use std::collections::HashSet;
-use std::io::{BufRead, BufReader};
-use std::process::{Command, Stdio};
-
-use crate::overrides::Overrides;
-use crate::triggers::{TRIGGERS, get_curated_threshold, is_curated_trigger};
+//!
+//! With soname detection enabled, library triggers are decided by whether
+//! the sonames they ship changed instead (see [`crate::soname`]), and the
+//! threshold only applies when the sonames can't be compared.
+//!
+//! ## Architecture Awareness
+//!
+//! Library triggers skip `arch=any` dependents, since there's no compiled code
+//! to rebuild (controlled by `skip_any_arch`, language runtimes are exempt).
+//! Triggers named `lib32-*` only mark `lib32-*` dependents, as only multilib
+//! packages link against the 32-bit libraries.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::output;
+use crate::overrides::{Overrides, PackageOverride, TriggerOverride, matches_glob};
+use crate::pacman::{self, PacmanError};
+use crate::soname;
+use crate::triggers::{
+    RUNTIME_TRIGGERS, curated, get_curated_threshold, is_curated_trigger, is_library_trigger,
+};
 use crate::version::{Threshold, Version, exceeds_threshold};
 
 /// Parsed trigger input with optional version info.
@@ -78,6 +92,7 @@ impl TriggerInput {
 
 /// Result of processing triggers.
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TriggerResult {
     /// Packages that were marked (or would be marked in dry-run).
     pub marked: Vec<MarkedPackage>,
@@ -85,10 +100,15 @@ pub struct TriggerResult {
     pub skipped: Vec<String>,
     /// Triggers that were skipped due to version threshold.
     pub below_threshold: Vec<String>,
+    /// Triggers that were skipped because their sonames didn't change.
+    pub same_sonames: Vec<String>,
+    /// Dependents that were skipped because they are architecture-independent.
+    pub skipped_any_arch: Vec<String>,
 }
 
 /// A package that was marked by a trigger.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MarkedPackage {
     /// The package name.
     pub package: String,
@@ -96,41 +116,17 @@ pub struct MarkedPackage {
     pub trigger: String,
 }
 
-/// Errors that can occur during trigger processing.
-#[derive(Debug)]
-pub enum TriggerError {
-    /// Failed to run pactree.
-    Pactree(std::io::Error),
-    /// Failed to run pacman.
-    Pacman(std::io::Error),
-    /// pactree returned non-zero exit code.
-    PactreeExitCode(i32),
-    /// pacman returned non-zero exit code.
-    PacmanExitCode(i32),
-}
-
-impl std::fmt::Display for TriggerError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Pactree(e) => write!(f, "failed to run pactree: {e}"),
-            Self::Pacman(e) => write!(f, "failed to run pacman: {e}"),
-            Self::PactreeExitCode(code) => write!(f, "pactree exited with code {code}"),
-            Self::PacmanExitCode(code) => write!(f, "pacman exited with code {code}"),
-        }
-    }
-}
-
-impl std::error::Error for TriggerError {}
-
 /// Process a list of upgraded packages and find AUR dependents to mark.
 ///
 /// For each package that's a known trigger:
-/// 1. Check version threshold (if version info provided)
+/// 1. Check version threshold (if version info provided), or the sonames
+///    with `soname_detection`
 /// 2. Query reverse dependencies via pactree (or use override patterns)
-/// 3. Filter to AUR packages only
+/// 3. Filter to AUR packages only (minus `skip_packages` patterns)
 /// 4. Filter out -bin packages
 /// 5. Apply package overrides
-/// 6. Return the list of packages to mark
+/// 6. Filter by architecture (see module docs)
+/// 7. Return the list of packages to mark
 ///
 /// Package format: `name` or `name:oldver:newver`
 ///
@@ -141,31 +137,87 @@ pub fn process_triggers(
     packages: &[String],
     default_threshold: Threshold,
     overrides: &Overrides,
-) -> Result<TriggerResult, TriggerError> {
+    skip_any_arch: bool,
+    skip_packages: &[String],
+    soname_detection: bool,
+) -> Result<TriggerResult, PacmanError> {
     let mut result = TriggerResult::default();
 
-    // Get list of AUR packages once (expensive operation)
-    let aur_packages = get_aur_packages()?;
+    // Get list of AUR packages once (expensive operation), leaving out
+    // companion packages that are never rebuilt on their own
+    let mut aur_packages = pacman::foreign_packages()?;
+    aur_packages.retain(|pkg| {
+        let skip = skip_packages
+            .iter()
+            .any(|pattern| matches_glob(pattern, pkg));
+        if skip {
+            output::debug(2, &format!("{pkg}: never marked (skip_packages)"));
+        }
+        !skip
+    });
+
+    // pactree can take a while per trigger, so a big upgrade would look
+    // hung without a progress line
+    let total = packages
+        .iter()
+        .filter(|pkg| is_trigger(&TriggerInput::parse(pkg).name, overrides))
+        .count();
+    let mut progress = output::Progress::new();
+    let mut done = 0;
 
     for pkg_input in packages {
         let input = TriggerInput::parse(pkg_input);
 
         if !is_trigger(&input.name, overrides) {
+            output::debug(2, &format!("{}: not a trigger", input.name));
             result.skipped.push(input.name);
             continue;
         }
+        done += 1;
+        progress.update(&format!(
+            "resolving dependents for {} ({done}/{total})...",
+            input.name
+        ));
 
         // Use per-trigger threshold for curated triggers, global config for user-defined
         let threshold = get_curated_threshold(&input.name).unwrap_or(default_threshold);
 
-        // Check version threshold
-        if !input.exceeds_threshold(threshold) {
-            result.below_threshold.push(input.name);
-            continue;
+        // Runtimes break on module paths, not sonames, so they always go by
+        // the threshold
+        let sonames_changed =
+            if soname_detection && !RUNTIME_TRIGGERS.contains(&input.name.as_str()) {
+                soname::changed(&input.name, input.old_version.as_deref())?
+            } else {
+                None
+            };
+
+        match sonames_changed {
+            Some(true) => output::debug(1, &format!("{}: sonames changed", input.name)),
+            Some(false) => {
+                output::debug(1, &format!("{}: sonames unchanged", input.name));
+                result.same_sonames.push(input.name);
+                continue;
+            }
+            None if !input.exceeds_threshold(threshold) => {
+                output::debug(
+                    1,
+                    &format!("{}: below the {} threshold", input.name, threshold.as_str()),
+                );
+                result.below_threshold.push(input.name);
+                continue;
+            }
+            None => {}
         }
 
         let dependents = get_aur_dependents(&input.name, &aur_packages, overrides)?;
         for dep in dependents {
+            if !is_multilib_match(&input.name, &dep) {
+                output::debug(
+                    2,
+                    &format!("{dep}: not marked by {} (not lib32)", input.name),
+                );
+                continue;
+            }
             result.marked.push(MarkedPackage {
                 package: dep,
                 trigger: input.name.clone(),
@@ -173,12 +225,83 @@ pub fn process_triggers(
         }
     }
 
+    drop(progress);
+
+    if skip_any_arch {
+        skip_any_arch_dependents(&mut result)?;
+    }
+
     // Deduplicate - a package might be marked by multiple triggers
     deduplicate_marked(&mut result.marked);
 
     Ok(result)
 }
 
+/// Get the installed versions of the triggers among some packages.
+///
+/// Packages that aren't triggers or aren't installed are left out.
+///
+/// # Errors
+///
+/// Returns an error if pacman can't be run.
+pub fn installed_versions(
+    names: &[String],
+    overrides: &Overrides,
+) -> Result<Vec<(String, String)>, PacmanError> {
+    let triggers: Vec<&str> = names
+        .iter()
+        .map(String::as_str)
+        .filter(|name| is_trigger(name, overrides))
+        .collect();
+    Ok(pacman::package_info(&triggers)?
+        .into_iter()
+        .map(|info| (info.name, info.version))
+        .collect())
+}
+
+/// Add versions to upgraded package names as a pacman hook delivers them
+/// (`NeedsTargets`: names only), so thresholds apply.
+///
+/// Runs after the transaction, so the new version is the installed one. The
+/// old version is the one captured before the transaction (see
+/// [`crate::capture`]), else the newest older package in the pacman cache.
+/// Only triggers are looked up; names without an old version are left as
+/// they are (and always fire).
+///
+/// # Errors
+///
+/// Returns an error if pacman can't be run.
+pub fn with_versions(
+    names: &[String],
+    captured: &HashMap<String, String>,
+    overrides: &Overrides,
+) -> Result<Vec<String>, PacmanError> {
+    let installed: HashMap<String, String> =
+        installed_versions(names, overrides)?.into_iter().collect();
+
+    Ok(names
+        .iter()
+        .map(|name| {
+            let Some(new) = installed.get(name) else {
+                return name.clone();
+            };
+            let old = captured.get(name).cloned().or_else(|| {
+                soname::newest_older(pacman::cached_packages(name), new).map(|(old, _)| old)
+            });
+            match old {
+                Some(old) => {
+                    output::debug(1, &format!("{name}: {old} -> {new}"));
+                    format!("{name}:{old}:{new}")
+                }
+                None => {
+                    output::debug(1, &format!("{name}: no captured or cached old version"));
+                    name.clone()
+                }
+            }
+        })
+        .collect())
+}
+
 /// Check if a package is a known trigger.
 ///
 /// A package is a trigger if it's in the curated list OR has a user override file.
@@ -191,87 +314,130 @@ fn get_aur_dependents(
     package: &str,
     aur_packages: &HashSet<String>,
     overrides: &Overrides,
-) -> Result<Vec<String>, TriggerError> {
+) -> Result<Vec<String>, PacmanError> {
     // Check for trigger override first
     if let Some(targets) = overrides.get_trigger_targets(package, aur_packages) {
+        match overrides.trigger_override(package) {
+            Some(TriggerOverride::Disabled) => {
+                output::debug(1, &format!("{package}: disabled by trigger override"));
+            }
+            _ => output::debug(
+                1,
+                &format!(
+                    "{package}: trigger override matched {} package(s)",
+                    targets.len()
+                ),
+            ),
+        }
         // Override handles -bin filtering internally
         // Apply package overrides to the results
         let filtered: Vec<String> = targets
             .into_iter()
-            .filter(|dep| overrides.should_mark_package(dep, package))
+            .filter(|dep| allowed_by_override(dep, package, overrides))
             .collect();
         return Ok(filtered);
     }
 
     // Default: pactree lookup
-    let reverse_deps = get_reverse_deps(package)?;
+    let reverse_deps = pacman::reverse_deps(package)?;
+    output::debug(
+        1,
+        &format!(
+            "{package}: pactree found {} reverse dependent(s)",
+            reverse_deps.len()
+        ),
+    );
 
     let dependents: Vec<String> = reverse_deps
         .into_iter()
         .filter(|dep| {
             // Must be an AUR package
-            aur_packages.contains(dep)
+            if !aur_packages.contains(dep) {
+                output::debug(
+                    2,
+                    &format!("{dep}: not marked (not foreign, or in skip_packages)"),
+                );
+                return false;
+            }
             // Filter out -bin packages (rebuilding just re-downloads the same binary)
-            && !dep.ends_with("-bin")
+            if dep.ends_with("-bin") {
+                output::debug(2, &format!("{dep}: not marked (-bin package)"));
+                return false;
+            }
             // Check package override
-            && overrides.should_mark_package(dep, package)
+            allowed_by_override(dep, package, overrides)
         })
         .collect();
 
     Ok(dependents)
 }
 
-/// Get reverse dependencies of a package using pactree.
-fn get_reverse_deps(package: &str) -> Result<Vec<String>, TriggerError> {
-    let output = Command::new("pactree")
-        .args(["-r", "-u", package])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .output()
-        .map_err(TriggerError::Pactree)?;
-
-    if !output.status.success() {
-        // pactree returns 1 if package not found, which is fine
-        // (package might have been removed or not installed)
-        return Ok(Vec::new());
+/// Check a dependent's package override, explaining a refusal with `-vv`.
+fn allowed_by_override(dep: &str, trigger: &str, overrides: &Overrides) -> bool {
+    let allowed = overrides.should_mark_package(dep, trigger);
+    if !allowed {
+        let reason = match overrides.package_override(dep) {
+            Some(PackageOverride::NeverMark) => "ignored",
+            _ => "package override doesn't list this trigger",
+        };
+        output::debug(2, &format!("{dep}: not marked by {trigger} ({reason})"));
     }
+    allowed
+}
 
-    let deps: Vec<String> = BufReader::new(&output.stdout[..])
-        .lines()
-        .map_while(Result::ok)
-        .map(|line| line.trim().to_string())
-        .filter(|line| !line.is_empty() && line != package)
-        .collect();
-
-    Ok(deps)
+/// Check whether a dependent can be affected by a trigger's architecture.
+///
+/// `lib32-*` triggers only affect `lib32-*` dependents; other triggers affect
+/// everything.
+fn is_multilib_match(trigger: &str, dependent: &str) -> bool {
+    !trigger.starts_with("lib32-") || dependent.starts_with("lib32-")
 }
 
-/// Get list of AUR (foreign) packages.
-fn get_aur_packages() -> Result<HashSet<String>, TriggerError> {
-    let output = Command::new("pacman")
-        .args(["-Qmq"])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .output()
-        .map_err(TriggerError::Pacman)?;
-
-    if !output.status.success() {
-        let code = output.status.code().unwrap_or(-1);
-        // Exit code 1 with no output means no foreign packages
-        if code == 1 && output.stdout.is_empty() {
-            return Ok(HashSet::new());
-        }
-        return Err(TriggerError::PacmanExitCode(code));
+/// Drop `arch=any` dependents of library triggers from the marked list.
+///
+/// A package marked by both a library and a runtime trigger is kept, since
+/// the runtime entry survives.
+fn skip_any_arch_dependents(result: &mut TriggerResult) -> Result<(), PacmanError> {
+    let candidates: Vec<&str> = result
+        .marked
+        .iter()
+        .filter(|m| is_library_trigger(&m.trigger))
+        .map(|m| m.package.as_str())
+        .collect();
+
+    if candidates.is_empty() {
+        return Ok(());
     }
 
-    let packages: HashSet<String> = BufReader::new(&output.stdout[..])
-        .lines()
-        .map_while(Result::ok)
-        .map(|line| line.trim().to_string())
-        .filter(|line| !line.is_empty())
+    let arches: HashMap<String, String> = pacman::package_info(&candidates)?
+        .into_iter()
+        .map(|info| (info.name, info.arch))
+        .collect();
+    let is_any = |m: &MarkedPackage| {
+        is_library_trigger(&m.trigger) && arches.get(&m.package).is_some_and(|a| a == "any")
+    };
+
+    let mut skipped: Vec<String> = result
+        .marked
+        .iter()
+        .filter(|m| is_any(m))
+        .map(|m| {
+            output::debug(
+                2,
+                &format!("{}: not marked by {} (arch=any)", m.package, m.trigger),
+            );
+            m.package.clone()
+        })
         .collect();
+    result.marked.retain(|m| !is_any(m));
 
-    Ok(packages)
+    // Only report packages that didn't survive via another trigger
+    skipped.retain(|pkg| !result.marked.iter().any(|m| &m.package == pkg));
+    skipped.sort();
+    skipped.dedup();
+    result.skipped_any_arch = skipped;
+
+    Ok(())
 }
 
 /// Deduplicate marked packages, keeping the first trigger for each package.
@@ -285,10 +451,7 @@ pub fn list_all_triggers(
     overrides: &Overrides,
     default_threshold: Threshold,
 ) -> Vec<(String, Threshold)> {
-    let mut triggers: Vec<(String, Threshold)> = TRIGGERS
-        .iter()
-        .map(|(name, threshold)| ((*name).to_string(), *threshold))
-        .collect();
+    let mut triggers = curated().triggers.clone();
 
     // Add user-defined triggers with the global default threshold
     for trigger in overrides.user_triggers() {
@@ -338,6 +501,14 @@ mod tests {
         assert_eq!(marked[1].package, "pkg2");
     }
 
+    #[test]
+    fn multilib_match() {
+        assert!(is_multilib_match("lib32-openssl", "lib32-foo"));
+        assert!(!is_multilib_match("lib32-openssl", "foo"));
+        assert!(is_multilib_match("openssl", "foo"));
+        assert!(is_multilib_match("openssl", "lib32-foo"));
+    }
+
     #[test]
     fn bin_suffix_detection() {
         assert!("foo-bin".ends_with("-bin"));
@@ -3,21 +3,53 @@
 
 //! Curated trigger list for ABI-sensitive packages.
 //!
-//! This module contains the embedded list of packages known to cause ABI breakage
+//! This module contains the list of packages known to cause ABI breakage
 //! when upgraded. The list is community-maintained and versioned.
 //!
 //! Each trigger has a per-package threshold that determines the minimum version
 //! change severity required to fire the trigger. See `docs/CURATED_LIST.md` for
 //! rationale behind each threshold selection.
+//!
+//! The list is read from [`TRIGGER_LIST_FILE`], so it can be updated by a data
+//! package or patched by a distribution without rebuilding anneal, and from
+//! [`FETCHED_TRIGGER_LIST_FILE`], written by [`update_list`]
+//! (`anneal update-triggers`). The
+//! newest of these is used, unless the list compiled into the binary is newer
+//! still (a stale file left behind by an upgrade). The compiled list is also
+//! the fallback when no file can be read.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
+use crate::db::{self, get_db_path};
+use crate::error::Error;
+use crate::net::{self, NetError, NetOptions};
+use crate::output;
+use crate::tr;
 use crate::version::Threshold;
 
-/// Version of the curated trigger list.
+/// Data file the curated list is loaded from.
+pub const TRIGGER_LIST_FILE: &str = "/usr/share/anneal/triggers.list";
+
+/// Where `anneal update-triggers` stores the fetched list.
+pub const FETCHED_TRIGGER_LIST_FILE: &str = "/var/lib/anneal/triggers.list";
+
+/// Default URL the curated list is fetched from.
+pub const DEFAULT_TRIGGER_LIST_URL: &str =
+    "https://raw.githubusercontent.com/MarkWells-Dev/Anneal/main/contrib/triggers.list";
+
+/// Version of the compiled-in trigger list.
 ///
-/// Increment this when adding, removing, or modifying triggers.
+/// Increment this when adding, removing, or modifying triggers, together with
+/// the version in `contrib/triggers.list`.
 pub const TRIGGER_LIST_VERSION: u32 = 4;
 
-/// Curated list of ABI-sensitive packages with per-trigger thresholds.
+/// Curated list in effect, set once by [`load_curated`].
+static CURATED: OnceLock<TriggerList> = OnceLock::new();
+
+/// Compiled-in list of ABI-sensitive packages with per-trigger thresholds.
 ///
 /// Each entry is `(package_name, threshold)`. The threshold determines the
 /// minimum version change severity that triggers a rebuild:
@@ -67,25 +99,375 @@ pub const TRIGGERS: &[(&str, Threshold)] = &[
     ("lua", Threshold::Minor),
 ];
 
+/// Curated triggers that are language runtimes rather than libraries.
+///
+/// Architecture-independent (`arch=any`) packages can still break when a
+/// runtime upgrades (e.g., Python modules installed to a versioned site-packages
+/// directory), so these are exempt from the `skip_any_arch` heuristic.
+pub const RUNTIME_TRIGGERS: &[&str] = &["electron", "lua", "nodejs", "python", "ruby"];
+
+/// Ecosystem tags applied automatically to packages marked by a trigger.
+///
+/// Each entry is `(trigger, tag)`. Triggers not listed get no automatic tag.
+pub const ECOSYSTEM_TAGS: &[(&str, &str)] = &[
+    ("qt5-base", "qt"),
+    ("qt6-base", "qt"),
+    ("glib2", "gtk"),
+    ("gtk2", "gtk"),
+    ("gtk3", "gtk"),
+    ("gtk4", "gtk"),
+    ("electron", "electron"),
+    ("mesa", "graphics"),
+    ("vulkan-icd-loader", "graphics"),
+    ("ffmpeg", "multimedia"),
+    ("pipewire", "multimedia"),
+    ("python", "python"),
+    ("nodejs", "nodejs"),
+    ("ruby", "ruby"),
+    ("lua", "lua"),
+];
+
+/// A versioned list of curated triggers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TriggerList {
+    /// List version.
+    pub version: u32,
+    /// Triggers and their thresholds, in list order.
+    pub triggers: Vec<(String, Threshold)>,
+}
+
+/// Errors reading a trigger list file.
+#[derive(Debug)]
+pub enum TriggerListError {
+    /// The file couldn't be read.
+    Io(PathBuf, io::Error),
+    /// A line couldn't be parsed (1-based line number).
+    Parse(usize, String),
+    /// The file has no `version` line.
+    MissingVersion,
+    /// The list has no triggers.
+    Empty,
+    /// The list couldn't be downloaded.
+    Fetch(NetError),
+}
+
+impl std::fmt::Display for TriggerListError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(path, e) => f.write_str(&tr!(
+                "trigger-list-io",
+                path = path.display().to_string(),
+                error = e.to_string()
+            )),
+            Self::Parse(line, message) => f.write_str(&tr!(
+                "trigger-list-parse",
+                line = *line,
+                message = message.as_str()
+            )),
+            Self::MissingVersion => f.write_str(&tr!("trigger-list-no-version")),
+            Self::Empty => f.write_str(&tr!("trigger-list-empty")),
+            Self::Fetch(e) => f.write_str(&tr!("trigger-list-fetch", error = e.to_string())),
+        }
+    }
+}
+
+impl std::error::Error for TriggerListError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(_, e) => Some(e),
+            Self::Fetch(e) => Some(e),
+            Self::Parse(..) | Self::MissingVersion | Self::Empty => None,
+        }
+    }
+}
+
+impl TriggerList {
+    /// The list compiled into the binary.
+    pub fn builtin() -> Self {
+        Self {
+            version: TRIGGER_LIST_VERSION,
+            triggers: TRIGGERS
+                .iter()
+                .map(|(name, threshold)| ((*name).to_string(), *threshold))
+                .collect(),
+        }
+    }
+
+    /// Parse a trigger list.
+    ///
+    /// The format is line-based: a `version <n>` line, then one
+    /// `<package> <threshold>` line per trigger. Blank lines and `#` comments
+    /// are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for a malformed line, an unknown threshold, a
+    /// duplicate trigger, or a missing version.
+    pub fn parse(content: &str) -> Result<Self, TriggerListError> {
+        let mut version = None;
+        let mut triggers: Vec<(String, Threshold)> = Vec::new();
+
+        for (index, line) in content.lines().enumerate() {
+            let line_no = index + 1;
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [key, value] = fields[..] else {
+                return Err(TriggerListError::Parse(
+                    line_no,
+                    format!("expected '<package> <threshold>', got '{line}'"),
+                ));
+            };
+
+            if key == "version" {
+                let parsed = value.parse().map_err(|_| {
+                    TriggerListError::Parse(line_no, format!("invalid version '{value}'"))
+                })?;
+                version = Some(parsed);
+                continue;
+            }
+
+            let threshold = value.parse().map_err(|()| {
+                TriggerListError::Parse(line_no, format!("unknown threshold '{value}'"))
+            })?;
+            if triggers.iter().any(|(name, _)| name == key) {
+                return Err(TriggerListError::Parse(
+                    line_no,
+                    format!("duplicate trigger '{key}'"),
+                ));
+            }
+            triggers.push((key.to_string(), threshold));
+        }
+
+        Ok(Self {
+            version: version.ok_or(TriggerListError::MissingVersion)?,
+            triggers,
+        })
+    }
+
+    /// Get the threshold for a trigger on this list.
+    pub fn threshold(&self, package: &str) -> Option<Threshold> {
+        self.triggers
+            .iter()
+            .find(|(name, _)| name == package)
+            .map(|(_, threshold)| *threshold)
+    }
+}
+
+/// Read a trigger list file.
+///
+/// Returns `None` if the file doesn't exist.
+///
+/// # Errors
+///
+/// Returns an error if the file exists but can't be read or parsed.
+pub fn read_list(path: &Path) -> Result<Option<TriggerList>, TriggerListError> {
+    match fs::read_to_string(path) {
+        Ok(content) => TriggerList::parse(&content).map(Some),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(TriggerListError::Io(path.to_path_buf(), e)),
+    }
+}
+
+/// Download a trigger list, returning it parsed along with the raw file.
+///
+/// # Errors
+///
+/// Returns an error if the download fails, or if the result isn't a valid,
+/// non-empty list.
+pub fn fetch_list(url: &str, opts: &NetOptions) -> Result<(TriggerList, String), TriggerListError> {
+    let content = net::fetch(url, opts).map_err(TriggerListError::Fetch)?;
+    let list = TriggerList::parse(&content)?;
+    if list.triggers.is_empty() {
+        return Err(TriggerListError::Empty);
+    }
+    Ok((list, content))
+}
+
+/// Use the newest of the given lists as the curated list.
+///
+/// Lists older than the compiled-in one are ignored, and of lists with the
+/// same version the last one wins. Only the first call has
+/// an effect, and it must come before the list is first used.
+pub fn load_curated(lists: impl IntoIterator<Item = TriggerList>) {
+    if let Some(list) = newest_list(lists) {
+        let _ = CURATED.set(list);
+    }
+}
+
+/// Pick the newest list that isn't older than the compiled-in one.
+fn newest_list(lists: impl IntoIterator<Item = TriggerList>) -> Option<TriggerList> {
+    lists
+        .into_iter()
+        .filter(|list| list.version >= TRIGGER_LIST_VERSION)
+        .max_by_key(|list| list.version)
+}
+
+/// The curated list in effect.
+pub fn curated() -> &'static TriggerList {
+    CURATED.get_or_init(TriggerList::builtin)
+}
+
+/// Get where `update-triggers` saves the fetched list: next to the
+/// database in use, [`FETCHED_TRIGGER_LIST_FILE`] for the default one.
+pub fn fetched_list_path() -> PathBuf {
+    let db_path = get_db_path();
+    if db::is_memory_path(&db_path) {
+        PathBuf::from(FETCHED_TRIGGER_LIST_FILE)
+    } else {
+        db_path.with_file_name("triggers.list")
+    }
+}
+
+/// Read the packaged and fetched trigger lists and use the newest as the
+/// curated list (see [`load_curated`]).
+///
+/// A list that can't be read or parsed is skipped with a warning.
+pub fn load_lists() {
+    let paths = [PathBuf::from(TRIGGER_LIST_FILE), fetched_list_path()];
+    let lists = paths
+        .iter()
+        .filter_map(|path| match read_list(path) {
+            Ok(list) => list,
+            Err(e) => {
+                output::warning(&format!("Ignoring trigger list {}: {e}", path.display()));
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    load_curated(lists);
+}
+
+/// Result of [`update_list`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListUpdate {
+    /// The list in use is as new as the published one (its version).
+    UpToDate(u32),
+    /// The published list was saved.
+    Updated {
+        /// Version of the list in use before.
+        from: u32,
+        /// Version of the saved list.
+        to: u32,
+        /// Triggers the new list adds.
+        added: Vec<String>,
+        /// Triggers the new list drops.
+        removed: Vec<String>,
+        /// Triggers whose threshold changed, as `(name, old, new)`.
+        changed: Vec<(String, Threshold, Threshold)>,
+    },
+}
+
+/// Download the published trigger list and save it to
+/// [`fetched_list_path`] if it's newer than the curated list in use
+/// (`update-triggers`).
+///
+/// The list is written to a temporary file first, so a reader never sees
+/// half a list. It takes effect the next time anneal starts.
+///
+/// # Errors
+///
+/// Returns an error if the download fails, the list isn't valid, or it
+/// can't be saved.
+pub fn update_list(url: &str, opts: &NetOptions) -> Result<ListUpdate, Error> {
+    let (list, content) = fetch_list(url, opts)?;
+    let current = curated();
+    if list.version <= current.version {
+        return Ok(ListUpdate::UpToDate(current.version));
+    }
+
+    let path = fetched_list_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp = path.with_extension("list.tmp");
+    fs::write(&temp, content)?;
+    fs::rename(&temp, &path)?;
+
+    Ok(list_changes(current, &list))
+}
+
+/// Compare the list in use with a newer one.
+fn list_changes(current: &TriggerList, list: &TriggerList) -> ListUpdate {
+    ListUpdate::Updated {
+        from: current.version,
+        to: list.version,
+        added: list
+            .triggers
+            .iter()
+            .filter(|(name, _)| current.threshold(name).is_none())
+            .map(|(name, _)| name.clone())
+            .collect(),
+        removed: current
+            .triggers
+            .iter()
+            .filter(|(name, _)| list.threshold(name).is_none())
+            .map(|(name, _)| name.clone())
+            .collect(),
+        changed: list
+            .triggers
+            .iter()
+            .filter_map(|(name, threshold)| {
+                let old = current.threshold(name)?;
+                (old != *threshold).then(|| (name.clone(), old, *threshold))
+            })
+            .collect(),
+    }
+}
+
+/// Returns the ecosystem tag for a trigger, if any.
+pub fn ecosystem_tag(trigger: &str) -> Option<&'static str> {
+    ECOSYSTEM_TAGS
+        .iter()
+        .find(|(name, _)| *name == trigger)
+        .map(|(_, tag)| *tag)
+}
+
 /// Returns whether a package name is in the curated trigger list.
 #[inline]
 pub fn is_curated_trigger(package: &str) -> bool {
-    TRIGGERS.iter().any(|(name, _)| *name == package)
+    get_curated_threshold(package).is_some()
 }
 
 /// Returns the per-trigger threshold for a curated trigger, if it exists.
 #[inline]
 pub fn get_curated_threshold(package: &str) -> Option<Threshold> {
-    TRIGGERS
-        .iter()
-        .find(|(name, _)| *name == package)
-        .map(|(_, threshold)| *threshold)
+    curated().threshold(package)
+}
+
+/// Returns whether a curated trigger is a library (has compiled dependents).
+///
+/// User-defined triggers are not considered libraries, since nothing is
+/// known about what they provide.
+#[inline]
+pub fn is_library_trigger(package: &str) -> bool {
+    is_curated_trigger(package) && !RUNTIME_TRIGGERS.contains(&package)
 }
 
 #[cfg(test)]
+#[allow(clippy::unwrap_used)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn list_changes_between_versions() {
+        let current = TriggerList::parse("version 1\nqt6-base minor\nicu major\n").unwrap();
+        let list = TriggerList::parse("version 2\nqt6-base major\nboost minor\n").unwrap();
+        assert_eq!(
+            list_changes(&current, &list),
+            ListUpdate::Updated {
+                from: 1,
+                to: 2,
+                added: vec!["boost".to_string()],
+                removed: vec!["icu".to_string()],
+                changed: vec![("qt6-base".to_string(), Threshold::Minor, Threshold::Major)],
+            }
+        );
+    }
+
     #[test]
     fn trigger_list_is_sorted() {
         // Triggers should be grouped by category, not globally sorted
@@ -121,6 +503,39 @@ mod tests {
         assert_eq!(get_curated_threshold("not-a-trigger"), None);
     }
 
+    #[test]
+    fn runtime_triggers_are_curated() {
+        for name in RUNTIME_TRIGGERS {
+            assert!(is_curated_trigger(name), "unknown runtime trigger: {name}");
+        }
+    }
+
+    #[test]
+    fn library_trigger_classification() {
+        assert!(is_library_trigger("qt6-base"));
+        assert!(is_library_trigger("openssl"));
+        assert!(!is_library_trigger("python"));
+        assert!(!is_library_trigger("electron"));
+        assert!(!is_library_trigger("not-a-trigger"));
+    }
+
+    #[test]
+    fn ecosystem_tags_are_curated() {
+        for (name, _) in ECOSYSTEM_TAGS {
+            assert!(
+                is_curated_trigger(name),
+                "unknown ecosystem trigger: {name}"
+            );
+        }
+    }
+
+    #[test]
+    fn ecosystem_tag_lookup() {
+        assert_eq!(ecosystem_tag("qt6-base"), Some("qt"));
+        assert_eq!(ecosystem_tag("python"), Some("python"));
+        assert_eq!(ecosystem_tag("openssl"), None);
+    }
+
     #[test]
     fn no_duplicate_triggers() {
         let mut seen = std::collections::HashSet::new();
@@ -129,6 +544,74 @@ mod tests {
         }
     }
 
+    #[test]
+    fn shipped_list_matches_builtin() {
+        let shipped = TriggerList::parse(include_str!("../contrib/triggers.list"));
+        assert_eq!(shipped.ok(), Some(TriggerList::builtin()));
+    }
+
+    #[test]
+    fn parse_trigger_list() {
+        let list =
+            TriggerList::parse("# comment\n\nversion 7\nqt6-base minor  # Qt\nabseil-cpp Always\n");
+        assert_eq!(
+            list.ok(),
+            Some(TriggerList {
+                version: 7,
+                triggers: vec![
+                    ("qt6-base".to_string(), Threshold::Minor),
+                    ("abseil-cpp".to_string(), Threshold::Always),
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn parse_trigger_list_errors() {
+        assert!(matches!(
+            TriggerList::parse("qt6-base minor\n"),
+            Err(TriggerListError::MissingVersion)
+        ));
+        assert!(matches!(
+            TriggerList::parse("version 1\nqt6-base sometimes\n"),
+            Err(TriggerListError::Parse(2, _))
+        ));
+        assert!(matches!(
+            TriggerList::parse("version 1\nqt6-base\n"),
+            Err(TriggerListError::Parse(2, _))
+        ));
+        assert!(matches!(
+            TriggerList::parse("version 1\ngtk4 minor\ngtk4 major\n"),
+            Err(TriggerListError::Parse(3, _))
+        ));
+        assert!(matches!(
+            TriggerList::parse("version x\n"),
+            Err(TriggerListError::Parse(1, _))
+        ));
+    }
+
+    #[test]
+    fn newest_list_wins() {
+        let list = |version, name: &str| TriggerList {
+            version,
+            triggers: vec![(name.to_string(), Threshold::Minor)],
+        };
+
+        let stale = list(TRIGGER_LIST_VERSION - 1, "stale");
+        assert_eq!(newest_list([stale.clone()]), None);
+
+        let packaged = list(TRIGGER_LIST_VERSION, "packaged");
+        let fetched = list(TRIGGER_LIST_VERSION + 1, "fetched");
+        assert_eq!(
+            newest_list([fetched.clone(), packaged.clone(), stale]),
+            Some(fetched)
+        );
+
+        // Same version: the later list wins
+        let same = list(TRIGGER_LIST_VERSION, "same");
+        assert_eq!(newest_list([packaged, same.clone()]), Some(same));
+    }
+
     #[test]
     fn no_empty_triggers() {
         for (name, _) in TRIGGERS {
@@ -0,0 +1,297 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2026 Mark Wells Dev
+
+//! Post-build validation of rebuilt packages.
+//!
+//! A clean exit from the AUR helper only means the package built and
+//! installed. A package can still be broken afterwards: a library that moved
+//! between sonames, files that didn't make it to disk, or a PKGBUILD that
+//! silently picked up the wrong dependency. Validation runs three checks:
+//!
+//! - **namcap** on the built package file, if it can be found (errors only)
+//! - **files**: `pacman -Qkk` on the installed files
+//! - **sonames**: `ldd` on installed ELF files, for NEEDED libraries that
+//!   don't resolve
+//!
+//! Checks whose tool isn't installed are skipped.
+
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
+
+use crate::pacman::{self, PacmanError};
+
+/// ELF file magic number.
+const ELF_MAGIC: &[u8; 4] = b"\x7fELF";
+
+/// A validation check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    /// namcap on the built package file.
+    Namcap,
+    /// `pacman -Qkk` on the installed files.
+    Files,
+    /// Unresolved NEEDED sonames.
+    Sonames,
+}
+
+impl Check {
+    /// Name shown in reports.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Namcap => "namcap",
+            Self::Files => "files",
+            Self::Sonames => "sonames",
+        }
+    }
+}
+
+impl std::fmt::Display for Check {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A problem found in a rebuilt package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// Package the problem was found in.
+    pub package: String,
+    /// Check that found it.
+    pub check: Check,
+    /// Description of the problem.
+    pub message: String,
+}
+
+/// Validate rebuilt packages.
+///
+/// `package_dir` is where the helper left the built package files (namcap is
+/// skipped without it). Problems are returned in package order.
+///
+/// # Errors
+///
+/// Returns an error if pacman can't be run.
+pub fn validate(
+    packages: &[&str],
+    package_dir: Option<&Path>,
+) -> Result<Vec<Problem>, PacmanError> {
+    let mut problems = Vec::new();
+
+    for pkg in packages {
+        if let Some(file) = package_dir.and_then(|dir| find_package_file(dir, pkg)) {
+            problems.extend(namcap(pkg, &file));
+        }
+    }
+
+    problems.extend(
+        pacman::check_files(packages)?
+            .into_iter()
+            .map(|(package, message)| Problem {
+                package,
+                check: Check::Files,
+                message,
+            }),
+    );
+
+    problems.extend(missing_sonames(&pacman::package_files(packages)?));
+
+    // Stable sort keeps check order within a package
+    problems.sort_by(|a, b| a.package.cmp(&b.package));
+    Ok(problems)
+}
+
+/// Run namcap on a package file, returning its errors.
+///
+/// Returns nothing if namcap isn't installed.
+fn namcap(package: &str, file: &Path) -> Vec<Problem> {
+    let Ok(output) = Command::new("namcap")
+        .arg(file)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+    else {
+        return Vec::new();
+    };
+
+    parse_namcap(&String::from_utf8_lossy(&output.stdout))
+        .into_iter()
+        .map(|message| Problem {
+            package: package.to_string(),
+            check: Check::Namcap,
+            message,
+        })
+        .collect()
+}
+
+/// Parse namcap output, keeping errors (`<pkg> E: <message>`).
+///
+/// Warnings are mostly packaging style and are left out.
+fn parse_namcap(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            line.split_once(" E: ")
+                .map(|(_, msg)| msg.trim().to_string())
+        })
+        .collect()
+}
+
+/// Find unresolved sonames in the ELF files of installed packages.
+///
+/// Each missing soname is reported once per package, with the first file
+/// that needs it.
+fn missing_sonames(files: &[(String, String)]) -> Vec<Problem> {
+    let mut seen: BTreeSet<(&str, String)> = BTreeSet::new();
+    let mut problems = Vec::new();
+
+    for (package, path) in files {
+        if path.ends_with('/') || !is_elf(Path::new(path)) {
+            continue;
+        }
+        let Ok(output) = Command::new("ldd")
+            .arg(path)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .output()
+        else {
+            // Without ldd there's nothing to check
+            return problems;
+        };
+
+        for soname in parse_ldd(&String::from_utf8_lossy(&output.stdout)) {
+            if seen.insert((package.as_str(), soname.clone())) {
+                problems.push(Problem {
+                    package: package.clone(),
+                    check: Check::Sonames,
+                    message: format!("{soname} not found (needed by {path})"),
+                });
+            }
+        }
+    }
+
+    problems
+}
+
+/// Parse ldd output for libraries that don't resolve
+/// (`libfoo.so.1 => not found`).
+fn parse_ldd(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (soname, target) = line.trim().split_once(" => ")?;
+            (target.trim() == "not found").then(|| soname.to_string())
+        })
+        .collect()
+}
+
+/// Check whether a file is an ELF object.
+fn is_elf(path: &Path) -> bool {
+    let mut magic = [0u8; 4];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok()
+        && &magic == ELF_MAGIC
+}
+
+/// Find the most recently built package file for a package in a directory.
+fn find_package_file(dir: &Path, package: &str) -> Option<PathBuf> {
+    let entries = fs::read_dir(dir).ok()?;
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| is_package_file(name, package))
+        })
+        .max_by_key(|entry| modified(entry).unwrap_or(SystemTime::UNIX_EPOCH))
+        .map(|entry| entry.path())
+}
+
+/// Modification time of a directory entry.
+fn modified(entry: &fs::DirEntry) -> io::Result<SystemTime> {
+    entry.metadata()?.modified()
+}
+
+/// Check whether a file name is a package file for `package`.
+fn is_package_file(file_name: &str, package: &str) -> bool {
+    pacman::package_file_version(file_name, package).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn namcap_errors_only() {
+        let output = "\
+qt6gtk2 E: Dependency gtk2 detected and not included (libraries ['usr/lib/libgtk-x11-2.0.so.0'] needed in files ['usr/lib/qt6/plugins/styles/libqt6gtk2-style.so'])
+qt6gtk2 W: Dependency included, but may not be needed ('cmake')
+";
+        assert_eq!(
+            parse_namcap(output),
+            vec![
+                "Dependency gtk2 detected and not included (libraries ['usr/lib/libgtk-x11-2.0.so.0'] needed in files ['usr/lib/qt6/plugins/styles/libqt6gtk2-style.so'])"
+            ]
+        );
+    }
+
+    #[test]
+    fn ldd_not_found() {
+        let output = "\
+\tlinux-vdso.so.1 (0x00007ffd)
+\tlibQt6Widgets.so.6 => not found
+\tlibc.so.6 => /usr/lib/libc.so.6 (0x00007f)
+";
+        assert_eq!(parse_ldd(output), vec!["libQt6Widgets.so.6"]);
+        assert!(parse_ldd("\tnot a dynamic executable\n").is_empty());
+    }
+
+    #[test]
+    fn package_file_names() {
+        assert!(is_package_file("foo-1.0-1-x86_64.pkg.tar.zst", "foo"));
+        assert!(is_package_file("foo-1:1.0-2-any.pkg.tar.xz", "foo"));
+        assert!(is_package_file(
+            "foo-git-r12.abc-1-x86_64.pkg.tar.zst",
+            "foo-git"
+        ));
+        assert!(!is_package_file(
+            "foo-git-r12.abc-1-x86_64.pkg.tar.zst",
+            "foo"
+        ));
+        assert!(!is_package_file("foo-1.0-1-x86_64.pkg.tar.zst.sig", "foo"));
+        assert!(!is_package_file("foo-1.0.tar.gz", "foo"));
+    }
+
+    #[test]
+    #[allow(clippy::unwrap_used)]
+    fn finds_package_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(dir.path().join("foo-1.0-1-x86_64.pkg.tar.zst"), "").unwrap();
+        fs::write(dir.path().join("foo-1.0-1-x86_64.pkg.tar.zst.sig"), "").unwrap();
+        fs::write(dir.path().join("foo-debug-1.0-1-x86_64.pkg.tar.zst"), "").unwrap();
+
+        assert_eq!(
+            find_package_file(dir.path(), "foo"),
+            Some(dir.path().join("foo-1.0-1-x86_64.pkg.tar.zst"))
+        );
+        assert_eq!(find_package_file(dir.path(), "bar"), None);
+    }
+
+    #[test]
+    #[allow(clippy::unwrap_used)]
+    fn elf_detection() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let elf = dir.path().join("elf");
+        let text = dir.path().join("text");
+        fs::write(&elf, b"\x7fELF\x02\x01\x01").unwrap();
+        fs::write(&text, "#!/bin/sh\n").unwrap();
+
+        assert!(is_elf(&elf));
+        assert!(!is_elf(&text));
+        assert!(!is_elf(&dir.path().join("missing")));
+    }
+}
@@ -15,6 +15,8 @@ use std::cmp::Ordering;
 
 /// Threshold for determining when a version change should trigger a rebuild.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum Threshold {
     /// Trigger only on major version changes (1.x.x -> 2.x.x)
     Major,
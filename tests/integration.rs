@@ -5,10 +5,38 @@
 
 #![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic)]
 
+use std::path::Path;
 use std::process::Command;
 
 fn anneal() -> Command {
-    Command::new(env!("CARGO_BIN_EXE_anneal"))
+    let mut command = Command::new(env!("CARGO_BIN_EXE_anneal"));
+    // Keep rebuild tests out of /var/log
+    command.env("ANNEAL_BUILD_LOG_DIR", "");
+    command
+}
+
+/// Create a database as the first release left it: only the queue and the
+/// trigger events, no schema version. `foo` is queued, marked by `qt6-base`
+/// in 2020.
+fn old_database(path: &Path) {
+    let conn = rusqlite::Connection::open(path).expect("failed to create database");
+    conn.execute_batch(
+        "CREATE TABLE queue (
+             package TEXT PRIMARY KEY,
+             first_marked_at TEXT NOT NULL
+         );
+         CREATE TABLE trigger_events (
+             id INTEGER PRIMARY KEY,
+             package TEXT NOT NULL,
+             trigger_package TEXT,
+             trigger_version TEXT,
+             marked_at TEXT NOT NULL
+         );
+         INSERT INTO queue VALUES ('foo', '2020-01-01T00:00:00.000Z');
+         INSERT INTO trigger_events (package, trigger_package, marked_at)
+         VALUES ('foo', 'qt6-base', '2020-01-01T00:00:00.000Z');",
+    )
+    .expect("failed to create old schema");
 }
 
 mod help {
@@ -69,7 +97,51 @@ mod triggers {
         // Quiet mode should still output the trigger names
         assert!(stdout.contains("qt6-base"));
         // But not the header
-        assert!(!stdout.contains("Curated triggers"));
+        assert!(!stdout.contains("curated list"));
+    }
+
+    #[test]
+    fn fetched_list_is_used_when_newer() {
+        // The fetched list lives next to the database
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        std::fs::write(
+            temp.path().join("triggers.list"),
+            "version 9999\nqt6-base minor\nmy-lib major\n",
+        )
+        .expect("failed to write list");
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", temp.path().join("anneal.db"))
+            .args(["--json", "triggers"])
+            .output()
+            .expect("failed to run");
+        assert!(output.status.success());
+
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("invalid JSON");
+        assert_eq!(json["version"], 9999);
+        let names: Vec<&str> = json["triggers"]
+            .as_array()
+            .expect("triggers array")
+            .iter()
+            .filter_map(|t| t["name"].as_str())
+            .collect();
+        assert!(names.contains(&"my-lib"), "triggers: {names:?}");
+        assert!(!names.contains(&"gtk4"), "triggers: {names:?}");
+    }
+
+    #[test]
+    fn update_offline() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", temp.path().join("anneal.db"))
+            .args(["--offline", "update-triggers"])
+            .output()
+            .expect("failed to run");
+
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("offline mode"), "stderr: {stderr}");
+        assert!(!temp.path().join("triggers.list").exists());
     }
 }
 
@@ -85,6 +157,58 @@ mod config {
         assert!(stdout.contains("version_threshold"));
         assert!(stdout.contains("retention_days"));
     }
+
+    #[test]
+    fn user_config() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let dir = temp.path().join("anneal");
+        std::fs::create_dir_all(&dir).expect("failed to create config dir");
+        std::fs::write(dir.join("config.conf"), "helper = my-helper -S\n")
+            .expect("failed to write config");
+
+        let output = anneal()
+            .env("XDG_CONFIG_HOME", temp.path())
+            .args(["config", "get", "helper"])
+            .output()
+            .expect("failed to run");
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "my-helper -S\n");
+    }
+
+    #[test]
+    fn env_override() {
+        let output = anneal()
+            .env("ANNEAL_RETENTION_DAYS", "7")
+            .args(["config", "get", "retention_days"])
+            .output()
+            .expect("failed to run");
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "7\n");
+
+        let output = anneal()
+            .env("ANNEAL_SKIP_ANY_ARCH", "sometimes")
+            .arg("config")
+            .output()
+            .expect("failed to run");
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("ANNEAL_SKIP_ANY_ARCH"), "stderr: {stderr}");
+    }
+
+    #[test]
+    fn get_unknown_key() {
+        let output = anneal()
+            .args(["config", "get", "nonexistent"])
+            .output()
+            .expect("failed to run");
+        assert!(!output.status.success());
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("Unknown config key 'nonexistent'"),
+            "stderr: {stderr}"
+        );
+    }
 }
 
 mod root_required {
@@ -140,6 +264,30 @@ mod root_required {
         assert!(stderr.contains("Permission denied"));
     }
 
+    #[test]
+    fn writable_database_allows_mark() {
+        // A database the user can write (e.g., via the anneal group) is enough,
+        // root isn't required
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .args(["mark", "--allow-missing", "test-pkg"])
+            .output()
+            .expect("failed to run");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "stderr: {stderr}");
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .arg("list")
+            .output()
+            .expect("failed to run");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("test-pkg"), "stdout: {stdout}");
+    }
+
     #[test]
     fn trigger_requires_root() {
         if unsafe { libc::getuid() } == 0 {
@@ -160,6 +308,114 @@ mod root_required {
 mod readonly_commands {
     use super::*;
 
+    #[test]
+    fn list_in_memory_database() {
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", ":memory:")
+            .arg("list")
+            .output()
+            .expect("failed to run");
+
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("No packages in queue"));
+    }
+
+    #[test]
+    fn color_choice() {
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", ":memory:")
+            .env("NO_COLOR", "1")
+            .args(["--color", "always", "list"])
+            .output()
+            .expect("failed to run");
+        assert!(String::from_utf8_lossy(&output.stdout).contains("\x1b["));
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", ":memory:")
+            .args(["--color", "never", "list"])
+            .output()
+            .expect("failed to run");
+        assert!(!String::from_utf8_lossy(&output.stdout).contains("\x1b["));
+    }
+
+    #[test]
+    fn db_info_and_check() {
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", ":memory:")
+            .args(["--json", "db"])
+            .output()
+            .expect("failed to run");
+        assert!(output.status.success());
+        let json: serde_json::Value =
+            serde_json::from_slice(&output.stdout).expect("stdout is JSON");
+        assert_eq!(json["tables"]["queue"], 0);
+        assert!(json["schema_version"].as_u64().is_some());
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", ":memory:")
+            .args(["db", "check"])
+            .output()
+            .expect("failed to run");
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("Database is intact"));
+    }
+
+    #[test]
+    fn count_empty_queue() {
+        for db_path in [":memory:", "/non/existent/path/db.sqlite"] {
+            let output = anneal()
+                .env("ANNEAL_DB_PATH", db_path)
+                .arg("count")
+                .output()
+                .expect("failed to run");
+
+            assert_eq!(output.status.code(), Some(2));
+            assert_eq!(String::from_utf8_lossy(&output.stdout), "0\n");
+
+            let output = anneal()
+                .env("ANNEAL_DB_PATH", db_path)
+                .args(["--quiet", "status", "--waybar"])
+                .output()
+                .expect("failed to run");
+            assert!(output.status.success());
+            assert_eq!(
+                String::from_utf8_lossy(&output.stdout),
+                "{\"class\":\"empty\",\"text\":\"0\",\"tooltip\":\"Queue empty\"}\n"
+            );
+        }
+    }
+
+    #[test]
+    fn list_check_signals_queue_state() {
+        for db_path in [":memory:", "/non/existent/path/db.sqlite"] {
+            let output = anneal()
+                .env("ANNEAL_DB_PATH", db_path)
+                .args(["-q", "list", "--check"])
+                .output()
+                .expect("failed to run");
+            assert_eq!(output.status.code(), Some(0));
+        }
+
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("test.db");
+        let run = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .args(args)
+                .output()
+                .expect("failed to run")
+        };
+        assert!(run(&["mark", "--allow-missing", "foo"]).status.success());
+        let output = run(&["list", "--check"]);
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&output.stdout).contains("foo"));
+        assert_eq!(
+            run(&["list", "--check", "--tag", "none"]).status.code(),
+            Some(0)
+        );
+        assert!(run(&["list"]).status.success());
+    }
+
     #[test]
     fn list_without_database() {
         // When no database exists, list should give a helpful error
@@ -211,14 +467,9 @@ mod readonly_commands {
             .output()
             .expect("failed to run");
 
-        // Should either succeed with empty output or fail with no database
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        if !output.status.success() {
-            assert!(
-                stderr.contains("No database found") || stderr.contains("unable to open"),
-                "unexpected error: {stderr}"
-            );
-        }
+        // Nothing is queued without a database
+        assert_eq!(output.status.code(), Some(2));
+        assert!(output.stdout.is_empty());
     }
 
     #[test]
@@ -265,16 +516,1195 @@ mod readonly_commands {
         let stderr = String::from_utf8_lossy(&output.stderr);
         let stdout = String::from_utf8_lossy(&output.stdout);
 
-        // Cleanup permissions so TempDir can delete itself
-        let _ = fs::set_permissions(&db_dir, fs::Permissions::from_mode(0o755));
+        // Cleanup permissions so TempDir can delete itself
+        let _ = fs::set_permissions(&db_dir, fs::Permissions::from_mode(0o755));
+
+        assert!(
+            output.status.success(),
+            "list should succeed on readonly WAL database. stderr: {stderr}"
+        );
+        assert!(
+            stdout.contains("test-pkg"),
+            "should find the package. stdout: {stdout}"
+        );
+    }
+}
+
+mod json_output {
+    use super::*;
+
+    fn parse(output: &std::process::Output) -> serde_json::Value {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        serde_json::from_str(&stdout).unwrap_or_else(|e| panic!("invalid JSON ({e}): {stdout}"))
+    }
+
+    #[test]
+    fn list_and_query() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .args([
+                "mark",
+                "--allow-missing",
+                "--trigger",
+                "qt6-base",
+                "qt6gtk2",
+            ])
+            .output()
+            .expect("failed to run");
+        assert!(output.status.success());
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .args(["--json", "list"])
+            .output()
+            .expect("failed to run");
+        let list = parse(&output);
+        assert_eq!(list[0]["package"], "qt6gtk2");
+        assert_eq!(list[0]["trigger"], "qt6-base");
+        assert_eq!(list[0]["tags"], serde_json::json!(["qt"]));
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .args(["query", "--json", "qt6gtk2", "other"])
+            .output()
+            .expect("failed to run");
+        assert_eq!(
+            parse(&output),
+            serde_json::json!({"qt6gtk2": true, "other": false})
+        );
+        assert_eq!(output.status.code(), Some(3));
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .args(["query", "qt6gtk2"])
+            .output()
+            .expect("failed to run");
+        assert_eq!(output.status.code(), Some(0));
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .args(["query", "other"])
+            .output()
+            .expect("failed to run");
+        assert_eq!(output.status.code(), Some(2));
+        assert!(output.stdout.is_empty());
+    }
+
+    #[test]
+    fn empty_list() {
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", ":memory:")
+            .args(["--json", "list"])
+            .output()
+            .expect("failed to run");
+        assert_eq!(parse(&output), serde_json::json!([]));
+    }
+
+    #[test]
+    fn config_and_triggers() {
+        let output = anneal()
+            .args(["--json", "config"])
+            .output()
+            .expect("failed to run");
+        assert!(parse(&output)["retention_days"].is_number());
+
+        let output = anneal()
+            .args(["--json", "triggers"])
+            .output()
+            .expect("failed to run");
+        assert!(
+            parse(&output)["triggers"]
+                .as_array()
+                .is_some_and(|t| !t.is_empty())
+        );
+    }
+
+    #[test]
+    fn trigger_dry_run() {
+        let output = anneal()
+            .env(
+                "ANNEAL_FIXTURE_DIR",
+                format!("{}/tests/fixtures/basic", env!("CARGO_MANIFEST_DIR")),
+            )
+            .args(["--json", "trigger", "--dry-run", "qt6-base"])
+            .output()
+            .expect("failed to run");
+        let result = parse(&output);
+        assert_eq!(result["dry_run"], true);
+        assert_eq!(result["marked"][0]["package"], "qt6gtk2");
+    }
+
+    #[test]
+    fn rebuild_requires_force() {
+        let output = anneal()
+            .args(["--json", "rebuild"])
+            .output()
+            .expect("failed to run");
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("--json"), "stderr: {stderr}");
+    }
+}
+
+mod porcelain_output {
+    use super::*;
+
+    #[test]
+    fn list_query_and_history() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+        let run = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .args(args)
+                .output()
+                .expect("failed to run")
+        };
+        run(&[
+            "mark",
+            "--allow-missing",
+            "--trigger",
+            "qt6-base",
+            "--trigger-version",
+            "6.7.0-1",
+            "qt6gtk2",
+        ]);
+        run(&["mark", "--allow-missing", "python-foo"]);
+
+        let output = run(&["--porcelain", "list", "--sort", "name"]);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<Vec<&str>> = stdout.lines().map(|l| l.split(' ').collect()).collect();
+        assert_eq!(lines.len(), 2, "stdout: {stdout}");
+        assert_eq!(lines[0][..3], ["python-foo", "-", "-"]);
+        assert_eq!(lines[1][..3], ["qt6gtk2", "qt6-base", "6.7.0-1"]);
+        assert!(lines[1][3].ends_with('Z'), "stdout: {stdout}");
+
+        let output = run(&["--porcelain", "query", "qt6gtk2", "other"]);
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "qt6gtk2 queued\nother absent\n"
+        );
+        assert_eq!(output.status.code(), Some(3));
+
+        let output = run(&["--porcelain", "history", "qt6gtk2"]);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.ends_with("Z qt6-base 6.7.0-1\n"), "stdout: {stdout}");
+        let output = run(&["--porcelain", "history", "--rebuilds", "qt6gtk2"]);
+        assert!(output.stdout.is_empty());
+        assert_eq!(output.status.code(), Some(2));
+    }
+
+    #[test]
+    fn unsupported_commands() {
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", ":memory:")
+            .args(["--porcelain", "stats"])
+            .output()
+            .expect("failed to run");
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("--porcelain"), "stderr: {stderr}");
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", ":memory:")
+            .args(["--porcelain", "list", "--format", "{package}"])
+            .output()
+            .expect("failed to run");
+        assert!(!output.status.success());
+    }
+}
+
+mod tags {
+    use super::*;
+
+    #[test]
+    fn list_by_tag() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+
+        for args in [
+            &["mark", "--allow-missing", "--tag", "gaming", "game-pkg"][..],
+            &["mark", "--allow-missing", "other-pkg"][..],
+        ] {
+            let output = anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .args(args)
+                .output()
+                .expect("failed to run");
+            assert!(output.status.success());
+        }
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .args(["list", "--tag", "gaming"])
+            .output()
+            .expect("failed to run");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("game-pkg"), "stdout: {stdout}");
+        assert!(!stdout.contains("other-pkg"), "stdout: {stdout}");
+    }
+}
+
+mod mark_reason {
+    use super::*;
+
+    #[test]
+    fn shown_by_list_verbose_and_why() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .args([
+                "mark",
+                "--allow-missing",
+                "--reason",
+                "rebuilt against icu 76",
+                "qt6gtk2",
+            ])
+            .output()
+            .expect("failed to run");
+        assert!(output.status.success());
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .args(["list", "--verbose"])
+            .output()
+            .expect("failed to run");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("qt6gtk2"), "stdout: {stdout}");
+        assert!(
+            stdout.contains("  reason: rebuilt against icu 76"),
+            "stdout: {stdout}"
+        );
+
+        // Plain list stays one line per package
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .arg("list")
+            .output()
+            .expect("failed to run");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(!stdout.contains("reason"), "stdout: {stdout}");
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .args(["why", "qt6gtk2", "--json"])
+            .output()
+            .expect("failed to run");
+        let why: serde_json::Value = serde_json::from_slice(&output.stdout).expect("invalid JSON");
+        assert_eq!(why["reason"], "rebuilt against icu 76");
+    }
+}
+
+mod priorities {
+    use super::*;
+
+    #[test]
+    fn listed_before_age() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+        let run = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .args(args)
+                .output()
+                .expect("failed to run")
+        };
+        assert!(run(&["mark", "--allow-missing", "old"]).status.success());
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert!(
+            run(&["mark", "--allow-missing", "--priority", "high", "new"])
+                .status
+                .success()
+        );
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert!(run(&["mark", "--allow-missing", "newest"]).status.success());
+
+        let output = run(&["list", "--format", "{package} {priority}"]);
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "new high\nold normal\nnewest normal\n"
+        );
+
+        assert!(run(&["prioritize", "low", "old"]).status.success());
+        assert_eq!(
+            run(&["prioritize", "high", "missing"]).status.code(),
+            Some(2)
+        );
+        let output = run(&["list", "--format", "{package}"]);
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "new\nnewest\nold\n"
+        );
+    }
+}
+
+mod clean_command {
+    use super::*;
+
+    #[test]
+    fn clean_drops_entries_that_need_no_rebuild() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+        let info = temp.path().join("fixture/info");
+        std::fs::create_dir_all(&info).expect("failed to create fixture");
+        let install = |pkg: &str, version: &str| {
+            std::fs::write(
+                info.join(pkg),
+                format!(
+                    "Name            : {pkg}\nVersion         : {version}\nArchitecture    : x86_64\n"
+                ),
+            )
+            .expect("failed to write fixture");
+        };
+        let run = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .env("ANNEAL_FIXTURE_DIR", temp.path().join("fixture"))
+                .args(args)
+                .output()
+                .expect("failed to run")
+        };
+        install("foo", "1.0-1");
+        install("bar", "2.0-1");
+        assert!(run(&["mark", "foo", "bar"]).status.success());
+        assert!(run(&["mark", "--allow-missing", "gone"]).status.success());
+        install("foo", "1.1-1");
+
+        let output = run(&["--json", "clean", "--dry-run"]);
+        let value: serde_json::Value =
+            serde_json::from_slice(&output.stdout).expect("invalid JSON");
+        assert_eq!(value["uninstalled"], serde_json::json!(["gone"]));
+        assert_eq!(value["stale"], serde_json::json!(["foo"]));
+        assert_eq!(run(&["count"]).stdout, b"3\n");
+
+        let output = run(&["clean"]);
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("Unmarked gone (no longer installed)"),
+            "{stdout}"
+        );
+        let output = run(&["list", "--format", "{package}"]);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "bar\n");
+
+        let output = run(&["clean"]);
+        assert!(String::from_utf8_lossy(&output.stdout).contains("Nothing to clean"));
+    }
+
+    #[test]
+    fn clean_old_database() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+        old_database(&db_path);
+        let info = temp.path().join("fixture/info");
+        std::fs::create_dir_all(&info).expect("failed to create fixture");
+        std::fs::write(
+            info.join("foo"),
+            "Name            : foo\nVersion         : 1.0-1\nArchitecture    : x86_64\n",
+        )
+        .expect("failed to write fixture");
+        let run = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .env("ANNEAL_FIXTURE_DIR", temp.path().join("fixture"))
+                .args(args)
+                .output()
+                .expect("failed to run")
+        };
+
+        // Read-only, so the schema isn't upgraded yet
+        let output = run(&["clean", "--dry-run"]);
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("Would prune 1 history record(s)"),
+            "{stdout}"
+        );
+
+        let output = run(&["clean"]);
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(run(&["count"]).stdout, b"1\n");
+    }
+}
+
+mod expiry {
+    use super::*;
+
+    #[test]
+    fn marked_again_keeps_entry() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+        let run = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .args(args)
+                .output()
+                .expect("failed to run")
+        };
+        assert!(
+            run(&["mark", "--allow-missing", "--expires", "30d", "foo"])
+                .status
+                .success()
+        );
+        assert_eq!(
+            run(&["mark", "--allow-missing", "--expires", "soon", "foo"])
+                .status
+                .code(),
+            Some(2)
+        );
+
+        let output = run(&["why", "foo"]);
+        assert!(
+            String::from_utf8_lossy(&output.stdout).contains("  expires: "),
+            "{output:?}"
+        );
+        let output = run(&["list", "--format", "{expires_at}"]);
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout).len(),
+            "2026-01-15T10:32:01.000Z\n".len()
+        );
+
+        assert!(run(&["mark", "--allow-missing", "foo"]).status.success());
+        let output = run(&["list", "--format", "[{expires_at}]"]);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "[]\n");
+    }
+}
+
+mod list_filters {
+    use super::*;
+
+    #[test]
+    fn trigger_filter_with_format() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+
+        for args in [
+            &[
+                "mark",
+                "--allow-missing",
+                "--trigger",
+                "qt6-base",
+                "--trigger-version",
+                "6.7.0",
+                "--tag",
+                "qt",
+                "qt6ct",
+            ][..],
+            &["mark", "--allow-missing", "--trigger", "gtk4", "gtk-app"][..],
+        ] {
+            let output = anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .args(args)
+                .output()
+                .expect("failed to run");
+            assert!(output.status.success());
+        }
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .args([
+                "list",
+                "--trigger",
+                "qt6-base",
+                "--since",
+                "1d",
+                "--format",
+                "{package}\\t{trigger} {trigger_version}\\t{tags}",
+            ])
+            .output()
+            .expect("failed to run");
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "qt6ct\tqt6-base 6.7.0\tqt\n"
+        );
+
+        // Nothing was marked before yesterday
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .args(["list", "--before", "1d", "--format", "{package}"])
+            .output()
+            .expect("failed to run");
+        assert!(output.status.success());
+        assert!(output.stdout.is_empty());
+    }
+
+    #[test]
+    fn long_table() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+        let run = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .args(args)
+                .output()
+                .expect("failed to run")
+        };
+        let marks: [&[&str]; 2] = [
+            &[
+                "mark",
+                "--allow-missing",
+                "--trigger",
+                "qt6-base",
+                "--trigger-version",
+                "6.7.0-1",
+                "qt6gtk2",
+            ],
+            &["mark", "--allow-missing", "foo"],
+        ];
+        for args in marks {
+            assert!(run(args).status.success());
+        }
+
+        let output = run(&["list", "--long", "--sort", "name"]);
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(lines.len(), 3, "stdout: {stdout}");
+        assert!(lines[0].starts_with("PACKAGE  TRIGGER   VERSION  MARKED"));
+        assert!(lines[1].starts_with("foo      external  -        20"));
+        assert!(lines[2].starts_with("qt6gtk2  qt6-base  6.7.0-1  20"));
+        assert!(lines[2].ends_with("  0d"), "stdout: {stdout}");
+
+        assert!(!run(&["list", "--long", "--verbose"]).status.success());
+    }
+
+    #[test]
+    fn unknown_format_field() {
+        let output = anneal()
+            .args(["list", "--format", "{size}"])
+            .output()
+            .expect("failed to run");
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("unknown field '{size}'"),
+            "stderr: {stderr}"
+        );
+    }
+}
+
+mod local_sources {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn rebuild_runs_makepkg_in_source_dir() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+        let src = temp.path().join("my-pkg");
+        let bin = temp.path().join("bin");
+        std::fs::create_dir(&src).expect("create source dir");
+        std::fs::create_dir(&bin).expect("create bin dir");
+        std::fs::write(src.join("PKGBUILD"), "pkgname=my-pkg\n").expect("write PKGBUILD");
+
+        // Fake makepkg recording where it ran
+        let makepkg = bin.join("makepkg");
+        std::fs::write(&makepkg, "#!/bin/sh\npwd > \"$MAKEPKG_LOG\"\n").expect("write makepkg");
+        std::fs::set_permissions(&makepkg, std::fs::Permissions::from_mode(0o755))
+            .expect("chmod makepkg");
+
+        let run = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .env("PATH", format!("{}:/usr/bin:/bin", bin.display()))
+                .env("MAKEPKG_LOG", temp.path().join("makepkg.log"))
+                .args(args)
+                .output()
+                .expect("failed to run")
+        };
+
+        let src_arg = src.to_string_lossy().to_string();
+        assert!(run(&["source", "add", "my-pkg", &src_arg]).status.success());
+        assert!(run(&["mark", "--allow-missing", "my-pkg"]).status.success());
+
+        let output = run(&["source", "list"]);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("my-pkg"), "stdout: {stdout}");
+
+        let output = run(&["rebuild", "-f"]);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "stderr: {stderr}");
+        assert!(
+            String::from_utf8_lossy(&output.stdout).contains(":: Rebuild summary:"),
+            "stdout: {}",
+            String::from_utf8_lossy(&output.stdout)
+        );
+        assert!(stderr.contains("  Rebuilt       1\n"), "stderr: {stderr}");
+        assert!(stderr.contains("  Still queued  0\n"), "stderr: {stderr}");
+
+        let log = std::fs::read_to_string(temp.path().join("makepkg.log")).expect("makepkg ran");
+        assert_eq!(
+            log.trim(),
+            src.canonicalize().expect("canonicalize").to_string_lossy()
+        );
+
+        // Rebuilt and unmarked
+        let output = run(&["ismarked", "my-pkg"]);
+        assert_eq!(output.status.code(), Some(2));
+
+        // The attempt is in the rebuild history
+        let output = run(&["history", "--rebuilds", "my-pkg"]);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "stdout: {stdout}");
+        assert!(
+            stdout.trim_end().ends_with("makepkg  ok"),
+            "stdout: {stdout}"
+        );
+    }
+
+    #[test]
+    fn add_requires_pkgbuild() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", temp.path().join("anneal.db"))
+            .args(["source", "add", "my-pkg"])
+            .arg(temp.path())
+            .output()
+            .expect("failed to run");
+
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("No PKGBUILD"), "stderr: {stderr}");
+    }
+}
+
+mod setup {
+    use super::*;
+
+    #[test]
+    fn installs_hook_once() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let dir = temp.path().join("hooks");
+        let run = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", temp.path().join("anneal.db"))
+                .arg("setup")
+                .args(args)
+                .arg("--dir")
+                .arg(&dir)
+                .output()
+                .expect("failed to run")
+        };
+
+        let output = run(&[]);
+        assert!(output.status.success());
+        let hook = std::fs::read_to_string(dir.join("anneal-trigger.hook")).expect("hook written");
+        assert!(hook.contains("When = PostTransaction"));
+        assert!(hook.contains(" trigger --hook\n"), "hook: {hook}");
+        let hook = std::fs::read_to_string(dir.join("anneal-capture.hook")).expect("hook written");
+        assert!(hook.contains("When = PreTransaction"));
+        assert!(hook.contains(" capture-versions\n"), "hook: {hook}");
+
+        let output = run(&[]);
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("up to date"), "stdout: {stdout}");
+
+        // Local edits are kept unless forced
+        std::fs::write(dir.join("anneal-trigger.hook"), "edited").expect("edit hook");
+        assert!(!run(&[]).status.success());
+        assert!(run(&["--force"]).status.success());
+    }
+
+    #[test]
+    fn print() {
+        let output = anneal()
+            .args(["setup", "--print"])
+            .output()
+            .expect("failed to run");
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("[Trigger]"), "stdout: {stdout}");
+        assert!(
+            stdout.contains("# anneal-capture.hook\n"),
+            "stdout: {stdout}"
+        );
+    }
+
+    #[test]
+    fn systemd_units() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let dir = temp.path().join("units");
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", temp.path().join("anneal.db"))
+            .env("ANNEAL_SCAN_INTERVAL", "weekly")
+            .args(["setup", "--systemd", "--dir"])
+            .arg(&dir)
+            .output()
+            .expect("failed to run");
+        assert!(output.status.success());
+        let service =
+            std::fs::read_to_string(dir.join("anneal-scan.service")).expect("service written");
+        assert!(
+            service.contains(" --quiet scan --checkrebuild\n"),
+            "service: {service}"
+        );
+        let timer = std::fs::read_to_string(dir.join("anneal-scan.timer")).expect("timer written");
+        assert!(timer.contains("OnCalendar=weekly\n"), "timer: {timer}");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("systemctl enable --now anneal-scan.timer"));
+    }
+}
+
+mod override_command {
+    use super::*;
+
+    #[test]
+    fn rejects_invalid_pattern() {
+        // Validation happens before anything is written
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", temp.path().join("anneal.db"))
+            .args(["override", "add", "trigger", "my-lib", "app/*"])
+            .output()
+            .expect("failed to run");
+
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("invalid pattern 'app/*'"),
+            "stderr: {stderr}"
+        );
+    }
+}
+
+mod ignore {
+    use super::*;
+
+    #[test]
+    fn rejects_invalid_name() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", temp.path().join("anneal.db"))
+            .args(["ignore", "../foo"])
+            .output()
+            .expect("failed to run");
+
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("invalid name '../foo'"), "stderr: {stderr}");
+    }
+}
+
+mod trigger_def {
+    use super::*;
+
+    #[test]
+    fn rejects_curated_trigger() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", temp.path().join("anneal.db"))
+            .args(["trigger-def", "add", "qt6-base", "my-app"])
+            .output()
+            .expect("failed to run");
+
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("qt6-base is a curated trigger"),
+            "stderr: {stderr}"
+        );
+    }
+}
+
+mod why {
+    use super::*;
+
+    #[test]
+    fn explains_trigger_marks() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .args([
+                "mark",
+                "--allow-missing",
+                "qt6gtk2",
+                "--trigger",
+                "qt6-base",
+            ])
+            .args(["--trigger-version", "6.7.0-1"])
+            .output()
+            .expect("failed to run");
+        assert!(output.status.success());
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .args(["why", "qt6gtk2"])
+            .output()
+            .expect("failed to run");
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("qt6-base 6.7.0-1: qt6gtk2 depends on qt6-base"),
+            "stdout: {stdout}"
+        );
+    }
+
+    #[test]
+    fn not_queued() {
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", ":memory:")
+            .args(["why", "nope"])
+            .output()
+            .expect("failed to run");
+        assert_eq!(output.status.code(), Some(2));
+    }
+}
+
+mod history {
+    use super::*;
+
+    #[test]
+    fn limit_and_reverse() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+
+        for version in ["6.6.0-1", "6.6.1-1", "6.7.0-1"] {
+            let output = anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .args([
+                    "mark",
+                    "--allow-missing",
+                    "qt6gtk2",
+                    "--trigger",
+                    "qt6-base",
+                ])
+                .args(["--trigger-version", version])
+                .output()
+                .expect("failed to run");
+            assert!(output.status.success());
+        }
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .args(["history", "qt6gtk2", "-n", "2", "-r"])
+            .output()
+            .expect("failed to run");
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(lines.len(), 2, "stdout: {stdout}");
+        assert!(lines[0].ends_with("qt6-base 6.6.1-1"), "stdout: {stdout}");
+        assert!(lines[1].ends_with("qt6-base 6.7.0-1"), "stdout: {stdout}");
+    }
+
+    #[test]
+    fn relative_and_absolute_times() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+        let run = |args: &[&str]| {
+            let output = anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .args(args)
+                .output()
+                .expect("failed to run");
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        };
+        run(&[
+            "mark",
+            "--allow-missing",
+            "--trigger",
+            "qt6-base",
+            "qt6gtk2",
+        ]);
+
+        let stdout = run(&["history", "qt6gtk2"]);
+        assert!(stdout.contains(" (just now)  qt6-base"), "stdout: {stdout}");
+        let stdout = run(&["why", "qt6gtk2"]);
+        assert!(stdout.contains("(just now)"), "stdout: {stdout}");
+
+        let stdout = run(&["history", "--absolute-time", "qt6gtk2"]);
+        assert!(!stdout.contains("just now"), "stdout: {stdout}");
+        assert!(stdout.starts_with("20"), "stdout: {stdout}");
+    }
+
+    #[test]
+    fn unknown_package() {
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", ":memory:")
+            .args(["history", "nope"])
+            .output()
+            .expect("failed to run");
+        assert_eq!(output.status.code(), Some(2));
+    }
+}
+
+mod stats {
+    use super::*;
+
+    #[test]
+    fn old_database() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+        super::old_database(&db_path);
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .args(["--json", "stats"])
+            .output()
+            .expect("failed to run");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let json: serde_json::Value =
+            serde_json::from_slice(&output.stdout).expect("stdout is JSON");
+        assert_eq!(json["queued"], 1);
+        assert_eq!(json["dequeued"], 0);
+        assert!(json["average_wait_secs"].is_null());
+    }
+
+    #[test]
+    fn counts_marks_and_unmarks() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+
+        for args in [
+            &[
+                "mark",
+                "--allow-missing",
+                "--trigger",
+                "qt6-base",
+                "qt6ct",
+                "qt6gtk2",
+            ][..],
+            &["mark", "--allow-missing", "my-app"][..],
+            &["unmark", "qt6gtk2"][..],
+        ] {
+            let output = anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .args(args)
+                .output()
+                .expect("failed to run");
+            assert!(output.status.success());
+        }
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .args(["--json", "stats"])
+            .output()
+            .expect("failed to run");
+        assert!(output.status.success());
+        let json: serde_json::Value =
+            serde_json::from_slice(&output.stdout).expect("stdout is JSON");
+        assert_eq!(json["queued"], 2);
+        assert_eq!(
+            json["busiest_triggers"],
+            serde_json::json!([{ "trigger": "qt6-base", "marks": 2 }])
+        );
+        assert_eq!(json["external_marks"], 1);
+        assert_eq!(json["dequeued"], 1);
+        assert!(json["average_wait_secs"].is_u64());
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .arg("stats")
+            .output()
+            .expect("failed to run");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("Queue: 2 packages"), "stdout: {stdout}");
+        assert!(stdout.contains("External marks: 1"), "stdout: {stdout}");
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .arg("count")
+            .output()
+            .expect("failed to run");
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "2\n");
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .args(["status", "--waybar"])
+            .output()
+            .expect("failed to run");
+        assert!(output.status.success());
+        let json: serde_json::Value =
+            serde_json::from_slice(&output.stdout).expect("stdout is JSON");
+        assert_eq!(json["text"], "2");
+        assert_eq!(json["class"], "pending");
+        let tooltip = json["tooltip"].as_str().expect("tooltip");
+        assert!(tooltip.starts_with("2 packages queued, oldest for "));
+        assert!(tooltip.ends_with("\nqt6ct\nmy-app"), "tooltip: {tooltip}");
+    }
+}
+
+mod backup {
+    use super::*;
+
+    #[test]
+    fn backup_and_restore() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let source = temp.path().join("source.db");
+        let target = temp.path().join("target.db");
+        let file = temp.path().join("backup.json");
+
+        let run = |db: &std::path::Path, args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", db)
+                .args(args)
+                .output()
+                .expect("failed to run")
+        };
+
+        assert!(
+            run(
+                &source,
+                &["mark", "--allow-missing", "--trigger", "qt6-base", "qt6ct"]
+            )
+            .status
+            .success()
+        );
+        let file_arg = file.to_str().expect("utf-8 path");
+        assert!(run(&source, &["backup", file_arg]).status.success());
+
+        assert!(
+            run(&target, &["mark", "--allow-missing", "other"])
+                .status
+                .success()
+        );
+        // Replacing the queue needs confirmation
+        let output = run(&target, &["--quiet", "restore", file_arg]);
+        assert_eq!(output.status.code(), Some(1));
+
+        let output = run(&target, &["restore", "-f", file_arg]);
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("Restored 1 package"));
+
+        let output = run(&target, &["--json", "list"]);
+        let json: serde_json::Value =
+            serde_json::from_slice(&output.stdout).expect("stdout is JSON");
+        let packages: Vec<&str> = json
+            .as_array()
+            .expect("list is an array")
+            .iter()
+            .filter_map(|entry| entry["package"].as_str())
+            .collect();
+        assert_eq!(packages, vec!["qt6ct"]);
+    }
+
+    #[test]
+    fn restore_rejects_invalid_file() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let file = temp.path().join("backup.json");
+        std::fs::write(&file, "{}").expect("write file");
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", ":memory:")
+            .args(["restore", "-f"])
+            .arg(&file)
+            .output()
+            .expect("failed to run");
+        assert_eq!(output.status.code(), Some(1));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("is not a valid backup"));
+    }
+}
+
+mod timeline {
+    use super::*;
+
+    #[test]
+    fn shows_marks_and_unmarks_in_order() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+
+        for args in [
+            &[
+                "mark",
+                "--allow-missing",
+                "--trigger",
+                "qt6-base",
+                "test-pkg",
+            ][..],
+            &["unmark", "test-pkg"][..],
+        ] {
+            let output = anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .args(args)
+                .output()
+                .expect("failed to run");
+            assert!(output.status.success());
+        }
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .args(["timeline", "test-pkg"])
+            .output()
+            .expect("failed to run");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let marked = stdout.find("marked by qt6-base").expect("mark shown");
+        let unmarked = stdout
+            .find("unmarked (command line)")
+            .expect("unmark shown");
+        assert!(marked < unmarked, "stdout: {stdout}");
+
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", &db_path)
+            .args(["why", "test-pkg"])
+            .output()
+            .expect("failed to run");
+        assert_eq!(output.status.code(), Some(2));
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("test-pkg is not in the queue: unmarked (command line) at"),
+            "stdout: {stdout}"
+        );
+    }
+
+    #[test]
+    fn unknown_package() {
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", ":memory:")
+            .args(["timeline", "test-pkg"])
+            .output()
+            .expect("failed to run");
+        assert_eq!(output.status.code(), Some(2));
+    }
 
+    #[test]
+    fn old_database() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+        super::old_database(&db_path);
+        let run = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .args(args)
+                .output()
+                .expect("failed to run")
+        };
+
+        let output = run(&["timeline", "foo"]);
         assert!(
             output.status.success(),
-            "list should succeed on readonly WAL database. stderr: {stderr}"
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
         );
-        assert!(
-            stdout.contains("test-pkg"),
-            "should find the package. stdout: {stdout}"
+        assert!(String::from_utf8_lossy(&output.stdout).contains("marked by qt6-base"));
+
+        let output = run(&["why", "bar"]);
+        assert_eq!(
+            output.status.code(),
+            Some(2),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
         );
     }
 }
@@ -378,6 +1808,7 @@ mod rebuild_command {
         assert!(stdout.contains("--checkrebuild"));
         assert!(stdout.contains("--cmd"));
         assert!(stdout.contains("--force"));
+        assert!(stdout.contains("--skip-flagged"));
     }
 
     #[test]
@@ -420,6 +1851,367 @@ mod rebuild_command {
         );
     }
 
+    #[test]
+    fn rebuild_refuses_while_locked() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+        let run = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .args(args)
+                .output()
+                .expect("failed to run")
+        };
+        assert!(run(&["mark", "--allow-missing", "foo"]).status.success());
+
+        let lock_path = anneal::lock::rebuild_lock_path(&db_path).expect("lock path");
+        let lock = anneal::lock::try_lock(&lock_path).expect("open lock");
+        assert!(lock.is_some());
+
+        let output = run(&["rebuild", "-f", "--cmd", "true"]);
+        assert_eq!(output.status.code(), Some(1));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("Another rebuild is already running"),
+            "stderr: {stderr}"
+        );
+
+        // Still queued
+        assert_eq!(run(&["ismarked", "foo"]).status.code(), Some(0));
+    }
+
+    #[test]
+    fn rebuild_limit_takes_oldest() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+        let run = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .args(args)
+                .output()
+                .expect("failed to run")
+        };
+        for pkg in ["old", "middle", "new"] {
+            assert!(run(&["mark", "--allow-missing", pkg]).status.success());
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+
+        let output = run(&[
+            "--offline",
+            "rebuild",
+            "-f",
+            "--limit",
+            "2",
+            "--cmd",
+            "true",
+        ]);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "stderr: {stderr}");
+        assert!(
+            stderr.contains("1 newer queued package(s) left for later"),
+            "stderr: {stderr}"
+        );
+
+        assert_eq!(run(&["ismarked", "old"]).status.code(), Some(2));
+        assert_eq!(run(&["ismarked", "middle"]).status.code(), Some(2));
+        assert_eq!(run(&["ismarked", "new"]).status.code(), Some(0));
+
+        let output = run(&["rebuild", "-f", "--limit", "0"]);
+        assert!(!output.status.success());
+    }
+
+    #[test]
+    fn pinned_survives_rebuild_and_clear() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+        let run = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .args(args)
+                .output()
+                .expect("failed to run")
+        };
+        for pkg in ["kept", "other"] {
+            assert!(run(&["mark", "--allow-missing", pkg]).status.success());
+        }
+        assert!(run(&["pin", "kept"]).status.success());
+        assert_eq!(run(&["pin", "missing"]).status.code(), Some(2));
+
+        let output = run(&["--offline", "rebuild", "-f", "--cmd", "true"]);
+        assert!(output.status.success());
+        assert_eq!(run(&["ismarked", "kept"]).status.code(), Some(0));
+        assert_eq!(run(&["ismarked", "other"]).status.code(), Some(2));
+
+        assert!(run(&["unmark", "kept"]).status.success());
+        assert!(run(&["clear", "-f"]).status.success());
+        assert_eq!(run(&["ismarked", "kept"]).status.code(), Some(0));
+
+        assert!(run(&["clear", "-f", "--include-pinned"]).status.success());
+        assert_eq!(run(&["ismarked", "kept"]).status.code(), Some(2));
+    }
+
+    #[test]
+    fn stale_versions_are_flagged_and_unmarked() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+        let info = temp.path().join("fixture/info");
+        std::fs::create_dir_all(&info).expect("failed to create fixture");
+        let install = |pkg: &str, version: &str| {
+            std::fs::write(
+                info.join(pkg),
+                format!(
+                    "Name            : {pkg}\nVersion         : {version}\nArchitecture    : x86_64\n"
+                ),
+            )
+            .expect("failed to write fixture");
+        };
+        let run = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .env("ANNEAL_FIXTURE_DIR", temp.path().join("fixture"))
+                .args(args)
+                .output()
+                .expect("failed to run")
+        };
+        install("foo", "1.0-1");
+        install("bar", "2.0-1");
+        assert!(run(&["mark", "foo", "bar"]).status.success());
+
+        // foo got upgraded by something other than anneal
+        install("foo", "1.1-1");
+        let output = run(&["list"]);
+        assert!(
+            String::from_utf8_lossy(&output.stderr).contains("1 package(s) changed version"),
+            "{output:?}"
+        );
+        let output = run(&["why", "foo"]);
+        assert!(
+            String::from_utf8_lossy(&output.stdout).contains("version: marked at 1.0-1, now 1.1-1"),
+            "{output:?}"
+        );
+
+        let output = run(&[
+            "--offline",
+            "--json",
+            "rebuild",
+            "-f",
+            "--skip-stale",
+            "--cmd",
+            "true",
+        ]);
+        assert!(output.status.success());
+        let json: serde_json::Value =
+            serde_json::from_slice(&output.stdout).expect("stdout is JSON");
+        assert_eq!(json["rebuilt"], serde_json::json!(["bar"]));
+        assert_eq!(json["skipped"], serde_json::json!(["foo"]));
+        assert_eq!(run(&["ismarked", "foo"]).status.code(), Some(0));
+        assert_eq!(run(&["ismarked", "bar"]).status.code(), Some(2));
+
+        let output = run(&[
+            "--offline",
+            "rebuild",
+            "-f",
+            "--unmark-stale",
+            "--cmd",
+            "false",
+        ]);
+        assert!(output.status.success(), "{output:?}");
+        assert_eq!(run(&["ismarked", "foo"]).status.code(), Some(2));
+    }
+
+    #[test]
+    fn rebuild_exclude_globs() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+        let run = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .args(args)
+                .output()
+                .expect("failed to run")
+        };
+        assert!(
+            run(&[
+                "mark",
+                "--allow-missing",
+                "foo",
+                "electron-app",
+                "slack-electron"
+            ])
+            .status
+            .success()
+        );
+
+        let output = run(&[
+            "--offline",
+            "rebuild",
+            "-f",
+            "--exclude",
+            "electron-*",
+            "--exclude",
+            "*-electron",
+            "--cmd",
+            "true",
+        ]);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "stderr: {stderr}");
+        assert!(
+            stderr.contains("2 queued package(s) excluded"),
+            "stderr: {stderr}"
+        );
+
+        assert_eq!(run(&["ismarked", "foo"]).status.code(), Some(2));
+        assert_eq!(run(&["ismarked", "electron-app"]).status.code(), Some(0));
+        assert_eq!(run(&["ismarked", "slack-electron"]).status.code(), Some(0));
+    }
+
+    #[test]
+    fn rebuild_interactive_requires_terminal() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+        let run = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .args(args)
+                .stdin(std::process::Stdio::null())
+                .output()
+                .expect("failed to run")
+        };
+        assert!(run(&["mark", "--allow-missing", "foo"]).status.success());
+
+        let output = run(&["--offline", "rebuild", "-f", "-i", "--cmd", "true"]);
+        assert_eq!(output.status.code(), Some(1));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("--interactive requires a terminal"),
+            "stderr: {stderr}"
+        );
+        assert_eq!(run(&["ismarked", "foo"]).status.code(), Some(0));
+    }
+
+    #[test]
+    fn rebuild_dry_run_prints_commands() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+        let run = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .args(args)
+                .output()
+                .expect("failed to run")
+        };
+        // Marked apart, so the queue order doesn't depend on the names
+        for pkg in ["foo", "bar"] {
+            assert!(run(&["mark", "--allow-missing", pkg]).status.success());
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+
+        let output = run(&[
+            "--offline",
+            "--quiet",
+            "rebuild",
+            "--dry-run",
+            "--cmd",
+            "true --rebuild",
+            "--",
+            "--needed",
+            "a b",
+        ]);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "stdout: {stdout}");
+        assert_eq!(stdout, "true --rebuild foo bar --needed 'a b'\n");
+
+        let output = run(&[
+            "--offline",
+            "--json",
+            "rebuild",
+            "-n",
+            "-k",
+            "--cmd",
+            "true",
+        ]);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let value: serde_json::Value = serde_json::from_str(&stdout).expect("valid JSON");
+        assert_eq!(
+            value["commands"][1],
+            serde_json::json!({"packages": ["bar"], "command": ["true", "bar"], "directory": null})
+        );
+
+        // Nothing was built
+        assert_eq!(run(&["ismarked", "foo"]).status.code(), Some(0));
+    }
+
+    #[test]
+    fn rebuild_with_builtin_makepkg() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+        let cache = temp.path().join("cache");
+        let bin = temp.path().join("bin");
+        std::fs::create_dir(&bin).expect("create bin dir");
+
+        // Fake makepkg recording where it ran and with what
+        let makepkg = bin.join("makepkg");
+        std::fs::write(&makepkg, "#!/bin/sh\necho \"$PWD $*\" > \"$MAKEPKG_LOG\"\n")
+            .expect("write makepkg");
+        std::fs::set_permissions(&makepkg, std::fs::Permissions::from_mode(0o755))
+            .expect("chmod makepkg");
+
+        // Offline, an existing checkout is built as it is
+        let checkout = cache.join("clone/foo");
+        std::fs::create_dir_all(checkout.join(".git")).expect("create checkout");
+        std::fs::write(checkout.join("PKGBUILD"), "pkgname=foo\n").expect("write PKGBUILD");
+
+        let run = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .env("ANNEAL_CACHE_DIR", &cache)
+                .env("PATH", format!("{}:/usr/bin:/bin", bin.display()))
+                .env("MAKEPKG_LOG", temp.path().join("makepkg.log"))
+                .args(args)
+                .output()
+                .expect("failed to run")
+        };
+        assert!(run(&["mark", "--allow-missing", "foo"]).status.success());
+
+        let output = run(&["--offline", "--quiet", "rebuild", "-n", "--cmd", "makepkg"]);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "stdout: {stdout}");
+        assert_eq!(
+            stdout,
+            format!(
+                "cd {} && makepkg --syncdeps --rmdeps --clean --install --force\n",
+                checkout.display()
+            )
+        );
+
+        let output = run(&["--offline", "rebuild", "-f", "--cmd", "makepkg"]);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "stderr: {stderr}");
+        assert!(stderr.contains("existing checkout"), "stderr: {stderr}");
+        let log = std::fs::read_to_string(temp.path().join("makepkg.log")).expect("makepkg ran");
+        assert_eq!(
+            log.trim(),
+            format!(
+                "{} --syncdeps --rmdeps --clean --install --force",
+                checkout.display()
+            )
+        );
+        assert_eq!(run(&["ismarked", "foo"]).status.code(), Some(2));
+
+        // Without a checkout there's nothing to build offline
+        assert!(run(&["mark", "--allow-missing", "bar"]).status.success());
+        let output = run(&["--offline", "rebuild", "-f", "--cmd", "makepkg"]);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success());
+        assert!(
+            stderr.contains("AUR repository of 'bar'"),
+            "stderr: {stderr}"
+        );
+        assert_eq!(run(&["ismarked", "bar"]).status.code(), Some(0));
+    }
+
     #[test]
     fn rebuild_quiet_without_force_fails() {
         // --quiet without -f should fail since we can't prompt
@@ -454,6 +2246,93 @@ mod rebuild_command {
         );
     }
 
+    #[test]
+    fn keep_going_then_retry_failed() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+        let bin = temp.path().join("bin");
+        std::fs::create_dir(&bin).expect("create bin dir");
+
+        // Fake helper failing on one package
+        let helper = bin.join("fake-helper");
+        std::fs::write(
+            &helper,
+            "#!/bin/sh\nfor p in \"$@\"; do [ \"$p\" = bad ] && exit 3; done\nexit 0\n",
+        )
+        .expect("write helper");
+        std::fs::set_permissions(&helper, std::fs::Permissions::from_mode(0o755))
+            .expect("chmod helper");
+
+        let log_dir = temp.path().join("logs");
+        let run = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .env("ANNEAL_BUILD_LOG_DIR", &log_dir)
+                .env("PATH", format!("{}:/usr/bin:/bin", bin.display()))
+                .args(args)
+                .output()
+                .expect("failed to run")
+        };
+
+        assert!(
+            run(&["mark", "--allow-missing", "bad", "good"])
+                .status
+                .success()
+        );
+
+        let output = run(&[
+            "--offline",
+            "--json",
+            "rebuild",
+            "-f",
+            "--keep-going",
+            "--cmd",
+            "fake-helper",
+        ]);
+        assert_eq!(output.status.code(), Some(1));
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let value: serde_json::Value = serde_json::from_str(&stdout).expect("valid JSON");
+        assert_eq!(value["rebuilt"], serde_json::json!(["good"]));
+        assert_eq!(value["failed"], serde_json::json!(["bad"]));
+        assert_eq!(value["exit_code"], 3);
+
+        // Each package got a log, and the failed one's is pointed out
+        let mut logs: Vec<String> = std::fs::read_dir(&log_dir)
+            .expect("log dir")
+            .map(|entry| entry.expect("entry").file_name().to_string_lossy().into())
+            .collect();
+        logs.sort();
+        assert_eq!(logs.len(), 2, "logs: {logs:?}");
+        assert!(logs[0].starts_with("bad-") && logs[1].starts_with("good-"));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains(&format!("Build log: {}", log_dir.join(&logs[0]).display())),
+            "stderr: {stderr}"
+        );
+
+        assert_eq!(run(&["ismarked", "good"]).status.code(), Some(2));
+        assert_eq!(run(&["ismarked", "bad"]).status.code(), Some(0));
+
+        // --failed retries only the package that failed
+        assert!(run(&["mark", "--allow-missing", "fresh"]).status.success());
+        let output = run(&[
+            "--offline",
+            "--json",
+            "rebuild",
+            "-f",
+            "--failed",
+            "--cmd",
+            "fake-helper",
+        ]);
+        assert_eq!(output.status.code(), Some(1));
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let value: serde_json::Value = serde_json::from_str(&stdout).expect("valid JSON");
+        assert_eq!(value["failed"], serde_json::json!(["bad"]));
+        assert_eq!(run(&["ismarked", "fresh"]).status.code(), Some(0));
+    }
+
     #[test]
     fn rebuild_nonexistent_helper() {
         // Using a non-existent helper should fail gracefully
@@ -616,6 +2495,469 @@ mod trigger_command {
     }
 }
 
+mod fixtures {
+    use super::*;
+
+    fn fixture_dir() -> String {
+        format!("{}/tests/fixtures/basic", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    #[test]
+    fn trigger_marks_compiled_dependents() {
+        let output = anneal()
+            .args(["trigger", "--dry-run", "--fixture-dir", &fixture_dir()])
+            .arg("qt6-base")
+            .output()
+            .expect("failed to run");
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stdout.contains("qt6gtk2"), "stdout: {stdout}");
+        // Repo package, -bin and -debug packages, and arch=any package are
+        // all skipped
+        assert!(!stdout.contains("qt6-declarative"), "stdout: {stdout}");
+        assert!(!stdout.contains("foo-bin"), "stdout: {stdout}");
+        assert!(!stdout.contains("qt6gtk2-debug"), "stdout: {stdout}");
+        assert!(!stdout.contains("qt6-theme-any"), "stdout: {stdout}");
+        assert!(
+            stderr.contains("Skipped 1 architecture-independent"),
+            "stderr: {stderr}"
+        );
+        // No progress line when stderr isn't a terminal
+        assert!(!stderr.contains("resolving dependents"), "stderr: {stderr}");
+    }
+
+    #[test]
+    fn verbose_trigger_explains_filtering() {
+        let output = anneal()
+            .env("ANNEAL_FIXTURE_DIR", fixture_dir())
+            .args(["-vv", "trigger", "--dry-run", "qt6-base"])
+            .output()
+            .expect("failed to run");
+
+        assert!(output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("qt6-base: pactree found 5 reverse dependent(s)"),
+            "stderr: {stderr}"
+        );
+        assert!(
+            stderr.contains("foo-bin: not marked (-bin package)"),
+            "stderr: {stderr}"
+        );
+        assert!(
+            stderr.contains("qt6-theme-any: not marked by qt6-base (arch=any)"),
+            "stderr: {stderr}"
+        );
+
+        let output = anneal()
+            .env("ANNEAL_FIXTURE_DIR", fixture_dir())
+            .args(["trigger", "--dry-run", "qt6-base"])
+            .output()
+            .expect("failed to run");
+        assert!(!String::from_utf8_lossy(&output.stderr).contains("debug:"));
+    }
+
+    #[test]
+    fn runtime_trigger_marks_any_arch() {
+        let output = anneal()
+            .env("ANNEAL_FIXTURE_DIR", fixture_dir())
+            .args(["trigger", "--dry-run", "python"])
+            .output()
+            .expect("failed to run");
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("python-foo"), "stdout: {stdout}");
+    }
+
+    #[test]
+    fn scan_finds_old_python_files() {
+        let output = anneal()
+            .env("ANNEAL_FIXTURE_DIR", fixture_dir())
+            .args(["scan", "--dry-run"])
+            .output()
+            .expect("failed to run");
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("python-foo (files under python3.12)"),
+            "stdout: {stdout}"
+        );
+        assert!(!stdout.contains("qt6gtk2"), "stdout: {stdout}");
+    }
+
+    #[test]
+    fn scan_finds_missing_libraries() {
+        let output = anneal()
+            .env("ANNEAL_FIXTURE_DIR", fixture_dir())
+            .args(["scan", "--dry-run", "--checkrebuild"])
+            .output()
+            .expect("failed to run");
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("qt6gtk2 (libicuuc.so.74 not found)"),
+            "stdout: {stdout}"
+        );
+        // -bin and skip_packages (-debug) are left out like for triggers
+        assert!(!stdout.contains("foo-bin"), "stdout: {stdout}");
+        assert!(!stdout.contains("qt6gtk2-debug"), "stdout: {stdout}");
+    }
+
+    #[test]
+    fn verify_reports_linkage_problems() {
+        let verify = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", ":memory:")
+                .env("ANNEAL_FIXTURE_DIR", fixture_dir())
+                .arg("verify")
+                .args(args)
+                .output()
+                .expect("failed to run")
+        };
+
+        let output = verify(&["qt6gtk2"]);
+        assert_eq!(output.status.code(), Some(1));
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert_eq!(
+            stdout,
+            "/usr/lib/qt6/plugins/platformthemes/libqt6gtk2.so: libicuuc.so.74 not found\n"
+        );
+
+        let output = verify(&["python-foo"]);
+        assert_eq!(output.status.code(), Some(0));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("No linkage problems in python-foo"),
+            "stderr: {stderr}"
+        );
+
+        assert_eq!(verify(&["not-installed"]).status.code(), Some(2));
+
+        let output = verify(&["--json", "qt6gtk2"]);
+        let value: serde_json::Value = serde_json::from_slice(&output.stdout).expect("valid JSON");
+        assert_eq!(value["installed"], true);
+        assert_eq!(value["queued"], false);
+        assert_eq!(value["problems"][0]["library"], "libicuuc.so.74");
+        assert_eq!(value["problems"][0]["version"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn trigger_unmarks_reinstalled_and_uninstalled() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+        let run = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .env("ANNEAL_FIXTURE_DIR", fixture_dir())
+                .args(args)
+                .output()
+                .expect("failed to run")
+        };
+        assert!(run(&["mark", "qt6gtk2", "python-foo"]).status.success());
+        assert!(
+            run(&["mark", "--allow-missing", "gone", "kept"])
+                .status
+                .success()
+        );
+        assert!(run(&["pin", "kept"]).status.success());
+
+        let output = run(&["trigger", "qt6gtk2"]);
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("Unmarked qt6gtk2 (reinstalled)"),
+            "stdout: {stdout}"
+        );
+        assert!(
+            stdout.contains("Unmarked gone (no longer installed)"),
+            "stdout: {stdout}"
+        );
+
+        let output = run(&["list", "--format", "{package}"]);
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "python-foo\nkept\n"
+        );
+        let output = run(&["timeline", "gone"]);
+        assert!(
+            String::from_utf8_lossy(&output.stdout).contains("no longer installed (pacman hook)")
+        );
+    }
+
+    #[test]
+    fn mark_expands_globs_and_reads_file() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+        let list = temp.path().join("pkglist.txt");
+        std::fs::write(&list, "# leftovers\npython-*  qt6gtk2\n").expect("failed to write list");
+        let run = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .env("ANNEAL_FIXTURE_DIR", fixture_dir())
+                .args(args)
+                .output()
+                .expect("failed to run")
+        };
+
+        let output = run(&["mark", "--file", &list.to_string_lossy(), "qt6gtk2*"]);
+        assert!(output.status.success());
+        let output = run(&["list", "--format", "{package}"]);
+        let mut marked: Vec<String> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(String::from)
+            .collect();
+        marked.sort();
+        assert_eq!(marked, ["python-foo", "qt6gtk2", "qt6gtk2-debug"]);
+
+        let output = run(&["mark", "kde-*"]);
+        assert_eq!(output.status.code(), Some(2));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("No installed foreign packages match 'kde-*'"),
+            "stderr: {stderr}"
+        );
+    }
+
+    #[test]
+    fn mark_foreign_skips_binary_and_companion_packages() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+        let run = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .env("ANNEAL_FIXTURE_DIR", fixture_dir())
+                .args(args)
+                .output()
+                .expect("failed to run")
+        };
+
+        let output = run(&["mark", "--foreign", "--trigger", "glibc"]);
+        assert!(output.status.success());
+        let output = run(&["list", "--format", "{package}"]);
+        let mut marked: Vec<String> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(String::from)
+            .collect();
+        marked.sort();
+        assert_eq!(marked, ["python-foo", "qt6-theme-any", "qt6gtk2"]);
+    }
+
+    #[test]
+    fn hook_mode_looks_up_versions() {
+        use std::io::Write;
+
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let fixture = temp.path().join("fixture");
+        for dir in ["info", "pactree", "cache"] {
+            std::fs::create_dir_all(fixture.join(dir)).expect("failed to create fixture");
+        }
+        let write = |path: &str, content: &str| {
+            std::fs::write(fixture.join(path), content).expect("failed to write fixture");
+        };
+        write("foreign", "qt6gtk2\n");
+        write("pactree/qt6-base", "qt6-base\nqt6gtk2\n");
+        write(
+            "info/qt6gtk2",
+            "Name            : qt6gtk2\nVersion         : 0.2-1\nArchitecture    : x86_64\n",
+        );
+        write("cache/qt6-base-6.7.0-1-x86_64.pkg.tar.zst", "");
+
+        let hook = |db: &str, installed: &str| {
+            write(
+                "info/qt6-base",
+                &format!(
+                    "Name            : qt6-base\nVersion         : {installed}\nArchitecture    : x86_64\n"
+                ),
+            );
+            let mut child = anneal()
+                .env("ANNEAL_DB_PATH", temp.path().join(db))
+                .env("ANNEAL_FIXTURE_DIR", &fixture)
+                .args(["trigger", "--hook"])
+                .stdin(std::process::Stdio::piped())
+                .stdout(std::process::Stdio::piped())
+                .spawn()
+                .expect("failed to run");
+            child
+                .stdin
+                .take()
+                .expect("no stdin")
+                .write_all(b"qt6-base\nqt6gtk2\n")
+                .expect("failed to write stdin");
+            let output = child.wait_with_output().expect("failed to run");
+            assert!(output.status.success());
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        };
+        let history = |db: &str| {
+            let output = anneal()
+                .env("ANNEAL_DB_PATH", temp.path().join(db))
+                .args(["--absolute-time", "history", "qt6gtk2"])
+                .output()
+                .expect("failed to run");
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        };
+
+        // 6.7 to 6.8 crosses the minor threshold, and the version is recorded
+        let stdout = hook("a.db", "6.8.0-1");
+        assert!(stdout.contains("Marked qt6gtk2"), "stdout: {stdout}");
+        let stdout = history("a.db");
+        assert!(stdout.ends_with("qt6-base 6.8.0-1\n"), "stdout: {stdout}");
+
+        // A rebuild of the cached version doesn't
+        write("cache/qt6-base-6.8.0-1-x86_64.pkg.tar.zst", "");
+        let stdout = hook("b.db", "6.8.0-2");
+        assert!(!stdout.contains("Marked"), "stdout: {stdout}");
+    }
+
+    #[test]
+    fn hook_mode_uses_captured_versions() {
+        use std::io::Write;
+
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let fixture = temp.path().join("fixture");
+        for dir in ["info", "pactree"] {
+            std::fs::create_dir_all(fixture.join(dir)).expect("failed to create fixture");
+        }
+        let write = |path: &str, content: &str| {
+            std::fs::write(fixture.join(path), content).expect("failed to write fixture");
+        };
+        write("foreign", "qt6gtk2\n");
+        write("pactree/qt6-base", "qt6-base\nqt6gtk2\n");
+        let install = |version: &str| {
+            write(
+                "info/qt6-base",
+                &format!(
+                    "Name            : qt6-base\nVersion         : {version}\nArchitecture    : x86_64\n"
+                ),
+            );
+        };
+        let db = temp.path().join("anneal.db");
+        let run = |args: &[&str]| {
+            let mut child = anneal()
+                .env("ANNEAL_DB_PATH", &db)
+                .env("ANNEAL_FIXTURE_DIR", &fixture)
+                .args(args)
+                .stdin(std::process::Stdio::piped())
+                .stdout(std::process::Stdio::piped())
+                .spawn()
+                .expect("failed to run");
+            child
+                .stdin
+                .take()
+                .expect("no stdin")
+                .write_all(b"qt6-base\nqt6gtk2\n")
+                .expect("failed to write stdin");
+            let output = child.wait_with_output().expect("failed to run");
+            assert!(output.status.success());
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        };
+        let capture = temp.path().join("anneal.db.versions");
+
+        // Only triggers are captured, and the capture is used up
+        install("6.8.0-1");
+        run(&["capture-versions"]);
+        let captured = std::fs::read_to_string(&capture).expect("versions captured");
+        assert_eq!(captured, "qt6-base 6.8.0-1\n");
+        install("6.8.1-1");
+        let stdout = run(&["trigger", "--hook"]);
+        assert!(!stdout.contains("Marked"), "stdout: {stdout}");
+        assert!(!capture.exists());
+
+        // Without a capture or a cached package, the trigger always fires
+        let stdout = run(&["trigger", "--hook"]);
+        assert!(stdout.contains("Marked qt6gtk2"), "stdout: {stdout}");
+    }
+
+    #[test]
+    fn preview_reads_update_list() {
+        use std::io::Write;
+
+        let preview = |updates: &str| {
+            let mut child = anneal()
+                .env("ANNEAL_FIXTURE_DIR", fixture_dir())
+                .args(["--json", "preview", "-"])
+                .stdin(std::process::Stdio::piped())
+                .stdout(std::process::Stdio::piped())
+                .spawn()
+                .expect("failed to run");
+            child
+                .stdin
+                .take()
+                .expect("no stdin")
+                .write_all(updates.as_bytes())
+                .expect("failed to write stdin");
+            let output = child.wait_with_output().expect("failed to run");
+            assert!(output.status.success());
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            serde_json::from_str::<serde_json::Value>(&stdout)
+                .unwrap_or_else(|e| panic!("invalid JSON ({e}): {stdout}"))
+        };
+
+        let result = preview("qt6-base 6.7.0-1 -> 6.8.0-1\nlinux 6.9-1 -> 6.10-1\n");
+        assert_eq!(result["updates"], 2);
+        assert_eq!(result["marked"][0]["package"], "qt6gtk2");
+        assert_eq!(result["marked"][0]["trigger"], "qt6-base");
+
+        // A rebuild of the same version doesn't reach the threshold
+        let result = preview("qt6-base 6.7.0-1 -> 6.7.0-2\n");
+        assert_eq!(result["marked"], serde_json::json!([]));
+
+        let result = preview("");
+        assert_eq!(result, serde_json::json!({"updates": 0, "marked": []}));
+    }
+
+    #[test]
+    fn null_delimited_names() {
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let db_path = temp.path().join("anneal.db");
+        let run = |args: &[&str]| {
+            anneal()
+                .env("ANNEAL_DB_PATH", &db_path)
+                .env("ANNEAL_FIXTURE_DIR", fixture_dir())
+                .args(args)
+                .output()
+                .expect("failed to run")
+        };
+
+        let output = run(&["trigger", "--dry-run", "-0", "qt6-base"]);
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"qt6gtk2\0");
+
+        // An empty queue prints nothing at all
+        let output = anneal()
+            .env("ANNEAL_DB_PATH", ":memory:")
+            .args(["list", "-0"])
+            .output()
+            .expect("failed to run");
+        assert!(output.status.success());
+        assert!(output.stdout.is_empty());
+
+        run(&["mark", "qt6gtk2", "python-foo"]);
+        let output = run(&["list", "-0", "--sort", "name"]);
+        assert_eq!(output.stdout, b"python-foo\0qt6gtk2\0");
+        let output = run(&["query", "-0", "qt6gtk2", "foo-bin"]);
+        assert_eq!(output.stdout, b"qt6gtk2\0");
+        assert_eq!(output.status.code(), Some(3));
+    }
+
+    #[test]
+    fn trigger_not_installed() {
+        let output = anneal()
+            .env("ANNEAL_FIXTURE_DIR", fixture_dir())
+            .args(["trigger", "--dry-run", "gtk4"])
+            .output()
+            .expect("failed to run");
+
+        assert!(output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("No packages to mark"), "stderr: {stderr}");
+    }
+}
+
 mod completions {
     use super::*;
 